@@ -0,0 +1,194 @@
+// anevicon: A high-performant UDP-based load generator, written in Rust.
+// Copyright (C) 2019  Temirkhan Myrzamadi <gymmasssorla@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// For more information see <https://github.com/Gymmasssorla/anevicon>.
+
+//! Benchmarks of the hot path: sending batches by `sendmmsg`, sending single
+//! datagrams with and without concatenating them, and crafting IP/UDP
+//! datagrams. anevicon has no library target, so the benchmarked
+//! modules (which don't depend on the rest of the crate) are included
+//! directly.
+
+#[macro_use]
+extern crate failure_derive;
+
+use std::io::IoSlice;
+use std::net::UdpSocket;
+use std::os::unix::io::AsRawFd;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+#[allow(dead_code, unused_imports)]
+#[path = "../src/core/craft_datagrams/craft_packets.rs"]
+mod craft_packets;
+#[allow(dead_code, unused_imports)]
+#[path = "../src/config/endpoints.rs"]
+mod endpoints;
+#[allow(dead_code, unused_imports)]
+#[path = "../src/core/udp_sender/sendmmsg_wrapper.rs"]
+mod sendmmsg_wrapper;
+
+/// `craft_packets` refers to endpoints and IP options as `crate::config::*`.
+mod config {
+    pub use crate::endpoints::{Endpoints, EndpointsV4, EndpointsV6};
+
+    #[allow(dead_code)]
+    #[derive(Debug, Copy, Clone)]
+    pub enum IpOption {
+        RecordRoute,
+        Timestamp,
+    }
+}
+
+/// `craft_packets` refers to it as `super::UDP_HEADER_LEN`.
+const UDP_HEADER_LEN: usize = 8;
+
+use config::Endpoints;
+use sendmmsg_wrapper::DataPortion;
+
+/// Numbers of packets sent per a system call (`--test-intensity`).
+const BATCH_SIZES: [usize; 4] = [1, 64, 600, 1024];
+
+/// Sizes of payloads: empty, small, the default one, and the largest one
+/// fitting into a 1500-byte Ethernet frame with IPv4 and UDP headers.
+const PAYLOAD_SIZES: [usize; 4] = [0, 64, 1024, 1472];
+
+const HEADER: [u8; 28] = [0; 28];
+const PAYLOAD: [u8; 1024] = [0; 1024];
+
+fn portions(batch_size: usize) -> Vec<DataPortion<'static>> {
+    (0..batch_size)
+        .map(|_| DataPortion {
+            transmitted: 0,
+            slices: [IoSlice::new(&HEADER), IoSlice::new(&PAYLOAD)],
+        })
+        .collect()
+}
+
+fn bench_sendmmsg(c: &mut Criterion) {
+    // Datagrams are never received, so the kernel simply drops them once the
+    // receiver's buffer is full
+    let receiver = UdpSocket::bind("127.0.0.1:0").expect("Failed to bind a receiver");
+    let sender = UdpSocket::bind("127.0.0.1:0").expect("Failed to bind a sender");
+    sender
+        .connect(receiver.local_addr().unwrap())
+        .expect("Failed to connect a sender");
+
+    let mut group = c.benchmark_group("sendmmsg");
+    for &batch_size in &BATCH_SIZES {
+        let mut packets = portions(batch_size);
+
+        group.throughput(Throughput::Elements(batch_size as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(batch_size),
+            &batch_size,
+            |b, _| b.iter(|| sendmmsg_wrapper::sendmmsg(sender.as_raw_fd(), &mut packets).unwrap()),
+        );
+    }
+    group.finish();
+}
+
+/// Compares the two ways of sending a single datagram crafted during a test
+/// (`UdpSender::send_datagram`): copying its header and payload into a new
+/// buffer for `send`, and passing them to `writev` as they are.
+fn bench_send_datagram(c: &mut Criterion) {
+    let receiver = UdpSocket::bind("127.0.0.1:0").expect("Failed to bind a receiver");
+    let sender = UdpSocket::bind("127.0.0.1:0").expect("Failed to bind a sender");
+    sender
+        .connect(receiver.local_addr().unwrap())
+        .expect("Failed to connect a sender");
+    let fd = sender.as_raw_fd();
+    let payload = vec![0u8; *PAYLOAD_SIZES.iter().max().unwrap()];
+
+    let mut group = c.benchmark_group("send_datagram");
+    for &payload_size in &PAYLOAD_SIZES {
+        let payload = &payload[..payload_size];
+
+        group.throughput(Throughput::Bytes((HEADER.len() + payload_size) as u64));
+        group.bench_with_input(
+            BenchmarkId::new("concatenated", payload_size),
+            &payload_size,
+            |b, _| {
+                b.iter(|| {
+                    let mut packet = Vec::with_capacity(HEADER.len() + payload.len());
+                    packet.extend_from_slice(&HEADER);
+                    packet.extend_from_slice(payload);
+                    unsafe { libc::send(fd, packet.as_ptr() as *const _, packet.len(), 0) }
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("vectored", payload_size),
+            &payload_size,
+            |b, _| {
+                let slices = [IoSlice::new(&HEADER), IoSlice::new(payload)];
+                b.iter(|| unsafe {
+                    libc::writev(fd, slices.as_ptr() as *const libc::iovec, slices.len() as _)
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+fn bench_prepare_messages(c: &mut Criterion) {
+    let mut group = c.benchmark_group("prepare_mmsghdr_vector");
+    for &batch_size in &BATCH_SIZES {
+        let mut packets = portions(batch_size);
+
+        group.throughput(Throughput::Elements(batch_size as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(batch_size),
+            &batch_size,
+            |b, _| b.iter(|| sendmmsg_wrapper::prepare_mmsghdr_vector(&mut packets)),
+        );
+    }
+    group.finish();
+}
+
+fn bench_ip_udp_packet(c: &mut Criterion) {
+    let families = [
+        ("ipv4", "192.168.1.41:17333&93.184.216.34:80"),
+        ("ipv6", "[2001:db8::1]:17333&[2001:db8::2]:80"),
+    ];
+
+    for &(family, endpoints) in &families {
+        let endpoints = endpoints.parse::<Endpoints>().unwrap();
+        let payload = vec![0u8; *PAYLOAD_SIZES.iter().max().unwrap()];
+
+        let mut group = c.benchmark_group(format!("ip_udp_packet/{}", family));
+        for &payload_size in &PAYLOAD_SIZES {
+            group.throughput(Throughput::Bytes(payload_size as u64));
+            group.bench_with_input(
+                BenchmarkId::from_parameter(payload_size),
+                &payload_size,
+                |b, &size| {
+                    b.iter(|| craft_packets::ip_udp_packet(&endpoints, &payload[..size], 64, 0, 0))
+                },
+            );
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(
+    benches,
+    bench_sendmmsg,
+    bench_send_datagram,
+    bench_prepare_messages,
+    bench_ip_udp_packet
+);
+criterion_main!(benches);
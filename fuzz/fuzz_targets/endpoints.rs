@@ -0,0 +1,41 @@
+// anevicon: A high-performant UDP-based load generator, written in Rust.
+// Copyright (C) 2019  Temirkhan Myrzamadi <gymmasssorla@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// For more information see <https://github.com/Gymmasssorla/anevicon>.
+
+//! Feeds arbitrary strings into `Endpoints::from_str`, which must never panic.
+//! Run it by `cargo fuzz run endpoints`.
+
+#![no_main]
+
+#[macro_use]
+extern crate failure_derive;
+
+use libfuzzer_sys::fuzz_target;
+
+// anevicon has no library target, so the parser is included directly
+#[allow(dead_code)]
+#[path = "../../src/config/endpoints.rs"]
+mod endpoints;
+
+use endpoints::Endpoints;
+
+fuzz_target!(|format: &str| {
+    if let Ok(endpoints) = format.parse::<Endpoints>() {
+        // Valid endpoints must be printed in the same format as they're parsed
+        assert_eq!(endpoints.to_string().parse::<Endpoints>().ok(), Some(endpoints));
+    }
+});
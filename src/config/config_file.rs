@@ -0,0 +1,297 @@
+// anevicon: A high-performant UDP-based load generator, written in Rust.
+// Copyright (C) 2019  Temirkhan Myrzamadi <gymmasssorla@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// For more information see <https://github.com/Gymmasssorla/anevicon>.
+
+//! Loading of `SendConfig` from TOML files specified by `--config-file`.
+//!
+//! A file consists of top-level keys named exactly as the fields of
+//! `SendConfig` and its flattened structures (`endpoints`, `test_intensity`,
+//! `random_packets`, `broadcast`, and so on). The same format is printed by
+//! `--dump-config`.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use structopt::clap::ArgMatches;
+
+use super::SendConfig;
+
+#[derive(Debug, Fail)]
+pub enum ConfigFileError {
+    #[fail(display = "Failed to read the configuration file '{}'", filename)]
+    ReadFailed {
+        #[fail(cause)]
+        source: io::Error,
+        filename: String,
+    },
+
+    #[fail(display = "Failed to parse the configuration file")]
+    InvalidFormat(#[fail(cause)] toml::de::Error),
+
+    #[fail(display = "The configuration file contains the unknown key '{}'", _0)]
+    UnknownKey(String),
+}
+
+/// Merges the TOML file located at `path` into `config`. Only the values that
+/// haven't been explicitly specified on the command line (according to
+/// `matches`) are taken from the file, so command-line flags always override
+/// the file.
+pub fn merge<P: AsRef<Path>>(
+    config: &SendConfig,
+    path: P,
+    matches: &ArgMatches,
+) -> Result<SendConfig, ConfigFileError> {
+    let content =
+        fs::read_to_string(path.as_ref()).map_err(|error| ConfigFileError::ReadFailed {
+            source: error,
+            filename: path.as_ref().to_string_lossy().into_owned(),
+        })?;
+    let file =
+        toml::from_str::<toml::value::Table>(&content).map_err(ConfigFileError::InvalidFormat)?;
+
+    let mut merged = toml::Value::try_from(config)
+        .expect("Failed to serialize SendConfig")
+        .try_into::<toml::value::Table>()
+        .expect("SendConfig must be serialized as a table");
+    for (key, value) in &file {
+        if matches.occurrences_of(key) == 0 {
+            merged.insert(key.clone(), value.clone());
+        }
+    }
+
+    let mut result = toml::Value::Table(merged)
+        .try_into::<SendConfig>()
+        .map_err(ConfigFileError::InvalidFormat)?;
+
+    // Unknown keys are silently ignored by serde because of the flattened
+    // structures, so ensure that each key of the file has been consumed
+    let consumed = toml::Value::try_from(&result).expect("Failed to serialize SendConfig");
+    if let Some(key) = file.keys().find(|key| consumed.get(key.as_str()).is_none()) {
+        return Err(ConfigFileError::UnknownKey(key.clone()));
+    }
+
+    result.copy_cli_only(config);
+    Ok(result)
+}
+
+/// Prints `config` as TOML that can be loaded back by `--config-file`.
+pub fn dump(config: &SendConfig) -> String {
+    toml::to_string(config).expect("Failed to serialize SendConfig")
+}
+
+/// (De)serializes `Duration` as a human-readable time span like `5secs`.
+pub mod duration {
+    use std::time::Duration;
+
+    use serde::de::{self, Deserialize, Deserializer};
+    use serde::Serializer;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&humantime::format_duration(*duration))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        humantime::parse_duration(&String::deserialize(deserializer)?).map_err(de::Error::custom)
+    }
+}
+
+/// (De)serializes `Option<Duration>` just like `duration`, omitting `None`.
+pub mod optional_duration {
+    use std::time::Duration;
+
+    use serde::de::{Deserialize, Deserializer};
+    use serde::Serializer;
+
+    pub fn serialize<S: Serializer>(
+        duration: &Option<Duration>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match duration {
+            Some(duration) => super::duration::serialize(duration, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Duration>, D::Error> {
+        struct Wrapper(Duration);
+
+        impl<'de> Deserialize<'de> for Wrapper {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                super::duration::deserialize(deserializer).map(Wrapper)
+            }
+        }
+
+        Ok(Option::<Wrapper>::deserialize(deserializer)?.map(|Wrapper(duration)| duration))
+    }
+}
+
+/// (De)serializes `NonZeroUsize` which might not fit into a TOML integer
+/// (`i64`), so such values are represented as strings. Strings may also use
+/// SI suffixes, just like `--packets-count`.
+pub mod large_non_zero_usize {
+    use std::convert::TryFrom;
+    use std::fmt::{self, Formatter};
+    use std::num::NonZeroUsize;
+
+    use serde::de::{self, Deserializer, Visitor};
+    use serde::Serializer;
+
+    pub fn serialize<S: Serializer>(
+        value: &NonZeroUsize,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match i64::try_from(value.get()) {
+            Ok(value) => serializer.serialize_i64(value),
+            Err(_) => serializer.collect_str(value),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<NonZeroUsize, D::Error> {
+        struct NonZeroUsizeVisitor;
+
+        impl<'de> Visitor<'de> for NonZeroUsizeVisitor {
+            type Value = NonZeroUsize;
+
+            fn expecting(&self, formatter: &mut Formatter) -> fmt::Result {
+                formatter.write_str("a positive integer")
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<NonZeroUsize, E> {
+                usize::try_from(value)
+                    .ok()
+                    .and_then(NonZeroUsize::new)
+                    .ok_or_else(|| E::custom("the value must be a positive integer"))
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<NonZeroUsize, E> {
+                super::super::parse_si_non_zero_usize(value).map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(NonZeroUsizeVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::time::Duration;
+
+    use structopt::StructOpt;
+
+    use super::*;
+
+    fn merge_with(name: &str, file: &str, args: &[&str]) -> Result<SendConfig, ConfigFileError> {
+        let path = std::env::temp_dir().join(format!(
+            "anevicon-{name}-{pid}.toml",
+            name = name,
+            pid = std::process::id()
+        ));
+        fs::File::create(&path)
+            .and_then(|mut output| output.write_all(file.as_bytes()))
+            .expect("Failed to write a configuration file");
+
+        let matches = SendConfig::clap().get_matches_from(args);
+        let result = merge(&SendConfig::from_clap(&matches), &path, &matches);
+
+        fs::remove_file(&path).expect("Failed to remove a configuration file");
+        result
+    }
+
+    #[test]
+    fn loads_file_values() {
+        let config = merge_with(
+            "loads",
+            "endpoints = [\"127.0.0.1:8080&127.0.0.1:9090\"]\ntest_intensity = 42\nwait = \
+             \"3s\"\nbroadcast = true\n",
+            &["send"],
+        )
+        .expect("merge() failed");
+
+        assert_eq!(config.packets_config.endpoints.len(), 1);
+        assert_eq!(config.test_intensity.get(), 42);
+        assert_eq!(config.wait, Duration::from_secs(3));
+        assert!(config.sockets_config.broadcast);
+    }
+
+    #[test]
+    fn command_line_overrides_file() {
+        let config = merge_with(
+            "overrides",
+            "endpoints = [\"127.0.0.1:8080&127.0.0.1:9090\"]\ntest_intensity = 42\n",
+            &["send", "--test-intensity", "7", "--wait", "0secs"],
+        )
+        .expect("merge() failed");
+
+        assert_eq!(config.test_intensity.get(), 7);
+        assert_eq!(config.wait, Duration::from_secs(0));
+        assert_eq!(config.packets_config.endpoints.len(), 1);
+    }
+
+    #[test]
+    fn keeps_command_line_only_flags() {
+        let config = merge_with(
+            "cli-only",
+            "endpoints = [\"127.0.0.1:8080&127.0.0.1:9090\"]\n",
+            &[
+                "send",
+                "--count-only",
+                "--fd",
+                "3",
+                "--random-seed",
+                "42",
+                "--calibrate",
+                "--list-payloads",
+            ],
+        )
+        .expect("merge() failed");
+
+        assert!(config.count_only);
+        assert_eq!(config.sockets_config.fd, Some(3));
+        assert_eq!(config.random_seed, Some(42));
+        assert!(config.calibrate);
+        assert!(config.list_payloads);
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        match merge_with("unknown", "no_such_option = 5\n", &["send", "--wait", "1s"]) {
+            Err(ConfigFileError::UnknownKey(key)) => assert_eq!(key, "no_such_option"),
+            _ => panic!("ConfigFileError::UnknownKey must be returned"),
+        }
+    }
+
+    #[test]
+    fn dumped_config_is_loadable() {
+        let matches = SendConfig::clap().get_matches_from([
+            "send",
+            "--endpoints",
+            "127.0.0.1:8080&127.0.0.1:9090",
+            "--send-message",
+            "Hello",
+        ]);
+        let config = SendConfig::from_clap(&matches);
+
+        let reloaded = merge_with("dumped", &dump(&config), &["send"]).expect("merge() failed");
+        assert_eq!(reloaded, config);
+    }
+}
@@ -0,0 +1,121 @@
+// anevicon: A high-performant UDP-based load generator, written in Rust.
+// Copyright (C) 2019  Temirkhan Myrzamadi <gymmasssorla@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// For more information see <https://github.com/Gymmasssorla/anevicon>.
+
+//! The structure representing a test intensity of a single endpoint.
+
+use std::fmt::{self, Display, Formatter};
+use std::num::{NonZeroUsize, ParseIntError};
+use std::str::FromStr;
+
+use serde::de::{self, Deserialize, Deserializer};
+use serde::{Serialize, Serializer};
+
+/// A test intensity of the endpoints specified by a zero-based index of
+/// `--endpoints`, specified as `<INDEX>:<PACKETS>`. It overrides
+/// `--test-intensity` for these endpoints only.
+#[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+pub struct EndpointRate {
+    pub index: usize,
+    pub test_intensity: NonZeroUsize,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Fail)]
+#[allow(clippy::enum_variant_names)]
+pub enum ParseEndpointRateError {
+    #[fail(display = "An endpoint rate must be specified as <INDEX>:<PACKETS>")]
+    InvalidFormat,
+
+    #[fail(display = "{}", _0)]
+    InvalidIndex(#[fail(cause)] ParseIntError),
+
+    #[fail(display = "{}", _0)]
+    InvalidRate(#[fail(cause)] ParseIntError),
+}
+
+/// Formats a rate as `<INDEX>:<PACKETS>`, so the result can be parsed back by
+/// `EndpointRate::from_str`.
+impl Display for EndpointRate {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{index}:{test_intensity}",
+            index = self.index,
+            test_intensity = self.test_intensity
+        )
+    }
+}
+
+impl Serialize for EndpointRate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for EndpointRate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl FromStr for EndpointRate {
+    type Err = ParseEndpointRateError;
+
+    fn from_str(format: &str) -> Result<Self, ParseEndpointRateError> {
+        let parts = format.split(':').collect::<Vec<&str>>();
+        if parts.len() != 2 {
+            return Err(ParseEndpointRateError::InvalidFormat);
+        }
+
+        Ok(EndpointRate {
+            index: parts[0]
+                .parse()
+                .map_err(ParseEndpointRateError::InvalidIndex)?,
+            test_intensity: parts[1]
+                .parse()
+                .map_err(ParseEndpointRateError::InvalidRate)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_endpoint_rates() {
+        let rate = "2:500".parse::<EndpointRate>().unwrap();
+        assert_eq!(rate.index, 2);
+        assert_eq!(rate.test_intensity.get(), 500);
+        assert_eq!(rate.to_string(), "2:500");
+
+        assert_eq!(
+            "2".parse::<EndpointRate>(),
+            Err(ParseEndpointRateError::InvalidFormat)
+        );
+        match "x:500".parse::<EndpointRate>() {
+            Err(ParseEndpointRateError::InvalidIndex(_)) => (),
+            _ => panic!("ParseEndpointRateError::InvalidIndex must be returned"),
+        }
+        match "2:0".parse::<EndpointRate>() {
+            Err(ParseEndpointRateError::InvalidRate(_)) => (),
+            _ => panic!("ParseEndpointRateError::InvalidRate must be returned"),
+        }
+    }
+}
@@ -18,9 +18,14 @@
 
 //! The structures representing user-specified communication endpoints.
 
-use std::net::{AddrParseError, SocketAddr, SocketAddrV4, SocketAddrV6};
+use std::ffi::CString;
+use std::fmt::{self, Display, Formatter};
+use std::net::{AddrParseError, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
 use std::str::FromStr;
 
+use serde::de::{self, Deserialize, Deserializer};
+use serde::{Serialize, Serializer};
+
 #[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
 pub struct EndpointsV4 {
     pub sender: SocketAddrV4,
@@ -55,6 +60,15 @@ pub enum ParseEndpointsError {
                    address is defined as <IP>:<PORT>"
     )]
     DifferentIpVersions,
+
+    #[fail(display = "There is no network interface named '{}'", _0)]
+    UnknownZone(String),
+
+    #[fail(
+        display = "The link-local receiver {} must specify a zone, such as [{}%eth0]",
+        _0, _0
+    )]
+    LinkLocalWithoutZone(Ipv6Addr),
 }
 
 impl Endpoints {
@@ -71,6 +85,43 @@ impl Endpoints {
             Self::V6(v6) => SocketAddr::V6(v6.receiver),
         }
     }
+
+    /// Returns the same endpoints, but with a receiver port replaced by `port`.
+    pub fn with_receiver_port(mut self, port: u16) -> Endpoints {
+        match self {
+            Self::V4(ref mut v4) => v4.receiver.set_port(port),
+            Self::V6(ref mut v6) => v6.receiver.set_port(port),
+        }
+
+        self
+    }
+}
+
+/// Formats endpoints as `<SENDER-ADDRESS>&<RECEIVER-ADDRESS>`, so the result
+/// can be parsed back by `Endpoints::from_str`.
+impl Display for Endpoints {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{sender}&{receiver}",
+            sender = self.sender(),
+            receiver = self.receiver()
+        )
+    }
+}
+
+impl Serialize for Endpoints {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Endpoints {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
 }
 
 impl FromStr for Endpoints {
@@ -82,12 +133,14 @@ impl FromStr for Endpoints {
             return Err(ParseEndpointsError::InvalidFormat);
         }
 
-        let sender = addresses[0]
-            .parse::<SocketAddr>()
-            .map_err(ParseEndpointsError::InvalidAddressFormat)?;
-        let receiver = addresses[1]
-            .parse::<SocketAddr>()
-            .map_err(ParseEndpointsError::InvalidAddressFormat)?;
+        let sender = parse_address(addresses[0])?;
+        let receiver = parse_address(addresses[1])?;
+
+        if let SocketAddr::V6(receiver_v6) = receiver {
+            if is_link_local(receiver_v6.ip()) && receiver_v6.scope_id() == 0 {
+                return Err(ParseEndpointsError::LinkLocalWithoutZone(*receiver_v6.ip()));
+            }
+        }
 
         match sender {
             SocketAddr::V4(sender_v4) => match receiver {
@@ -108,6 +161,36 @@ impl FromStr for Endpoints {
     }
 }
 
+/// Parses `<IP>:<PORT>`, where an IPv6 address can specify its zone either by
+/// a scope id (`[fe80::1%2]:80`) or by an interface name (`[fe80::1%eth0]:80`).
+fn parse_address(address: &str) -> Result<SocketAddr, ParseEndpointsError> {
+    let zone = match (address.find('%'), address.find(']')) {
+        (Some(start), Some(end)) if start < end => &address[start + 1..end],
+        _ => "",
+    };
+    if zone.is_empty() || zone.bytes().all(|byte| byte.is_ascii_digit()) {
+        return address
+            .parse()
+            .map_err(ParseEndpointsError::InvalidAddressFormat);
+    }
+
+    let index = CString::new(zone)
+        .ok()
+        .map(|name| unsafe { libc::if_nametoindex(name.as_ptr()) })
+        .filter(|&index| index != 0)
+        .ok_or_else(|| ParseEndpointsError::UnknownZone(zone.to_owned()))?;
+    address
+        .replacen(&format!("%{}]", zone), &format!("%{}]", index), 1)
+        .parse()
+        .map_err(ParseEndpointsError::InvalidAddressFormat)
+}
+
+/// Returns `true` if `address` belongs to `fe80::/10`, so it's ambiguous
+/// without a zone.
+fn is_link_local(address: &Ipv6Addr) -> bool {
+    address.segments()[0] & 0xffc0 == 0xfe80
+}
+
 #[cfg(test)]
 mod tests {
     use std::net::{Ipv4Addr, Ipv6Addr};
@@ -169,6 +252,17 @@ mod tests {
         );
     }
 
+    #[test]
+    fn displays_parsable_endpoints() {
+        let check = |format| {
+            let endpoints = Endpoints::from_str(format).expect("Endpoints::from_str() failed");
+            assert_eq!(endpoints.to_string(), format);
+        };
+
+        check("233.43.24.53:34&29.32.45.111:9191");
+        check("[2001:db8:85a3::8a2e:370:7334]:18281&[2001:db8:85a3::8a2e:370:7334]:9191");
+    }
+
     #[test]
     fn check_invalid_versions() {
         assert_eq!(
@@ -203,4 +297,50 @@ mod tests {
         check("233.43.24:34&92.52.113.43:9191");
         check("hello:51919&world:21342");
     }
+
+    #[test]
+    fn parses_zones_by_interface_names() {
+        // The loopback interface always exists
+        let index = unsafe { libc::if_nametoindex(b"lo\0".as_ptr() as *const libc::c_char) };
+        assert_ne!(index, 0);
+
+        let endpoints = Endpoints::from_str("[fe80::2%lo]:34&[fe80::1%lo]:9191")
+            .expect("Endpoints::from_str() failed");
+        match endpoints {
+            Endpoints::V6(v6) => {
+                assert_eq!(v6.sender.scope_id(), index);
+                assert_eq!(v6.receiver.scope_id(), index);
+                assert_eq!(
+                    v6.receiver.ip(),
+                    &Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1)
+                );
+                assert_eq!(v6.receiver.port(), 9191);
+            }
+            _ => panic!("IPv6 endpoints must be parsed"),
+        }
+
+        // Numeric zones are parsed as is, and are displayed so
+        assert_eq!(
+            endpoints.to_string(),
+            format!("[fe80::2%{0}]:34&[fe80::1%{0}]:9191", index)
+        );
+        assert_eq!(Endpoints::from_str(&endpoints.to_string()), Ok(endpoints));
+    }
+
+    #[test]
+    fn check_invalid_zones() {
+        assert_eq!(
+            Endpoints::from_str("[fe80::2%lo]:34&[fe80::1%nosuchif0]:9191"),
+            Err(ParseEndpointsError::UnknownZone(String::from("nosuchif0")))
+        );
+        assert_eq!(
+            Endpoints::from_str("[fe80::2]:34&[fe80::1]:9191"),
+            Err(ParseEndpointsError::LinkLocalWithoutZone(Ipv6Addr::new(
+                0xfe80, 0, 0, 0, 0, 0, 0, 1
+            )))
+        );
+
+        // Only the receiver needs a zone to be routed
+        assert!(Endpoints::from_str("[fe80::2]:34&[2001:db8::1]:9191").is_ok());
+    }
 }
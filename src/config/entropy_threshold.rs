@@ -0,0 +1,115 @@
+// anevicon: A high-performant UDP-based load generator, written in Rust.
+// Copyright (C) 2019  Temirkhan Myrzamadi <gymmasssorla@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// For more information see <https://github.com/Gymmasssorla/anevicon>.
+
+//! The structure representing a threshold of `--warn-entropy`.
+
+use std::fmt::{self, Display, Formatter};
+use std::num::ParseFloatError;
+use std::str::FromStr;
+
+use serde::de::{self, Deserialize, Deserializer};
+use serde::{Serialize, Serializer};
+
+/// The largest Shannon entropy of a byte sequence, in bits per byte.
+const MAX_ENTROPY: f64 = 8.0;
+
+/// The Shannon entropy of a payload, in bits per byte from 0 to 8, below
+/// which the payload is reported as compressible.
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
+pub struct EntropyThreshold(f64);
+
+// A threshold is never NaN, see `EntropyThreshold::new`
+impl Eq for EntropyThreshold {}
+
+#[derive(Debug, Clone, Eq, PartialEq, Fail)]
+pub enum ParseEntropyThresholdError {
+    #[fail(display = "{}", _0)]
+    InvalidNumber(#[fail(cause)] ParseFloatError),
+
+    #[fail(display = "An entropy must be from 0 to 8 bits per byte")]
+    OutOfRange,
+}
+
+impl EntropyThreshold {
+    pub fn new(bits_per_byte: f64) -> Result<EntropyThreshold, ParseEntropyThresholdError> {
+        if (0.0..=MAX_ENTROPY).contains(&bits_per_byte) {
+            Ok(EntropyThreshold(bits_per_byte))
+        } else {
+            Err(ParseEntropyThresholdError::OutOfRange)
+        }
+    }
+
+    pub fn bits_per_byte(self) -> f64 {
+        self.0
+    }
+}
+
+impl Display for EntropyThreshold {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Serialize for EntropyThreshold {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for EntropyThreshold {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        EntropyThreshold::new(f64::deserialize(deserializer)?).map_err(de::Error::custom)
+    }
+}
+
+impl FromStr for EntropyThreshold {
+    type Err = ParseEntropyThresholdError;
+
+    fn from_str(format: &str) -> Result<Self, ParseEntropyThresholdError> {
+        EntropyThreshold::new(
+            format
+                .parse()
+                .map_err(ParseEntropyThresholdError::InvalidNumber)?,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_entropy_thresholds() {
+        let threshold = "7.5".parse::<EntropyThreshold>().unwrap();
+        assert_eq!(threshold.bits_per_byte(), 7.5);
+        assert_eq!(threshold.to_string(), "7.5");
+
+        assert_eq!(
+            "8.1".parse::<EntropyThreshold>(),
+            Err(ParseEntropyThresholdError::OutOfRange)
+        );
+        assert_eq!(
+            "NaN".parse::<EntropyThreshold>(),
+            Err(ParseEntropyThresholdError::OutOfRange)
+        );
+        match "high".parse::<EntropyThreshold>() {
+            Err(ParseEntropyThresholdError::InvalidNumber(_)) => (),
+            _ => panic!("ParseEntropyThresholdError::InvalidNumber must be returned"),
+        }
+    }
+}
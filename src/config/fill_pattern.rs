@@ -0,0 +1,155 @@
+// anevicon: A high-performant UDP-based load generator, written in Rust.
+// Copyright (C) 2019  Temirkhan Myrzamadi <gymmasssorla@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// For more information see <https://github.com/Gymmasssorla/anevicon>.
+
+//! The deterministic content of `--random-packet` payloads.
+
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use serde::de::{self, Deserialize, Deserializer};
+use serde::{Serialize, Serializer};
+
+/// A pattern used to fill `--random-packet` payloads instead of random bytes,
+/// specified as `zero`, `ones`, `incrementing`, or a hexadecimal byte.
+#[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+pub enum FillPattern {
+    /// All the bytes are `0x00`.
+    Zero,
+
+    /// All the bytes are `0xFF` (all the bits are set).
+    Ones,
+
+    /// The bytes are `0, 1, 2, ..., 255, 0, 1, ...`.
+    Incrementing,
+
+    /// All the bytes are equal to the specified one.
+    Byte(u8),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Fail)]
+#[fail(
+    display = "A fill pattern must be `zero`, `ones`, `incrementing`, or a hexadecimal byte \
+               such as `AB`"
+)]
+pub struct ParseFillPatternError;
+
+impl FillPattern {
+    /// Fills the whole `buffer` with the current pattern.
+    pub fn fill(&self, buffer: &mut [u8]) {
+        match self {
+            FillPattern::Zero => buffer.fill(0x00),
+            FillPattern::Ones => buffer.fill(0xFF),
+            FillPattern::Incrementing => buffer
+                .iter_mut()
+                .enumerate()
+                .for_each(|(index, byte)| *byte = index as u8),
+            FillPattern::Byte(value) => buffer.fill(*value),
+        }
+    }
+}
+
+/// Formats a pattern so the result can be parsed back by
+/// `FillPattern::from_str`.
+impl Display for FillPattern {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            FillPattern::Zero => write!(f, "zero"),
+            FillPattern::Ones => write!(f, "ones"),
+            FillPattern::Incrementing => write!(f, "incrementing"),
+            FillPattern::Byte(value) => write!(f, "{:02X}", value),
+        }
+    }
+}
+
+impl Serialize for FillPattern {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for FillPattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl FromStr for FillPattern {
+    type Err = ParseFillPatternError;
+
+    fn from_str(pattern: &str) -> Result<Self, ParseFillPatternError> {
+        match pattern {
+            "zero" => Ok(FillPattern::Zero),
+            "ones" => Ok(FillPattern::Ones),
+            "incrementing" => Ok(FillPattern::Incrementing),
+            _ => {
+                let digits = pattern.strip_prefix("0x").unwrap_or(pattern);
+                if digits.len() != 2 {
+                    return Err(ParseFillPatternError);
+                }
+
+                u8::from_str_radix(digits, 16)
+                    .map(FillPattern::Byte)
+                    .map_err(|_| ParseFillPatternError)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_patterns() {
+        assert_eq!("zero".parse(), Ok(FillPattern::Zero));
+        assert_eq!("ones".parse(), Ok(FillPattern::Ones));
+        assert_eq!("incrementing".parse(), Ok(FillPattern::Incrementing));
+        assert_eq!("AB".parse(), Ok(FillPattern::Byte(0xAB)));
+        assert_eq!("0x0f".parse(), Ok(FillPattern::Byte(0x0F)));
+        assert_eq!(FillPattern::Byte(0x0F).to_string(), "0F");
+
+        for invalid in &["", "A", "ABC", "0x", "zz", "random"] {
+            assert_eq!(
+                invalid.parse::<FillPattern>(),
+                Err(ParseFillPatternError),
+                "{:?} must be rejected",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn fills_buffers() {
+        let mut buffer = [0x42u8; 258];
+
+        FillPattern::Incrementing.fill(&mut buffer);
+        assert_eq!(&buffer[..3], &[0, 1, 2]);
+        assert_eq!(&buffer[255..], &[255, 0, 1]);
+
+        FillPattern::Ones.fill(&mut buffer);
+        assert!(buffer.iter().all(|&byte| byte == 0xFF));
+
+        FillPattern::Zero.fill(&mut buffer);
+        assert!(buffer.iter().all(|&byte| byte == 0x00));
+
+        FillPattern::Byte(0xA5).fill(&mut buffer);
+        assert!(buffer.iter().all(|&byte| byte == 0xA5));
+    }
+}
@@ -19,33 +19,379 @@
 //! A module containing command-line configurations such as receivers, date-time
 //! format and so on.
 
+use std::convert::TryFrom;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use std::net::SocketAddr;
 use std::num::NonZeroUsize;
+use std::os::unix::io::RawFd;
 use std::path::PathBuf;
+use std::str::FromStr;
 use std::time::Duration;
 
+use serde::{Deserialize, Serialize};
+use structopt::clap::{self, ArgMatches, Shell};
 use structopt::StructOpt;
 
+pub use endpoint_rate::EndpointRate;
 pub use endpoints::{Endpoints, EndpointsV4, EndpointsV6, ParseEndpointsError};
+pub use entropy_threshold::EntropyThreshold;
+pub use fill_pattern::FillPattern;
+pub use payload_weight::PayloadWeight;
+pub use port_range::PortRange;
+pub use subnet::Subnet;
 
 const DEFAULT_RANDOM_PACKET_SIZE: usize = 1024;
 
+mod config_file;
+mod endpoint_rate;
 mod endpoints;
+mod entropy_threshold;
+mod fill_pattern;
+mod payload_weight;
+mod port_range;
+pub mod subnet;
 
 #[derive(Debug, Clone, Eq, PartialEq, StructOpt)]
 #[structopt(
     author = "Temirkhan Myrzamadi <gymmasssorla@gmail.com>",
     about = "A high-performant UDP-based load generator, written in Rust.",
-    after_help = "The `--send-file`, `--random-packet`, and `--send-message` options can be \
-                  specified several times to send multiple messages to a server. But there are \
-                  not guarantees about sending order because UDP is unreliable protocol.\n\nSome \
-                  options accept time spans. If you want to read the specification, see \
-                  <https://docs.rs/humantime/1.2.0/humantime/fn.parse_duration.html>.\n\nFor more \
-                  information see <https://github.com/Gymmasssorla/anevicon>.",
+    after_help = "For more information see <https://github.com/Gymmasssorla/anevicon>.",
     set_term_width = 90
 )]
 pub struct ArgsConfig {
+    #[structopt(flatten)]
+    pub logging_config: LoggingConfig,
+
+    /// Print a completion script for the specified shell to stdout and exit
+    #[structopt(
+        long = "generate-completion",
+        takes_value = true,
+        value_name = "SHELL",
+        raw(possible_values = r#"&["bash", "zsh", "fish", "powershell"]"#)
+    )]
+    pub generate_completion: Option<String>,
+
+    /// Always present after `ArgsConfig::setup()`, which exits the program if
+    /// a user has specified neither a subcommand nor `--generate-completion`
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+}
+
+// `Command` is parsed only once at startup, so there's no point in boxing
+// `SendConfig`
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, Eq, PartialEq, StructOpt)]
+pub enum Command {
+    /// Send UDP/IP datagrams to the specified receivers (the testing itself)
+    #[structopt(
+        name = "send",
+        after_help = "The `--send-file`, `--send-dir`, `--random-packet`, and `--send-message` \
+                      options can be specified several times to send multiple messages to a \
+                      server. But there are not guarantees about sending order because UDP \
+                      is unreliable protocol.\n\nSome options accept time spans. If you want \
+                      to read the specification, see \
+                      <https://docs.rs/humantime/1.2.0/humantime/fn.parse_duration.html>."
+    )]
+    Send(SendConfig),
+
+    /// List the network interfaces of this machine with their addresses and
+    /// exit
+    #[structopt(name = "interfaces")]
+    Interfaces,
+
+    /// Check that this machine is able to send raw UDP/IP datagrams by
+    /// transmitting several of them to a loopback receiver, and exit
+    #[structopt(name = "self-test")]
+    SelfTest,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, StructOpt, Serialize, Deserialize)]
+pub struct SendConfig {
+    /// Load options from the specified TOML file. Its keys are named after the
+    /// fields printed by `--dump-config`, and options specified on the command
+    /// line override the file values
+    #[structopt(long = "config-file", takes_value = true, value_name = "FILENAME")]
+    #[serde(skip)]
+    pub config_file: Option<PathBuf>,
+
+    /// Print the fully resolved configuration as TOML (accepted by
+    /// `--config-file`) and exit
+    #[structopt(long = "dump-config", takes_value = false)]
+    #[serde(skip)]
+    pub dump_config: bool,
+
+    /// Print how many packets and bytes would be sent to each endpoint and
+    /// exit without sending anything
+    #[structopt(long = "count-only", takes_value = false)]
+    #[serde(skip)]
+    pub count_only: bool,
+
+    /// Print each payload in the order they are sent together with its source
+    /// and length, and exit without sending anything
+    #[structopt(long = "list-payloads", takes_value = false)]
+    #[serde(skip)]
+    pub list_payloads: bool,
+
+    /// Print the size of each datagram on the wire (a payload with UDP and IP
+    /// headers) and the bandwidth it makes at the test intensity, and exit
+    /// without sending anything
+    #[structopt(long = "show-wire-size", takes_value = false)]
+    #[serde(skip)]
+    pub show_wire_size: bool,
+
+    /// Write the final summary of each endpoint to the specified file after a
+    /// test is finished, encoded as `--output-format` specifies
+    #[structopt(long = "output-file", takes_value = true, value_name = "FILENAME")]
+    pub output_file: Option<PathBuf>,
+
+    /// An encoding of `--output-file`: `json`, `cbor` (requires the `cbor`
+    /// feature), or `msgpack` (requires the `msgpack` feature)
+    #[structopt(
+        long = "output-format",
+        takes_value = true,
+        value_name = "FORMAT",
+        default_value = "json",
+        raw(possible_values = r#"&["json", "cbor", "msgpack"]"#)
+    )]
+    pub output_format: OutputFormat,
+
+    /// Stream a JSON object per reporting interval of each endpoint into the
+    /// specified file (newline-delimited JSON), with the cumulative counts,
+    /// the counts and rates of the interval. Each line is flushed as soon as
+    /// it's written
+    #[structopt(long = "json-lines", takes_value = true, value_name = "FILENAME")]
+    pub json_lines: Option<PathBuf>,
+
+    /// Keep testing the other endpoints if one of them fails (for example,
+    /// if its socket cannot be created). It's the default behaviour unless
+    /// `--abort-on-error` is specified
+    #[structopt(long = "continue-on-error", takes_value = false)]
+    pub continue_on_error: bool,
+
+    /// Stop testing all the endpoints as soon as one of them fails
+    #[structopt(
+        long = "abort-on-error",
+        takes_value = false,
+        conflicts_with = "continue_on_error"
+    )]
+    pub abort_on_error: bool,
+
+    /// Warn if the packets sent to an endpoint fall short of the expected ones
+    /// (for example, because of failed system calls) by more than the
+    /// specified percent, which is checked before resending the unsent
+    /// packets and at the end of a test
+    #[structopt(
+        long = "loss-alert",
+        takes_value = true,
+        value_name = "PERCENT",
+        parse(try_from_str = "parse_percent")
+    )]
+    pub loss_alert: Option<u8>,
+
+    /// Count the specified percent of randomly chosen packets as expected but
+    /// not sent without sending them, so the loss accounting of summaries and
+    /// outputs can be checked. Such packets aren't resent
+    #[structopt(
+        long = "simulate-loss",
+        takes_value = true,
+        value_name = "PERCENT",
+        parse(try_from_str = "parse_percent")
+    )]
+    pub simulate_loss: Option<u8>,
+
+    /// Also count the bytes sent on the wire, which add the overhead of
+    /// `--wire-overhead` to each packet, and report them together with the
+    /// bytes of datagrams. It gives a more honest bandwidth for comparisons at
+    /// the link layer
+    #[structopt(long = "wire-accounting", takes_value = false)]
+    pub wire_accounting: bool,
+
+    /// A number of bytes added to each packet on the wire by
+    /// `--wire-accounting`. The default is the overhead of Ethernet: a header
+    /// (14 bytes), a frame check sequence (4), a preamble with a start frame
+    /// delimiter (8), and an interframe gap (12). The padding of frames
+    /// shorter than the minimum isn't counted
+    #[structopt(
+        long = "wire-overhead",
+        takes_value = true,
+        value_name = "BYTES",
+        default_value = "38"
+    )]
+    pub wire_overhead: u16,
+
+    /// Seed the random number generators, so a test with the same seed and
+    /// options crafts the same random payloads, identifications, and flow
+    /// labels, and randomizes the packets of each endpoint (`--mutate-bits`,
+    /// `--simulate-loss`) the same way. The worker of each endpoint is seeded
+    /// by the seed XORed with the endpoint's zero-based index
+    #[structopt(
+        long = "random-seed",
+        takes_value = true,
+        value_name = "UNSIGNED-INTEGER"
+    )]
+    #[serde(skip)]
+    pub random_seed: Option<u64>,
+
+    /// Treat suspicious configurations (such as unspecified ports) as errors
+    /// instead of warnings, and exit before a test execution
+    #[structopt(long = "strict", takes_value = false)]
+    pub strict: bool,
+
+    /// Refuse to test the receivers belonging to the specified subnet
+    /// (`<IP>/<PREFIX>`). This option can be specified several times
+    #[structopt(
+        long = "deny-subnet",
+        takes_value = true,
+        value_name = "CIDR",
+        multiple = true,
+        number_of_values = 1
+    )]
+    pub deny_subnets: Vec<Subnet>,
+
+    /// Refuse to test the receivers not belonging to any of the specified
+    /// subnets (`<IP>/<PREFIX>`). It also allows the reserved subnets denied by
+    /// `--safe`. This option can be specified several times
+    #[structopt(
+        long = "allow-subnet",
+        takes_value = true,
+        value_name = "CIDR",
+        multiple = true,
+        number_of_values = 1
+    )]
+    pub allow_subnets: Vec<Subnet>,
+
+    /// Refuse to test loopback, multicast, and broadcast receivers unless
+    /// they're explicitly allowed by `--allow-subnet`
+    #[structopt(long = "safe", takes_value = false)]
+    pub safe: bool,
+
+    /// Don't ask for a confirmation before testing globally routable
+    /// receivers (neither private, loopback, nor link-local ones). The
+    /// confirmation is also skipped if stdin isn't a terminal
+    #[structopt(long = "yes", takes_value = false)]
+    #[serde(skip)]
+    pub yes: bool,
+
+    /// Print the first crafted datagram of each endpoint as a hex dump
+    /// annotated with the decoded IP and UDP header fields before a test
+    #[structopt(long = "dump-sample-packet", takes_value = false)]
+    pub dump_sample_packet: bool,
+
+    /// Probe the path MTU to each receiver before a test by sending UDP
+    /// packets with the Don't Fragment bit set, and refuse to send datagrams
+    /// exceeding it
+    #[structopt(long = "discover-mtu", takes_value = false)]
+    pub discover_mtu: bool,
+
+    /// Find the highest rate at which this machine keeps up with sending to
+    /// the first endpoint by short probes at increasing rates, print it as a
+    /// recommended `--test-intensity`, and exit. It measures the sender, not
+    /// the receiver: packets the receiver fails to handle aren't noticed
+    #[structopt(
+        long = "calibrate",
+        takes_value = false,
+        raw(conflicts_with_all = r#"&["keepalive", "burst", "fair_interleave"]"#)
+    )]
+    #[serde(skip)]
+    pub calibrate: bool,
+
+    /// Before a test, send to the first endpoint by short probes in batches of
+    /// 64, 256, 600, and 1024 packets per system call as fast as possible, and
+    /// then split `--test-intensity` into equal batches of at most the fastest
+    /// size instead of sending it by a single system call per a second
+    #[structopt(
+        long = "auto-batch",
+        takes_value = false,
+        raw(conflicts_with_all = r#"&["keepalive", "burst", "calibrate", "fair_interleave"]"#)
+    )]
+    pub auto_batch: bool,
+
+    /// Display a full-screen dashboard with the throughput of each endpoint
+    /// instead of the periodic log messages during a test. It's ignored if
+    /// stdout isn't a terminal
+    #[structopt(long = "tui", takes_value = false)]
+    pub tui: bool,
+
+    /// Start the workers of the endpoints in a random order instead of the
+    /// specified one, so many tests don't hit shared infrastructure in the
+    /// same order. It only affects the start ordering: the endpoints are
+    /// still numbered and reported in the specified order, and the packets
+    /// themselves aren't affected
+    #[structopt(long = "randomize-endpoint-order", takes_value = false)]
+    pub randomize_endpoint_order: bool,
+
+    /// Test all the endpoints from a single thread, sending one packet to
+    /// each endpoint per cycle in turn, instead of a worker thread per
+    /// endpoint. Each endpoint gets the same share of packets regardless of
+    /// how the threads would be scheduled, and `--test-intensity` is the
+    /// number of cycles per second
+    #[structopt(
+        long = "fair-interleave",
+        takes_value = false,
+        raw(conflicts_with_all = r#"&[
+            "keepalive",
+            "burst",
+            "endpoint_rates",
+            "stagger",
+            "randomize_endpoint_order",
+        ]"#)
+    )]
+    pub fair_interleave: bool,
+
+    /// A stack size of each worker thread, in bytes (SI suffixes are
+    /// allowed). Defaults to the Rust default (2 MiB, unless overridden by
+    /// `RUST_MIN_STACK`). Workers don't need much of it, but anything below
+    /// 64k might overflow, depending on the options
+    #[structopt(
+        long = "worker-stack-size",
+        takes_value = true,
+        value_name = "BYTES",
+        parse(try_from_str = "parse_si_non_zero_usize")
+    )]
+    pub worker_stack_size: Option<NonZeroUsize>,
+
+    /// Lock all the current and future memory of the process into RAM
+    /// (`mlockall(2)`) before crafting datagrams, so sending isn't stalled by
+    /// page faults or swapping. Requires `CAP_IPC_LOCK` or a large enough
+    /// `RLIMIT_MEMLOCK`. Locked memory cannot be reclaimed by the system, and
+    /// allocations beyond the limit fail
+    #[structopt(long = "mlockall", takes_value = false)]
+    pub mlockall: bool,
+
+    /// Refuse to start if the crafted datagrams might take more memory than
+    /// the specified number of bytes (SI suffixes are allowed). The memory is
+    /// estimated before crafting as if each endpoints had its own copy of
+    /// every payload, assuming the largest datagram for `--send-command`
+    #[structopt(
+        long = "max-craft-memory",
+        takes_value = true,
+        value_name = "BYTES",
+        parse(try_from_str = "parse_si_non_zero_usize")
+    )]
+    pub max_craft_memory: Option<NonZeroUsize>,
+
+    /// Switch to the specified unprivileged user (and its groups) once the
+    /// sockets of all the endpoints are created, before any packet is sent.
+    /// The sockets stay usable, but files written after a test (such as
+    /// `--output-file`) are created by this user
+    #[structopt(
+        long = "drop-privileges",
+        takes_value = true,
+        value_name = "USER",
+        raw(conflicts_with_all = r#"&["stagger", "calibrate"]"#)
+    )]
+    pub drop_privileges: Option<String>,
+
+    /// Send NetFlow v5 records of the packets sent to each IPv4 receiver to
+    /// the specified collector (`<IP>:<PORT>`) every five seconds. Requires
+    /// the `netflow` feature
+    #[structopt(long = "netflow-export", takes_value = true, value_name = "IP:PORT")]
+    pub netflow_export: Option<SocketAddr>,
+
     /// A waiting time span before a test execution used to prevent a
-    /// launch of an erroneous (unwanted) test
+    /// launch of an erroneous (unwanted) test. Press Ctrl-C to skip the rest
+    /// of the waiting
     #[structopt(
         short = "w",
         long = "wait",
@@ -54,8 +400,26 @@ pub struct ArgsConfig {
         default_value = "5secs",
         parse(try_from_str = "humantime::parse_duration")
     )]
+    #[serde(with = "config_file::duration")]
     pub wait: Duration,
 
+    /// Start a test immediately, just like `--wait=0secs`
+    #[structopt(long = "no-wait", takes_value = false)]
+    #[serde(skip)]
+    pub no_wait: bool,
+
+    /// Delay the start of each next endpoint worker by the specified time
+    /// span more than the previous one (after `--wait`), which spreads the
+    /// initial burst across the endpoints
+    #[structopt(
+        long = "stagger",
+        takes_value = true,
+        value_name = "TIME-SPAN",
+        parse(try_from_str = "humantime::parse_duration")
+    )]
+    #[serde(default, with = "config_file::optional_duration")]
+    pub stagger: Option<Duration>,
+
     /// A maximum number of packets transmitted per a second. It's guaranteed
     /// that a number of packets sent per a second will never exceed this value
     #[structopt(
@@ -66,28 +430,209 @@ pub struct ArgsConfig {
     )]
     pub test_intensity: NonZeroUsize,
 
+    /// Overrides `--test-intensity` for the endpoints specified by a
+    /// zero-based index of `--endpoints` (`<INDEX>:<PACKETS>`). This option
+    /// can be specified several times
+    #[structopt(
+        long = "endpoint-rate",
+        takes_value = true,
+        value_name = "INDEX:PACKETS",
+        multiple = true,
+        number_of_values = 1
+    )]
+    pub endpoint_rates: Vec<EndpointRate>,
+
+    /// Send packets in bursts of the specified size as fast as possible,
+    /// sleeping `--burst-pause` between them, instead of pacing them by
+    /// `--test-intensity` and `--endpoint-rate`
+    #[structopt(
+        long = "burst",
+        takes_value = true,
+        value_name = "PACKETS",
+        parse(try_from_str = "parse_si_non_zero_usize")
+    )]
+    pub burst: Option<NonZeroUsize>,
+
+    /// A period of time to sleep between two bursts (see `--burst`)
+    #[structopt(
+        long = "burst-pause",
+        takes_value = true,
+        value_name = "TIME-SPAN",
+        default_value = "1sec",
+        parse(try_from_str = "humantime::parse_duration")
+    )]
+    #[serde(with = "config_file::duration")]
+    pub burst_pause: Duration,
+
+    /// Send a single packet per the specified period of time to each endpoint
+    /// instead of pacing packets by `--test-intensity`, which keeps the
+    /// mappings of stateful UDP proxies and NATs alive during a long test
+    #[structopt(
+        long = "keepalive",
+        takes_value = true,
+        value_name = "TIME-SPAN",
+        parse(try_from_str = "humantime::parse_duration"),
+        conflicts_with = "burst"
+    )]
+    #[serde(default, with = "config_file::optional_duration")]
+    pub keepalive: Option<Duration>,
+
+    /// Send a single packet to each endpoint, sleep the specified period of
+    /// time, and only then start sending at the full rate, which lets a
+    /// receiver warm up (e.g., fill its caches) before a burst. Unlike
+    /// `--wait`, the first packet is sent before waiting. The delay counts
+    /// towards `--test-duration`
+    #[structopt(
+        long = "first-packet-delay",
+        takes_value = true,
+        value_name = "TIME-SPAN",
+        parse(try_from_str = "humantime::parse_duration"),
+        raw(conflicts_with_all = r#"&["keepalive", "fair_interleave"]"#)
+    )]
+    #[serde(default, with = "config_file::optional_duration")]
+    pub first_packet_delay: Option<Duration>,
+
+    /// A maximum allowed number of packets transmitted per a second by all the
+    /// endpoints together (a sum of their `--test-intensity` or
+    /// `--endpoint-rate`). It prevents an accidental launch of an overly intensive test
+    #[structopt(
+        long = "max-packet-rate",
+        takes_value = true,
+        value_name = "PACKETS",
+        default_value = "10000000"
+    )]
+    pub max_packet_rate: NonZeroUsize,
+
+    /// Allow a test intensity exceeding `--max-packet-rate`
+    #[structopt(long = "i-know-what-im-doing", takes_value = false)]
+    pub i_know_what_im_doing: bool,
+
     #[structopt(flatten)]
+    #[serde(flatten)]
     pub sockets_config: SocketsConfig,
 
     #[structopt(flatten)]
+    #[serde(flatten)]
     pub packets_config: PacketsConfig,
 
     #[structopt(flatten)]
-    pub logging_config: LoggingConfig,
-
-    #[structopt(flatten)]
+    #[serde(flatten)]
     pub exit_config: ExitConfig,
 }
 
-#[derive(StructOpt, Debug, Clone, Eq, PartialEq)]
+#[derive(StructOpt, Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct SocketsConfig {
     /// Allow sockets to send packets to a broadcast address specified using the
     /// `--endpoints` option
     #[structopt(short = "b", long = "allow-broadcast", takes_value = false)]
     pub broadcast: bool,
+
+    /// Ask the kernel to timestamp each transmitted packet (`SO_TIMESTAMPING`)
+    /// and print the distribution of delays between passing packets to the
+    /// kernel and their handoff to a network device. It adds a per-packet
+    /// overhead
+    #[structopt(long = "tx-timestamps", takes_value = false)]
+    pub tx_timestamps: bool,
+
+    /// Print a number of bytes queued in the socket send buffer after each
+    /// flush in the periodic summaries, which shows whether the kernel buffer
+    /// is a bottleneck. It's Linux-specific (`SIOCOUTQ`) and adds a system call
+    /// per flush
+    #[structopt(long = "show-sndq", takes_value = false)]
+    pub show_sndq: bool,
+
+    /// Warn about each packet sent by fewer bytes than it has, printing its
+    /// index within a batch and both byte counts. It surfaces MTU and buffer
+    /// issues, which otherwise show up only as a gap between the expected and
+    /// sent bytes
+    #[structopt(long = "warn-short-send", takes_value = false)]
+    pub warn_short_send: bool,
+
+    /// Count the datagrams sent back by each receiver to its sender and print
+    /// the response rate (responses per a sent packet), together with the
+    /// ICMP errors caused by the sent packets and their distinct sources.
+    /// Replies are received by a UDP socket bound to the sender address, so it
+    /// only works if the sender is a local address with a fixed (non-zero)
+    /// port that isn't used by another program
+    #[structopt(
+        long = "count-responses",
+        takes_value = false,
+        conflicts_with = "receiver_port_range"
+    )]
+    pub count_responses: bool,
+
+    /// Keep counting responses and ICMP errors for the specified period of
+    /// time after the last packet is sent to an endpoint (instead of 500
+    /// milliseconds), and report the ICMP errors arrived during it separately.
+    /// Otherwise, late errors caused by a short burst are missed. Requires
+    /// `--count-responses`
+    #[structopt(
+        long = "cooldown",
+        takes_value = true,
+        value_name = "TIME-SPAN",
+        parse(try_from_str = "humantime::parse_duration"),
+        requires = "count_responses"
+    )]
+    #[serde(default, with = "config_file::optional_duration")]
+    pub cooldown: Option<Duration>,
+
+    /// A number of times a failed system call sending packets is retried
+    /// before its error is reported. Permanent errors (such as `EMSGSIZE`) are
+    /// never retried
+    #[structopt(
+        long = "send-retries",
+        takes_value = true,
+        value_name = "UNSIGNED-INTEGER",
+        default_value = "0"
+    )]
+    pub send_retries: usize,
+
+    /// A waiting time span before the first retry of a failed system call
+    /// (see `--send-retries`). It doubles after each next retry
+    #[structopt(
+        long = "retry-backoff",
+        takes_value = true,
+        value_name = "TIME-SPAN",
+        default_value = "10ms",
+        parse(try_from_str = "humantime::parse_duration")
+    )]
+    #[serde(with = "config_file::duration")]
+    pub retry_backoff: Duration,
+
+    /// A number of times the unsent rest of a batch is sent again right away
+    /// if a system call has sent only a part of it, instead of leaving the
+    /// rest to the next batches
+    #[structopt(
+        long = "partial-retries",
+        takes_value = true,
+        value_name = "UNSIGNED-INTEGER",
+        default_value = "0"
+    )]
+    pub partial_retries: usize,
+
+    /// Mark each sent packet with the specified value (`SO_MARK`), so it can
+    /// be steered by fwmark-based policy routing rules or matched by packet
+    /// filters. It requires the `CAP_NET_ADMIN` capability
+    #[structopt(long = "so-mark", takes_value = true, value_name = "UNSIGNED-INTEGER")]
+    pub so_mark: Option<u32>,
+
+    /// Send the datagrams to multicast receivers through the specified network
+    /// interface (`IP_MULTICAST_IF` or `IPV6_MULTICAST_IF`) instead of the one
+    /// chosen by the routing table
+    #[structopt(long = "multicast-if", takes_value = true, value_name = "INTERFACE")]
+    pub multicast_if: Option<String>,
+
+    /// Send the datagrams through the specified file descriptor inherited
+    /// from a parent process instead of creating a socket, so its network
+    /// namespace, cgroup, and options apply. It must be either a raw socket
+    /// with `IPPROTO_RAW`, or a UDP socket, which sends only payloads (the
+    /// kernel builds the headers). Requires a single endpoint
+    #[structopt(long = "fd", takes_value = true, value_name = "FD")]
+    #[serde(skip)]
+    pub fd: Option<RawFd>,
 }
 
-#[derive(StructOpt, Debug, Clone, Eq, PartialEq)]
+#[derive(StructOpt, Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct PayloadConfig {
     /// Repeatedly send a random-generated packet with a specified bytes length.
     /// The default is 1024
@@ -99,6 +644,13 @@ pub struct PayloadConfig {
     )]
     pub random_packets: Vec<NonZeroUsize>,
 
+    /// Fill `--random-packet` payloads with the specified pattern instead of
+    /// random bytes: `zero`, `ones` (0xFF), `incrementing` (0, 1, ..., 255, 0,
+    /// ...), or a hexadecimal byte such as `AB`. It's useful to test how a
+    /// receiver reacts to low-entropy traffic
+    #[structopt(long = "fill-pattern", takes_value = true, value_name = "PATTERN")]
+    pub fill_pattern: Option<FillPattern>,
+
     /// Interpret the specified file content as a single packet and repeatedly
     /// send it to each receiver
     #[structopt(
@@ -109,6 +661,18 @@ pub struct PayloadConfig {
     )]
     pub send_files: Vec<PathBuf>,
 
+    /// Interpret each non-empty regular file of the specified directory as a
+    /// single packet and repeatedly send them to each receiver in the order of
+    /// their names
+    #[structopt(
+        long = "send-dir",
+        takes_value = true,
+        value_name = "DIRECTORY",
+        multiple = true,
+        number_of_values = 1
+    )]
+    pub send_dirs: Vec<PathBuf>,
+
     /// Interpret the specified UTF-8 encoded text message as a single packet
     /// and repeatedly send it to each receiver
     #[structopt(
@@ -118,26 +682,74 @@ pub struct PayloadConfig {
         value_name = "STRING"
     )]
     pub send_messages: Vec<String>,
+
+    /// Run the specified shell command (by `sh -c`) before a test and
+    /// interpret its standard output as a single packet. Fails if the command
+    /// exits with a non-zero status or prints nothing. The command runs with
+    /// the privileges of anevicon (typically root for raw sockets), so never
+    /// take it from an untrusted configuration file
+    #[structopt(
+        long = "send-command",
+        takes_value = true,
+        value_name = "COMMAND",
+        multiple = true,
+        number_of_values = 1
+    )]
+    pub send_commands: Vec<String>,
+
+    /// Run the commands of `--send-command` again for each packet instead of
+    /// only once, so each packet carries a fresh output. Such packets are
+    /// sent one by one, so this is much slower than sending fixed payloads
+    #[structopt(long = "send-command-each", takes_value = false)]
+    pub send_command_each: bool,
+
+    /// Memory-map the files specified by `--send-file` and `--send-dir`
+    /// instead of reading them, so their content is paged in lazily. The
+    /// files must not be modified or truncated until the test is finished.
+    /// Requires the `mmap` feature
+    #[structopt(long = "mmap-files", takes_value = false)]
+    pub mmap_files: bool,
+
+    /// Repeat the content of each file specified by `--send-file` and
+    /// `--send-dir` the specified number of times within a single packet. It
+    /// cannot be combined with `--mmap-files`
+    #[structopt(
+        long = "repeat-file",
+        takes_value = true,
+        value_name = "POSITIVE-INTEGER",
+        default_value = "1"
+    )]
+    pub repeat_file: NonZeroUsize,
+
+    /// Warn about each payload whose Shannon entropy is below the specified
+    /// number of bits per byte (from 0 to 8), such as a file of zeros. A
+    /// compressing link carries such payloads faster than they're sent, which
+    /// inflates the apparent throughput. Sending isn't affected
+    #[structopt(long = "warn-entropy", takes_value = true, value_name = "BITS")]
+    pub warn_entropy: Option<EntropyThreshold>,
 }
 
-#[derive(StructOpt, Debug, Clone, Eq, PartialEq)]
+#[derive(StructOpt, Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct PacketsConfig {
     /// Two endpoints specified as `<SENDER-ADDRESS>&<RECEIVER-ADDRESS>`, where
     /// address is a string of a `<IP>:<PORT>` format.
     ///
     /// A sender and a receiver can be absolutely any valid IPv4/IPv6 addresses
-    /// (which is used to send spoofed packets sometimes).
+    /// (which is used to send spoofed packets sometimes). A link-local IPv6
+    /// receiver must specify its zone by an interface name or index, e.g.
+    /// `[fe80::1%eth0]:80`.
     ///
     /// This option can be specified several times to identically test multiple
-    /// web servers in concurrent mode.
+    /// web servers in concurrent mode. Several endpoints can also be separated
+    /// by commas in a single value.
     #[structopt(
         short = "e",
         long = "endpoints",
         takes_value = true,
         value_name = "SENDER&RECEIVER",
         multiple = true,
-        number_of_values = 1,
-        required = true
+        raw(use_delimiter = "true", require_delimiter = "true"),
+        parse(try_from_str = "parse_endpoints")
     )]
     pub endpoints: Vec<Endpoints>,
 
@@ -151,7 +763,149 @@ pub struct PacketsConfig {
     )]
     pub ip_ttl: u8,
 
+    /// The TTL (hop limit) of the datagrams sent to multicast receivers
+    /// instead of `--ip-ttl`. It's written into their IP headers, since raw
+    /// sockets don't take it from the socket options as regular UDP sockets
+    /// do, and is also set as `IP_MULTICAST_TTL` (`IPV6_MULTICAST_HOPS`)
+    #[structopt(
+        long = "multicast-ttl",
+        takes_value = true,
+        value_name = "UNSIGNED-INTEGER"
+    )]
+    pub multicast_ttl: Option<u8>,
+
+    /// Send successive datagrams to each port of the specified range in turn
+    /// (`<MIN>:<MAX>`), keeping a receiver IP fixed. It overrides receiver
+    /// ports of `--endpoints`, which is useful to test load balancers
+    #[structopt(
+        long = "receiver-port-range",
+        takes_value = true,
+        value_name = "MIN:MAX"
+    )]
+    pub receiver_port_range: Option<PortRange>,
+
+    /// Write a random identification into the IPv4 header of each crafted
+    /// datagram instead of zero (which is replaced by the kernel). Datagrams
+    /// are crafted once, so each of them keeps its identification when it's
+    /// sent again
+    #[structopt(long = "randomize-ip-id", takes_value = false)]
+    pub randomize_ip_id: bool,
+
+    /// Write a random flow label into the IPv6 header of each crafted
+    /// datagram instead of zero, which spreads datagrams across ECMP paths.
+    /// Datagrams are crafted once, so each of them keeps its flow label when
+    /// it's sent again
+    #[structopt(long = "randomize-flow-label", takes_value = false)]
+    pub randomize_flow_label: bool,
+
+    /// Send `--random-packet` payloads of a different random length to each
+    /// endpoint, from one byte up to the specified length. Each endpoint keeps
+    /// its length for the whole test
+    #[structopt(long = "vary-per-endpoint", takes_value = false)]
+    pub vary_per_endpoint: bool,
+
+    /// Send the payload specified by a zero-based index (in the order printed
+    /// by `--list-payloads`) the specified number of times in a row per a
+    /// single send of the payloads without weights (`<INDEX>:<WEIGHT>`). This
+    /// option can be specified several times
+    #[structopt(
+        long = "payload-weight",
+        takes_value = true,
+        value_name = "INDEX:WEIGHT",
+        multiple = true,
+        number_of_values = 1
+    )]
+    pub payload_weights: Vec<PayloadWeight>,
+
+    /// Concatenate the specified number of consecutive payloads (in the order
+    /// they're sent, after `--payload-weight`) into a single datagram, which
+    /// is useful to test how a receiver frames application-layer messages.
+    /// It changes the message boundaries: a receiver gets several messages in
+    /// a single datagram. An aggregated datagram cannot exceed the maximum UDP
+    /// payload size (65507 bytes), and is fragmented if it exceeds the path
+    /// MTU
+    #[structopt(
+        long = "aggregate",
+        takes_value = true,
+        value_name = "POSITIVE-INTEGER",
+        conflicts_with = "send_command_each"
+    )]
+    pub aggregate: Option<NonZeroUsize>,
+
+    /// Set the reserved (the highest) bit of the flags field of each IPv4
+    /// header ("the evil bit" of RFC 3514). It's non-standard and makes
+    /// datagrams malformed, so it's only useful to verify how firewalls and
+    /// other middleboxes handle such datagrams. IPv6 datagrams are unaffected
+    #[structopt(long = "set-reserved-flag", takes_value = false, raw(hidden = "true"))]
+    pub set_reserved_flag: bool,
+
+    /// Append the specified option to each IPv4 header, which is useful for
+    /// path analysis: `record-route` asks routers to record their addresses,
+    /// and `timestamp` asks them to record timestamps (up to nine of either).
+    /// Options may take at most 40 bytes, since the header length field counts
+    /// 32-bit words up to 15 (60 bytes), so each option takes all of them and
+    /// IPv4 payloads may take at most 65467 bytes. IPv6 datagrams are
+    /// unaffected. Many routers drop such datagrams
+    #[structopt(
+        long = "ip-option",
+        takes_value = true,
+        value_name = "OPTION",
+        raw(possible_values = r#"&["record-route", "timestamp"]"#)
+    )]
+    pub ip_option: Option<IpOption>,
+
+    /// Flip the specified number of random bits of the payload of each packet
+    /// before sending it, which is useful to fuzz a receiver's parser. Headers
+    /// are updated, so the packets stay deliverable. Such packets are sent
+    /// one by one, so this is much slower than sending fixed payloads
+    #[structopt(
+        long = "mutate-bits",
+        takes_value = true,
+        value_name = "POSITIVE-INTEGER"
+    )]
+    pub mutate_bits: Option<NonZeroUsize>,
+
+    /// Write a number of the packets sent to an endpoint before (starting
+    /// from zero) into the payload of each packet, so a receiver can detect
+    /// lost and reordered packets. Its position and encoding are specified by
+    /// `--seq-offset`, `--seq-width`, and `--seq-endian`. Such packets are
+    /// sent one by one, just like with `--mutate-bits`
+    #[structopt(long = "sequence-number", takes_value = false)]
+    pub sequence_number: bool,
+
+    /// A zero-based offset of the `--sequence-number` counter within a
+    /// payload. The counter must fit into each payload
+    #[structopt(
+        long = "seq-offset",
+        takes_value = true,
+        value_name = "BYTES",
+        default_value = "0"
+    )]
+    pub seq_offset: usize,
+
+    /// A number of bytes of the `--sequence-number` counter. The counter wraps
+    /// around when it exceeds this width
+    #[structopt(
+        long = "seq-width",
+        takes_value = true,
+        value_name = "BYTES",
+        default_value = "4",
+        raw(possible_values = r#"&["2", "4", "8"]"#)
+    )]
+    pub seq_width: usize,
+
+    /// A byte order of the `--sequence-number` counter
+    #[structopt(
+        long = "seq-endian",
+        takes_value = true,
+        value_name = "ORDER",
+        default_value = "big",
+        raw(possible_values = r#"&["big", "little"]"#)
+    )]
+    pub seq_endian: Endianness,
+
     #[structopt(flatten)]
+    #[serde(flatten)]
     pub payload_config: PayloadConfig,
 }
 
@@ -168,7 +922,10 @@ pub struct LoggingConfig {
         takes_value = true,
         value_name = "LEVEL",
         default_value = "3",
-        raw(possible_values = r#"&["0", "1", "2", "3", "4", "5"]"#)
+        raw(
+            possible_values = r#"&["0", "1", "2", "3", "4", "5"]"#,
+            global = "true"
+        )
     )]
     pub verbosity: i32,
 
@@ -179,24 +936,40 @@ pub struct LoggingConfig {
         takes_value = true,
         value_name = "STRING",
         default_value = "%X",
-        raw(validator = "validate_date_time_format")
+        raw(validator = "validate_date_time_format", global = "true")
     )]
     pub date_time_format: String,
 }
 
-#[derive(StructOpt, Debug, Clone, Eq, PartialEq)]
+#[derive(StructOpt, Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct ExitConfig {
     /// A count of packets for sending. When this limit is reached, then the
-    /// program will immediately stop its execution
+    /// program will immediately stop its execution. SI suffixes are accepted,
+    /// e.g. `500k`, `1M`, or `2.5G`
     #[structopt(
         short = "p",
         long = "packets-count",
         takes_value = true,
         value_name = "POSITIVE-INTEGER",
-        default_value = "18446744073709551615"
+        default_value = "18446744073709551615",
+        parse(try_from_str = "parse_si_non_zero_usize")
     )]
+    #[serde(with = "config_file::large_non_zero_usize")]
     pub packets_count: NonZeroUsize,
 
+    /// Whether `--packets-count` limits each endpoint separately
+    /// (`per-endpoint`, so the total is multiplied by a number of endpoints),
+    /// or all the endpoints together (`total`, so the count is divided between
+    /// them)
+    #[structopt(
+        long = "packets-count-mode",
+        takes_value = true,
+        value_name = "MODE",
+        default_value = "per-endpoint",
+        raw(possible_values = r#"&["per-endpoint", "total"]"#)
+    )]
+    pub packets_count_mode: PacketsCountMode,
+
     /// A whole test duration. When this limit is reached, then the program will
     /// immediately stop its execution
     #[structopt(
@@ -207,37 +980,305 @@ pub struct ExitConfig {
         default_value = "64years 64hours 64secs",
         parse(try_from_str = "humantime::parse_duration")
     )]
+    #[serde(with = "config_file::duration")]
     pub test_duration: Duration,
 }
 
+/// Specifies how `--output-file` is encoded.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    Json,
+    Cbor,
+    Msgpack,
+}
+
+impl OutputFormat {
+    /// Returns `true` if anevicon has been built with the cargo feature
+    /// required by this format.
+    pub fn is_supported(self) -> bool {
+        match self {
+            OutputFormat::Json => true,
+            OutputFormat::Cbor => cfg!(feature = "cbor"),
+            OutputFormat::Msgpack => cfg!(feature = "msgpack"),
+        }
+    }
+}
+
+impl Display for OutputFormat {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match self {
+            OutputFormat::Json => "json",
+            OutputFormat::Cbor => "cbor",
+            OutputFormat::Msgpack => "msgpack",
+        })
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "json" => Ok(OutputFormat::Json),
+            "cbor" => Ok(OutputFormat::Cbor),
+            "msgpack" => Ok(OutputFormat::Msgpack),
+            _ => Err(format!("'{}' isn't a valid output format", format)),
+        }
+    }
+}
+
+/// Specifies how `--packets-count` is applied to multiple endpoints.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PacketsCountMode {
+    PerEndpoint,
+    Total,
+}
+
+impl FromStr for PacketsCountMode {
+    type Err = String;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode {
+            "per-endpoint" => Ok(PacketsCountMode::PerEndpoint),
+            "total" => Ok(PacketsCountMode::Total),
+            _ => Err(format!("'{}' isn't a valid packets count mode", mode)),
+        }
+    }
+}
+
+/// Specifies how `--sequence-number` is encoded.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Endianness {
+    Big,
+    Little,
+}
+
+impl FromStr for Endianness {
+    type Err = String;
+
+    fn from_str(order: &str) -> Result<Self, Self::Err> {
+        match order {
+            "big" => Ok(Endianness::Big),
+            "little" => Ok(Endianness::Little),
+            _ => Err(format!("'{}' isn't a valid byte order", order)),
+        }
+    }
+}
+
+/// An IPv4 option appended by `--ip-option`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IpOption {
+    RecordRoute,
+    Timestamp,
+}
+
+impl FromStr for IpOption {
+    type Err = String;
+
+    fn from_str(option: &str) -> Result<Self, Self::Err> {
+        match option {
+            "record-route" => Ok(IpOption::RecordRoute),
+            "timestamp" => Ok(IpOption::Timestamp),
+            _ => Err(format!("'{}' isn't a supported IP option", option)),
+        }
+    }
+}
+
 impl ArgsConfig {
     /// Use it to setup the current structure. It does special additional stuff
     /// unlike the typical `StructOpt::from_args()`.
     pub fn setup() -> ArgsConfig {
-        let mut matches = ArgsConfig::from_args();
-
-        // If a user hasn't specified both a file, a text message, and a packet length,
-        // then set the default packet length
-        if matches.packets_config.payload_config.send_files.is_empty()
-            && matches
-                .packets_config
-                .payload_config
-                .random_packets
-                .is_empty()
-            && matches
-                .packets_config
-                .payload_config
-                .send_messages
-                .is_empty()
+        let app_matches = ArgsConfig::clap().get_matches();
+        let mut matches = ArgsConfig::from_clap(&app_matches);
+
+        // Generating a completion script doesn't need any test setup, so exit
+        // right after it has been printed
+        if let Some(ref shell) = matches.generate_completion {
+            ArgsConfig::clap().gen_completions_to(
+                structopt::clap::crate_name!(),
+                shell
+                    .parse::<Shell>()
+                    .expect("Shells must be validated by clap"),
+                &mut io::stdout(),
+            );
+            std::process::exit(libc::EXIT_SUCCESS);
+        }
+
+        match matches.command {
+            Some(Command::Send(ref mut send_config)) => send_config.setup(
+                app_matches
+                    .subcommand_matches("send")
+                    .expect("The send subcommand must be present"),
+            ),
+            Some(_) => (),
+            None => clap::Error::with_description(
+                "A subcommand wasn't specified, see `--help` for the list of subcommands",
+                clap::ErrorKind::MissingSubcommand,
+            )
+            .exit(),
+        }
+
+        matches
+    }
+}
+
+impl SendConfig {
+    /// Merges `--config-file` into the current structure, sets the default
+    /// payload, and handles `--dump-config`. It exits the program on invalid
+    /// configurations, just like clap does.
+    fn setup(&mut self, matches: &ArgMatches) {
+        if let Some(ref path) = self.config_file {
+            match config_file::merge(self, path, matches) {
+                Err(error) => clap::Error::with_description(
+                    &format!(
+                        "Failed to load `--config-file`\n{causes}",
+                        causes = crate::helpers::format_failure(&error.into())
+                    ),
+                    clap::ErrorKind::InvalidValue,
+                )
+                .exit(),
+                Ok(merged) => *self = merged,
+            }
+        }
+
+        // `--endpoints` isn't required by clap itself because endpoints may be
+        // specified by `--config-file` too, and payloads can be listed without
+        // any endpoints
+        if self.packets_config.endpoints.is_empty() && !self.list_payloads {
+            clap::Error::with_description(
+                "At least one `--endpoints` must be specified",
+                clap::ErrorKind::MissingRequiredArgument,
+            )
+            .exit();
+        }
+
+        if self.no_wait {
+            self.wait = Duration::from_secs(0);
+        }
+
+        self.setup_default_payload();
+
+        if self.dump_config {
+            print!("{}", config_file::dump(self));
+            std::process::exit(libc::EXIT_SUCCESS);
+        }
+    }
+
+    /// Copies the options which can be specified only on the command line (the
+    /// `#[serde(skip)]` fields) from `from`, since they're lost by a round-trip
+    /// through TOML (see `config_file::merge`).
+    pub fn copy_cli_only(&mut self, from: &SendConfig) {
+        self.config_file = from.config_file.clone();
+        self.dump_config = from.dump_config;
+        self.count_only = from.count_only;
+        self.list_payloads = from.list_payloads;
+        self.show_wire_size = from.show_wire_size;
+        self.random_seed = from.random_seed;
+        self.yes = from.yes;
+        self.calibrate = from.calibrate;
+        self.no_wait = from.no_wait;
+        self.sockets_config.fd = from.sockets_config.fd;
+    }
+
+    /// Returns a test intensity of the endpoints with the specified index:
+    /// `--endpoint-rate` if it's specified for them, or `--test-intensity`
+    /// otherwise.
+    pub fn test_intensity_of(&self, index: usize) -> NonZeroUsize {
+        self.endpoint_rates
+            .iter()
+            .find(|rate| rate.index == index)
+            .map_or(self.test_intensity, |rate| rate.test_intensity)
+    }
+
+    /// If a user hasn't specified both a file, a text message, and a packet
+    /// length, then set the default packet length.
+    fn setup_default_payload(&mut self) {
+        let payload_config = &mut self.packets_config.payload_config;
+
+        if payload_config.send_files.is_empty()
+            && payload_config.send_dirs.is_empty()
+            && payload_config.random_packets.is_empty()
+            && payload_config.send_messages.is_empty()
+            && payload_config.send_commands.is_empty()
         {
-            matches.packets_config.payload_config.random_packets =
+            payload_config.random_packets =
                 vec![NonZeroUsize::new(DEFAULT_RANDOM_PACKET_SIZE).unwrap()];
         }
+    }
+}
 
-        matches
+/// Parses a positive integer with an optional SI suffix (`k`, `M`, or `G`),
+/// so `1M` means 1000000 and `2.5G` means 2500000000. Fractional values are
+/// accepted only if they result in a whole number.
+fn parse_si_non_zero_usize(value: &str) -> Result<NonZeroUsize, String> {
+    let (number, multiplier) = match value.chars().last() {
+        Some('k') => (&value[..value.len() - 1], 1_000),
+        Some('M') => (&value[..value.len() - 1], 1_000_000),
+        Some('G') => (&value[..value.len() - 1], 1_000_000_000),
+        _ => (value, 1),
+    };
+
+    let (integer, fraction) = match number.find('.') {
+        Some(dot) => (&number[..dot], &number[dot + 1..]),
+        None => (number, ""),
+    };
+
+    let is_digits = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+    if integer.is_empty() || !is_digits(integer) || !is_digits(fraction) {
+        return Err(format!("'{}' isn't a valid number", value));
+    }
+
+    let too_large = || format!("'{}' is too large", value);
+    let parse_u128 = |part: &str| {
+        if part.is_empty() {
+            Ok(0)
+        } else {
+            part.parse::<u128>().map_err(|_| too_large())
+        }
+    };
+
+    let fraction_scale = 10u128
+        .checked_pow(fraction.len() as u32)
+        .ok_or_else(too_large)?;
+    let fraction = parse_u128(fraction)?
+        .checked_mul(multiplier)
+        .ok_or_else(too_large)?;
+    if fraction % fraction_scale != 0 {
+        return Err(format!("'{}' isn't a whole number of packets", value));
+    }
+
+    let result = parse_u128(integer)?
+        .checked_mul(multiplier)
+        .and_then(|integer| integer.checked_add(fraction / fraction_scale))
+        .ok_or_else(too_large)?;
+
+    usize::try_from(result)
+        .map_err(|_| too_large())
+        .and_then(|result| {
+            NonZeroUsize::new(result).ok_or_else(|| String::from("The value must be positive"))
+        })
+}
+
+/// Parses an integer percent from 0 to 100 with an optional `%` suffix.
+fn parse_percent(value: &str) -> Result<u8, String> {
+    match value.trim_end_matches('%').parse::<u8>() {
+        Ok(percent) if percent <= 100 => Ok(percent),
+        _ => Err(format!("'{}' isn't a percent from 0 to 100", value)),
     }
 }
 
+/// Parses one of comma-separated `--endpoints`, mentioning the offending
+/// value in an error, since clap doesn't tell which of them is invalid.
+fn parse_endpoints(value: &str) -> Result<Endpoints, String> {
+    value
+        .parse()
+        .map_err(|error| format!("'{}' is invalid: {}", value, error))
+}
+
 fn validate_date_time_format(format: String) -> Result<(), String> {
     // If this call succeeds, `format` is correct
     time::strftime(&format, &time::now())
@@ -280,4 +1321,117 @@ mod tests {
         check("yufb%44htv");
         check("sf%jhei9%990");
     }
+
+    #[test]
+    fn parses_percents() {
+        assert_eq!(parse_percent("5"), Ok(5));
+        assert_eq!(parse_percent("100%"), Ok(100));
+        assert!(parse_percent("101").is_err());
+        assert!(parse_percent("-1").is_err());
+        assert!(parse_percent("half").is_err());
+    }
+
+    #[test]
+    fn parses_si_suffixes() {
+        let parse = |value| parse_si_non_zero_usize(value).map(NonZeroUsize::get);
+
+        assert_eq!(parse("1k"), Ok(1_000));
+        assert_eq!(parse("1M"), Ok(1_000_000));
+        assert_eq!(parse("2.5G"), Ok(2_500_000_000));
+        assert_eq!(parse("1.5k"), Ok(1_500));
+    }
+
+    #[test]
+    fn parses_plain_integers() {
+        let parse = |value| parse_si_non_zero_usize(value).map(NonZeroUsize::get);
+
+        assert_eq!(parse("1"), Ok(1));
+        assert_eq!(parse("4096"), Ok(4096));
+        assert_eq!(parse(&usize::MAX.to_string()), Ok(usize::MAX));
+    }
+
+    #[test]
+    fn rejects_invalid_packet_counts() {
+        let check = |value| {
+            assert!(
+                parse_si_non_zero_usize(value).is_err(),
+                "Parses an invalid count correctly"
+            )
+        };
+
+        check("0");
+        check("0k");
+        check("1.5");
+        check("1.0001k");
+        check("k");
+        check("-1");
+        check("1T");
+        check("100000000000G");
+    }
+
+    #[test]
+    fn selects_endpoint_rates() {
+        let config = SendConfig::from_iter(&[
+            "send",
+            "--endpoints",
+            "127.0.0.1:9&127.0.0.1:9",
+            "--endpoints",
+            "127.0.0.1:9&127.0.0.1:10",
+            "--test-intensity",
+            "100",
+            "--endpoint-rate",
+            "1:25",
+        ]);
+
+        assert_eq!(config.test_intensity_of(0).get(), 100);
+        assert_eq!(config.test_intensity_of(1).get(), 25);
+    }
+
+    #[test]
+    fn parses_comma_separated_endpoints() {
+        let config = SendConfig::from_iter(&[
+            "send",
+            "-e",
+            "127.0.0.1:1&127.0.0.1:2,127.0.0.1:3&127.0.0.1:4",
+            "--endpoints",
+            "[::1]:5&[::1]:6",
+        ]);
+
+        let receivers = config
+            .packets_config
+            .endpoints
+            .iter()
+            .map(|endpoints| endpoints.receiver().to_string())
+            .collect::<Vec<String>>();
+        assert_eq!(receivers, vec!["127.0.0.1:2", "127.0.0.1:4", "[::1]:6"]);
+    }
+
+    #[test]
+    fn reports_invalid_comma_separated_endpoints() {
+        let error = SendConfig::clap()
+            .get_matches_from_safe(["send", "-e", "127.0.0.1:1&127.0.0.1:2,127.0.0.1:3&oops"])
+            .expect_err("Invalid endpoints must be rejected");
+
+        assert!(error.message.contains("'127.0.0.1:3&oops' is invalid"));
+    }
+
+    #[test]
+    fn takes_one_value_per_occurrence() {
+        let parse = |option: &str| {
+            SendConfig::clap().get_matches_from_safe([
+                "send",
+                "-e",
+                "127.0.0.1:1&127.0.0.1:2",
+                option,
+                "first",
+                option,
+                "second",
+                "third",
+            ])
+        };
+
+        // The last value isn't swallowed by the repeated option
+        assert!(parse("--send-dir").is_err());
+        assert!(parse("--send-command").is_err());
+    }
 }
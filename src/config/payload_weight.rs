@@ -0,0 +1,121 @@
+// anevicon: A high-performant UDP-based load generator, written in Rust.
+// Copyright (C) 2019  Temirkhan Myrzamadi <gymmasssorla@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// For more information see <https://github.com/Gymmasssorla/anevicon>.
+
+//! The structure representing a weight of a single payload.
+
+use std::fmt::{self, Display, Formatter};
+use std::num::{NonZeroUsize, ParseIntError};
+use std::str::FromStr;
+
+use serde::de::{self, Deserialize, Deserializer};
+use serde::{Serialize, Serializer};
+
+/// A number of times the payload specified by a zero-based index (in the order
+/// printed by `--list-payloads`) is sent per a single send of the payloads
+/// without weights, specified as `<INDEX>:<WEIGHT>`.
+#[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+pub struct PayloadWeight {
+    pub index: usize,
+    pub weight: NonZeroUsize,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Fail)]
+#[allow(clippy::enum_variant_names)]
+pub enum ParsePayloadWeightError {
+    #[fail(display = "A payload weight must be specified as <INDEX>:<WEIGHT>")]
+    InvalidFormat,
+
+    #[fail(display = "{}", _0)]
+    InvalidIndex(#[fail(cause)] ParseIntError),
+
+    #[fail(display = "{}", _0)]
+    InvalidWeight(#[fail(cause)] ParseIntError),
+}
+
+/// Formats a weight as `<INDEX>:<WEIGHT>`, so the result can be parsed back by
+/// `PayloadWeight::from_str`.
+impl Display for PayloadWeight {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{index}:{weight}",
+            index = self.index,
+            weight = self.weight
+        )
+    }
+}
+
+impl Serialize for PayloadWeight {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for PayloadWeight {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl FromStr for PayloadWeight {
+    type Err = ParsePayloadWeightError;
+
+    fn from_str(format: &str) -> Result<Self, ParsePayloadWeightError> {
+        let parts = format.split(':').collect::<Vec<&str>>();
+        if parts.len() != 2 {
+            return Err(ParsePayloadWeightError::InvalidFormat);
+        }
+
+        Ok(PayloadWeight {
+            index: parts[0]
+                .parse()
+                .map_err(ParsePayloadWeightError::InvalidIndex)?,
+            weight: parts[1]
+                .parse()
+                .map_err(ParsePayloadWeightError::InvalidWeight)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_payload_weights() {
+        let weight = "1:10".parse::<PayloadWeight>().unwrap();
+        assert_eq!(weight.index, 1);
+        assert_eq!(weight.weight.get(), 10);
+        assert_eq!(weight.to_string(), "1:10");
+
+        assert_eq!(
+            "1:10:2".parse::<PayloadWeight>(),
+            Err(ParsePayloadWeightError::InvalidFormat)
+        );
+        match "-1:10".parse::<PayloadWeight>() {
+            Err(ParsePayloadWeightError::InvalidIndex(_)) => (),
+            _ => panic!("ParsePayloadWeightError::InvalidIndex must be returned"),
+        }
+        match "1:0".parse::<PayloadWeight>() {
+            Err(ParsePayloadWeightError::InvalidWeight(_)) => (),
+            _ => panic!("ParsePayloadWeightError::InvalidWeight must be returned"),
+        }
+    }
+}
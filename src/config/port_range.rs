@@ -0,0 +1,145 @@
+// anevicon: A high-performant UDP-based load generator, written in Rust.
+// Copyright (C) 2019  Temirkhan Myrzamadi <gymmasssorla@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// For more information see <https://github.com/Gymmasssorla/anevicon>.
+
+//! The structure representing a user-specified inclusive range of ports.
+
+use std::fmt::{self, Display, Formatter};
+use std::num::ParseIntError;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use serde::de::{self, Deserialize, Deserializer};
+use serde::{Serialize, Serializer};
+
+/// An inclusive range of non-zero ports specified as `<MIN>:<MAX>`.
+#[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+pub struct PortRange {
+    min: u16,
+    max: u16,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Fail)]
+pub enum ParsePortRangeError {
+    #[fail(display = "A port range must be specified as <MIN>:<MAX>")]
+    InvalidFormat,
+
+    #[fail(display = "{}", _0)]
+    InvalidPort(#[fail(cause)] ParseIntError),
+
+    #[fail(display = "Ports of a range must be positive")]
+    ZeroPort,
+
+    #[fail(display = "The minimum port of a range cannot exceed the maximum one")]
+    Reversed,
+}
+
+impl PortRange {
+    pub fn ports(&self) -> RangeInclusive<u16> {
+        self.min..=self.max
+    }
+}
+
+/// Formats a range as `<MIN>:<MAX>`, so the result can be parsed back by
+/// `PortRange::from_str`.
+impl Display for PortRange {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{min}:{max}", min = self.min, max = self.max)
+    }
+}
+
+impl Serialize for PortRange {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for PortRange {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl FromStr for PortRange {
+    type Err = ParsePortRangeError;
+
+    fn from_str(format: &str) -> Result<Self, ParsePortRangeError> {
+        let ports = format.split(':').collect::<Vec<&str>>();
+        if ports.len() != 2 {
+            return Err(ParsePortRangeError::InvalidFormat);
+        }
+
+        let min = ports[0]
+            .parse::<u16>()
+            .map_err(ParsePortRangeError::InvalidPort)?;
+        let max = ports[1]
+            .parse::<u16>()
+            .map_err(ParsePortRangeError::InvalidPort)?;
+
+        if min == 0 {
+            Err(ParsePortRangeError::ZeroPort)
+        } else if min > max {
+            Err(ParsePortRangeError::Reversed)
+        } else {
+            Ok(PortRange { min, max })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_valid_ranges() {
+        let range = "8000:8003".parse::<PortRange>().unwrap();
+        assert_eq!(
+            range.ports().collect::<Vec<u16>>(),
+            vec![8000, 8001, 8002, 8003]
+        );
+        assert_eq!(range.to_string(), "8000:8003");
+
+        assert_eq!("443:443".parse::<PortRange>().unwrap().ports().count(), 1);
+    }
+
+    #[test]
+    fn rejects_invalid_ranges() {
+        assert_eq!(
+            "8000".parse::<PortRange>(),
+            Err(ParsePortRangeError::InvalidFormat)
+        );
+        assert_eq!(
+            "1:2:3".parse::<PortRange>(),
+            Err(ParsePortRangeError::InvalidFormat)
+        );
+        assert_eq!(
+            "0:10".parse::<PortRange>(),
+            Err(ParsePortRangeError::ZeroPort)
+        );
+        assert_eq!(
+            "10:9".parse::<PortRange>(),
+            Err(ParsePortRangeError::Reversed)
+        );
+
+        match "1:65536".parse::<PortRange>() {
+            Err(ParsePortRangeError::InvalidPort(_)) => (),
+            _ => panic!("ParsePortRangeError::InvalidPort must be returned"),
+        }
+    }
+}
@@ -0,0 +1,333 @@
+// anevicon: A high-performant UDP-based load generator, written in Rust.
+// Copyright (C) 2019  Temirkhan Myrzamadi <gymmasssorla@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// For more information see <https://github.com/Gymmasssorla/anevicon>.
+
+//! Subnets used by `--allow-subnet`, `--deny-subnet`, and `--safe` to
+//! validate receivers before a test execution.
+
+use std::fmt::{self, Display, Formatter};
+use std::net::{AddrParseError, IpAddr, Ipv4Addr, Ipv6Addr};
+use std::num::ParseIntError;
+use std::str::FromStr;
+
+use serde::de::{self, Deserialize, Deserializer};
+use serde::{Serialize, Serializer};
+
+/// An IPv4/IPv6 subnet specified in the CIDR notation (`<IP>/<PREFIX>`).
+#[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+pub struct Subnet {
+    address: IpAddr,
+    prefix: u8,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Fail)]
+pub enum ParseSubnetError {
+    #[fail(display = "A subnet must be specified as <IP>/<PREFIX>")]
+    InvalidFormat,
+
+    #[fail(display = "{}", _0)]
+    InvalidAddress(#[fail(cause)] AddrParseError),
+
+    #[fail(display = "{}", _0)]
+    InvalidPrefix(#[fail(cause)] ParseIntError),
+
+    #[fail(display = "A prefix length cannot exceed {} bits", _0)]
+    PrefixTooLong(u8),
+}
+
+/// The reason why a receiver isn't allowed to be tested.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SubnetViolation {
+    /// The receiver belongs to the subnet specified by `--deny-subnet`.
+    Denied(Subnet),
+
+    /// The receiver doesn't belong to any subnet specified by
+    /// `--allow-subnet`.
+    NotAllowed,
+
+    /// The receiver belongs to the subnet denied by `--safe`.
+    Reserved(Subnet),
+}
+
+/// The subnets denied by `--safe` unless they're explicitly allowed: loopback,
+/// multicast, and limited broadcast addresses.
+const RESERVED_SUBNETS: [(IpAddr, u8); 5] = [
+    (IpAddr::V4(Ipv4Addr::new(127, 0, 0, 0)), 8),
+    (IpAddr::V4(Ipv4Addr::new(224, 0, 0, 0)), 4),
+    (IpAddr::V4(Ipv4Addr::BROADCAST), 32),
+    (IpAddr::V6(Ipv6Addr::LOCALHOST), 128),
+    (IpAddr::V6(Ipv6Addr::new(0xff00, 0, 0, 0, 0, 0, 0, 0)), 8),
+];
+
+/// The subnets that aren't routed over the Internet: private (RFC 1918 and
+/// IPv6 unique local), loopback, and link-local addresses.
+const LOCAL_SUBNETS: [(IpAddr, u8); 8] = [
+    (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 0)), 8),
+    (IpAddr::V4(Ipv4Addr::new(172, 16, 0, 0)), 12),
+    (IpAddr::V4(Ipv4Addr::new(192, 168, 0, 0)), 16),
+    (IpAddr::V4(Ipv4Addr::new(127, 0, 0, 0)), 8),
+    (IpAddr::V4(Ipv4Addr::new(169, 254, 0, 0)), 16),
+    (IpAddr::V6(Ipv6Addr::LOCALHOST), 128),
+    (IpAddr::V6(Ipv6Addr::new(0xfc00, 0, 0, 0, 0, 0, 0, 0)), 7),
+    (IpAddr::V6(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 0)), 10),
+];
+
+impl Subnet {
+    /// Returns `true` if `ip` belongs to the current subnet. Addresses of
+    /// different families never match each other.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.address, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                matches_prefix(&network.octets(), &ip.octets(), self.prefix)
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                matches_prefix(&network.octets(), &ip.octets(), self.prefix)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Checks that `receiver` may be tested: it mustn't belong to any of `denied`,
+/// it must belong to one of `allowed` if they're specified, and, if `safe` is
+/// `true`, it mustn't belong to the reserved subnets unless it's explicitly
+/// allowed.
+pub fn check_receiver(
+    receiver: IpAddr,
+    allowed: &[Subnet],
+    denied: &[Subnet],
+    safe: bool,
+) -> Result<(), SubnetViolation> {
+    if let Some(subnet) = denied.iter().find(|subnet| subnet.contains(receiver)) {
+        return Err(SubnetViolation::Denied(*subnet));
+    }
+
+    let explicitly_allowed = allowed.iter().any(|subnet| subnet.contains(receiver));
+    if !allowed.is_empty() && !explicitly_allowed {
+        return Err(SubnetViolation::NotAllowed);
+    }
+
+    if safe && !explicitly_allowed {
+        if let Some(subnet) = RESERVED_SUBNETS
+            .iter()
+            .map(|&(address, prefix)| Subnet { address, prefix })
+            .find(|subnet| subnet.contains(receiver))
+        {
+            return Err(SubnetViolation::Reserved(subnet));
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns `true` if `receiver` doesn't belong to any private, loopback, or
+/// link-local subnet, so packets sent to it might reach public
+/// infrastructure.
+pub fn is_globally_routable(receiver: IpAddr) -> bool {
+    !LOCAL_SUBNETS
+        .iter()
+        .map(|&(address, prefix)| Subnet { address, prefix })
+        .any(|subnet| subnet.contains(receiver))
+}
+
+fn matches_prefix(network: &[u8], ip: &[u8], prefix: u8) -> bool {
+    let (bytes, bits) = (usize::from(prefix / 8), prefix % 8);
+
+    if network[..bytes] != ip[..bytes] {
+        return false;
+    }
+    if bits == 0 {
+        return true;
+    }
+
+    let mask = !0u8 << (8 - bits);
+    network[bytes] & mask == ip[bytes] & mask
+}
+
+/// Formats a subnet as `<IP>/<PREFIX>`, so the result can be parsed back by
+/// `Subnet::from_str`.
+impl Display for Subnet {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{address}/{prefix}",
+            address = self.address,
+            prefix = self.prefix
+        )
+    }
+}
+
+impl Display for SubnetViolation {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            SubnetViolation::Denied(subnet) => write!(f, "belongs to --deny-subnet={}", subnet),
+            SubnetViolation::NotAllowed => write!(f, "doesn't belong to any --allow-subnet"),
+            SubnetViolation::Reserved(subnet) => write!(
+                f,
+                "belongs to the reserved subnet {} denied by --safe (see --allow-subnet)",
+                subnet
+            ),
+        }
+    }
+}
+
+impl Serialize for Subnet {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Subnet {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl FromStr for Subnet {
+    type Err = ParseSubnetError;
+
+    fn from_str(format: &str) -> Result<Self, ParseSubnetError> {
+        let parts = format.split('/').collect::<Vec<&str>>();
+        if parts.len() != 2 {
+            return Err(ParseSubnetError::InvalidFormat);
+        }
+
+        let address = parts[0]
+            .parse::<IpAddr>()
+            .map_err(ParseSubnetError::InvalidAddress)?;
+        let prefix = parts[1]
+            .parse::<u8>()
+            .map_err(ParseSubnetError::InvalidPrefix)?;
+
+        let max_prefix = match address {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        if prefix > max_prefix {
+            return Err(ParseSubnetError::PrefixTooLong(max_prefix));
+        }
+
+        Ok(Subnet { address, prefix })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subnet(format: &str) -> Subnet {
+        format.parse().expect("Failed to parse a subnet")
+    }
+
+    fn ip(format: &str) -> IpAddr {
+        format.parse().expect("Failed to parse an IP address")
+    }
+
+    #[test]
+    fn parses_subnets() {
+        assert_eq!(subnet("10.0.0.0/8").to_string(), "10.0.0.0/8");
+        assert_eq!(subnet("2001:db8::/32").to_string(), "2001:db8::/32");
+
+        assert_eq!(
+            "10.0.0.0".parse::<Subnet>(),
+            Err(ParseSubnetError::InvalidFormat)
+        );
+        assert_eq!(
+            "10.0.0.0/33".parse::<Subnet>(),
+            Err(ParseSubnetError::PrefixTooLong(32))
+        );
+        assert_eq!(
+            "::/129".parse::<Subnet>(),
+            Err(ParseSubnetError::PrefixTooLong(128))
+        );
+
+        match "10.0.0/8".parse::<Subnet>() {
+            Err(ParseSubnetError::InvalidAddress(_)) => (),
+            _ => panic!("ParseSubnetError::InvalidAddress must be returned"),
+        }
+        match "10.0.0.0/x".parse::<Subnet>() {
+            Err(ParseSubnetError::InvalidPrefix(_)) => (),
+            _ => panic!("ParseSubnetError::InvalidPrefix must be returned"),
+        }
+    }
+
+    #[test]
+    fn matches_addresses() {
+        assert!(subnet("192.168.0.0/16").contains(ip("192.168.255.1")));
+        assert!(!subnet("192.168.0.0/16").contains(ip("192.169.0.1")));
+        assert!(subnet("10.0.0.0/13").contains(ip("10.7.255.255")));
+        assert!(!subnet("10.0.0.0/13").contains(ip("10.8.0.0")));
+        assert!(subnet("0.0.0.0/0").contains(ip("8.8.8.8")));
+        assert!(subnet("1.2.3.4/32").contains(ip("1.2.3.4")));
+        assert!(subnet("2001:db8::/32").contains(ip("2001:db8:ffff::1")));
+
+        // Different families never match
+        assert!(!subnet("0.0.0.0/0").contains(ip("::1")));
+    }
+
+    #[test]
+    fn checks_receivers() {
+        let allowed = [subnet("10.0.0.0/8"), subnet("127.0.0.1/32")];
+        let denied = [subnet("10.1.0.0/16")];
+
+        assert_eq!(
+            check_receiver(ip("10.2.0.1"), &allowed, &denied, true),
+            Ok(())
+        );
+        assert_eq!(
+            check_receiver(ip("10.1.0.1"), &allowed, &denied, false),
+            Err(SubnetViolation::Denied(denied[0]))
+        );
+        assert_eq!(
+            check_receiver(ip("8.8.8.8"), &allowed, &denied, false),
+            Err(SubnetViolation::NotAllowed)
+        );
+
+        // Reserved subnets are denied only by `--safe` and can be allowed
+        assert_eq!(check_receiver(ip("127.0.0.2"), &[], &[], false), Ok(()));
+        assert_eq!(
+            check_receiver(ip("127.0.0.2"), &[], &[], true),
+            Err(SubnetViolation::Reserved(subnet("127.0.0.0/8")))
+        );
+        assert_eq!(check_receiver(ip("127.0.0.1"), &allowed, &[], true), Ok(()));
+        assert_eq!(
+            check_receiver(ip("ff02::1"), &[], &[], true),
+            Err(SubnetViolation::Reserved(subnet("ff00::/8")))
+        );
+    }
+
+    #[test]
+    fn classifies_routable_receivers() {
+        for local in &[
+            "10.1.2.3",
+            "172.31.255.255",
+            "192.168.0.1",
+            "127.0.0.1",
+            "169.254.1.1",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+        ] {
+            assert!(!is_globally_routable(ip(local)), "{} is local", local);
+        }
+        for global in &["8.8.8.8", "172.32.0.1", "93.184.216.34", "2001:db8::1"] {
+            assert!(is_globally_routable(ip(global)), "{} is global", global);
+        }
+    }
+}
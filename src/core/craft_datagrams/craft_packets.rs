@@ -20,16 +20,75 @@
 
 use etherparse::PacketBuilder;
 
-use crate::config::{Endpoints, EndpointsV4, EndpointsV6};
+use crate::config::{Endpoints, EndpointsV4, EndpointsV6, IpOption};
 
-pub fn ip_udp_packet(endpoints: &Endpoints, payload: &[u8], time_to_live: u8) -> Vec<u8> {
+use super::UDP_HEADER_LEN;
+
+const IPV4_HEADER_LEN: usize = 20;
+const IPV6_HEADER_LEN: usize = 40;
+
+/// The largest number of bytes of IPv4 options: the header length field
+/// counts 32-bit words up to 15, so a header takes at most 60 bytes.
+const MAX_IPV4_OPTIONS_LEN: usize = 40;
+
+/// The protocol number of UDP, also known as the next header value.
+const UDP_PROTOCOL: u32 = 17;
+
+/// The largest flow label fitting into its 20 bits of an IPv6 header.
+pub const MAX_FLOW_LABEL: u32 = 0xF_FFFF;
+
+/// Constructs a whole IP/UDP datagram carrying `payload`, see `ip_udp_header`.
+pub fn ip_udp_packet(
+    endpoints: &Endpoints,
+    payload: &[u8],
+    time_to_live: u8,
+    identification: u16,
+    flow_label: u32,
+) -> Vec<u8> {
     match endpoints {
-        Endpoints::V4(endpoints_v4) => ipv4_udp_packet(endpoints_v4, payload, time_to_live),
-        Endpoints::V6(endpoints_v6) => ipv6_udp_packet(endpoints_v6, payload, time_to_live),
+        Endpoints::V4(endpoints_v4) => {
+            ipv4_udp_packet(endpoints_v4, payload, time_to_live, identification)
+        }
+        Endpoints::V6(endpoints_v6) => {
+            ipv6_udp_packet(endpoints_v6, payload, time_to_live, flow_label)
+        }
     }
 }
 
-fn ipv4_udp_packet(endpoints: &EndpointsV4, payload: &[u8], time_to_live: u8) -> Vec<u8> {
+/// Returns a number of bytes of the IP and UDP headers constructed by
+/// `ip_udp_header` for `endpoints`, including `ip_option` appended by
+/// `append_ipv4_option` to an IPv4 header.
+pub fn header_len(endpoints: &Endpoints, ip_option: Option<IpOption>) -> usize {
+    match endpoints {
+        Endpoints::V4(_) if ip_option.is_some() => {
+            IPV4_HEADER_LEN + MAX_IPV4_OPTIONS_LEN + UDP_HEADER_LEN
+        }
+        Endpoints::V4(_) => IPV4_HEADER_LEN + UDP_HEADER_LEN,
+        Endpoints::V6(_) => IPV6_HEADER_LEN + UDP_HEADER_LEN,
+    }
+}
+
+/// Constructs only the IP and UDP headers of a datagram carrying `payload`
+/// (the UDP checksum covers `payload` too). `identification` is written only
+/// into an IPv4 header and `flow_label` only into an IPv6 header, since the
+/// other versions don't have such fields.
+pub fn ip_udp_header(
+    endpoints: &Endpoints,
+    payload: &[u8],
+    time_to_live: u8,
+    identification: u16,
+    flow_label: u32,
+) -> Vec<u8> {
+    let packet = ip_udp_packet(endpoints, payload, time_to_live, identification, flow_label);
+    packet[..packet.len() - payload.len()].to_vec()
+}
+
+fn ipv4_udp_packet(
+    endpoints: &EndpointsV4,
+    payload: &[u8],
+    time_to_live: u8,
+    identification: u16,
+) -> Vec<u8> {
     let builder = PacketBuilder::ipv4(
         endpoints.sender.ip().octets(),
         endpoints.receiver.ip().octets(),
@@ -40,10 +99,144 @@ fn ipv4_udp_packet(endpoints: &EndpointsV4, payload: &[u8], time_to_live: u8) ->
     builder
         .write(&mut serialized, payload)
         .expect("Failed to serialize a UDP/IPv4 packet into Vec<u8>");
+
+    // `PacketBuilder` always writes the zero identification, which is replaced
+    // by the kernel, so set it by ourselves only if it's required
+    if identification != 0 {
+        set_ipv4_identification(&mut serialized, identification);
+    }
     serialized
 }
 
-fn ipv6_udp_packet(endpoints: &EndpointsV6, payload: &[u8], time_to_live: u8) -> Vec<u8> {
+/// Overwrites the identification field of the IPv4 header at the beginning of
+/// `packet` and updates the header checksum accordingly. The UDP checksum
+/// doesn't cover this field, so it remains valid.
+fn set_ipv4_identification(packet: &mut [u8], identification: u16) {
+    packet[4..6].copy_from_slice(&identification.to_be_bytes());
+    update_ipv4_checksum(packet);
+}
+
+/// Sets the reserved (the highest) bit of the flags field of the IPv4 header
+/// at the beginning of `packet` and updates the header checksum accordingly.
+/// The bit must be zero (RFC 791), so such packets are malformed (RFC 3514
+/// calls it "the evil bit").
+pub fn set_ipv4_reserved_flag(packet: &mut [u8]) {
+    packet[6] |= 0x80;
+    update_ipv4_checksum(packet);
+}
+
+/// Appends `option` to the IPv4 header (without options) at the beginning of
+/// `packet`, updating the header length, the total length, and the header
+/// checksum. The UDP checksum doesn't cover the IPv4 header, so it remains
+/// valid.
+///
+/// Each option takes all the 40 bytes available, so the header length becomes
+/// 15 words (60 bytes). Record Route (RFC 791) has room for nine addresses
+/// and is padded by the End of Option List byte, and Timestamp (RFC 791) has
+/// room for nine timestamps without addresses. Routers fill the empty slots
+/// starting from the pointer.
+pub fn append_ipv4_option(packet: &mut Vec<u8>, option: IpOption) {
+    let mut bytes = [0u8; MAX_IPV4_OPTIONS_LEN];
+    match option {
+        // Type, length, pointer, then nine addresses
+        IpOption::RecordRoute => bytes[..3].copy_from_slice(&[7, 39, 4]),
+        // Type, length, pointer, overflow and flags, then nine timestamps
+        IpOption::Timestamp => bytes[..4].copy_from_slice(&[68, 40, 5, 0]),
+    }
+
+    packet.splice(IPV4_HEADER_LEN..IPV4_HEADER_LEN, bytes.iter().copied());
+    packet[0] = 0x40 | ((IPV4_HEADER_LEN + MAX_IPV4_OPTIONS_LEN) / 4) as u8;
+
+    let total_length = u16::from_be_bytes([packet[2], packet[3]])
+        .checked_add(MAX_IPV4_OPTIONS_LEN as u16)
+        .expect("A payload must leave room for IPv4 options, see check_size_with_ip_option");
+    packet[2..4].copy_from_slice(&total_length.to_be_bytes());
+    update_ipv4_checksum(packet);
+}
+
+/// Recomputes the header checksum of the IPv4 header (including options) at
+/// the beginning of `packet`.
+fn update_ipv4_checksum(packet: &mut [u8]) {
+    packet[10..12].copy_from_slice(&[0, 0]);
+
+    let header_len = usize::from(packet[0] & 0x0F) * 4;
+    let checksum = ipv4_header_checksum(&packet[..header_len]);
+    packet[10..12].copy_from_slice(&checksum.to_be_bytes());
+}
+
+/// Computes the one's complement of the one's complement sum of all the
+/// 16-bit words of `header` (RFC 791).
+fn ipv4_header_checksum(header: &[u8]) -> u16 {
+    fold_checksum(sum_words(header))
+}
+
+/// Updates the IP and UDP headers of a datagram (`header`, as constructed by
+/// `ip_udp_header`) so they describe `payload` instead of the original one:
+/// the length fields, the IPv4 header checksum, and the UDP checksum.
+pub fn replace_payload(header: &mut [u8], payload: &[u8]) {
+    let udp_offset = header.len() - UDP_HEADER_LEN;
+    let udp_length = (UDP_HEADER_LEN + payload.len()) as u16;
+
+    // The pseudo header covered by the UDP checksum consists of the addresses,
+    // the protocol number, and the UDP length (RFC 768, RFC 8200)
+    let addresses = if header[0] >> 4 == 4 {
+        let total_length = (udp_offset as u16)
+            .checked_add(udp_length)
+            .expect("A payload must fit the IPv4 total length, see check_size_with_ip_option");
+        header[2..4].copy_from_slice(&total_length.to_be_bytes());
+        update_ipv4_checksum(header);
+        12..20
+    } else {
+        header[4..6].copy_from_slice(&udp_length.to_be_bytes());
+        8..40
+    };
+
+    let udp_header = &mut header[udp_offset..];
+    udp_header[4..6].copy_from_slice(&udp_length.to_be_bytes());
+    udp_header[6..8].copy_from_slice(&[0, 0]);
+
+    let sum = sum_words(&header[addresses])
+        + UDP_PROTOCOL
+        + u32::from(udp_length)
+        + sum_words(&header[udp_offset..])
+        + sum_words(payload);
+
+    // The zero checksum means that there is no checksum at all, so it's
+    // transmitted as all ones instead
+    let checksum = match fold_checksum(sum) {
+        0 => 0xFFFF,
+        checksum => checksum,
+    };
+    header[udp_offset + 6..udp_offset + 8].copy_from_slice(&checksum.to_be_bytes());
+}
+
+/// Sums up all the 16-bit big-endian words of `bytes`, padding the last odd
+/// byte with zero.
+fn sum_words(bytes: &[u8]) -> u32 {
+    bytes
+        .chunks(2)
+        .map(|word| u32::from(u16::from_be_bytes([word[0], *word.get(1).unwrap_or(&0)])))
+        .fold(0u32, u32::wrapping_add)
+}
+
+/// Folds the carries of `sum` into its lower 16 bits and returns the one's
+/// complement of the result.
+fn fold_checksum(mut sum: u32) -> u16 {
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+
+    !(sum as u16)
+}
+
+/// Constructs a UDP/IPv6 packet. Only the lower 20 bits of `flow_label` are
+/// used.
+fn ipv6_udp_packet(
+    endpoints: &EndpointsV6,
+    payload: &[u8],
+    time_to_live: u8,
+    flow_label: u32,
+) -> Vec<u8> {
     let builder = PacketBuilder::ipv6(
         endpoints.sender.ip().octets(),
         endpoints.receiver.ip().octets(),
@@ -54,6 +247,14 @@ fn ipv6_udp_packet(endpoints: &EndpointsV6, payload: &[u8], time_to_live: u8) ->
     builder
         .write(&mut serialized, payload)
         .expect("Failed to serialize a UDP/IPv6 packet into Vec<u8>");
+
+    // The flow label occupies the lower 20 bits of the first 32-bit word right
+    // after the version and the traffic class, and isn't covered by the UDP
+    // checksum
+    let first_word =
+        u32::from_be_bytes([serialized[0], serialized[1], serialized[2], serialized[3]]);
+    let first_word = (first_word & !MAX_FLOW_LABEL) | (flow_label & MAX_FLOW_LABEL);
+    serialized[..4].copy_from_slice(&first_word.to_be_bytes());
     serialized
 }
 
@@ -63,6 +264,117 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn constructs_only_headers() {
+        let endpoints = Endpoints::V4(EndpointsV4 {
+            sender: SocketAddrV4::new(Ipv4Addr::BROADCAST, 3838),
+            receiver: SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 17172),
+        });
+        let payload = b"I wanna hold you in my arms, yeah";
+
+        let packet = ip_udp_packet(&endpoints, payload, 9, 0, 0);
+        assert_eq!(ip_udp_header(&endpoints, payload, 9, 0, 0), &packet[..28]);
+    }
+
+    #[test]
+    fn sets_ipv4_identification() {
+        let endpoints = EndpointsV4 {
+            sender: SocketAddrV4::new(Ipv4Addr::new(53, 76, 0, 112), 3838),
+            receiver: SocketAddrV4::new(Ipv4Addr::new(84, 10, 8, 81), 17172),
+        };
+        let payload = b"Havin' a nervous breakdown, a-drive me insane, yeah";
+
+        let original = ipv4_udp_packet(&endpoints, payload, 134, 0);
+        let packet = ipv4_udp_packet(&endpoints, payload, 134, 0xBEEF);
+
+        assert_eq!(&packet[4..6], &[0xBE, 0xEF]);
+        assert_ne!(&packet[10..12], &original[10..12]);
+
+        // A valid header sums up to zero together with its checksum
+        assert_eq!(ipv4_header_checksum(&packet[..IPV4_HEADER_LEN]), 0);
+        assert_eq!(ipv4_header_checksum(&original[..IPV4_HEADER_LEN]), 0);
+
+        // Only the identification and the header checksum have been changed
+        assert_eq!(&packet[..4], &original[..4]);
+        assert_eq!(&packet[6..10], &original[6..10]);
+        assert_eq!(&packet[12..], &original[12..]);
+    }
+
+    #[test]
+    fn sets_ipv4_reserved_flag() {
+        let endpoints = EndpointsV4 {
+            sender: SocketAddrV4::new(Ipv4Addr::new(53, 76, 0, 112), 3838),
+            receiver: SocketAddrV4::new(Ipv4Addr::new(84, 10, 8, 81), 17172),
+        };
+        let payload = b"Evil";
+
+        let original = ipv4_udp_packet(&endpoints, payload, 64, 0);
+        let mut packet = original.clone();
+        set_ipv4_reserved_flag(&mut packet);
+
+        // Only the highest bit of the flags is set, the DF bit is kept
+        assert_eq!(packet[6], original[6] | 0x80);
+        assert_eq!(packet[6] & 0x7F, original[6]);
+        assert_eq!(ipv4_header_checksum(&packet[..IPV4_HEADER_LEN]), 0);
+
+        assert_eq!(&packet[..6], &original[..6]);
+        assert_eq!(&packet[7..10], &original[7..10]);
+        assert_eq!(&packet[12..], &original[12..]);
+    }
+
+    #[test]
+    fn appends_ipv4_options() {
+        let endpoints = EndpointsV4 {
+            sender: SocketAddrV4::new(Ipv4Addr::new(53, 76, 0, 112), 3838),
+            receiver: SocketAddrV4::new(Ipv4Addr::new(84, 10, 8, 81), 17172),
+        };
+        let payload = b"Where have you been?";
+        let original = ipv4_udp_packet(&endpoints, payload, 64, 0);
+
+        for &(option, prefix) in &[
+            (IpOption::RecordRoute, &[7u8, 39, 4][..]),
+            (IpOption::Timestamp, &[68u8, 40, 5, 0][..]),
+        ] {
+            let mut packet = original.clone();
+            append_ipv4_option(&mut packet, option);
+
+            // The header length is 15 words, and the total length grows by 40
+            assert_eq!(packet.len(), original.len() + 40);
+            assert_eq!(packet[0], 0x4F);
+            assert_eq!(
+                u16::from_be_bytes([packet[2], packet[3]]) as usize,
+                packet.len()
+            );
+            assert_eq!(ipv4_header_checksum(&packet[..60]), 0);
+
+            assert_eq!(&packet[20..20 + prefix.len()], prefix);
+            assert!(packet[20 + prefix.len()..60].iter().all(|&byte| byte == 0));
+
+            // The UDP header and payload follow the options unchanged
+            assert_eq!(&packet[60..], &original[20..]);
+        }
+    }
+
+    #[test]
+    fn sets_ipv6_flow_label() {
+        let endpoints = EndpointsV6 {
+            sender: SocketAddrV6::new(Ipv6Addr::LOCALHOST, 18273, 0, 0),
+            receiver: SocketAddrV6::new(Ipv6Addr::LOCALHOST, 9492, 0, 0),
+        };
+        let payload = b"Communication breakdown, it's always the same";
+
+        let original = ipv6_udp_packet(&endpoints, payload, 61, 0);
+        let packet = ipv6_udp_packet(&endpoints, payload, 61, 0xABCDE);
+        assert_eq!(&packet[..4], &[0x60, 0x0A, 0xBC, 0xDE]);
+        assert_eq!(&packet[4..], &original[4..]);
+
+        // The bits above 20 are ignored
+        assert_eq!(
+            ipv6_udp_packet(&endpoints, payload, 61, !0)[..4],
+            [0x60, 0x0F, 0xFF, 0xFF]
+        );
+    }
+
     #[test]
     fn test_construct_ipv4_first() {
         let packet = ipv4_udp_packet(
@@ -72,6 +384,7 @@ mod tests {
             },
             b"I wanna hold you in my arms, yeah",
             9,
+            0,
         );
 
         assert_eq!(
@@ -94,6 +407,7 @@ mod tests {
             },
             b"Havin' a nervous breakdown, a-drive me insane, yeah",
             134,
+            0,
         );
 
         assert_eq!(
@@ -117,6 +431,7 @@ mod tests {
             },
             b"Communication breakdown, it's always the same",
             61,
+            0,
         );
 
         assert_eq!(
@@ -145,6 +460,7 @@ mod tests {
             },
             b"I wanna hold you in my arms, yeah",
             250,
+            0,
         );
 
         assert_eq!(
@@ -157,4 +473,32 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn replaces_payload() {
+        let endpoints = [
+            Endpoints::V4(EndpointsV4 {
+                sender: SocketAddrV4::new(Ipv4Addr::new(53, 76, 0, 112), 3838),
+                receiver: SocketAddrV4::new(Ipv4Addr::new(84, 10, 8, 81), 17172),
+            }),
+            Endpoints::V6(EndpointsV6 {
+                sender: SocketAddrV6::new(Ipv6Addr::LOCALHOST, 3838, 0, 0),
+                receiver: SocketAddrV6::new(
+                    Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 7),
+                    17172,
+                    0,
+                    0,
+                ),
+            }),
+        ];
+
+        for endpoints in &endpoints {
+            let mut header = ip_udp_header(endpoints, b"Short", 64, 0, 0);
+
+            // An odd length checks the padding of the UDP checksum
+            let payload = b"A much longer payload of an odd length";
+            replace_payload(&mut header, payload);
+            assert_eq!(header, ip_udp_header(endpoints, payload, 64, 0, 0));
+        }
+    }
 }
@@ -18,59 +18,399 @@
 
 //! This file is used to construct user's payload.
 
-use std::cell::RefCell;
+use std::fmt::{self, Display, Formatter};
 use std::fs;
 use std::io;
 use std::num::NonZeroUsize;
-use std::path::Path;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
 
 use failure::Fallible;
-use rand::rngs::ThreadRng;
 use rand::Rng;
 
-use crate::config::PayloadConfig;
+use crate::config::{Endianness, EntropyThreshold, PayloadConfig};
+use crate::core::random;
+
+/// The largest payload that fits into a single UDP/IPv4 datagram: 65535 bytes
+/// of the IPv4 total length minus 20 bytes of IPv4 and 8 bytes of UDP headers.
+pub const MAX_PAYLOAD_SIZE: usize = 65507;
+
+/// The largest payload that fits into a single UDP/IPv4 datagram carrying 40
+/// bytes of IPv4 options (`--ip-option`).
+pub const MAX_PAYLOAD_SIZE_WITH_IP_OPTION: usize = MAX_PAYLOAD_SIZE - 40;
+
+/// The content of a single payload. It's either stored in memory or, if
+/// `--mmap-files` is specified, memory-mapped from a file. The output of
+/// `--send-command` remembers its command, so it can be run again by
+/// `--send-command-each`.
+///
+/// A mapped file must outlive the test: modifying or truncating it while the
+/// test is running leads to sending garbage or even to `SIGBUS`.
+#[derive(Debug)]
+pub enum Payload {
+    Owned(Vec<u8>),
+
+    Command {
+        output: Vec<u8>,
+        command: String,
+    },
+
+    #[cfg(feature = "mmap")]
+    Mapped(memmap2::Mmap),
+}
+
+impl Deref for Payload {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Payload::Owned(content) => content,
+            Payload::Command { output, .. } => output,
+            #[cfg(feature = "mmap")]
+            Payload::Mapped(content) => content,
+        }
+    }
+}
+
+impl Payload {
+    /// Returns the command which has printed this payload (`--send-command`), if any.
+    pub fn command(&self) -> Option<&str> {
+        match self {
+            Payload::Command { command, .. } => Some(command),
+            _ => None,
+        }
+    }
+}
+
+impl PartialEq for Payload {
+    fn eq(&self, other: &Payload) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for Payload {}
+
+impl PartialEq<Vec<u8>> for Payload {
+    fn eq(&self, other: &Vec<u8>) -> bool {
+        **self == **other
+    }
+}
+
+/// A source of a single payload specified by a user.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum PayloadSource<'a> {
+    Message(&'a str),
+    File(PathBuf),
+    Random(NonZeroUsize),
+    Command(&'a str),
+}
+
+impl<'a> Display for PayloadSource<'a> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            PayloadSource::Message(message) => write!(f, "--send-message {:?}", message),
+            PayloadSource::File(path) => write!(f, "--send-file {:?}", path),
+            PayloadSource::Random(length) => write!(f, "--random-packet {}", length),
+            PayloadSource::Command(command) => write!(f, "--send-command {:?}", command),
+        }
+    }
+}
+
+/// Returns the sources of all the payloads in the same order as `craft_all`
+/// constructs them: messages, then files, then the files of directories, then
+/// random packets, then commands.
+pub fn sources(config: &PayloadConfig) -> Fallible<Vec<PayloadSource<'_>>> {
+    let mut sources = Vec::new();
+
+    sources.extend(
+        config
+            .send_messages
+            .iter()
+            .map(|message| PayloadSource::Message(message)),
+    );
+    sources.extend(
+        config
+            .send_files
+            .iter()
+            .map(|path| PayloadSource::File(path.clone())),
+    );
+    for directory in &config.send_dirs {
+        sources.extend(
+            list_directory(directory)?
+                .into_iter()
+                .map(PayloadSource::File),
+        );
+    }
+    sources.extend(
+        config
+            .random_packets
+            .iter()
+            .map(|&length| PayloadSource::Random(length)),
+    );
+    sources.extend(
+        config
+            .send_commands
+            .iter()
+            .map(|command| PayloadSource::Command(command)),
+    );
+
+    Ok(sources)
+}
+
+/// Returns the paths of the non-empty regular files of `directory` in sorted
+/// order. Subdirectories are skipped, and so are empty files (with a warning).
+fn list_directory(directory: &Path) -> Fallible<Vec<PathBuf>> {
+    let read_failed = |error| CraftPayloadError::ReadFailed {
+        source: error,
+        filename: directory.to_string_lossy().into_owned(),
+    };
+
+    let mut files = Vec::new();
+    for entry in fs::read_dir(directory).map_err(read_failed)? {
+        let path = entry.map_err(read_failed)?.path();
+        let metadata = fs::metadata(&path).map_err(read_failed)?;
+
+        if !metadata.is_file() {
+            continue;
+        }
+        if metadata.len() == 0 {
+            log::warn!("skipping the empty file {:?} of --send-dir!", path);
+            continue;
+        }
+
+        files.push(path);
+    }
+
+    if files.is_empty() {
+        return Err(CraftPayloadError::EmptyDirectory {
+            dirname: directory.to_string_lossy().into_owned(),
+        }
+        .into());
+    }
+
+    files.sort();
+    Ok(files)
+}
 
 /// Constructs a bytes packets from `PacketConfig`. Then it must be sent to all
 /// receivers multiple times.
 ///
 /// Note that this function constructs **ONLY** payload without
 /// protocol-specific headers and etc. Just payload that a user has specified by
-/// `--send-file`, `--send-message`, `--random-packet`.
-pub fn craft_all(config: &PayloadConfig) -> Fallible<Vec<Vec<u8>>> {
-    let mut packets = Vec::with_capacity(
-        config.send_messages.len() + config.send_files.len() + config.random_packets.len(),
-    );
+/// `--send-file`, `--send-message`, `--random-packet`, `--send-command`.
+///
+/// If `--warn-entropy` is specified, each payload whose entropy is below it is
+/// reported by a warning.
+pub fn craft_all(config: &PayloadConfig) -> Fallible<Vec<Payload>> {
+    sources(config)?
+        .into_iter()
+        .map(|source| {
+            let payload = craft_one(source.clone(), config)?;
+            if let Some(threshold) = config.warn_entropy {
+                warn_low_entropy(&source, &payload, threshold);
+            }
+            Ok(payload)
+        })
+        .collect()
+}
+
+fn warn_low_entropy(source: &PayloadSource, payload: &[u8], threshold: EntropyThreshold) {
+    let entropy = shannon_entropy(payload);
+
+    if entropy < threshold.bits_per_byte() {
+        log::warn!(
+            "the payload of {source} has an entropy of {entropy:.2} bits per byte, which is \
+             below --warn-entropy={threshold}, so a compressing link may carry it faster than \
+             it's sent!",
+            source = source,
+            entropy = entropy,
+            threshold = threshold,
+        );
+    }
+}
 
-    for message in &config.send_messages {
-        packets.push(message.as_bytes().to_owned());
+/// Constructs the single payload specified by `source`, taking into account
+/// `--mmap-files` and `--fill-pattern` of `config`.
+pub fn craft_one(source: PayloadSource, config: &PayloadConfig) -> Fallible<Payload> {
+    match source {
+        PayloadSource::Message(message) => {
+            check_size(message.len())?;
+            Ok(Payload::Owned(message.as_bytes().to_owned()))
+        }
+        PayloadSource::File(path) if config.mmap_files => map_payload(&path),
+        PayloadSource::File(path) => Ok(Payload::Owned(read_payload(&path, config.repeat_file)?)),
+        PayloadSource::Random(length) => {
+            check_size(length.get())?;
+            Ok(Payload::Owned(match config.fill_pattern {
+                Some(pattern) => {
+                    let mut buffer = vec![0u8; length.get()];
+                    pattern.fill(&mut buffer);
+                    buffer
+                }
+                None => random_payload(length),
+            }))
+        }
+        PayloadSource::Command(command) => Ok(Payload::Command {
+            output: run_command(command)?,
+            command: command.to_owned(),
+        }),
     }
+}
+
+/// Returns the size of the payload of `source` without constructing it, taking
+/// into account `--repeat-file` of `config`. The output of a command is
+/// unknown until it's run, so the largest payload is assumed for it, and so
+/// is for a payload which is too large to be crafted at all.
+pub fn estimated_size(source: &PayloadSource, config: &PayloadConfig) -> Fallible<usize> {
+    let size = match source {
+        PayloadSource::Message(message) => message.len() as u64,
+        PayloadSource::File(path) => fs::metadata(path)
+            .map_err(|error| CraftPayloadError::ReadFailed {
+                source: error,
+                filename: path.to_string_lossy().into_owned(),
+            })?
+            .len()
+            .saturating_mul(config.repeat_file.get() as u64),
+        PayloadSource::Random(length) => length.get() as u64,
+        PayloadSource::Command(_) => MAX_PAYLOAD_SIZE as u64,
+    };
+
+    Ok(size.min(MAX_PAYLOAD_SIZE as u64) as usize)
+}
+
+/// Runs `command` by `sh -c` and returns its standard output, which must be
+/// non-empty and fit into a single datagram. The standard error is inherited,
+/// so the messages of a failed command are visible to a user.
+pub fn run_command(command: &str) -> Result<Vec<u8>, CraftPayloadError> {
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|error| CraftPayloadError::CommandNotRun {
+            source: error,
+            command: command.to_owned(),
+        })?;
 
-    for file in &config.send_files {
-        packets.push(read_payload(file)?);
+    if !output.status.success() {
+        return Err(CraftPayloadError::CommandFailed {
+            command: command.to_owned(),
+            status: output.status,
+        });
     }
+    if output.stdout.is_empty() {
+        return Err(CraftPayloadError::EmptyCommandOutput {
+            command: command.to_owned(),
+        });
+    }
+
+    check_size(output.stdout.len())?;
+    Ok(output.stdout)
+}
 
-    for length in &config.random_packets {
-        packets.push(random_payload(*length));
+/// Returns `CraftPayloadError::TooLarge` if a payload of `size` bytes cannot
+/// be represented by the UDP length field.
+pub fn check_size(size: usize) -> Result<(), CraftPayloadError> {
+    if size > MAX_PAYLOAD_SIZE {
+        Err(CraftPayloadError::TooLarge { size })
+    } else {
+        Ok(())
     }
+}
 
-    Ok(packets)
+/// Returns `CraftPayloadError::TooLargeForIpOption` if a payload of `size`
+/// bytes cannot be represented by the IPv4 total length field together with
+/// `--ip-option`.
+pub fn check_size_with_ip_option(size: usize) -> Result<(), CraftPayloadError> {
+    if size > MAX_PAYLOAD_SIZE_WITH_IP_OPTION {
+        Err(CraftPayloadError::TooLargeForIpOption { size })
+    } else {
+        Ok(())
+    }
 }
 
 fn random_payload(length: NonZeroUsize) -> Vec<u8> {
-    thread_local! {
-        static PRNG: RefCell<ThreadRng> = RefCell::new(rand::thread_rng());
+    // Fill a zero-initialized buffer at once instead of generating each byte
+    // separately, without touching uninitialized memory
+    let mut buffer = vec![0u8; length.get()];
+    random::with(|generator| generator.fill(buffer.as_mut_slice()));
+    buffer
+}
+
+/// Returns the Shannon entropy of `bytes` in bits per byte, estimated from
+/// the frequencies of their values: from 0 for a single repeated value to 8
+/// for all the values occurring equally often.
+fn shannon_entropy(bytes: &[u8]) -> f64 {
+    let mut counts = [0usize; 256];
+    for &byte in bytes {
+        counts[byte as usize] += 1;
     }
 
-    let mut buffer = Vec::with_capacity(length.get());
-    PRNG.with(|generator| {
-        for _ in 0..length.get() {
-            buffer.push(generator.borrow_mut().gen::<u8>());
-        }
-    });
-    buffer
+    counts
+        .iter()
+        .filter(|&&count| count != 0)
+        .map(|&count| {
+            let probability = count as f64 / bytes.len() as f64;
+            probability * (1.0 / probability).log2()
+        })
+        .sum()
+}
+
+/// Flips `bits` distinct random bits of `bytes` (`--mutate-bits`), or all of
+/// them if `bytes` has fewer bits.
+pub fn flip_random_bits(bytes: &mut [u8], bits: NonZeroUsize) {
+    let total = bytes.len() * 8;
+
+    let sample =
+        random::with(|generator| rand::seq::index::sample(generator, total, bits.get().min(total)));
+    for bit in sample.into_iter() {
+        bytes[bit / 8] ^= 0x80 >> (bit % 8);
+    }
 }
 
-fn read_payload<P: AsRef<Path>>(path: P) -> Fallible<Vec<u8>> {
+/// Writes the lowest `width` bytes of `number` into `bytes` at `offset` in the
+/// specified byte order (`--sequence-number`).
+///
+/// Returns `CraftPayloadError::SequenceNumberOutside` if they don't fit.
+pub fn write_sequence_number(
+    bytes: &mut [u8],
+    number: u64,
+    offset: usize,
+    width: usize,
+    order: Endianness,
+) -> Result<(), CraftPayloadError> {
+    check_sequence_number(bytes.len(), offset, width)?;
+
+    let field = &mut bytes[offset..offset + width];
+    match order {
+        Endianness::Big => field.copy_from_slice(&number.to_be_bytes()[8 - width..]),
+        Endianness::Little => field.copy_from_slice(&number.to_le_bytes()[..width]),
+    }
+    Ok(())
+}
+
+/// Returns `CraftPayloadError::SequenceNumberOutside` if a counter of `width`
+/// bytes at `offset` doesn't fit into a payload of `size` bytes.
+pub fn check_sequence_number(
+    size: usize,
+    offset: usize,
+    width: usize,
+) -> Result<(), CraftPayloadError> {
+    match offset.checked_add(width) {
+        Some(end) if end <= size => Ok(()),
+        _ => Err(CraftPayloadError::SequenceNumberOutside {
+            offset,
+            width,
+            size,
+        }),
+    }
+}
+
+/// Reads the file located at `path` and repeats its content `repeat` times
+/// (`--repeat-file`), checking the size of the result.
+fn read_payload<P: AsRef<Path>>(path: P, repeat: NonZeroUsize) -> Fallible<Vec<u8>> {
     let content = fs::read(path.as_ref()).map_err(|error| CraftPayloadError::ReadFailed {
         source: error,
         filename: path
@@ -84,7 +424,42 @@ fn read_payload<P: AsRef<Path>>(path: P) -> Fallible<Vec<u8>> {
         return Err(CraftPayloadError::ZeroSize.into());
     }
 
-    Ok(content)
+    // Check the size before repeating, so a huge `--repeat-file` doesn't
+    // allocate anything
+    check_size(content.len().saturating_mul(repeat.get()))?;
+    Ok(content.repeat(repeat.get()))
+}
+
+/// Maps the file located at `path` into memory, so its content is paged in
+/// lazily by the kernel instead of being read at once.
+#[cfg(feature = "mmap")]
+fn map_payload(path: &Path) -> Fallible<Payload> {
+    use std::convert::TryFrom;
+
+    let read_failed = |error| CraftPayloadError::ReadFailed {
+        source: error,
+        filename: path.to_string_lossy().into_owned(),
+    };
+
+    let file = fs::File::open(path).map_err(read_failed)?;
+    let size = file.metadata().map_err(read_failed)?.len();
+
+    // Check the size before mapping the file, because an empty file cannot be
+    // mapped at all
+    if size == 0 {
+        return Err(CraftPayloadError::ZeroSize.into());
+    }
+    check_size(usize::try_from(size).unwrap_or(usize::MAX))?;
+
+    // It's safe as long as the file isn't modified during the test, which is
+    // documented in `--mmap-files`
+    let content = unsafe { memmap2::Mmap::map(&file) }.map_err(read_failed)?;
+    Ok(Payload::Mapped(content))
+}
+
+#[cfg(not(feature = "mmap"))]
+fn map_payload(_path: &Path) -> Fallible<Payload> {
+    Err(CraftPayloadError::MmapUnsupported.into())
 }
 
 #[derive(Debug, Fail)]
@@ -92,12 +467,62 @@ pub enum CraftPayloadError {
     #[fail(display = "Each packet must have content")]
     ZeroSize,
 
+    #[fail(
+        display = "A payload of {} bytes exceeds the maximum UDP payload size of 65507 bytes",
+        size
+    )]
+    TooLarge { size: usize },
+
+    #[fail(
+        display = "A payload of {} bytes exceeds the maximum UDP payload size of 65467 bytes \
+                   with `--ip-option`",
+        size
+    )]
+    TooLargeForIpOption { size: usize },
+
     #[fail(display = "Error while reading the file")]
     ReadFailed {
         #[fail(cause)]
         source: io::Error,
         filename: String,
     },
+
+    #[fail(display = "The directory '{}' has no non-empty files", dirname)]
+    EmptyDirectory { dirname: String },
+
+    #[fail(display = "Failed to run the command {:?}", command)]
+    CommandNotRun {
+        #[fail(cause)]
+        source: io::Error,
+        command: String,
+    },
+
+    #[fail(display = "The command {:?} has failed ({})", command, status)]
+    CommandFailed { command: String, status: ExitStatus },
+
+    #[fail(display = "The command {:?} has printed nothing", command)]
+    EmptyCommandOutput { command: String },
+
+    #[fail(
+        display = "--payload-weight refers to the payload #{}, but only {} payloads are specified",
+        index, count
+    )]
+    UnknownWeightedPayload { index: usize, count: usize },
+
+    #[fail(
+        display = "A sequence number of {} bytes at the offset {} doesn't fit into a payload of {} \
+                   bytes",
+        width, offset, size
+    )]
+    SequenceNumberOutside {
+        offset: usize,
+        width: usize,
+        size: usize,
+    },
+
+    #[cfg(not(feature = "mmap"))]
+    #[fail(display = "--mmap-files requires anevicon to be built with the `mmap` feature")]
+    MmapUnsupported,
 }
 
 #[cfg(test)]
@@ -106,7 +531,7 @@ mod tests {
 
     use lazy_static::lazy_static;
 
-    use crate::config::PayloadConfig;
+    use crate::config::{FillPattern, PayloadConfig};
 
     use super::*;
 
@@ -120,6 +545,227 @@ mod tests {
             fs::read("files/second_packet.txt").expect("fs::read(...) failed");
     }
 
+    #[test]
+    fn rejects_too_large_payload() {
+        let error = craft_all(&PayloadConfig {
+            send_files: Vec::new(),
+            send_dirs: Vec::new(),
+            random_packets: vec![NonZeroUsize::new(MAX_PAYLOAD_SIZE + 1).unwrap()],
+            send_messages: vec![String::from("A valid message")],
+            send_commands: Vec::new(),
+            send_command_each: false,
+            mmap_files: false,
+            fill_pattern: None,
+            repeat_file: NonZeroUsize::new(1).unwrap(),
+            warn_entropy: None,
+        })
+        .unwrap_err()
+        .downcast::<CraftPayloadError>()
+        .expect("Returned non-CraftPayloadError");
+
+        match error {
+            CraftPayloadError::TooLarge { size } => assert_eq!(size, MAX_PAYLOAD_SIZE + 1),
+            _ => panic!("Must return CraftPayloadError::TooLarge"),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "mmap")]
+    fn maps_files() {
+        let packets = craft_all(&PayloadConfig {
+            send_files: vec![PACKET_FILE.clone(), ZERO_FILE.clone()],
+            send_dirs: Vec::new(),
+            random_packets: Vec::new(),
+            send_messages: Vec::new(),
+            send_commands: Vec::new(),
+            send_command_each: false,
+            mmap_files: true,
+            fill_pattern: None,
+            repeat_file: NonZeroUsize::new(1).unwrap(),
+            warn_entropy: None,
+        });
+
+        match packets
+            .unwrap_err()
+            .downcast::<CraftPayloadError>()
+            .expect("Returned non-CraftPayloadError")
+        {
+            CraftPayloadError::ZeroSize => (),
+            _ => panic!("Must return CraftPayloadError::ZeroSize"),
+        }
+
+        let packets = craft_all(&PayloadConfig {
+            send_files: vec![PACKET_FILE.clone()],
+            send_dirs: Vec::new(),
+            random_packets: Vec::new(),
+            send_messages: Vec::new(),
+            send_commands: Vec::new(),
+            send_command_each: false,
+            mmap_files: true,
+            fill_pattern: None,
+            repeat_file: NonZeroUsize::new(1).unwrap(),
+            warn_entropy: None,
+        })
+        .expect("Cannot construct packets");
+
+        assert_eq!(packets, vec![PACKET_CONTENT.clone()]);
+        match packets[0] {
+            Payload::Mapped(_) => (),
+            _ => panic!("The file must be memory-mapped"),
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "mmap"))]
+    fn rejects_mmap_without_feature() {
+        let error = craft_all(&PayloadConfig {
+            send_files: vec![PACKET_FILE.clone()],
+            send_dirs: Vec::new(),
+            random_packets: Vec::new(),
+            send_messages: Vec::new(),
+            send_commands: Vec::new(),
+            send_command_each: false,
+            mmap_files: true,
+            fill_pattern: None,
+            repeat_file: NonZeroUsize::new(1).unwrap(),
+            warn_entropy: None,
+        })
+        .unwrap_err()
+        .downcast::<CraftPayloadError>()
+        .expect("Returned non-CraftPayloadError");
+
+        match error {
+            CraftPayloadError::MmapUnsupported => (),
+            _ => panic!("Must return CraftPayloadError::MmapUnsupported"),
+        }
+    }
+
+    #[test]
+    fn fills_random_packets_with_pattern() {
+        let packets = craft_all(&PayloadConfig {
+            send_files: Vec::new(),
+            send_dirs: Vec::new(),
+            random_packets: vec![NonZeroUsize::new(300).unwrap()],
+            send_messages: vec![String::from("Not affected")],
+            send_commands: Vec::new(),
+            send_command_each: false,
+            mmap_files: false,
+            fill_pattern: Some(FillPattern::Incrementing),
+            repeat_file: NonZeroUsize::new(1).unwrap(),
+            warn_entropy: None,
+        })
+        .expect("Cannot construct packets");
+
+        assert_eq!(packets[0], b"Not affected".to_vec());
+        assert_eq!(packets[1].len(), 300);
+        assert!(packets[1]
+            .iter()
+            .enumerate()
+            .all(|(index, &byte)| byte == (index % 256) as u8));
+    }
+
+    #[test]
+    fn accepts_max_size_payload() {
+        let packets = craft_all(&PayloadConfig {
+            send_files: Vec::new(),
+            send_dirs: Vec::new(),
+            random_packets: vec![NonZeroUsize::new(MAX_PAYLOAD_SIZE).unwrap()],
+            send_messages: Vec::new(),
+            send_commands: Vec::new(),
+            send_command_each: false,
+            mmap_files: false,
+            fill_pattern: None,
+            repeat_file: NonZeroUsize::new(1).unwrap(),
+            warn_entropy: None,
+        })
+        .expect("Cannot construct a packet");
+
+        assert_eq!(packets[0].len(), MAX_PAYLOAD_SIZE);
+    }
+
+    #[test]
+    fn lists_sources_in_craft_order() {
+        let config = PayloadConfig {
+            send_files: vec![PACKET_FILE.clone()],
+            send_dirs: Vec::new(),
+            random_packets: vec![NonZeroUsize::new(10).unwrap()],
+            send_messages: vec![String::from("First"), String::from("Second")],
+            send_commands: Vec::new(),
+            send_command_each: false,
+            mmap_files: false,
+            fill_pattern: None,
+            repeat_file: NonZeroUsize::new(1).unwrap(),
+            warn_entropy: None,
+        };
+        let sources = sources(&config).expect("sources() failed");
+
+        assert_eq!(
+            sources,
+            vec![
+                PayloadSource::Message("First"),
+                PayloadSource::Message("Second"),
+                PayloadSource::File(PACKET_FILE.clone()),
+                PayloadSource::Random(NonZeroUsize::new(10).unwrap()),
+            ]
+        );
+        assert_eq!(sources[0].to_string(), "--send-message \"First\"");
+    }
+
+    #[test]
+    fn reads_directory_files() {
+        let packets = craft_all(&PayloadConfig {
+            send_files: Vec::new(),
+            send_dirs: vec![PathBuf::from("files")],
+            random_packets: Vec::new(),
+            send_messages: Vec::new(),
+            send_commands: Vec::new(),
+            send_command_each: false,
+            mmap_files: false,
+            fill_pattern: None,
+            repeat_file: NonZeroUsize::new(1).unwrap(),
+            warn_entropy: None,
+        })
+        .expect("Cannot construct packets");
+
+        // `files/zero.txt` is empty, so it must be skipped
+        assert_eq!(
+            packets,
+            vec![PACKET_CONTENT.clone(), SECOND_PACKET_CONTENT.clone()]
+        );
+    }
+
+    #[test]
+    fn rejects_empty_directory() {
+        let directory = std::env::temp_dir().join(format!(
+            "anevicon-empty-dir-{pid}",
+            pid = std::process::id()
+        ));
+        fs::create_dir_all(directory.join("subdirectory")).expect("Failed to create a directory");
+
+        let result = craft_all(&PayloadConfig {
+            send_files: Vec::new(),
+            send_dirs: vec![directory.clone()],
+            random_packets: Vec::new(),
+            send_messages: Vec::new(),
+            send_commands: Vec::new(),
+            send_command_each: false,
+            mmap_files: false,
+            fill_pattern: None,
+            repeat_file: NonZeroUsize::new(1).unwrap(),
+            warn_entropy: None,
+        });
+        fs::remove_dir_all(&directory).expect("Failed to remove a directory");
+
+        match result
+            .unwrap_err()
+            .downcast::<CraftPayloadError>()
+            .expect("Returned non-CraftPayloadError")
+        {
+            CraftPayloadError::EmptyDirectory { .. } => (),
+            _ => panic!("Must return CraftPayloadError::EmptyDirectory"),
+        }
+    }
+
     #[test]
     fn generates_random_payload() {
         let length = NonZeroUsize::new(35684).unwrap();
@@ -133,7 +779,7 @@ mod tests {
     /// Check that the function must return the 'ZeroSize' error.
     #[test]
     fn test_read_zero_file() {
-        let error = read_payload(ZERO_FILE.to_str().unwrap())
+        let error = read_payload(ZERO_FILE.to_str().unwrap(), NonZeroUsize::new(1).unwrap())
             .unwrap_err()
             .downcast::<CraftPayloadError>()
             .expect("Returned non-CraftPayloadError");
@@ -143,13 +789,127 @@ mod tests {
         }
     }
 
+    #[test]
+    fn repeats_file_content() {
+        let file = std::env::temp_dir().join(format!(
+            "anevicon-repeat-file-{pid}",
+            pid = std::process::id()
+        ));
+        fs::write(&file, b"0123456789").expect("Failed to write a file");
+
+        let config = |repeat_file| PayloadConfig {
+            send_files: vec![file.clone()],
+            send_dirs: Vec::new(),
+            random_packets: Vec::new(),
+            send_messages: Vec::new(),
+            send_commands: Vec::new(),
+            send_command_each: false,
+            mmap_files: false,
+            fill_pattern: None,
+            repeat_file: NonZeroUsize::new(repeat_file).unwrap(),
+            warn_entropy: None,
+        };
+        let packets = craft_all(&config(3));
+        let too_large = craft_all(&config(MAX_PAYLOAD_SIZE / 10 + 1));
+        fs::remove_file(&file).expect("Failed to remove a file");
+
+        let packets = packets.expect("Cannot construct a packet");
+        assert_eq!(packets[0].len(), 30);
+        assert_eq!(&*packets[0], &b"012345678901234567890123456789"[..]);
+
+        match too_large
+            .unwrap_err()
+            .downcast::<CraftPayloadError>()
+            .expect("Returned non-CraftPayloadError")
+        {
+            CraftPayloadError::TooLarge { size } => assert_eq!(size, 65510),
+            _ => panic!("Must return CraftPayloadError::TooLarge"),
+        }
+    }
+
+    #[test]
+    fn estimates_shannon_entropy() {
+        assert_eq!(shannon_entropy(&[]), 0.0);
+        assert_eq!(shannon_entropy(&[0u8; 1024]), 0.0);
+        assert_eq!(shannon_entropy(b"abababab"), 1.0);
+        assert_eq!(shannon_entropy(&(0..=255).collect::<Vec<u8>>()), 8.0);
+        assert!(shannon_entropy(&random_payload(NonZeroUsize::new(65536).unwrap())) > 7.9);
+    }
+
+    #[test]
+    fn flips_random_bits() {
+        let original = b"Flip some bits, please".to_vec();
+
+        for &bits in &[1, 3, 17] {
+            let mut mutated = original.clone();
+            flip_random_bits(&mut mutated, NonZeroUsize::new(bits).unwrap());
+
+            let flipped = original
+                .iter()
+                .zip(&mutated)
+                .map(|(a, b)| (a ^ b).count_ones())
+                .sum::<u32>();
+            assert_eq!(flipped, bits as u32);
+        }
+
+        // There are only 8 bits to flip
+        let mut byte = [0u8];
+        flip_random_bits(&mut byte, NonZeroUsize::new(100).unwrap());
+        assert_eq!(byte, [0xFF]);
+    }
+
+    #[test]
+    fn writes_sequence_numbers() {
+        let cases: [(usize, Endianness, &[u8]); 6] = [
+            (2, Endianness::Big, &[0x07, 0x08]),
+            (2, Endianness::Little, &[0x08, 0x07]),
+            (4, Endianness::Big, &[0x05, 0x06, 0x07, 0x08]),
+            (4, Endianness::Little, &[0x08, 0x07, 0x06, 0x05]),
+            (8, Endianness::Big, &[1, 2, 3, 4, 5, 6, 7, 8]),
+            (8, Endianness::Little, &[8, 7, 6, 5, 4, 3, 2, 1]),
+        ];
+
+        for &(width, order, expected) in &cases {
+            let mut bytes = [0xAAu8; 12];
+            write_sequence_number(&mut bytes, 0x0102_0304_0506_0708, 3, width, order)
+                .expect("write_sequence_number(...) failed");
+
+            assert_eq!(&bytes[3..3 + width], expected);
+            assert!(bytes[..3].iter().all(|&byte| byte == 0xAA));
+            assert!(bytes[3 + width..].iter().all(|&byte| byte == 0xAA));
+        }
+    }
+
+    #[test]
+    fn rejects_sequence_numbers_outside_payload() {
+        let mut bytes = [0u8; 6];
+
+        assert!(write_sequence_number(&mut bytes, 1, 2, 4, Endianness::Big).is_ok());
+        match write_sequence_number(&mut bytes, 1, 3, 4, Endianness::Big) {
+            Err(CraftPayloadError::SequenceNumberOutside {
+                offset: 3,
+                width: 4,
+                size: 6,
+            }) => (),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        assert!(check_sequence_number(6, usize::MAX, 2).is_err());
+    }
+
     #[test]
     fn test_choose_random_payload() {
         let packet_length = NonZeroUsize::new(24550).unwrap();
         let packets = craft_all(&PayloadConfig {
             send_files: Vec::new(),
+            send_dirs: Vec::new(),
             random_packets: vec![packet_length],
             send_messages: Vec::new(),
+            send_commands: Vec::new(),
+            send_command_each: false,
+            mmap_files: false,
+            fill_pattern: None,
+            repeat_file: NonZeroUsize::new(1).unwrap(),
+            warn_entropy: None,
         })
         .expect("Cannot construct a packet");
         assert_eq!(packets.len(), 1);
@@ -162,15 +922,58 @@ mod tests {
     fn test_choose_file_payload() {
         let packets = craft_all(&PayloadConfig {
             send_files: vec![PACKET_FILE.clone()],
+            send_dirs: Vec::new(),
             random_packets: Vec::new(),
             send_messages: Vec::new(),
+            send_commands: Vec::new(),
+            send_command_each: false,
+            mmap_files: false,
+            fill_pattern: None,
+            repeat_file: NonZeroUsize::new(1).unwrap(),
+            warn_entropy: None,
         })
         .expect("Cannot construct a packet");
         assert_eq!(packets.len(), 1);
 
         // The function must return a valid file content that we have
         // already written
-        assert_eq!(&packets[0], &PACKET_CONTENT.as_slice());
+        assert_eq!(&*packets[0], PACKET_CONTENT.as_slice());
+    }
+
+    #[test]
+    fn runs_commands() {
+        let config = |command: &str| PayloadConfig {
+            send_files: Vec::new(),
+            send_dirs: Vec::new(),
+            random_packets: Vec::new(),
+            send_messages: Vec::new(),
+            send_commands: vec![command.to_owned()],
+            send_command_each: false,
+            mmap_files: false,
+            fill_pattern: None,
+            repeat_file: NonZeroUsize::new(1).unwrap(),
+            warn_entropy: None,
+        };
+
+        let packets =
+            craft_all(&config("printf 'Hello, %s' world")).expect("Cannot construct a packet");
+        assert_eq!(packets, vec![b"Hello, world".to_vec()]);
+        assert_eq!(packets[0].command(), Some("printf 'Hello, %s' world"));
+
+        let error = |command| {
+            craft_all(&config(command))
+                .unwrap_err()
+                .downcast::<CraftPayloadError>()
+                .expect("Returned non-CraftPayloadError")
+        };
+        match error("exit 3") {
+            CraftPayloadError::CommandFailed { status, .. } => assert_eq!(status.code(), Some(3)),
+            _ => panic!("Must return CraftPayloadError::CommandFailed"),
+        }
+        match error("true") {
+            CraftPayloadError::EmptyCommandOutput { .. } => (),
+            _ => panic!("Must return CraftPayloadError::EmptyCommandOutput"),
+        }
     }
 
     #[test]
@@ -179,8 +982,15 @@ mod tests {
 
         let packets = craft_all(&PayloadConfig {
             send_files: Vec::new(),
+            send_dirs: Vec::new(),
             random_packets: Vec::new(),
             send_messages: vec![message.clone()],
+            send_commands: Vec::new(),
+            send_command_each: false,
+            mmap_files: false,
+            fill_pattern: None,
+            repeat_file: NonZeroUsize::new(1).unwrap(),
+            warn_entropy: None,
         })
         .expect("Cannot construct a packet");
         assert_eq!(packets.len(), 1);
@@ -201,8 +1011,15 @@ mod tests {
 
         let packets = craft_all(&PayloadConfig {
             send_files: vec![PACKET_FILE.clone(), SECOND_PACKET_FILE.clone()],
+            send_dirs: Vec::new(),
             random_packets: vec![random_first, random_second],
             send_messages: vec![first_message.clone(), second_message.clone()],
+            send_commands: Vec::new(),
+            send_command_each: false,
+            mmap_files: false,
+            fill_pattern: None,
+            repeat_file: NonZeroUsize::new(1).unwrap(),
+            warn_entropy: None,
         })
         .expect("Cannot construct multiple packets");
 
@@ -211,8 +1028,8 @@ mod tests {
         assert_eq!(packets[0], first_message.into_bytes());
         assert_eq!(packets[1], second_message.into_bytes());
 
-        assert_eq!(&packets[2], &PACKET_CONTENT.as_slice());
-        assert_eq!(&packets[3], &SECOND_PACKET_CONTENT.as_slice());
+        assert_eq!(&*packets[2], PACKET_CONTENT.as_slice());
+        assert_eq!(&*packets[3], SECOND_PACKET_CONTENT.as_slice());
 
         assert_eq!(packets[4].len(), random_first.get());
         assert_eq!(packets[5].len(), random_second.get());
@@ -0,0 +1,228 @@
+// anevicon: A high-performant UDP-based load generator, written in Rust.
+// Copyright (C) 2019  Temirkhan Myrzamadi <gymmasssorla@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// For more information see <https://github.com/Gymmasssorla/anevicon>.
+
+//! Annotated hex dumps of crafted datagrams (`--dump-sample-packet`).
+
+use std::convert::{TryFrom, TryInto};
+use std::fmt::Write;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use super::{Datagram, UDP_HEADER_LEN};
+
+/// A number of bytes displayed per a line of a hex dump.
+const BYTES_PER_LINE: usize = 16;
+
+/// Returns a hex dump of the whole `datagram` split into the IP header, the
+/// UDP header, and the payload, each followed by its decoded fields.
+pub fn annotated_dump(datagram: &Datagram) -> String {
+    let (ip_header, udp_header) = datagram.header.split_at(datagram.ip_header_len());
+    let mut dump = String::new();
+
+    let (title, fields) = match ip_header[0] >> 4 {
+        4 => ("IPv4", ipv4_fields(ip_header)),
+        _ => ("IPv6", ipv6_fields(ip_header)),
+    };
+    write_section(&mut dump, title, 0, ip_header, &fields);
+    write_section(
+        &mut dump,
+        "UDP",
+        ip_header.len(),
+        udp_header,
+        &udp_fields(udp_header),
+    );
+    write_section(
+        &mut dump,
+        "Payload",
+        datagram.header.len(),
+        &datagram.payload,
+        &[],
+    );
+
+    dump
+}
+
+fn write_section(
+    dump: &mut String,
+    title: &str,
+    offset: usize,
+    bytes: &[u8],
+    fields: &[(&str, String)],
+) {
+    writeln!(dump, "{} ({} bytes):", title, bytes.len()).unwrap();
+
+    for (index, line) in bytes.chunks(BYTES_PER_LINE).enumerate() {
+        let hex = line
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<Vec<String>>()
+            .join(" ");
+        let ascii = line
+            .iter()
+            .map(|&byte| {
+                if byte.is_ascii_graphic() || byte == b' ' {
+                    byte as char
+                } else {
+                    '.'
+                }
+            })
+            .collect::<String>();
+
+        writeln!(
+            dump,
+            "    {offset:04x}  {hex:width$}  |{ascii}|",
+            offset = offset + index * BYTES_PER_LINE,
+            hex = hex,
+            width = BYTES_PER_LINE * 3 - 1,
+            ascii = ascii,
+        )
+        .unwrap();
+    }
+
+    for (name, value) in fields {
+        writeln!(dump, "        {}: {}", name, value).unwrap();
+    }
+}
+
+fn ipv4_fields(header: &[u8]) -> Vec<(&'static str, String)> {
+    let flags = header[6] >> 5;
+
+    vec![
+        ("version", (header[0] >> 4).to_string()),
+        (
+            "header length",
+            format!("{} bytes", (header[0] & 0x0F) as usize * 4),
+        ),
+        ("type of service", format!("0x{:02x}", header[1])),
+        ("total length", be_u16(&header[2..4]).to_string()),
+        ("identification", be_u16(&header[4..6]).to_string()),
+        ("flags", ipv4_flags(flags)),
+        (
+            "fragment offset",
+            (be_u16(&header[6..8]) & 0x1FFF).to_string(),
+        ),
+        ("TTL", header[8].to_string()),
+        ("protocol", protocol(header[9])),
+        ("checksum", format!("0x{:04x}", be_u16(&header[10..12]))),
+        (
+            "source",
+            Ipv4Addr::from(<[u8; 4]>::try_from(&header[12..16]).unwrap()).to_string(),
+        ),
+        (
+            "destination",
+            Ipv4Addr::from(<[u8; 4]>::try_from(&header[16..20]).unwrap()).to_string(),
+        ),
+    ]
+}
+
+/// Returns the names of the set bits of the 3-bit IPv4 `flags`, including the
+/// reserved one (see `--set-reserved-flag`).
+fn ipv4_flags(flags: u8) -> String {
+    let names = [(0b100, "reserved"), (0b010, "DF"), (0b001, "MF")]
+        .iter()
+        .filter(|&&(bit, _)| flags & bit != 0)
+        .map(|&(_, name)| name)
+        .collect::<Vec<&str>>();
+
+    if names.is_empty() {
+        String::from("none")
+    } else {
+        names.join(", ")
+    }
+}
+
+fn ipv6_fields(header: &[u8]) -> Vec<(&'static str, String)> {
+    let first_word = u32::from_be_bytes(header[..4].try_into().unwrap());
+
+    vec![
+        ("version", (header[0] >> 4).to_string()),
+        (
+            "traffic class",
+            format!("0x{:02x}", (first_word >> 20) & 0xFF),
+        ),
+        ("flow label", format!("0x{:05x}", first_word & 0xF_FFFF)),
+        ("payload length", be_u16(&header[4..6]).to_string()),
+        ("next header", protocol(header[6])),
+        ("hop limit", header[7].to_string()),
+        (
+            "source",
+            Ipv6Addr::from(<[u8; 16]>::try_from(&header[8..24]).unwrap()).to_string(),
+        ),
+        (
+            "destination",
+            Ipv6Addr::from(<[u8; 16]>::try_from(&header[24..40]).unwrap()).to_string(),
+        ),
+    ]
+}
+
+fn udp_fields(header: &[u8]) -> Vec<(&'static str, String)> {
+    debug_assert_eq!(header.len(), UDP_HEADER_LEN);
+
+    vec![
+        ("source port", be_u16(&header[0..2]).to_string()),
+        ("destination port", be_u16(&header[2..4]).to_string()),
+        ("length", be_u16(&header[4..6]).to_string()),
+        ("checksum", format!("0x{:04x}", be_u16(&header[6..8]))),
+    ]
+}
+
+fn protocol(number: u8) -> String {
+    match number {
+        17 => String::from("17 (UDP)"),
+        _ => number.to_string(),
+    }
+}
+
+fn be_u16(bytes: &[u8]) -> u16 {
+    u16::from_be_bytes(bytes.try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use structopt::StructOpt;
+
+    use crate::config::SendConfig;
+
+    use super::super::craft_all;
+    use super::*;
+
+    #[test]
+    fn dumps_ipv4_datagrams() {
+        let config = SendConfig::from_iter(&[
+            "send",
+            "--endpoints",
+            "10.0.0.1:1000&10.0.0.2:2000",
+            "--send-message",
+            "Hello, world! This is a sample.",
+            "--ip-ttl",
+            "42",
+        ]);
+
+        let datagram = craft_all(&config.packets_config).expect("craft_all() failed")[0].remove(0);
+        let dump = annotated_dump(&datagram);
+
+        assert!(dump.starts_with("IPv4 (20 bytes):\n    0000  45 "));
+        assert!(dump.contains("\n        TTL: 42\n"));
+        assert!(dump.contains("\n        protocol: 17 (UDP)\n"));
+        assert!(dump.contains("\n        source: 10.0.0.1\n"));
+        assert!(dump.contains("\nUDP (8 bytes):\n    0014  03 e8 07 d0 "));
+        assert!(dump.contains("\n        destination port: 2000\n"));
+        assert!(dump.contains("\n        length: 39\n"));
+        assert!(dump.contains("\nPayload (31 bytes):\n    001c  48 65 6c 6c 6f "));
+        assert!(dump.contains("|Hello, world! Th|\n    002c  "));
+    }
+}
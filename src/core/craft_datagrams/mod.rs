@@ -16,39 +16,804 @@
 //
 // For more information see <https://github.com/Gymmasssorla/anevicon>.
 
+use std::iter;
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+
 use failure::Fallible;
+use rand::Rng;
 
+pub use craft_payload::check_size_with_ip_option;
+pub use craft_payload::flip_random_bits;
+pub use craft_payload::run_command;
+pub use craft_payload::write_sequence_number;
 pub use craft_payload::CraftPayloadError;
+pub use craft_payload::Payload;
+pub use craft_payload::PayloadSource;
+pub use hex_dump::annotated_dump;
 
-use crate::config::PacketsConfig;
+use crate::config::{Endpoints, PacketsConfig, PayloadConfig, PayloadWeight};
+use crate::core::random;
 
 mod craft_packets;
 mod craft_payload;
+mod hex_dump;
+
+/// A length of a UDP header, which precedes a payload in each datagram.
+pub const UDP_HEADER_LEN: usize = 8;
+
+/// A crafted UDP/IP datagram. Its payload is shared between the datagrams of
+/// all the endpoints, so only the IP/UDP headers are stored per endpoint.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Datagram {
+    pub header: Vec<u8>,
+    pub payload: Arc<Payload>,
+}
+
+impl Datagram {
+    /// Returns a number of bytes of the whole datagram (headers + payload).
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.header.len() + self.payload.len()
+    }
+
+    /// Returns a number of bytes of the IP header (without the UDP one).
+    #[inline]
+    pub fn ip_header_len(&self) -> usize {
+        self.header.len() - UDP_HEADER_LEN
+    }
+
+    /// Returns a copy of this datagram carrying `payload` instead, with the
+    /// lengths and checksums of its headers updated accordingly.
+    pub fn with_payload(&self, payload: Arc<Payload>) -> Datagram {
+        let mut header = self.header.clone();
+        craft_packets::replace_payload(&mut header, &payload);
+        Datagram { header, payload }
+    }
+}
+
+/// Constructs all the payloads from `PayloadConfig` (without IP/UDP headers)
+/// and returns their sources together with their lengths, in the same order
+/// as the datagrams of each endpoints.
+pub fn list_payloads(config: &PayloadConfig) -> Fallible<Vec<(PayloadSource<'_>, usize)>> {
+    craft_payload::sources(config)?
+        .into_iter()
+        .map(|source| {
+            let length = craft_payload::craft_one(source.clone(), config)?.len();
+            Ok((source, length))
+        })
+        .collect()
+}
+
+/// Returns an estimated number of bytes taken by the datagrams which
+/// `craft_all` constructs from `config`, without constructing them. It's an
+/// upper bound: every payload is counted once per endpoints together with its
+/// IP/UDP headers, as if payloads weren't shared between endpoints.
+pub fn estimate_size(config: &PacketsConfig) -> Fallible<u64> {
+    let payload_sizes = craft_payload::sources(&config.payload_config)?
+        .iter()
+        .map(|source| craft_payload::estimated_size(source, &config.payload_config))
+        .collect::<Fallible<Vec<usize>>>()?;
+    let payload_size = payload_sizes.iter().map(|&size| size as u64).sum::<u64>();
+
+    Ok(config
+        .endpoints
+        .iter()
+        .map(|endpoints| {
+            payload_size
+                + payload_sizes.len() as u64
+                    * craft_packets::header_len(endpoints, config.ip_option) as u64
+        })
+        .sum())
+}
 
 /// Constructs raw UDP/IP datagrams from `PacketsConfig`.
 ///
 /// # Returns
-/// This function returns a vector of iterators that return UDP/IP datagrams.
+/// This function returns a vector of UDP/IP datagrams for each endpoints.
 ///
 /// Each datagram consists of IP header + UDP header + user's payload, and the
-/// resulting size of each iterator is equal to a total number of occurrences of
-/// `--random-packet`, `--send-message`, and `--send-file` options.
-pub fn craft_all(config: &PacketsConfig) -> Fallible<Vec<impl Iterator<Item = Vec<u8>>>> {
-    let payload = craft_payload::craft_all(&config.payload_config)?;
+/// resulting number of datagrams per endpoints is equal to a total number of
+/// occurrences of `--random-packet`, `--send-message`, `--send-file`, and
+/// `--send-command` options. Payloads are crafted only once and shared between
+/// endpoints.
+///
+/// If `--receiver-port-range` is specified, then the number is the least
+/// common multiple of a number of payloads and a number of ports instead, and
+/// the datagrams are ordered so that sending them cyclically alternates both
+/// payloads and receiver ports.
+///
+/// If `--vary-per-endpoint` is specified, then `--random-packet` payloads are
+/// crafted for each endpoint separately, with random lengths.
+///
+/// If `--payload-weight` is specified, then each weighted payload is repeated
+/// its weight times in a row, so sending datagrams cyclically respects the
+/// weights.
+///
+/// If `--aggregate` is specified, then each datagram carries several
+/// consecutive payloads (after the weights are applied) concatenated.
+///
+/// If `--sequence-number` is specified, then each payload is checked to fit
+/// the counter, which is written by a tester right before sending.
+///
+/// If `--ip-option` is specified, then each payload sent over IPv4 is checked
+/// to leave room for the options.
+pub fn craft_all(config: &PacketsConfig) -> Fallible<Vec<Vec<Datagram>>> {
+    let shared_payload = craft_payload::craft_all(&config.payload_config)?
+        .into_iter()
+        .map(Arc::new)
+        .collect::<Vec<Arc<Payload>>>();
+    for weight in &config.payload_weights {
+        if weight.index >= shared_payload.len() {
+            return Err(CraftPayloadError::UnknownWeightedPayload {
+                index: weight.index,
+                count: shared_payload.len(),
+            }
+            .into());
+        }
+    }
+
+    let sources = if config.vary_per_endpoint {
+        craft_payload::sources(&config.payload_config)?
+    } else {
+        Vec::new()
+    };
+
+    let datagram = |endpoints: &Endpoints, payload_portion: &Arc<Payload>| {
+        let mut header = craft_packets::ip_udp_header(
+            endpoints,
+            payload_portion,
+            match config.multicast_ttl {
+                Some(ttl) if endpoints.receiver().ip().is_multicast() => ttl,
+                _ => config.ip_ttl,
+            },
+            if config.randomize_ip_id {
+                random::with(|generator| generator.gen())
+            } else {
+                0
+            },
+            if config.randomize_flow_label {
+                random::with(|generator| generator.gen::<u32>()) & craft_packets::MAX_FLOW_LABEL
+            } else {
+                0
+            },
+        );
+        if config.set_reserved_flag && endpoints.receiver().is_ipv4() {
+            craft_packets::set_ipv4_reserved_flag(&mut header);
+        }
+        if let (Some(option), true) = (config.ip_option, endpoints.receiver().is_ipv4()) {
+            craft_packets::append_ipv4_option(&mut header, option);
+        }
+
+        Datagram {
+            header,
+            payload: payload_portion.clone(),
+        }
+    };
 
     let mut result = Vec::with_capacity(config.endpoints.len());
     for next_endpoints in &config.endpoints {
-        let mut datagrams = Vec::with_capacity(payload.len());
-        for payload_portion in &payload {
-            datagrams.push(craft_packets::ip_udp_packet(
-                next_endpoints,
-                payload_portion,
-                config.ip_ttl,
-            ));
+        let payload = if config.vary_per_endpoint {
+            vary_random_lengths(&shared_payload, &sources, next_endpoints, config)?
+        } else {
+            shared_payload.clone()
+        };
+        let payload = apply_weights(payload, &config.payload_weights);
+        let payload = match config.aggregate {
+            Some(count) => aggregate(&payload, count)?,
+            None => payload,
+        };
+        if config.sequence_number {
+            for payload_portion in &payload {
+                craft_payload::check_sequence_number(
+                    payload_portion.len(),
+                    config.seq_offset,
+                    config.seq_width,
+                )?;
+            }
         }
+        if config.ip_option.is_some() && next_endpoints.receiver().is_ipv4() {
+            for payload_portion in &payload {
+                craft_payload::check_size_with_ip_option(payload_portion.len())?;
+            }
+        }
+
+        let datagrams = match config.receiver_port_range {
+            None => payload
+                .iter()
+                .map(|payload_portion| datagram(next_endpoints, payload_portion))
+                .collect::<Vec<Datagram>>(),
+            Some(range) => {
+                let ports = range.ports().collect::<Vec<u16>>();
 
-        result.push(datagrams.into_iter());
+                (0..lcm(payload.len(), ports.len()))
+                    .map(|index| {
+                        datagram(
+                            &next_endpoints.with_receiver_port(ports[index % ports.len()]),
+                            &payload[index % payload.len()],
+                        )
+                    })
+                    .collect::<Vec<Datagram>>()
+            }
+        };
+
+        result.push(datagrams);
     }
 
     Ok(result)
 }
+
+/// Replaces each `--random-packet` payload of `payload` (crafted from
+/// `sources`) with a new one of a random length, from one byte up to the
+/// specified length (`--vary-per-endpoint`). The other payloads stay shared.
+fn vary_random_lengths(
+    payload: &[Arc<Payload>],
+    sources: &[PayloadSource],
+    endpoints: &Endpoints,
+    config: &PacketsConfig,
+) -> Fallible<Vec<Arc<Payload>>> {
+    payload
+        .iter()
+        .zip(sources)
+        .map(|(payload_portion, source)| match *source {
+            PayloadSource::Random(max_length) => {
+                let length = NonZeroUsize::new(random::with(|generator| {
+                    generator.gen_range(1, max_length.get() + 1)
+                }))
+                .unwrap();
+                log::debug!(
+                    "random payloads of {length} bytes (instead of {max_length}) will be sent to \
+                     {receiver} from {sender}.",
+                    length = length,
+                    max_length = max_length,
+                    receiver = endpoints.receiver(),
+                    sender = endpoints.sender(),
+                );
+
+                Ok(Arc::new(craft_payload::craft_one(
+                    PayloadSource::Random(length),
+                    &config.payload_config,
+                )?))
+            }
+            _ => Ok(payload_portion.clone()),
+        })
+        .collect()
+}
+
+/// Repeats each payload of `payload` its weight times in a row
+/// (`--payload-weight`). Payloads without a weight are kept once.
+fn apply_weights(payload: Vec<Arc<Payload>>, weights: &[PayloadWeight]) -> Vec<Arc<Payload>> {
+    if weights.is_empty() {
+        return payload;
+    }
+
+    payload
+        .into_iter()
+        .enumerate()
+        .flat_map(|(index, payload_portion)| {
+            let times = weights
+                .iter()
+                .find(|weight| weight.index == index)
+                .map_or(1, |weight| weight.weight.get());
+            iter::repeat_n(payload_portion, times)
+        })
+        .collect()
+}
+
+/// Concatenates each `count` consecutive payloads of `payload`, which are sent
+/// cyclically, into a single one (`--aggregate`). Groups wrap around the end
+/// of `payload`, so there are as many aggregated payloads as needed to make a
+/// whole cycle of both, and each payload occurs as often as before.
+fn aggregate(
+    payload: &[Arc<Payload>],
+    count: NonZeroUsize,
+) -> Result<Vec<Arc<Payload>>, CraftPayloadError> {
+    let count = count.get();
+
+    (0..lcm(payload.len(), count) / count)
+        .map(|group| {
+            let content = (0..count)
+                .flat_map(|index| payload[(group * count + index) % payload.len()].iter())
+                .copied()
+                .collect::<Vec<u8>>();
+            craft_payload::check_size(content.len())?;
+            Ok(Arc::new(Payload::Owned(content)))
+        })
+        .collect()
+}
+
+fn lcm(first: usize, second: usize) -> usize {
+    let (mut a, mut b) = (first, second);
+    while b != 0 {
+        let remainder = a % b;
+        a = b;
+        b = remainder;
+    }
+
+    first / a * second
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{SocketAddr, UdpSocket};
+    use std::time::Duration;
+
+    use structopt::StructOpt;
+
+    use crate::config::SendConfig;
+    use crate::core::statistics::TestSummary;
+    use crate::core::udp_sender::UdpSender;
+
+    use super::*;
+
+    /// The source port written into the packets sent by `assert_accepted`.
+    const SENDER_PORT: u16 = 17333;
+
+    /// Crafts datagrams from `config_args` for a single endpoint whose receiver
+    /// is `receiver`, sends them (and their copies with replaced payloads)
+    /// through a raw socket, and asserts that `receiver` gets them intact.
+    ///
+    /// The kernel drops datagrams with wrong lengths or checksums before they
+    /// reach `receiver`, which byte comparisons of crafted headers can't catch.
+    fn assert_accepted(receiver: UdpSocket, config_args: &[&str]) {
+        let receiver_addr = receiver.local_addr().unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .expect("UdpSocket::set_read_timeout(...) failed");
+
+        let mut sender_addr = receiver_addr;
+        sender_addr.set_port(SENDER_PORT);
+        let endpoints = format!("{}&{}", sender_addr, receiver_addr);
+
+        let mut args = vec!["send", "--endpoints", &endpoints];
+        args.extend_from_slice(config_args);
+        let datagrams = craft_all(&SendConfig::from_iter(&args).packets_config)
+            .expect("craft_all() failed")
+            .remove(0);
+        let replaced = datagrams
+            .iter()
+            .map(|datagram| {
+                datagram.with_payload(Arc::new(Payload::Owned(b"A replaced payload".to_vec())))
+            })
+            .collect::<Vec<Datagram>>();
+
+        let mut summary = TestSummary::default();
+        let mut sender = UdpSender::new(NonZeroUsize::new(1).unwrap(), &receiver_addr, false)
+            .expect("UdpSender::new(...) failed");
+        let mut buffer = [0u8; 2048];
+
+        for datagram in datagrams.iter().chain(&replaced) {
+            sender
+                .send_datagram(&mut summary, datagram)
+                .expect("sender.send_datagram(...) failed");
+
+            let (length, source) = receiver
+                .recv_from(&mut buffer)
+                .expect("The datagram has been dropped by the kernel");
+            assert_eq!(&buffer[..length], &datagram.payload[..]);
+            assert_eq!(source, SocketAddr::new(receiver_addr.ip(), SENDER_PORT));
+        }
+    }
+
+    #[test]
+    fn sends_valid_ipv4_datagrams() {
+        assert_accepted(
+            UdpSocket::bind("127.0.0.1:0").expect("Failed to bind an IPv4 socket"),
+            &[
+                "--send-message",
+                "An even payload",
+                "--send-message",
+                "An odd payload",
+                "--random-packet",
+                "1001",
+                "--ip-ttl",
+                "3",
+            ],
+        );
+    }
+
+    #[test]
+    fn sends_valid_ipv6_datagrams() {
+        assert_accepted(
+            UdpSocket::bind("[::1]:0").expect("Failed to bind an IPv6 socket"),
+            &[
+                "--send-message",
+                "An even payload",
+                "--send-message",
+                "An odd payload",
+                "--random-packet",
+                "1001",
+            ],
+        );
+    }
+
+    #[test]
+    fn uses_multicast_ttl() {
+        let config = SendConfig::from_iter(&[
+            "send",
+            "--endpoints",
+            "10.0.0.1:1000&239.1.2.3:2000",
+            "--endpoints",
+            "10.0.0.1:1000&10.0.0.2:2000",
+            "--endpoints",
+            "[::1]:1000&[ff02::1]:2000",
+            "--ip-ttl",
+            "64",
+            "--multicast-ttl",
+            "2",
+            "--send-message",
+            "Hello, group!",
+        ]);
+
+        let datagrams = craft_all(&config.packets_config).expect("craft_all() failed");
+        assert_eq!(datagrams[0][0].header[8], 2);
+        assert_eq!(datagrams[1][0].header[8], 64);
+        assert_eq!(datagrams[2][0].header[7], 2);
+    }
+
+    #[test]
+    fn alternates_receiver_ports() {
+        let config = SendConfig::from_iter(&[
+            "send",
+            "--endpoints",
+            "10.0.0.1:1000&10.0.0.2:2000",
+            "--receiver-port-range",
+            "3000:3002",
+            "--send-message",
+            "First",
+            "--send-message",
+            "Second",
+        ]);
+
+        let datagrams = craft_all(&config.packets_config)
+            .expect("craft_all() failed")
+            .remove(0);
+        assert_eq!(datagrams.len(), 6);
+
+        // The destination port follows the source one in the UDP header
+        // located after the 20-byte IPv4 header
+        let ports = datagrams
+            .iter()
+            .map(|datagram| u16::from_be_bytes([datagram.header[22], datagram.header[23]]))
+            .collect::<Vec<u16>>();
+        assert_eq!(ports, vec![3000, 3001, 3002, 3000, 3001, 3002]);
+
+        let payloads = datagrams
+            .iter()
+            .map(|datagram| &datagram.payload[..])
+            .collect::<Vec<&[u8]>>();
+        assert_eq!(
+            payloads,
+            vec![
+                &b"First"[..],
+                b"Second",
+                b"First",
+                b"Second",
+                b"First",
+                b"Second"
+            ]
+        );
+    }
+
+    #[test]
+    fn randomizes_ip_identification() {
+        let mut args = vec![
+            "send",
+            "--endpoints",
+            "10.0.0.1:1000&10.0.0.2:2000",
+            "--receiver-port-range",
+            "3000:3031",
+            "--send-message",
+            "Hello",
+        ];
+        let datagrams = |args: &[&str]| {
+            craft_all(&SendConfig::from_iter(args).packets_config)
+                .expect("craft_all() failed")
+                .remove(0)
+                .iter()
+                .map(|datagram| u16::from_be_bytes([datagram.header[4], datagram.header[5]]))
+                .collect::<Vec<u16>>()
+        };
+
+        assert!(datagrams(&args).iter().all(|&id| id == 0));
+
+        // The probability of 32 equal random identifications is negligible
+        args.push("--randomize-ip-id");
+        let identifications = datagrams(&args);
+        assert_eq!(identifications.len(), 32);
+        assert!(identifications.iter().any(|&id| id != identifications[0]));
+    }
+
+    #[test]
+    fn randomizes_flow_label() {
+        let mut args = vec![
+            "send",
+            "--endpoints",
+            "[::1]:1000&[::1]:2000",
+            "--receiver-port-range",
+            "3000:3031",
+            "--send-message",
+            "Hello",
+        ];
+        let flow_labels = |args: &[&str]| {
+            craft_all(&SendConfig::from_iter(args).packets_config)
+                .expect("craft_all() failed")
+                .remove(0)
+                .iter()
+                .map(|datagram| {
+                    let header = &datagram.header;
+                    u32::from_be_bytes([header[0], header[1], header[2], header[3]]) & 0xF_FFFF
+                })
+                .collect::<Vec<u32>>()
+        };
+
+        assert!(flow_labels(&args).iter().all(|&label| label == 0));
+
+        args.push("--randomize-flow-label");
+        let labels = flow_labels(&args);
+        assert_eq!(labels.len(), 32);
+        assert!(labels.iter().any(|&label| label != labels[0]));
+
+        // The version and the traffic class must remain untouched
+        for datagram in &craft_all(&SendConfig::from_iter(&args).packets_config).unwrap()[0] {
+            assert_eq!(datagram.header[0], 0x60);
+            assert_eq!(datagram.header[1] & 0xF0, 0);
+        }
+    }
+
+    #[test]
+    fn shares_payload_between_endpoints() {
+        let config = SendConfig::from_iter(&[
+            "send",
+            "--endpoints",
+            "10.0.0.1:1000&10.0.0.2:2000",
+            "--endpoints",
+            "10.0.0.1:1000&10.0.0.3:3000",
+            "--random-packet",
+            "4096",
+        ]);
+
+        let datagrams = craft_all(&config.packets_config).expect("craft_all() failed");
+        let (first, second) = (&datagrams[0][0], &datagrams[1][0]);
+
+        assert!(Arc::ptr_eq(&first.payload, &second.payload));
+        assert_ne!(first.header, second.header);
+        assert_eq!(first.header.len(), 28);
+        assert_eq!(first.size(), 28 + 4096);
+    }
+
+    #[test]
+    fn computes_lcm() {
+        assert_eq!(lcm(2, 3), 6);
+        assert_eq!(lcm(4, 6), 12);
+        assert_eq!(lcm(1, 5), 5);
+        assert_eq!(lcm(7, 7), 7);
+    }
+
+    #[test]
+    fn varies_random_lengths_per_endpoint() {
+        let config = SendConfig::from_iter(&[
+            "send",
+            "--endpoints",
+            "10.0.0.1:1000&10.0.0.2:2000",
+            "--endpoints",
+            "10.0.0.1:1000&10.0.0.3:2000",
+            "--send-message",
+            "Shared",
+            "--random-packet",
+            "500",
+            "--vary-per-endpoint",
+        ]);
+
+        let datagrams = craft_all(&config.packets_config).expect("craft_all() failed");
+        assert_eq!(datagrams.len(), 2);
+
+        // Messages are still shared, but each endpoint has its own random payload
+        assert!(Arc::ptr_eq(
+            &datagrams[0][0].payload,
+            &datagrams[1][0].payload
+        ));
+        assert!(!Arc::ptr_eq(
+            &datagrams[0][1].payload,
+            &datagrams[1][1].payload
+        ));
+        for endpoint_datagrams in &datagrams {
+            assert!((1..=500).contains(&endpoint_datagrams[1].payload.len()));
+        }
+    }
+
+    #[test]
+    fn repeats_weighted_payloads() {
+        let config = SendConfig::from_iter(&[
+            "send",
+            "--endpoints",
+            "10.0.0.1:1000&10.0.0.2:2000",
+            "--send-message",
+            "A",
+            "--send-message",
+            "B",
+            "--payload-weight",
+            "0:3",
+        ]);
+
+        let datagrams = craft_all(&config.packets_config)
+            .expect("craft_all() failed")
+            .remove(0);
+        assert_eq!(datagrams.len(), 4);
+
+        // Datagrams are sent cyclically, so any window of a multiple of four
+        // datagrams keeps the 3:1 ratio
+        let window = datagrams
+            .iter()
+            .cycle()
+            .skip(2)
+            .take(40)
+            .collect::<Vec<&Datagram>>();
+        let count = |message: &[u8]| {
+            window
+                .iter()
+                .filter(|datagram| &datagram.payload[..] == message)
+                .count()
+        };
+        assert_eq!((count(b"A"), count(b"B")), (30, 10));
+
+        let config =
+            SendConfig::from_iter(&["send", "--send-message", "A", "--payload-weight", "1:3"]);
+        assert!(craft_all(&config.packets_config).is_err());
+    }
+
+    #[test]
+    fn aggregates_payloads() {
+        let config = SendConfig::from_iter(&[
+            "send",
+            "--endpoints",
+            "10.0.0.1:1000&10.0.0.2:2000",
+            "--send-message",
+            "0123456789",
+            "--send-message",
+            "abcdefghij",
+            "--send-message",
+            "ABCDEFGHIJ",
+            "--aggregate",
+            "3",
+        ]);
+
+        let datagrams = craft_all(&config.packets_config)
+            .expect("craft_all() failed")
+            .remove(0);
+        assert_eq!(datagrams.len(), 1);
+        assert_eq!(datagrams[0].payload.len(), 30);
+        assert_eq!(&datagrams[0].payload[..], b"0123456789abcdefghijABCDEFGHIJ");
+
+        // The UDP and IPv4 length fields cover the whole aggregate
+        assert_eq!(&datagrams[0].header[24..26], &38u16.to_be_bytes());
+        assert_eq!(&datagrams[0].header[2..4], &58u16.to_be_bytes());
+
+        // Groups wrap around, so each payload keeps its share
+        let config = SendConfig::from_iter(&[
+            "send",
+            "--endpoints",
+            "10.0.0.1:1000&10.0.0.2:2000",
+            "--send-message",
+            "A",
+            "--send-message",
+            "B",
+            "--aggregate",
+            "3",
+        ]);
+        let payloads = craft_all(&config.packets_config).expect("craft_all() failed")[0]
+            .iter()
+            .map(|datagram| datagram.payload.to_vec())
+            .collect::<Vec<Vec<u8>>>();
+        assert_eq!(payloads, vec![b"ABA".to_vec(), b"BAB".to_vec()]);
+
+        let config = SendConfig::from_iter(&[
+            "send",
+            "--endpoints",
+            "10.0.0.1:1000&10.0.0.2:2000",
+            "--random-packet",
+            "40000",
+            "--aggregate",
+            "2",
+        ]);
+        assert!(craft_all(&config.packets_config).is_err());
+    }
+
+    #[test]
+    fn estimates_size() {
+        let config = SendConfig::from_iter(&[
+            "send",
+            "--endpoints",
+            "10.0.0.1:1000&10.0.0.2:2000",
+            "--endpoints",
+            "[::1]:1000&[::1]:2000",
+            "--send-message",
+            "0123456789",
+            "--random-packet",
+            "100",
+        ]);
+
+        // IPv4 datagrams take 28 bytes of headers, and IPv6 ones take 48
+        let estimated = estimate_size(&config.packets_config).expect("estimate_size() failed");
+        assert_eq!(estimated, (110 + 2 * 28) + (110 + 2 * 48));
+
+        let crafted = craft_all(&config.packets_config)
+            .expect("craft_all() failed")
+            .iter()
+            .flatten()
+            .map(|datagram| datagram.size() as u64)
+            .sum::<u64>();
+        assert_eq!(estimated, crafted);
+
+        // IPv4 options take 40 more bytes, and IPv6 headers don't have them
+        let config = SendConfig::from_iter(&[
+            "send",
+            "--endpoints",
+            "10.0.0.1:1000&10.0.0.2:2000",
+            "--endpoints",
+            "[::1]:1000&[::1]:2000",
+            "--send-message",
+            "0123456789",
+            "--ip-option",
+            "record-route",
+        ]);
+        let datagrams = craft_all(&config.packets_config).expect("craft_all() failed");
+        assert_eq!(datagrams[0][0].ip_header_len(), 60);
+        assert_eq!(datagrams[1][0].ip_header_len(), 40);
+        assert_eq!(
+            estimate_size(&config.packets_config).expect("estimate_size() failed"),
+            (10 + 68) + (10 + 48)
+        );
+    }
+
+    #[test]
+    fn checks_payloads_fit_ip_options() {
+        let config = |endpoints: &str, size: usize| {
+            SendConfig::from_iter(&[
+                "send",
+                "--endpoints",
+                endpoints,
+                "--random-packet",
+                &size.to_string(),
+                "--ip-option",
+                "timestamp",
+            ])
+        };
+
+        // The largest payload makes the largest IPv4 packet
+        let datagrams = craft_all(&config("10.0.0.1:1000&10.0.0.2:2000", 65467).packets_config)
+            .expect("craft_all() failed");
+        let header = &datagrams[0][0].header;
+        assert_eq!(u16::from_be_bytes([header[2], header[3]]), u16::MAX);
+
+        match craft_all(&config("10.0.0.1:1000&10.0.0.2:2000", 65468).packets_config) {
+            Err(error) => match error.downcast::<CraftPayloadError>() {
+                Ok(CraftPayloadError::TooLargeForIpOption { size }) => assert_eq!(size, 65468),
+                result => panic!("TooLargeForIpOption must be returned: {:?}", result),
+            },
+            Ok(_) => panic!("craft_all() must fail"),
+        }
+
+        // IPv6 headers don't carry the options
+        assert!(craft_all(&config("[::1]:1000&[::1]:2000", 65468).packets_config).is_ok());
+    }
+
+    #[test]
+    fn checks_sequence_numbers_fit() {
+        let config = |offset: &str| {
+            SendConfig::from_iter(&[
+                "send",
+                "--endpoints",
+                "10.0.0.1:1000&10.0.0.2:2000",
+                "--send-message",
+                "Eight by",
+                "--sequence-number",
+                "--seq-offset",
+                offset,
+            ])
+        };
+
+        assert!(craft_all(&config("4").packets_config).is_ok());
+        assert!(craft_all(&config("5").packets_config).is_err());
+    }
+}
@@ -0,0 +1,272 @@
+// anevicon: A high-performant UDP-based load generator, written in Rust.
+// Copyright (C) 2019  Temirkhan Myrzamadi <gymmasssorla@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// For more information see <https://github.com/Gymmasssorla/anevicon>.
+
+//! The full-screen terminal dashboard displayed instead of the log messages
+//! during a test (`--tui`).
+
+use std::fmt::Write as FmtWrite;
+use std::io::{self, Write};
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use termion::screen::AlternateScreen;
+use termion::{clear, color, cursor, style};
+
+use crate::config::{Endpoints, SendConfig};
+use crate::core::statistics::SharedCounters;
+
+/// How often the dashboard is redrawn.
+const REDRAW_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often the rendering thread checks whether the tests are finished or
+/// interrupted by Ctrl-C.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The state of the dashboard shared with the rendering thread. The testers
+/// update `counters` after each flush.
+pub struct Dashboard {
+    rows: Vec<Row>,
+    counters: Arc<Vec<SharedCounters>>,
+    test_duration: Duration,
+    start: Instant,
+    finished: AtomicBool,
+}
+
+/// The endpoints displayed by a single row.
+struct Row {
+    endpoints: Endpoints,
+    test_intensity: NonZeroUsize,
+}
+
+impl Dashboard {
+    /// Creates a dashboard displaying `counters` of each endpoint of `config`
+    /// in the same order.
+    pub fn new(config: &SendConfig, counters: Arc<Vec<SharedCounters>>) -> Dashboard {
+        Dashboard {
+            rows: config
+                .packets_config
+                .endpoints
+                .iter()
+                .enumerate()
+                .map(|(index, &endpoints)| Row {
+                    endpoints,
+                    test_intensity: config.test_intensity_of(index),
+                })
+                .collect(),
+            counters,
+            test_duration: config.exit_config.test_duration,
+            start: Instant::now(),
+            finished: AtomicBool::new(false),
+        }
+    }
+
+    /// Makes the rendering thread restore the terminal and exit.
+    pub fn finish(&self) {
+        self.finished.store(true, Ordering::SeqCst);
+    }
+
+    /// Renders a whole frame `width` columns wide. Throughputs are computed
+    /// from the packets sent since `previous` counters taken `interval` ago.
+    fn render(&self, previous: &[u64], interval: Duration, width: usize) -> String {
+        let elapsed = self.start.elapsed();
+        let mut frame = format!(
+            "{bold}anevicon{reset}    Elapsed: {cyan}{elapsed}{reset_color}    Remaining: \
+             {cyan}{remaining}{reset_color}    Press Ctrl-C to stop\n\n",
+            elapsed = humantime::format_duration(truncate_to_secs(elapsed)),
+            remaining = humantime::format_duration(truncate_to_secs(
+                self.test_duration.checked_sub(elapsed).unwrap_or_default()
+            )),
+            bold = style::Bold,
+            reset = style::Reset,
+            cyan = color::Fg(color::Cyan),
+            reset_color = color::Fg(color::Reset),
+        );
+
+        for (index, ((row, counters), &previous)) in self
+            .rows
+            .iter()
+            .zip(self.counters.iter())
+            .zip(previous)
+            .enumerate()
+        {
+            let packets_sent = counters.packets_sent();
+            let packets_per_sec =
+                (packets_sent - previous) as f64 / interval.as_secs_f64().max(f64::EPSILON);
+
+            write!(
+                frame,
+                "#{index} {sender} {yellow}~~~>{reset_color} {receiver}\n    {bar} \
+                 {cyan}{packets_per_sec:.0}/{test_intensity}{reset_color} packets/sec\n    \
+                 Sent: {cyan}{packets_sent}{reset_color} packets ({megabytes} MB)    Failed: \
+                 {red}{packets_failed}{reset_color} packets\n\n",
+                index = index,
+                sender = row.endpoints.sender(),
+                receiver = row.endpoints.receiver(),
+                bar = throughput_bar(
+                    packets_per_sec,
+                    row.test_intensity.get(),
+                    width.saturating_sub(40).max(10)
+                ),
+                packets_per_sec = packets_per_sec,
+                test_intensity = row.test_intensity,
+                packets_sent = packets_sent,
+                megabytes = counters.bytes_sent() / 1024 / 1024,
+                packets_failed = counters.packets_failed(),
+                yellow = color::Fg(color::Yellow),
+                cyan = color::Fg(color::Cyan),
+                red = color::Fg(color::Red),
+                reset_color = color::Fg(color::Reset),
+            )
+            .unwrap();
+        }
+
+        frame
+    }
+
+    fn packets_sent(&self) -> Vec<u64> {
+        self.counters
+            .iter()
+            .map(SharedCounters::packets_sent)
+            .collect()
+    }
+}
+
+/// Takes over the terminal and redraws `dashboard` each second on a separate
+/// thread until `Dashboard::finish` is called. Log messages would break the
+/// dashboard, so they are disabled until then.
+///
+/// The first Ctrl-C restores the terminal and terminates the program as usual.
+pub fn spawn(dashboard: Arc<Dashboard>) -> JoinHandle<()> {
+    let screen = AlternateScreen::from(io::stdout());
+
+    INTERRUPTED.store(false, Ordering::SeqCst);
+    unsafe {
+        libc::signal(
+            libc::SIGINT,
+            interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        )
+    };
+
+    let max_level = log::max_level();
+    log::set_max_level(log::LevelFilter::Off);
+
+    thread::spawn(move || {
+        let mut screen = screen;
+        let _ = write!(screen, "{}", cursor::Hide);
+
+        let mut previous = (dashboard.packets_sent(), Instant::now());
+        let mut last_redraw = None;
+        while !dashboard.finished.load(Ordering::SeqCst) && !INTERRUPTED.load(Ordering::SeqCst) {
+            if last_redraw.is_none_or(|time: Instant| time.elapsed() >= REDRAW_INTERVAL) {
+                let width = termion::terminal_size().map_or(80, |(width, _)| width as usize);
+                let frame = dashboard.render(&previous.0, previous.1.elapsed(), width);
+
+                // The terminal might be already gone, it's no reason to stop testing
+                let _ = write!(screen, "{}{}{}", clear::All, cursor::Goto(1, 1), frame)
+                    .and_then(|_| screen.flush());
+
+                previous = (dashboard.packets_sent(), Instant::now());
+                last_redraw = Some(Instant::now());
+            }
+
+            thread::sleep(POLL_INTERVAL);
+        }
+
+        // Dropping the screen switches back to the main one, but doesn't flush
+        // stdout, which must be done before a possible `raise`
+        let _ = write!(screen, "{}", cursor::Show);
+        drop(screen);
+        let _ = io::stdout().flush();
+        log::set_max_level(max_level);
+
+        unsafe { libc::signal(libc::SIGINT, libc::SIG_DFL) };
+        if INTERRUPTED.load(Ordering::SeqCst) {
+            unsafe { libc::raise(libc::SIGINT) };
+        }
+    })
+}
+
+/// Returns a bar `width` characters wide filled proportionally to
+/// `packets_per_sec` out of `test_intensity`.
+fn throughput_bar(packets_per_sec: f64, test_intensity: usize, width: usize) -> String {
+    let ratio = (packets_per_sec / test_intensity as f64).clamp(0.0, 1.0);
+    let filled = (ratio * width as f64).round() as usize;
+
+    format!(
+        "[{green}{filled}{reset}{empty}]",
+        filled = "#".repeat(filled),
+        empty = ".".repeat(width - filled),
+        green = color::Fg(color::Green),
+        reset = color::Fg(color::Reset),
+    )
+}
+
+fn truncate_to_secs(duration: Duration) -> Duration {
+    Duration::from_secs(duration.as_secs())
+}
+
+extern "C" fn interrupt(_signal: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// Set by the SIGINT handler installed by `spawn`.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(test)]
+mod tests {
+    use structopt::StructOpt;
+
+    use crate::core::statistics::SummaryPortion;
+
+    use super::*;
+
+    #[test]
+    fn fills_throughput_bars() {
+        let strip = |bar: String| {
+            bar.replace(&color::Fg(color::Green).to_string(), "")
+                .replace(&color::Fg(color::Reset).to_string(), "")
+        };
+
+        assert_eq!(strip(throughput_bar(0.0, 1000, 4)), "[....]");
+        assert_eq!(strip(throughput_bar(500.0, 1000, 4)), "[##..]");
+        assert_eq!(strip(throughput_bar(1000.0, 1000, 4)), "[####]");
+        assert_eq!(strip(throughput_bar(5000.0, 1000, 4)), "[####]");
+    }
+
+    #[test]
+    fn renders_shared_counters() {
+        let config = SendConfig::from_iter(&[
+            "send",
+            "--endpoints",
+            "127.0.0.1:17333&127.0.0.1:80",
+            "--endpoints",
+            "127.0.0.1:17333&127.0.0.1:81",
+        ]);
+        let counters = Arc::new(vec![SharedCounters::default(), SharedCounters::default()]);
+        let dashboard = Dashboard::new(&config, counters.clone());
+
+        counters[1].record(&SummaryPortion::new(300, 200, 3, 2));
+        assert_eq!(dashboard.packets_sent(), vec![0, 2]);
+
+        let frame = dashboard.render(&[0, 0], Duration::from_secs(1), 80);
+        assert!(frame.contains("127.0.0.1:81"));
+    }
+}
@@ -0,0 +1,126 @@
+// anevicon: A high-performant UDP-based load generator, written in Rust.
+// Copyright (C) 2019  Temirkhan Myrzamadi <gymmasssorla@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// For more information see <https://github.com/Gymmasssorla/anevicon>.
+
+//! Minimal safe bindings to the error queue of a socket (`MSG_ERRQUEUE`),
+//! which carries extended errors (`IP_RECVERR`, `IPV6_RECVERR`) and
+//! transmission timestamps (`SO_TIMESTAMPING`) as control messages.
+
+use std::io;
+use std::mem;
+use std::os::unix::io::RawFd;
+
+/// A control message of a message read from the error queue.
+pub struct ControlMessage<'a> {
+    pub level: libc::c_int,
+    pub kind: libc::c_int,
+
+    /// The data following the `cmsghdr` of this message.
+    pub data: &'a [u8],
+}
+
+impl ControlMessage<'_> {
+    /// Reads a value of `T` located `offset` bytes into the data, or returns
+    /// `None` if the data is too short.
+    ///
+    /// # Safety
+    /// Any bit pattern must be a valid `T` (such as plain C structures).
+    pub unsafe fn read<T: Copy>(&self, offset: usize) -> Option<T> {
+        if offset + mem::size_of::<T>() > self.data.len() {
+            return None;
+        }
+
+        Some((self.data.as_ptr().add(offset) as *const T).read_unaligned())
+    }
+
+    /// Returns the extended error carried by this message, or `None` if it's
+    /// not `IP_RECVERR` or `IPV6_RECVERR`.
+    pub fn extended_error(&self) -> Option<libc::sock_extended_err> {
+        if (self.level == libc::IPPROTO_IP && self.kind == libc::IP_RECVERR)
+            || (self.level == libc::IPPROTO_IPV6 && self.kind == libc::IPV6_RECVERR)
+        {
+            unsafe { self.read(0) }
+        } else {
+            None
+        }
+    }
+}
+
+/// Reads the next message queued on `fd` without blocking and calls `visit`
+/// with each of its control messages. Returns `false` if the queue is empty.
+pub fn read_next<F: FnMut(&ControlMessage)>(fd: RawFd, mut visit: F) -> io::Result<bool> {
+    // Enough for an extended error together with the offender's address, or
+    // for a `SCM_TIMESTAMPING` message, aligned as `cmsghdr`
+    let mut control = [0u64; 64];
+
+    let mut message = unsafe { mem::zeroed::<libc::msghdr>() };
+    message.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    message.msg_controllen = mem::size_of_val(&control) as _;
+
+    if unsafe { libc::recvmsg(fd, &mut message, libc::MSG_ERRQUEUE | libc::MSG_DONTWAIT) } == -1 {
+        let error = io::Error::last_os_error();
+        return match error.kind() {
+            io::ErrorKind::WouldBlock => Ok(false),
+            _ => Err(error),
+        };
+    }
+
+    let mut header = unsafe { libc::CMSG_FIRSTHDR(&message) };
+    while !header.is_null() {
+        let cmsg = unsafe { &*header };
+        let data = unsafe { libc::CMSG_DATA(header) };
+        let data_len = (cmsg.cmsg_len as usize).saturating_sub(data as usize - header as usize);
+
+        visit(&ControlMessage {
+            level: cmsg.cmsg_level,
+            kind: cmsg.cmsg_type,
+            data: unsafe { std::slice::from_raw_parts(data, data_len) },
+        });
+
+        header = unsafe { libc::CMSG_NXTHDR(&message, header) };
+    }
+
+    Ok(true)
+}
+
+/// Reads all the messages currently queued on `fd` without blocking, calling
+/// `visit` with each of their control messages.
+pub fn read_all<F: FnMut(&ControlMessage)>(fd: RawFd, mut visit: F) -> io::Result<()> {
+    while read_next(fd, &mut visit)? {}
+    Ok(())
+}
+
+/// Sets the integer socket option `name` of `level` on `fd` to `value`.
+pub fn set_int_option(
+    fd: RawFd,
+    level: libc::c_int,
+    name: libc::c_int,
+    value: libc::c_int,
+) -> io::Result<()> {
+    match unsafe {
+        libc::setsockopt(
+            fd,
+            level,
+            name,
+            &value as *const _ as *const libc::c_void,
+            mem::size_of_val(&value) as libc::socklen_t,
+        )
+    } {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
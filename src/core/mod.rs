@@ -19,21 +19,45 @@
 //! A module containing the key function `run` which does the main work.
 
 use std::cell::RefCell;
+use std::io;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::thread;
 use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
-use failure::Fallible;
+use rand::seq::SliceRandom;
 use termion::color;
 
-use crate::config::{ArgsConfig, Endpoints};
+use crate::config::{Endpoints, PacketsCountMode, SendConfig};
+use crate::core::craft_datagrams::Datagram;
+use crate::core::dashboard::Dashboard;
+#[cfg(feature = "netflow")]
+use crate::core::netflow::NetflowExporter;
+use crate::core::privileges::{Account, StartGate};
+use crate::core::statistics::{JsonLinesWriter, SharedCounters, SummaryPortion, SummaryReport};
+use crate::core::tester::{TesterError, TesterOptions, TesterStatus};
+use crate::core::udp_sender::FlushHook;
 use crate::helpers;
 
 mod craft_datagrams;
+mod dashboard;
+mod error_queue;
+#[cfg(feature = "netflow")]
+mod netflow;
+mod pmtu;
+mod privileges;
+mod random;
+mod responses;
+mod self_test;
 mod statistics;
 mod tester;
 mod udp_sender;
 
+/// A result of a worker thread testing a single endpoint.
+type WorkerResult = Result<(SummaryReport, TesterStatus), TesterError>;
+
 thread_local! {
     /// A sender for this thread.
     static SENDER: RefCell<String> = RefCell::new(String::from("Undefined"));
@@ -68,9 +92,36 @@ fn current_endpoints_colored() -> String {
     )
 }
 
-/// This is the key function which accepts a whole `ArgsConfig` and returns
+/// This is the key function which accepts a whole `SendConfig` and returns
 /// `Result<(), ()>` that needs to be returned out of `main()`.
-pub fn run(config: ArgsConfig) -> Result<(), ()> {
+pub fn run(config: SendConfig) -> Result<(), ()> {
+    if config.list_payloads {
+        return list_payloads(&config);
+    }
+
+    // Look the user up before a test, so a typo doesn't waste the waiting
+    let account = match config.drop_privileges {
+        Some(ref user) => match Account::lookup(user) {
+            Err(error) => {
+                log::error!(
+                    "failed to find the user of --drop-privileges!\n{causes}",
+                    causes = helpers::format_failure(&error.into()),
+                );
+                return Err(());
+            }
+            Ok(account) => Some(account),
+        },
+        None => None,
+    };
+
+    if let Some(limit) = config.max_craft_memory {
+        check_craft_memory(&config, limit)?;
+    }
+
+    if let Some(seed) = config.random_seed {
+        random::seed(seed);
+    }
+
     let datagrams = match craft_datagrams::craft_all(&config.packets_config) {
         Err(error) => {
             log::error!(
@@ -82,47 +133,1157 @@ pub fn run(config: ArgsConfig) -> Result<(), ()> {
         Ok(datagrams) => datagrams,
     };
 
+    if config.dump_sample_packet {
+        display_sample_packets(&config, &datagrams);
+    }
+
+    if config.count_only {
+        display_counts(&config, &datagrams);
+        return Ok(());
+    }
+
+    if config.show_wire_size {
+        display_wire_sizes(&config, &datagrams);
+        return Ok(());
+    }
+
+    if config.discover_mtu && discover_mtus(&config, &datagrams).is_err() {
+        return Err(());
+    }
+
     wait(&config);
 
+    if config.calibrate {
+        return calibrate(&config, datagrams);
+    }
+    let batch_size = if config.auto_batch {
+        Some(auto_batch(&config, &datagrams)?)
+    } else {
+        None
+    };
+
+    let counters = Arc::new(
+        (0..config.packets_config.endpoints.len())
+            .map(|_| SharedCounters::default())
+            .collect::<Vec<SharedCounters>>(),
+    );
+
+    // Errors must be reported before the dashboard disables log messages
+    let json_lines = match config.json_lines {
+        Some(ref path) => match JsonLinesWriter::create(path) {
+            Err(error) => {
+                log::error!(
+                    "failed to create the --json-lines file!\n    Caused by: {error}",
+                    error = error
+                );
+                return Err(());
+            }
+            Ok(writer) => Some(Arc::new(writer)),
+        },
+        None => None,
+    };
+
+    #[cfg(feature = "netflow")]
+    let exporter = match config.netflow_export {
+        Some(ref collector) => {
+            match NetflowExporter::new(
+                collector,
+                &config.packets_config.endpoints,
+                counters.clone(),
+            ) {
+                Err(error) => {
+                    log::error!(
+                        "failed to create a NetFlow exporter!\n    Caused by: {error}",
+                        error = error
+                    );
+                    return Err(());
+                }
+                Ok(exporter) => {
+                    let exporter = Arc::new(exporter);
+                    Some((exporter.clone(), netflow::spawn(exporter)))
+                }
+            }
+        }
+        None => None,
+    };
+
+    let dashboard = if config.tui && termion::is_tty(&io::stdout()) {
+        Some(Arc::new(Dashboard::new(&config, counters.clone())))
+    } else {
+        if config.tui {
+            log::warn!("--tui is ignored because stdout isn't a terminal.");
+        }
+        None
+    };
+    let renderer = dashboard.clone().map(dashboard::spawn);
+
+    let share_counters = dashboard.is_some() || config.netflow_export.is_some();
+    let aborted = Arc::new(AtomicBool::new(false));
+
     let config = Arc::new(config);
-    let mut workers =
-        Vec::<JoinHandle<Fallible<()>>>::with_capacity(config.packets_config.endpoints.len());
+    let start = Instant::now();
+    let shared_counters = if share_counters { Some(counters) } else { None };
+
+    // Log messages are disabled while the dashboard is displayed, so report
+    // the results only after it's closed
+    let results = if config.fair_interleave {
+        run_interleaved(
+            config.clone(),
+            datagrams,
+            shared_counters,
+            json_lines,
+            aborted,
+            account,
+        )
+    } else {
+        run_workers(
+            config.clone(),
+            datagrams,
+            batch_size,
+            shared_counters,
+            json_lines,
+            aborted,
+            account,
+        )
+    };
+    let elapsed = start.elapsed();
+    if let (Some(dashboard), Some(renderer)) = (dashboard, renderer) {
+        dashboard.finish();
+        renderer.join().expect("The dashboard thread has panicked");
+    }
+    #[cfg(feature = "netflow")]
+    if let Some((exporter, thread)) = exporter {
+        exporter.finish();
+        thread
+            .join()
+            .expect("The NetFlow exporter thread has panicked");
+    }
+
+    for error in results.iter().filter_map(|result| result.as_ref().err()) {
+        log::error!(
+            "a tester exited unexpectedly!\n{causes}",
+            causes = helpers::format_error(error),
+        );
+    }
+    display_outcomes(&config, &results, elapsed);
+
+    let reports = results
+        .into_iter()
+        .filter_map(|result| result.ok().map(|(report, _)| report))
+        .collect::<Vec<SummaryReport>>();
+
+    if let Some(ref output_file) = config.output_file {
+        if let Err(error) = statistics::write_reports(&reports, config.output_format, output_file) {
+            log::error!(
+                "failed to write the summary!\n{causes}",
+                causes = helpers::format_failure(&error.into()),
+            );
+            return Err(());
+        }
+    }
+    Ok(())
+}
 
-    for (&endpoints, datagrams) in config
+/// Spawns a worker thread testing each endpoint of `config` and returns their
+/// results in the order of the endpoints.
+fn run_workers(
+    config: Arc<SendConfig>,
+    datagrams: Vec<Vec<Datagram>>,
+    batch_size: Option<NonZeroUsize>,
+    counters: Option<Arc<Vec<SharedCounters>>>,
+    json_lines: Option<Arc<JsonLinesWriter>>,
+    aborted: Arc<AtomicBool>,
+    account: Option<Account>,
+) -> Vec<WorkerResult> {
+    let mut workers = Vec::<(usize, io::Result<JoinHandle<WorkerResult>>)>::with_capacity(
+        config.packets_config.endpoints.len(),
+    );
+
+    let mut jobs = config
         .packets_config
         .endpoints
         .iter()
         .zip(datagrams.into_iter())
+        .zip(packets_counts(&config))
+        .enumerate()
+        .collect::<Vec<_>>();
+    if config.randomize_endpoint_order {
+        random::with(|generator| jobs.shuffle(generator));
+    }
+    let start_gate = account.as_ref().map(|_| Arc::new(StartGate::default()));
+
+    for (position, (index, ((&endpoints, datagrams), packets_count))) in
+        jobs.into_iter().enumerate()
     {
         let config = config.clone();
+        let test_intensity = config.test_intensity_of(index);
+        let counters = counters.clone();
+        let json_lines = json_lines.clone();
+        let aborted = aborted.clone();
+        let start_gate = start_gate.clone();
 
-        workers.push(thread::spawn(move || {
-            init_endpoints(endpoints);
-            tester::run_tester(config, datagrams.collect(), endpoints)?;
-            Ok(())
-        }));
+        let builder = worker_builder(&config, worker_name(index, &endpoints));
+        workers.push((
+            index,
+            builder.spawn(move || {
+                init_endpoints(endpoints);
+                if let Some(seed) = config.random_seed {
+                    random::seed(random::worker_seed(seed, index));
+                }
+                if let Some(stagger) = config.stagger {
+                    let offset = stagger * position as u32;
+                    log::debug!(
+                        "{endpoints} starts after {offset} (--stagger).",
+                        endpoints = current_endpoints_colored(),
+                        offset = humantime::format_duration(offset),
+                    );
+                    thread::sleep(offset);
+                }
+
+                let flush_hook = flush_hook(counters, index);
+                let abort_on_error = config.abort_on_error;
+                let (summary, status) = tester::run_tester(
+                    config,
+                    datagrams,
+                    endpoints,
+                    packets_count,
+                    test_intensity,
+                    TesterOptions {
+                        batch_size,
+                        flush_hook,
+                        json_lines: json_lines.as_deref(),
+                        start_gate: start_gate.as_deref(),
+                    },
+                    &aborted,
+                )
+                .inspect_err(|_| {
+                    if abort_on_error {
+                        aborted.store(true, Ordering::Relaxed);
+                    }
+                })?;
+                Ok((SummaryReport::new(&endpoints, &summary), status))
+            }),
+        ));
     }
 
+    if let (Some(account), Some(start_gate)) = (account, start_gate) {
+        let spawned = workers.iter().filter(|(_, worker)| worker.is_ok()).count();
+        drop_privileges(&account, &start_gate, spawned);
+    }
+
+    // Workers might be started in a random order (`--randomize-endpoint-order`),
+    // but their results are reported in the specified one
+    workers.sort_by_key(|&(index, _)| index);
     workers
         .into_iter()
-        .for_each(|worker: JoinHandle<Result<_, failure::Error>>| {
-            if let Err(error) = worker.join().expect("A child thread has panicked") {
+        .map(|(_, worker)| match worker {
+            Ok(worker) => worker.join().expect("A child thread has panicked"),
+            Err(error) => Err(TesterError::SpawnWorker(error)),
+        })
+        .collect()
+}
+
+/// Tests all the endpoints of `config` from a single thread
+/// (`--fair-interleave`) and returns their results in the order of the
+/// endpoints.
+fn run_interleaved(
+    config: Arc<SendConfig>,
+    datagrams: Vec<Vec<Datagram>>,
+    counters: Option<Arc<Vec<SharedCounters>>>,
+    json_lines: Option<Arc<JsonLinesWriter>>,
+    aborted: Arc<AtomicBool>,
+    account: Option<Account>,
+) -> Vec<WorkerResult> {
+    let endpoints_count = config.packets_config.endpoints.len();
+    let start_gate = account.as_ref().map(|_| Arc::new(StartGate::default()));
+    let thread_start_gate = start_gate.clone();
+    let worker = worker_builder(&config, String::from("interleaved")).spawn(move || {
+        if let Some(seed) = config.random_seed {
+            random::seed(seed);
+        }
+        let packets_counts = packets_counts(&config);
+        let flush_hooks = (0..datagrams.len())
+            .map(|index| flush_hook(counters.clone(), index))
+            .collect();
+        tester::run_interleaved(
+            config.clone(),
+            datagrams,
+            packets_counts,
+            flush_hooks,
+            json_lines.as_deref(),
+            thread_start_gate.as_deref(),
+            &aborted,
+        )
+        .into_iter()
+        .zip(&config.packets_config.endpoints)
+        .map(|(result, endpoints)| {
+            result.map(|(summary, status)| (SummaryReport::new(endpoints, &summary), status))
+        })
+        .collect()
+    });
+
+    if let (Some(account), Some(start_gate), Ok(_)) = (account, start_gate, &worker) {
+        drop_privileges(&account, &start_gate, 1);
+    }
+
+    match worker {
+        Ok(worker) => worker.join().expect("The interleaving thread has panicked"),
+        // Every endpoint has failed, but `io::Error` cannot be cloned
+        Err(error) => (0..endpoints_count)
+            .map(|_| {
+                Err(TesterError::SpawnWorker(io::Error::new(
+                    error.kind(),
+                    error.to_string(),
+                )))
+            })
+            .collect(),
+    }
+}
+
+/// Waits until `testers` have created their sockets, switches the process to
+/// `account`, and then lets the testers send (`--drop-privileges`).
+fn drop_privileges(account: &Account, start_gate: &StartGate, testers: usize) {
+    start_gate.wait_for(testers);
+
+    match account.switch_to() {
+        Ok(()) => {
+            log::info!(
+                "the privileges have been dropped to the user {cyan}{user}{reset}.",
+                user = account.name(),
+                cyan = color::Fg(color::Cyan),
+                reset = color::Fg(color::Reset),
+            );
+            start_gate.open(true);
+        }
+        Err(error) => {
+            log::error!(
+                "failed to drop the privileges, so no packets will be sent!\n{causes}",
+                causes = helpers::format_failure(&error.into()),
+            );
+            start_gate.open(false);
+        }
+    }
+}
+
+/// Returns a builder of a worker thread named `name`, with a stack of
+/// `--worker-stack-size` bytes if it's specified.
+fn worker_builder(config: &SendConfig, name: String) -> thread::Builder {
+    let builder = thread::Builder::new().name(name);
+    match config.worker_stack_size {
+        Some(stack_size) => builder.stack_size(stack_size.get()),
+        None => builder,
+    }
+}
+
+/// Returns a name of the worker testing `endpoints` (the `index`th ones), as
+/// seen in panic messages, `perf`, and `gdb`. Linux truncates thread names to
+/// 15 bytes, so the name starts with the index and the receiver, which tell
+/// the workers apart.
+fn worker_name(index: usize, endpoints: &Endpoints) -> String {
+    format!(
+        "#{} {} from {}",
+        index,
+        endpoints.receiver(),
+        endpoints.sender()
+    )
+}
+
+/// Returns a hook updating the shared `counters` of the endpoint at `index`
+/// after each flush, if the counters are displayed by the dashboard or
+/// exported as NetFlow records.
+fn flush_hook(
+    counters: Option<Arc<Vec<SharedCounters>>>,
+    index: usize,
+) -> Option<FlushHook<'static>> {
+    counters.map(|counters| {
+        Box::new(move |portion: &SummaryPortion| counters[index].record(portion)) as FlushHook
+    })
+}
+
+/// Prints a table of the outcome and the final counts of each endpoint, and
+/// how many endpoints have been tested successfully and how many have failed.
+fn display_outcomes(config: &SendConfig, results: &[WorkerResult], elapsed: Duration) {
+    log::info!(
+        "all the tests have finished in {cyan}{elapsed}{reset}:",
+        elapsed = humantime::format_duration(Duration::from_millis(elapsed.as_millis() as u64)),
+        cyan = color::Fg(color::Cyan),
+        reset = color::Fg(color::Reset),
+    );
+
+    let endpoints = config
+        .packets_config
+        .endpoints
+        .iter()
+        .map(|endpoints| format!("{} -> {}", endpoints.sender(), endpoints.receiver()))
+        .collect::<Vec<String>>();
+    let endpoints_width = endpoints.iter().map(String::len).max().unwrap_or(0);
+
+    log::info!(
+        "    {index:<4} {endpoints:<endpoints_width$}  {status:<12}  {packets:>27}  {bytes:>27}",
+        index = "#",
+        endpoints = "ENDPOINTS",
+        endpoints_width = endpoints_width,
+        status = "STATUS",
+        packets = "PACKETS SENT",
+        bytes = "BYTES SENT",
+    );
+    for (index, (endpoints, result)) in endpoints.iter().zip(results).enumerate() {
+        let (status, packets, bytes) = match result {
+            Ok((report, status)) => (
+                status.to_string(),
+                format!("{}/{}", report.packets_sent, report.packets_expected),
+                format!("{}/{}", report.bytes_sent, report.bytes_expected),
+            ),
+            Err(_) => (String::from("failed"), String::from("-"), String::from("-")),
+        };
+
+        log::info!(
+            "    {index:<4} {endpoints:<endpoints_width$}  {color}{status:<12}{reset}  \
+             {packets:>27}  {bytes:>27}",
+            index = index,
+            endpoints = endpoints,
+            endpoints_width = endpoints_width,
+            status = status,
+            packets = packets,
+            bytes = bytes,
+            color = match result {
+                Ok((_, TesterStatus::Completed)) => color::Fg(color::Green).to_string(),
+                Ok(_) => color::Fg(color::Yellow).to_string(),
+                Err(_) => color::Fg(color::Red).to_string(),
+            },
+            reset = color::Fg(color::Reset),
+        );
+    }
+
+    let succeeded = results.iter().filter(|result| result.is_ok()).count();
+    if succeeded == results.len() {
+        log::info!(
+            "all {cyan}{total}{reset} endpoints have been tested successfully.",
+            total = results.len(),
+            cyan = color::Fg(color::Cyan),
+            reset = color::Fg(color::Reset),
+        );
+    } else {
+        log::error!(
+            "{cyan}{succeeded}{reset} endpoints have been tested successfully, and \
+             {red}{failed}{reset} have failed!",
+            succeeded = succeeded,
+            failed = results.len() - succeeded,
+            cyan = color::Fg(color::Cyan),
+            red = color::Fg(color::Red),
+            reset = color::Fg(color::Reset),
+        );
+    }
+}
+
+/// Returns a number of packets that each endpoint's worker has to send,
+/// according to `--packets-count-mode`. In the `total` mode the packets count
+/// is divided between the endpoints, so the first ones might send one packet
+/// more than the rest.
+fn packets_counts(config: &SendConfig) -> Vec<NonZeroUsize> {
+    let (endpoints, packets_count) = (
+        config.packets_config.endpoints.len(),
+        config.exit_config.packets_count,
+    );
+
+    match config.exit_config.packets_count_mode {
+        PacketsCountMode::PerEndpoint => vec![packets_count; endpoints],
+        PacketsCountMode::Total => tester::datagram_counts(endpoints, packets_count)
+            .into_iter()
+            .map(|count| {
+                NonZeroUsize::new(count)
+                    .expect("Each endpoint must send at least one packet in the total mode")
+            })
+            .collect(),
+    }
+}
+
+/// Estimates the memory taken by the datagrams before crafting them and fails
+/// if it exceeds `limit` (`--max-craft-memory`), so a test with huge payloads
+/// and many endpoints doesn't exhaust memory before sending anything.
+fn check_craft_memory(config: &SendConfig, limit: NonZeroUsize) -> Result<(), ()> {
+    let estimated = match craft_datagrams::estimate_size(&config.packets_config) {
+        Err(error) => {
+            log::error!(
+                "failed to estimate the size of datagrams!\n{causes}",
+                causes = helpers::format_failure(&error),
+            );
+            return Err(());
+        }
+        Ok(estimated) => estimated,
+    };
+
+    if estimated > limit.get() as u64 {
+        log::error!(
+            "the datagrams might take up to {estimated} bytes, which exceeds \
+             --max-craft-memory={limit}! Send fewer or smaller payloads, or split --endpoints \
+             between several runs.",
+            estimated = estimated,
+            limit = limit,
+        );
+        return Err(());
+    }
+
+    log::trace!(
+        "the datagrams might take up to {estimated} bytes (--max-craft-memory={limit}).",
+        estimated = estimated,
+        limit = limit,
+    );
+    Ok(())
+}
+
+/// Prints how `--packets-count` is divided between the crafted datagrams of
+/// each endpoint (`--count-only`).
+fn display_counts(config: &SendConfig, datagrams: &[Vec<Datagram>]) {
+    for ((endpoints, datagrams), packets_count) in config
+        .packets_config
+        .endpoints
+        .iter()
+        .zip(datagrams)
+        .zip(packets_counts(config))
+    {
+        let counts = tester::datagram_counts(datagrams.len(), packets_count);
+
+        let bytes = datagrams
+            .iter()
+            .zip(&counts)
+            .map(|(datagram, &count)| datagram.size() as u128 * count as u128)
+            .sum::<u128>();
+
+        log::info!(
+            "{cyan}{packets}{reset} packets ({cyan}{bytes}{reset} bytes including IP/UDP headers) \
+             would be sent to {receiver} from {sender} using {cyan}{payloads}{reset} distinct \
+             payloads.",
+            receiver = endpoints.receiver(),
+            sender = endpoints.sender(),
+            packets = counts.iter().map(|&count| count as u128).sum::<u128>(),
+            bytes = bytes,
+            payloads = distinct_payloads(datagrams).len(),
+            cyan = color::Fg(color::Cyan),
+            reset = color::Fg(color::Reset),
+        );
+    }
+}
+
+/// Prints the first datagram of each endpoint as an annotated hex dump
+/// (`--dump-sample-packet`).
+fn display_sample_packets(config: &SendConfig, datagrams: &[Vec<Datagram>]) {
+    for (endpoints, datagrams) in config.packets_config.endpoints.iter().zip(datagrams) {
+        log::info!(
+            "the first datagram sent to {receiver} from {sender}:\n{dump}",
+            receiver = endpoints.receiver(),
+            sender = endpoints.sender(),
+            dump = craft_datagrams::annotated_dump(&datagrams[0]).trim_end(),
+        );
+    }
+}
+
+/// Prints the wire size of each distinct datagram of each endpoint, and the
+/// bandwidth that the endpoint's test intensity makes (`--show-wire-size`).
+fn display_wire_sizes(config: &SendConfig, datagrams: &[Vec<Datagram>]) {
+    for (index, (endpoints, datagrams)) in config
+        .packets_config
+        .endpoints
+        .iter()
+        .zip(datagrams)
+        .enumerate()
+    {
+        let test_intensity = config.test_intensity_of(index).get();
+        log::info!(
+            "datagrams sent to {receiver} from {sender} at {cyan}{test_intensity}{reset} \
+             packets per second:",
+            receiver = endpoints.receiver(),
+            sender = endpoints.sender(),
+            test_intensity = test_intensity,
+            cyan = color::Fg(color::Cyan),
+            reset = color::Fg(color::Reset),
+        );
+
+        // The headers of the repeated payloads have the same length
+        for (payload, datagram) in distinct_payloads(datagrams).iter().enumerate() {
+            log::info!(
+                "    payload #{payload}: {length} bytes + {udp} bytes of UDP header + {ip} bytes \
+                 of {family} header = {cyan}{size}{reset} bytes ({cyan}{bandwidth:.3}{reset} \
+                 Mbit/s if only it is sent).",
+                payload = payload,
+                length = datagram.payload.len(),
+                udp = craft_datagrams::UDP_HEADER_LEN,
+                ip = datagram.ip_header_len(),
+                family = if endpoints.receiver().is_ipv4() {
+                    "IPv4"
+                } else {
+                    "IPv6"
+                },
+                size = datagram.size(),
+                bandwidth = megabits_per_sec(datagram.size() as f64, test_intensity),
+                cyan = color::Fg(color::Cyan),
+                reset = color::Fg(color::Reset),
+            );
+        }
+
+        // Datagrams are sent cyclically, so the average size determines the
+        // bandwidth of a whole test
+        let average_size =
+            datagrams.iter().map(Datagram::size).sum::<usize>() as f64 / datagrams.len() as f64;
+        log::info!(
+            "    {cyan}{bandwidth:.3}{reset} Mbit/s in total, including IP/UDP headers.",
+            bandwidth = megabits_per_sec(average_size, test_intensity),
+            cyan = color::Fg(color::Cyan),
+            reset = color::Fg(color::Reset),
+        );
+    }
+}
+
+/// Returns the first datagram of each distinct payload of `datagrams`, which
+/// are repeated by `--receiver-port-range` and `--payload-weight`.
+fn distinct_payloads(datagrams: &[Datagram]) -> Vec<&Datagram> {
+    let mut distinct = Vec::<&Datagram>::new();
+    for datagram in datagrams {
+        if !distinct
+            .iter()
+            .any(|seen| Arc::ptr_eq(&seen.payload, &datagram.payload))
+        {
+            distinct.push(datagram);
+        }
+    }
+
+    distinct
+}
+
+/// Returns a bandwidth (in megabits per second) made by sending
+/// `packets_per_sec` datagrams of `wire_size` bytes per second.
+fn megabits_per_sec(wire_size: f64, packets_per_sec: usize) -> f64 {
+    wire_size * 8.0 * packets_per_sec as f64 / 1_000_000.0
+}
+
+/// How long each probe of `--calibrate` sends packets.
+const CALIBRATION_PROBE: Duration = Duration::from_secs(1);
+
+/// The rate of the first probe of `--calibrate`, in packets per second.
+const CALIBRATION_START: usize = 1000;
+
+/// A probe keeps up if it sends at least this share of the requested rate.
+const CALIBRATION_TOLERANCE: f64 = 0.95;
+
+/// How many probes narrow the range between the last rate kept up with and
+/// the first one which wasn't.
+const CALIBRATION_REFINEMENTS: usize = 4;
+
+/// Finds the highest rate (up to `--max-packet-rate`) at which this machine
+/// keeps up with sending to the first endpoint, and prints it (`--calibrate`).
+///
+/// Probes double the rate until the achieved one falls below
+/// `CALIBRATION_TOLERANCE` of the requested one, and then bisect the range
+/// between the last two probes.
+fn calibrate(config: &SendConfig, mut datagrams: Vec<Vec<Datagram>>) -> Result<(), ()> {
+    let endpoints = config.packets_config.endpoints[0];
+    let datagrams = datagrams.swap_remove(0);
+    init_endpoints(endpoints);
+
+    let mut probe_config = config.clone();
+    probe_config.exit_config.test_duration = CALIBRATION_PROBE;
+    probe_config.exit_config.packets_count = NonZeroUsize::new(usize::MAX).unwrap();
+    probe_config.endpoint_rates.clear();
+    probe_config.sockets_config.count_responses = false;
+    probe_config.loss_alert = None;
+    let probe_config = Arc::new(probe_config);
+
+    // Returns the achieved rate if it's close enough to the requested one
+    let probe = |rate: usize| -> Result<Option<usize>, ()> {
+        let test_intensity = NonZeroUsize::new(rate).unwrap();
+
+        // The statistics of each probe would bury the results
+        let max_level = log::max_level();
+        log::set_max_level(log::LevelFilter::Warn.min(max_level));
+        let result = tester::run_tester(
+            probe_config.clone(),
+            datagrams.clone(),
+            endpoints,
+            probe_config.exit_config.packets_count,
+            test_intensity,
+            TesterOptions::default(),
+            &AtomicBool::new(false),
+        );
+        log::set_max_level(max_level);
+
+        let summary = match result {
+            Err(error) => {
                 log::error!(
-                    "a tester exited unexpectedly!\n{causes}",
-                    causes = helpers::format_failure(&error),
+                    "a calibration probe has failed!\n{causes}",
+                    causes = helpers::format_error(&error),
                 );
+                return Err(());
+            }
+            Ok((summary, _)) => summary,
+        };
+
+        let achieved = summary.packets_per_sec_f64();
+        let kept_up = achieved >= rate as f64 * CALIBRATION_TOLERANCE;
+        log::info!(
+            "requested {rate} packets/sec, achieved {cyan}{achieved:.0}{reset} packets/sec \
+             ({percent:.1}%).",
+            rate = rate,
+            achieved = achieved,
+            percent = achieved / rate as f64 * 100.0,
+            cyan = color::Fg(color::Cyan),
+            reset = color::Fg(color::Reset),
+        );
+        Ok(if kept_up {
+            Some((achieved as usize).min(rate))
+        } else {
+            None
+        })
+    };
+
+    let max_rate = config.max_packet_rate.get();
+    let (mut kept_up, mut fell_behind) = (None, None);
+    let mut rate = CALIBRATION_START.min(max_rate);
+    loop {
+        match probe(rate)? {
+            Some(achieved) => {
+                kept_up = Some((rate, achieved));
+                if rate == max_rate {
+                    break;
+                }
+                rate = rate.saturating_mul(2).min(max_rate);
+            }
+            None => {
+                fell_behind = Some(rate);
+                break;
+            }
+        }
+    }
+
+    if let (Some((mut low, _)), Some(mut high)) = (kept_up, fell_behind) {
+        for _ in 0..CALIBRATION_REFINEMENTS {
+            let middle = low + (high - low) / 2;
+            if middle == low {
+                break;
+            }
+
+            match probe(middle)? {
+                Some(achieved) => {
+                    low = middle;
+                    kept_up = Some((middle, achieved));
+                }
+                None => high = middle,
             }
-        });
+        }
+    }
+
+    // The achieved rate of the fastest probe kept up with is recommended,
+    // since it might be up to `CALIBRATION_TOLERANCE` below the requested one
+    match (kept_up.map(|(_, achieved)| achieved), fell_behind) {
+        (None, _) => log::warn!(
+            "this machine hasn't kept up even with {rate} packets/sec to {endpoints}.",
+            rate = CALIBRATION_START.min(max_rate),
+            endpoints = current_endpoints_colored(),
+        ),
+        (Some(rate), None) => log::info!(
+            "this machine has kept up with {endpoints} up to --max-packet-rate, so consider \
+             {cyan}--test-intensity {rate}{reset} (the receiver's capability isn't measured).",
+            endpoints = current_endpoints_colored(),
+            rate = rate,
+            cyan = color::Fg(color::Cyan),
+            reset = color::Fg(color::Reset),
+        ),
+        (Some(rate), Some(_)) => log::info!(
+            "this machine keeps up with {endpoints} at up to {rate} packets/sec, so consider \
+             {cyan}--test-intensity {rate}{reset} (the receiver's capability isn't measured).",
+            endpoints = current_endpoints_colored(),
+            rate = rate,
+            cyan = color::Fg(color::Cyan),
+            reset = color::Fg(color::Reset),
+        ),
+    }
+
+    Ok(())
+}
+
+/// How long each probe of `--auto-batch` sends packets.
+const AUTO_BATCH_PROBE: Duration = Duration::from_millis(250);
+
+/// The batch sizes tried by `--auto-batch`, in packets per system call.
+const AUTO_BATCH_SIZES: [usize; 4] = [64, 256, 600, 1024];
+
+/// Returns the batch size of `AUTO_BATCH_SIZES` at which this machine sends to
+/// the first endpoint the fastest (`--auto-batch`).
+///
+/// Each probe sends batches of a single size as fast as possible, just as
+/// `--burst` does with a zero `--burst-pause`.
+fn auto_batch(config: &SendConfig, datagrams: &[Vec<Datagram>]) -> Result<NonZeroUsize, ()> {
+    let endpoints = config.packets_config.endpoints[0];
+    init_endpoints(endpoints);
+
+    let mut probe_config = config.clone();
+    probe_config.exit_config.test_duration = AUTO_BATCH_PROBE;
+    probe_config.exit_config.packets_count = NonZeroUsize::new(usize::MAX).unwrap();
+    probe_config.burst_pause = Duration::from_secs(0);
+    probe_config.first_packet_delay = None;
+    probe_config.sockets_config.count_responses = false;
+    probe_config.loss_alert = None;
+
+    log::info!(
+        "probing {count} batch sizes by {probe} each to {endpoints} (--auto-batch)...",
+        count = AUTO_BATCH_SIZES.len(),
+        probe = humantime::format_duration(AUTO_BATCH_PROBE),
+        endpoints = current_endpoints_colored(),
+    );
+
+    let mut fastest: Option<(NonZeroUsize, f64)> = None;
+    for &size in &AUTO_BATCH_SIZES {
+        let size = NonZeroUsize::new(size).unwrap();
+        probe_config.burst = Some(size);
+
+        // The statistics of each probe would bury the results
+        let max_level = log::max_level();
+        log::set_max_level(log::LevelFilter::Warn.min(max_level));
+        let result = tester::run_tester(
+            Arc::new(probe_config.clone()),
+            datagrams[0].clone(),
+            endpoints,
+            probe_config.exit_config.packets_count,
+            size,
+            TesterOptions::default(),
+            &AtomicBool::new(false),
+        );
+        log::set_max_level(max_level);
+
+        let achieved = match result {
+            Err(error) => {
+                log::error!(
+                    "a batch size probe has failed!\n{causes}",
+                    causes = helpers::format_error(&error),
+                );
+                return Err(());
+            }
+            Ok((summary, _)) => summary.packets_per_sec_f64(),
+        };
+
+        log::info!(
+            "batches of {size} packets have achieved {cyan}{achieved:.0}{reset} packets/sec.",
+            size = size,
+            achieved = achieved,
+            cyan = color::Fg(color::Cyan),
+            reset = color::Fg(color::Reset),
+        );
+        if fastest.is_none_or(|(_, rate)| achieved > rate) {
+            fastest = Some((size, achieved));
+        }
+    }
+
+    let (size, _) = fastest.expect("At least one batch size must be probed");
+    log::info!(
+        "the test will be sent in batches of at most {cyan}{size}{reset} packets per system call.",
+        size = size,
+        cyan = color::Fg(color::Cyan),
+        reset = color::Fg(color::Reset),
+    );
+    Ok(size)
+}
+
+/// Discovers the path MTU to each receiver (`--discover-mtu`) and checks that
+/// all the datagrams sent to it fit into this MTU.
+fn discover_mtus(config: &SendConfig, datagrams: &[Vec<Datagram>]) -> Result<(), ()> {
+    for (endpoints, datagrams) in config.packets_config.endpoints.iter().zip(datagrams) {
+        let mtu = match pmtu::discover(&endpoints.receiver()) {
+            Err(error) => {
+                log::error!(
+                    "failed to discover the path MTU to {receiver}!\n    Caused by: {error}",
+                    receiver = endpoints.receiver(),
+                    error = error,
+                );
+                return Err(());
+            }
+            Ok(mtu) => mtu,
+        };
+
+        let headers_len = craft_datagrams::UDP_HEADER_LEN + datagrams[0].ip_header_len();
+        log::info!(
+            "the path MTU to {receiver} is {cyan}{mtu}{reset} bytes, so payloads up to \
+             {cyan}{payload_cap}{reset} bytes fit into a single packet.",
+            receiver = endpoints.receiver(),
+            mtu = mtu,
+            payload_cap = mtu.saturating_sub(headers_len),
+            cyan = color::Fg(color::Cyan),
+            reset = color::Fg(color::Reset),
+        );
+
+        if let Some(datagram) = datagrams.iter().find(|datagram| datagram.size() > mtu) {
+            log::error!(
+                "a payload of {length} bytes doesn't fit into the path MTU to {receiver} \
+                 ({mtu} bytes including {headers_len} bytes of IP/UDP headers)!",
+                length = datagram.payload.len(),
+                receiver = endpoints.receiver(),
+                mtu = mtu,
+                headers_len = headers_len,
+            );
+            return Err(());
+        }
+    }
+
     Ok(())
 }
 
-fn wait(config: &ArgsConfig) {
-    log::warn!(
-        "waiting {time} and then starting to execute the tests until {packets} packets will be \
-         sent or {duration} duration will be passed...",
-        time = humantime::format_duration(config.wait),
-        packets = config.exit_config.packets_count,
-        duration = humantime::format_duration(config.exit_config.test_duration)
+/// Prints each payload with its source and length (`--list-payloads`).
+fn list_payloads(config: &SendConfig) -> Result<(), ()> {
+    let payloads = match craft_datagrams::list_payloads(&config.packets_config.payload_config) {
+        Err(error) => {
+            log::error!(
+                "failed to construct payloads!\n{causes}",
+                causes = helpers::format_failure(&error),
+            );
+            return Err(());
+        }
+        Ok(payloads) => payloads,
+    };
+
+    for (index, (source, length)) in payloads.iter().enumerate() {
+        log::info!(
+            "payload #{index}: {cyan}{length}{reset} bytes from {source}",
+            index = index,
+            length = length,
+            source = source,
+            cyan = color::Fg(color::Cyan),
+            reset = color::Fg(color::Reset),
+        );
+    }
+
+    log::info!(
+        "{cyan}{count}{reset} payloads in total.",
+        count = payloads.len(),
+        cyan = color::Fg(color::Cyan),
+        reset = color::Fg(color::Reset),
     );
-    thread::sleep(config.wait);
+    Ok(())
+}
+
+/// Runs the `self-test` subcommand, returning `Result<(), ()>` that needs to
+/// be returned out of `main()`.
+pub fn self_test() -> Result<(), ()> {
+    match self_test::run_self_test() {
+        Err(error) => {
+            log::error!(
+                "the self-test has failed!\n{causes}",
+                causes = helpers::format_failure(&error),
+            );
+            Err(())
+        }
+        Ok(()) => {
+            log::info!("the self-test has passed, this machine is able to send raw datagrams.");
+            Ok(())
+        }
+    }
+}
+
+fn wait(config: &SendConfig) {
+    if config.calibrate {
+        log::warn!(
+            "waiting {time} and then starting to calibrate the sending rate by probes of \
+             {probe} each to {receiver}...",
+            time = humantime::format_duration(config.wait),
+            probe = humantime::format_duration(CALIBRATION_PROBE),
+            receiver = config.packets_config.endpoints[0].receiver(),
+        );
+    } else {
+        log::warn!(
+            "waiting {time} and then starting to execute the tests until {packets} packets will \
+             be sent {mode} or {duration} duration will be passed...",
+            time = humantime::format_duration(config.wait),
+            packets = config.exit_config.packets_count,
+            mode = match config.exit_config.packets_count_mode {
+                PacketsCountMode::PerEndpoint => "to each endpoint",
+                PacketsCountMode::Total => "in total",
+            },
+            duration = humantime::format_duration(config.exit_config.test_duration)
+        );
+    }
+
+    if config.wait == Duration::from_secs(0) {
+        return;
+    }
+
+    // The first Ctrl-C skips the rest of the waiting, and the next ones
+    // terminate the program as usual
+    WAIT_SKIPPED.store(false, Ordering::SeqCst);
+    unsafe {
+        libc::signal(
+            libc::SIGINT,
+            skip_wait as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        )
+    };
+    count_down(config.wait);
+    unsafe { libc::signal(libc::SIGINT, libc::SIG_DFL) };
+}
+
+/// Sleeps `wait`, displaying the countdown, until it passes or `WAIT_SKIPPED`
+/// is set.
+fn count_down(wait: Duration) {
+    let deadline = Instant::now() + wait;
+    let mut last_displayed = None;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining == Duration::from_secs(0) {
+            break;
+        }
+        if WAIT_SKIPPED.load(Ordering::SeqCst) {
+            log::warn!("the waiting has been skipped by Ctrl-C.");
+            break;
+        }
+
+        // Display the countdown once per second, rounding the remaining time up
+        let seconds = remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0);
+        if last_displayed != Some(seconds) {
+            log::info!(
+                "{seconds} seconds left before the tests start (press Ctrl-C to skip)...",
+                seconds = seconds
+            );
+            last_displayed = Some(seconds);
+        }
+
+        thread::sleep(remaining.min(WAIT_POLL_INTERVAL));
+    }
+}
+
+/// Set by the SIGINT handler installed by `wait`.
+static WAIT_SKIPPED: AtomicBool = AtomicBool::new(false);
+
+/// How often `wait` checks whether it has been skipped.
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+extern "C" fn skip_wait(_signal: libc::c_int) {
+    WAIT_SKIPPED.store(true, Ordering::SeqCst);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::UdpSocket;
+
+    use structopt::StructOpt;
+
+    use super::*;
+
+    fn counts(args: &[&str]) -> Vec<usize> {
+        let mut config = SendConfig::from_iter(
+            [
+                "send",
+                "-e",
+                "1.1.1.1:1&2.2.2.2:2",
+                "-e",
+                "1.1.1.1:1&3.3.3.3:3",
+            ]
+            .iter()
+            .chain(args),
+        );
+        config
+            .packets_config
+            .endpoints
+            .push(config.packets_config.endpoints[0]);
+
+        packets_counts(&config)
+            .into_iter()
+            .map(NonZeroUsize::get)
+            .collect()
+    }
+
+    #[test]
+    fn divides_packets_between_endpoints() {
+        assert_eq!(counts(&["-p", "10"]), vec![10, 10, 10]);
+        assert_eq!(
+            counts(&["-p", "10", "--packets-count-mode", "per-endpoint"]),
+            vec![10, 10, 10]
+        );
+        assert_eq!(
+            counts(&["-p", "10", "--packets-count-mode", "total"]),
+            vec![4, 3, 3]
+        );
+    }
+
+    #[test]
+    fn converts_wire_sizes_to_bandwidth() {
+        // The default 1024-byte payload with IPv4 and UDP headers
+        assert_eq!(megabits_per_sec(1052.0, 1000), 8.416);
+        assert_eq!(megabits_per_sec(1052.0, 0), 0.0);
+        assert_eq!(megabits_per_sec(125.0, 1_000_000), 1000.0);
+    }
+
+    #[test]
+    fn reproduces_seeded_workers() {
+        let receivers = (0..2)
+            .map(|_| {
+                let socket = UdpSocket::bind("127.0.0.1:0").expect("UdpSocket::bind(...) failed");
+                socket
+                    .set_read_timeout(Some(Duration::from_secs(5)))
+                    .expect("UdpSocket::set_read_timeout(...) failed");
+                socket
+            })
+            .collect::<Vec<UdpSocket>>();
+
+        let first_packets = || {
+            let mut args = vec![String::from("send")];
+            for receiver in &receivers {
+                args.push(String::from("-e"));
+                args.push(format!("{0}&{0}", receiver.local_addr().unwrap()));
+            }
+            args.extend(
+                [
+                    "--send-message",
+                    "Each endpoint mutates this in its own way",
+                    "--mutate-bits",
+                    "8",
+                    "--packets-count",
+                    "1",
+                    "--random-seed",
+                    "1679",
+                ]
+                .iter()
+                .map(|arg| arg.to_string()),
+            );
+            let config = SendConfig::from_iter(&args);
+
+            let datagrams =
+                craft_datagrams::craft_all(&config.packets_config).expect("craft_all(...) failed");
+            for result in run_workers(
+                Arc::new(config),
+                datagrams,
+                None,
+                None,
+                None,
+                Arc::new(AtomicBool::new(false)),
+                None,
+            ) {
+                result.expect("A worker has failed");
+            }
+
+            receivers
+                .iter()
+                .map(|receiver| {
+                    let mut buffer = [0u8; 64];
+                    let length = receiver
+                        .recv(&mut buffer)
+                        .expect("UdpSocket::recv(...) failed");
+                    buffer[..length].to_vec()
+                })
+                .collect::<Vec<Vec<u8>>>()
+        };
+
+        let first_run = first_packets();
+        assert_eq!(first_packets(), first_run);
+
+        // Each endpoint has its own stream
+        assert_ne!(first_run[0], first_run[1]);
+    }
+
+    #[test]
+    fn skips_wait_on_sigint() {
+        // Call the handler directly, since a real SIGINT would hit the whole
+        // test binary
+        skip_wait(libc::SIGINT);
+
+        let start = Instant::now();
+        count_down(Duration::from_secs(30));
+        WAIT_SKIPPED.store(false, Ordering::SeqCst);
+
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
 }
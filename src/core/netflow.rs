@@ -0,0 +1,350 @@
+// anevicon: A high-performant UDP-based load generator, written in Rust.
+// Copyright (C) 2019  Temirkhan Myrzamadi <gymmasssorla@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// For more information see <https://github.com/Gymmasssorla/anevicon>.
+
+//! The exporter of NetFlow v5 records describing the generated traffic
+//! (`--netflow-export`).
+
+use std::convert::TryFrom;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::config::Endpoints;
+use crate::core::statistics::SharedCounters;
+
+/// How often the records of the packets sent since the previous export are
+/// sent to a collector.
+const EXPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How often the exporting thread checks whether the tests are finished.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The largest number of records a NetFlow v5 packet can carry.
+const MAX_RECORDS: usize = 30;
+
+const HEADER_LEN: usize = 24;
+const RECORD_LEN: usize = 48;
+
+/// A flow of the packets sent from one endpoint to another during a single
+/// export interval. `first` and `last` are milliseconds since the exporter has
+/// been started (the NetFlow system uptime).
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct Flow {
+    sender: SocketAddrV4,
+    receiver: SocketAddrV4,
+    packets: u64,
+    octets: u64,
+    first: u32,
+    last: u32,
+}
+
+/// Periodically exports the counters of the IPv4 endpoints as NetFlow v5
+/// flows. NetFlow v5 cannot describe IPv6 addresses, so IPv6 endpoints are
+/// skipped.
+pub struct NetflowExporter {
+    socket: UdpSocket,
+    endpoints: Vec<(usize, SocketAddrV4, SocketAddrV4)>,
+    counters: Arc<Vec<SharedCounters>>,
+    start: Instant,
+    finished: AtomicBool,
+}
+
+impl NetflowExporter {
+    /// Creates an exporter sending `counters` of each of `endpoints` (in the
+    /// same order) to `collector`.
+    pub fn new(
+        collector: &SocketAddr,
+        endpoints: &[Endpoints],
+        counters: Arc<Vec<SharedCounters>>,
+    ) -> io::Result<NetflowExporter> {
+        let socket = UdpSocket::bind(match collector {
+            SocketAddr::V4(_) => "0.0.0.0:0",
+            SocketAddr::V6(_) => "[::]:0",
+        })?;
+        socket.connect(collector)?;
+
+        let mut exported = Vec::with_capacity(endpoints.len());
+        for (index, next_endpoints) in endpoints.iter().enumerate() {
+            match next_endpoints {
+                Endpoints::V4(endpoints) => {
+                    exported.push((index, endpoints.sender, endpoints.receiver))
+                }
+                Endpoints::V6(_) => log::warn!(
+                    "NetFlow v5 cannot describe IPv6 flows, so the endpoints #{index} won't be \
+                     exported.",
+                    index = index,
+                ),
+            }
+        }
+
+        Ok(NetflowExporter {
+            socket,
+            endpoints: exported,
+            counters,
+            start: Instant::now(),
+            finished: AtomicBool::new(false),
+        })
+    }
+
+    /// Makes the exporting thread export the last records and exit.
+    pub fn finish(&self) {
+        self.finished.store(true, Ordering::SeqCst);
+    }
+
+    fn uptime(&self) -> u32 {
+        u32::try_from(self.start.elapsed().as_millis()).unwrap_or(u32::MAX)
+    }
+}
+
+/// Exports the records of `exporter` each five seconds on a separate thread
+/// until `NetflowExporter::finish` is called, and then exports the rest.
+pub fn spawn(exporter: Arc<NetflowExporter>) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut exported = vec![(0, 0); exporter.counters.len()];
+        let mut flow_sequence = 0u32;
+        let mut interval_start = 0;
+        let mut last_export = Instant::now();
+
+        loop {
+            let finished = exporter.finished.load(Ordering::SeqCst);
+            if !finished && last_export.elapsed() < EXPORT_INTERVAL {
+                thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+
+            let uptime = exporter.uptime();
+            let mut flows = Vec::new();
+            for &(index, sender, receiver) in &exporter.endpoints {
+                let counters = &exporter.counters[index];
+                let current = (counters.packets_sent(), counters.bytes_sent());
+
+                if current.0 > exported[index].0 {
+                    flows.push(Flow {
+                        sender,
+                        receiver,
+                        packets: current.0 - exported[index].0,
+                        octets: current.1 - exported[index].1,
+                        first: interval_start,
+                        last: uptime,
+                    });
+                }
+                exported[index] = current;
+            }
+
+            for packet in encode_packets(&flows, uptime, SystemTime::now(), &mut flow_sequence) {
+                if let Err(error) = exporter.socket.send(&packet) {
+                    log::warn!(
+                        "failed to export NetFlow records!\n    Caused by: {error}",
+                        error = error
+                    );
+                }
+            }
+
+            if finished {
+                break;
+            }
+            interval_start = uptime;
+            last_export = Instant::now();
+        }
+    })
+}
+
+/// Encodes `flows` into NetFlow v5 packets, splitting the flows which octets
+/// don't fit into 32 bits into several records. `flow_sequence` is a number of
+/// records exported before, and it's advanced by the encoded ones.
+fn encode_packets(
+    flows: &[Flow],
+    uptime: u32,
+    now: SystemTime,
+    flow_sequence: &mut u32,
+) -> Vec<Vec<u8>> {
+    let records = flows.iter().flat_map(split_flow).collect::<Vec<Flow>>();
+    let since_epoch = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+
+    records
+        .chunks(MAX_RECORDS)
+        .map(|chunk| {
+            let mut packet = Vec::with_capacity(HEADER_LEN + RECORD_LEN * chunk.len());
+
+            packet.extend_from_slice(&5u16.to_be_bytes());
+            packet.extend_from_slice(&(chunk.len() as u16).to_be_bytes());
+            packet.extend_from_slice(&uptime.to_be_bytes());
+            packet.extend_from_slice(&(since_epoch.as_secs() as u32).to_be_bytes());
+            packet.extend_from_slice(&since_epoch.subsec_nanos().to_be_bytes());
+            packet.extend_from_slice(&flow_sequence.to_be_bytes());
+            // The engine type and ID, and sampling (every packet is counted)
+            packet.extend_from_slice(&[0, 0, 0, 0]);
+
+            for flow in chunk {
+                encode_record(&mut packet, flow);
+            }
+
+            *flow_sequence = flow_sequence.wrapping_add(chunk.len() as u32);
+            packet
+        })
+        .collect()
+}
+
+fn encode_record(packet: &mut Vec<u8>, flow: &Flow) {
+    packet.extend_from_slice(&flow.sender.ip().octets());
+    packet.extend_from_slice(&flow.receiver.ip().octets());
+    packet.extend_from_slice(&Ipv4Addr::UNSPECIFIED.octets());
+    // The input and output interfaces are unknown
+    packet.extend_from_slice(&[0, 0, 0, 0]);
+    packet.extend_from_slice(&(flow.packets as u32).to_be_bytes());
+    packet.extend_from_slice(&(flow.octets as u32).to_be_bytes());
+    packet.extend_from_slice(&flow.first.to_be_bytes());
+    packet.extend_from_slice(&flow.last.to_be_bytes());
+    packet.extend_from_slice(&flow.sender.port().to_be_bytes());
+    packet.extend_from_slice(&flow.receiver.port().to_be_bytes());
+    // A padding, TCP flags, UDP, and ToS
+    packet.extend_from_slice(&[0, 0, libc::IPPROTO_UDP as u8, 0]);
+    // Autonomous systems, prefix masks, and a padding
+    packet.extend_from_slice(&[0; 8]);
+}
+
+/// Splits `flow` into the least number of flows which packets and octets fit
+/// into the 32-bit record fields, dividing them evenly.
+fn split_flow(flow: &Flow) -> Vec<Flow> {
+    let limit = u64::from(u32::MAX);
+    let parts = (flow.octets.div_ceil(limit))
+        .max(flow.packets.div_ceil(limit))
+        .max(1);
+
+    (0..parts)
+        .map(|part| {
+            // The first parts get the remainders
+            let share = |total: u64| total / parts + u64::from(part < total % parts);
+            Flow {
+                packets: share(flow.packets),
+                octets: share(flow.octets),
+                ..*flow
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn flow(packets: u64, octets: u64) -> Flow {
+        Flow {
+            sender: "10.0.0.1:17333".parse().unwrap(),
+            receiver: "10.0.0.2:80".parse().unwrap(),
+            packets,
+            octets,
+            first: 1000,
+            last: 6000,
+        }
+    }
+
+    #[test]
+    fn encodes_records() {
+        let mut flow_sequence = 7;
+        let now = UNIX_EPOCH + Duration::new(1_500_000_000, 42);
+        let packets = encode_packets(&[flow(3, 3156)], 6000, now, &mut flow_sequence);
+
+        assert_eq!(packets.len(), 1);
+        assert_eq!(flow_sequence, 8);
+
+        let packet = &packets[0];
+        assert_eq!(packet.len(), HEADER_LEN + RECORD_LEN);
+        assert_eq!(&packet[..4], &[0, 5, 0, 1]);
+        assert_eq!(&packet[4..8], &6000u32.to_be_bytes());
+        assert_eq!(&packet[8..12], &1_500_000_000u32.to_be_bytes());
+        assert_eq!(&packet[12..16], &42u32.to_be_bytes());
+        assert_eq!(&packet[16..20], &7u32.to_be_bytes());
+
+        let record = &packet[HEADER_LEN..];
+        assert_eq!(&record[0..4], &[10, 0, 0, 1]);
+        assert_eq!(&record[4..8], &[10, 0, 0, 2]);
+        assert_eq!(&record[16..20], &3u32.to_be_bytes());
+        assert_eq!(&record[20..24], &3156u32.to_be_bytes());
+        assert_eq!(&record[24..28], &1000u32.to_be_bytes());
+        assert_eq!(&record[28..32], &6000u32.to_be_bytes());
+        assert_eq!(&record[32..34], &17333u16.to_be_bytes());
+        assert_eq!(&record[34..36], &80u16.to_be_bytes());
+        assert_eq!(record[38], 17);
+    }
+
+    #[test]
+    fn splits_large_flows() {
+        let octets = u64::from(u32::MAX) * 2 + 1;
+        let parts = split_flow(&flow(5, octets));
+
+        assert_eq!(parts.len(), 3);
+        assert_eq!(parts.iter().map(|part| part.packets).sum::<u64>(), 5);
+        assert_eq!(parts.iter().map(|part| part.octets).sum::<u64>(), octets);
+        assert!(parts.iter().all(|part| part.octets <= u64::from(u32::MAX)));
+
+        assert_eq!(split_flow(&flow(1, 10)), vec![flow(1, 10)]);
+    }
+
+    #[test]
+    fn chunks_packets() {
+        let mut flow_sequence = 0;
+        let flows = vec![flow(1, 100); MAX_RECORDS + 1];
+        let packets = encode_packets(&flows, 0, SystemTime::now(), &mut flow_sequence);
+
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0].len(), HEADER_LEN + RECORD_LEN * MAX_RECORDS);
+        assert_eq!(&packets[1][2..4], &[0, 1]);
+        assert_eq!(&packets[1][16..20], &(MAX_RECORDS as u32).to_be_bytes());
+        assert_eq!(flow_sequence, MAX_RECORDS as u32 + 1);
+    }
+
+    #[test]
+    fn exports_to_collector() {
+        let collector = UdpSocket::bind("127.0.0.1:0").unwrap();
+        collector
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+
+        let endpoints = vec![
+            "10.0.0.1:17333&10.0.0.2:80".parse().unwrap(),
+            "[::1]:17333&[::1]:80".parse().unwrap(),
+        ];
+        let counters = Arc::new(vec![SharedCounters::default(), SharedCounters::default()]);
+        counters[0].record(&crate::core::statistics::SummaryPortion::new(
+            200, 200, 2, 2,
+        ));
+        counters[1].record(&crate::core::statistics::SummaryPortion::new(
+            200, 200, 2, 2,
+        ));
+
+        let exporter = Arc::new(
+            NetflowExporter::new(&collector.local_addr().unwrap(), &endpoints, counters).unwrap(),
+        );
+        exporter.finish();
+        spawn(exporter).join().unwrap();
+
+        let mut buffer = [0u8; 1500];
+        let length = collector.recv(&mut buffer).unwrap();
+        assert_eq!(length, HEADER_LEN + RECORD_LEN);
+        assert_eq!(
+            &buffer[HEADER_LEN + 16..HEADER_LEN + 24],
+            &[0, 0, 0, 2, 0, 0, 0, 200]
+        );
+    }
+}
@@ -0,0 +1,225 @@
+// anevicon: A high-performant UDP-based load generator, written in Rust.
+// Copyright (C) 2019  Temirkhan Myrzamadi <gymmasssorla@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// For more information see <https://github.com/Gymmasssorla/anevicon>.
+
+//! Path MTU discovery of the receivers performed before a test
+//! (`--discover-mtu`).
+
+use std::io;
+use std::mem;
+use std::net::{SocketAddr, UdpSocket};
+use std::os::unix::io::{AsRawFd, RawFd};
+
+use crate::core::craft_datagrams::UDP_HEADER_LEN;
+use crate::core::error_queue;
+
+/// How long to wait for an ICMP error after each probe. If nothing is
+/// received, the probe is considered to have reached the receiver.
+const PROBE_TIMEOUT_MILLIS: libc::c_int = 500;
+
+/// The maximum number of probes sent to a single receiver.
+const MAX_PROBES: usize = 10;
+
+/// The largest IP packet, limited by the 16-bit length fields.
+const MAX_PACKET_SIZE: usize = u16::MAX as usize;
+
+/// The smallest MTU which every IPv4 link must support (RFC 791).
+const MIN_IPV4_MTU: usize = 68;
+
+/// The smallest MTU which every IPv6 link must support (RFC 8200).
+const MIN_IPV6_MTU: usize = 1280;
+
+/// Returns the path MTU (in bytes, including IP headers) to `receiver`.
+///
+/// Probes with the Don't Fragment bit set are sent from a regular UDP socket,
+/// starting from the MTU of the outgoing interface. Each time a router replies
+/// with "fragmentation needed" (ICMP) or "packet too big" (ICMPv6), the next
+/// probe is shrunk to the MTU reported by that router.
+pub fn discover(receiver: &SocketAddr) -> io::Result<usize> {
+    let socket = UdpSocket::bind(match receiver {
+        SocketAddr::V4(_) => "0.0.0.0:0",
+        SocketAddr::V6(_) => "[::]:0",
+    })?;
+    socket.connect(receiver)?;
+
+    let fd = socket.as_raw_fd();
+    let (level, discover_option, recverr_option, mtu_option, ip_header_len, min_mtu) =
+        match receiver {
+            SocketAddr::V4(_) => (
+                libc::IPPROTO_IP,
+                libc::IP_MTU_DISCOVER,
+                libc::IP_RECVERR,
+                libc::IP_MTU,
+                20,
+                MIN_IPV4_MTU,
+            ),
+            SocketAddr::V6(_) => (
+                libc::IPPROTO_IPV6,
+                libc::IPV6_MTU_DISCOVER,
+                libc::IPV6_RECVERR,
+                libc::IPV6_MTU,
+                40,
+                MIN_IPV6_MTU,
+            ),
+        };
+
+    // Both `IP_PMTUDISC_DO` and `IPV6_PMTUDISC_DO` are equal to 2
+    error_queue::set_int_option(fd, level, discover_option, libc::IP_PMTUDISC_DO)?;
+    error_queue::set_int_option(fd, level, recverr_option, 1)?;
+
+    // A bogus MTU (such as zero) reported by the kernel or a router would leave
+    // no room for the headers
+    let clamp = |mtu: usize| mtu.clamp(min_mtu, MAX_PACKET_SIZE);
+    let mut mtu = clamp(get_int_option(fd, level, mtu_option)? as usize);
+    let probe = vec![0u8; MAX_PACKET_SIZE];
+
+    for _ in 0..MAX_PROBES {
+        let payload_len = mtu - ip_header_len - UDP_HEADER_LEN;
+
+        let reported = match socket.send(&probe[..payload_len]) {
+            // The kernel already knows a smaller MTU of this path
+            Err(ref error) if error.raw_os_error() == Some(libc::EMSGSIZE) => {
+                Some(clamp(get_int_option(fd, level, mtu_option)? as usize))
+            }
+            // A previous probe has reached the receiver's closed port
+            Err(ref error) if error.raw_os_error() == Some(libc::ECONNREFUSED) => None,
+            Err(error) => return Err(error),
+            Ok(_) => wait_for_mtu(fd)?.map(clamp),
+        };
+
+        match reported {
+            Some(reported) if reported < mtu => mtu = reported,
+            _ => return Ok(mtu),
+        }
+    }
+
+    Ok(mtu)
+}
+
+/// Waits for an error queued on `fd` and returns the MTU reported by it, or
+/// `None` if the probe wasn't rejected because of its size.
+fn wait_for_mtu(fd: RawFd) -> io::Result<Option<usize>> {
+    let mut poll_fd = libc::pollfd {
+        fd,
+        events: 0,
+        revents: 0,
+    };
+
+    loop {
+        match unsafe { libc::poll(&mut poll_fd, 1, PROBE_TIMEOUT_MILLIS) } {
+            -1 => return Err(io::Error::last_os_error()),
+            0 => return Ok(None),
+            _ => match read_error(fd)? {
+                Some(error) => return Ok(reported_mtu(&error).map(|mtu| mtu as usize)),
+                // Something else woke us up, keep waiting
+                None => continue,
+            },
+        }
+    }
+}
+
+/// Reads the next extended error queued on `fd` without blocking.
+fn read_error(fd: RawFd) -> io::Result<Option<libc::sock_extended_err>> {
+    let mut error = None;
+    error_queue::read_next(fd, |message| {
+        error = error.or_else(|| message.extended_error());
+    })?;
+
+    Ok(error)
+}
+
+/// Returns the MTU reported by `error` if it was caused by a too large probe.
+fn reported_mtu(error: &libc::sock_extended_err) -> Option<u32> {
+    if error.ee_errno != libc::EMSGSIZE as u32 {
+        return None;
+    }
+
+    match error.ee_origin {
+        // The MTU is known by the kernel itself
+        libc::SO_EE_ORIGIN_LOCAL => Some(error.ee_info),
+        // Destination Unreachable, Fragmentation Needed
+        libc::SO_EE_ORIGIN_ICMP if error.ee_type == 3 && error.ee_code == 4 => Some(error.ee_info),
+        // Packet Too Big
+        libc::SO_EE_ORIGIN_ICMP6 if error.ee_type == 2 => Some(error.ee_info),
+        _ => None,
+    }
+}
+
+fn get_int_option(fd: RawFd, level: libc::c_int, name: libc::c_int) -> io::Result<libc::c_int> {
+    let mut value: libc::c_int = 0;
+    let mut length = mem::size_of_val(&value) as libc::socklen_t;
+
+    match unsafe {
+        libc::getsockopt(
+            fd,
+            level,
+            name,
+            &mut value as *mut _ as *mut libc::c_void,
+            &mut length,
+        )
+    } {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extended_err(origin: u8, icmp_type: u8, icmp_code: u8) -> libc::sock_extended_err {
+        libc::sock_extended_err {
+            ee_errno: libc::EMSGSIZE as u32,
+            ee_origin: origin,
+            ee_type: icmp_type,
+            ee_code: icmp_code,
+            ee_pad: 0,
+            ee_info: 1400,
+            ee_data: 0,
+        }
+    }
+
+    #[test]
+    fn extracts_reported_mtus() {
+        assert_eq!(
+            reported_mtu(&extended_err(libc::SO_EE_ORIGIN_ICMP, 3, 4)),
+            Some(1400)
+        );
+        assert_eq!(
+            reported_mtu(&extended_err(libc::SO_EE_ORIGIN_ICMP6, 2, 0)),
+            Some(1400)
+        );
+        assert_eq!(
+            reported_mtu(&extended_err(libc::SO_EE_ORIGIN_LOCAL, 0, 0)),
+            Some(1400)
+        );
+
+        // Port Unreachable means that a probe has reached the receiver
+        let mut unreachable = extended_err(libc::SO_EE_ORIGIN_ICMP, 3, 3);
+        unreachable.ee_errno = libc::ECONNREFUSED as u32;
+        assert_eq!(reported_mtu(&unreachable), None);
+    }
+
+    #[test]
+    fn discovers_loopback_mtu() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").expect("UdpSocket::bind() has failed");
+        let mtu = discover(&receiver.local_addr().unwrap()).expect("discover() has failed");
+
+        // The loopback interface usually has an MTU of 65536 bytes
+        assert!(mtu >= 1280);
+    }
+}
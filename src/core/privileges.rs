@@ -0,0 +1,266 @@
+// anevicon: A high-performant UDP-based load generator, written in Rust.
+// Copyright (C) 2019  Temirkhan Myrzamadi <gymmasssorla@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// For more information see <https://github.com/Gymmasssorla/anevicon>.
+
+//! Dropping root privileges once the sockets of a test are created
+//! (`--drop-privileges`).
+
+use std::ffi::{CStr, CString};
+use std::io;
+use std::mem;
+use std::ptr;
+use std::sync::{Condvar, Mutex, MutexGuard};
+
+/// An unprivileged user to switch to.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Account {
+    name: CString,
+    uid: libc::uid_t,
+    gid: libc::gid_t,
+}
+
+#[derive(Debug, Fail)]
+pub enum DropPrivilegesError {
+    #[fail(display = "There is no user named '{}'", _0)]
+    UnknownUser(String),
+
+    #[fail(display = "Failed to look up the user '{}'", user)]
+    LookupFailed {
+        #[fail(cause)]
+        source: io::Error,
+        user: String,
+    },
+
+    #[fail(display = "{} has failed", call)]
+    CallFailed {
+        #[fail(cause)]
+        source: io::Error,
+        call: &'static str,
+    },
+
+    #[fail(display = "The root privileges can still be regained after setuid()")]
+    NotDropped,
+}
+
+impl Account {
+    /// Looks up the user named `user` in the password database.
+    pub fn lookup(user: &str) -> Result<Account, DropPrivilegesError> {
+        let name =
+            CString::new(user).map_err(|_| DropPrivilegesError::UnknownUser(user.to_owned()))?;
+        let mut entry = unsafe { mem::zeroed::<libc::passwd>() };
+        let mut buffer = vec![0 as libc::c_char; 16 * 1024];
+        let mut result = ptr::null_mut();
+
+        let code = unsafe {
+            libc::getpwnam_r(
+                name.as_ptr(),
+                &mut entry,
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                &mut result,
+            )
+        };
+        if code != 0 {
+            return Err(DropPrivilegesError::LookupFailed {
+                source: io::Error::from_raw_os_error(code),
+                user: user.to_owned(),
+            });
+        }
+        if result.is_null() {
+            return Err(DropPrivilegesError::UnknownUser(user.to_owned()));
+        }
+
+        Ok(Account {
+            name: unsafe { CStr::from_ptr(entry.pw_name) }.to_owned(),
+            uid: entry.pw_uid,
+            gid: entry.pw_gid,
+        })
+    }
+
+    /// Switches the whole process (all its threads) to this user and its
+    /// groups, so the root privileges cannot be regained. Already open sockets
+    /// stay usable.
+    pub fn switch_to(&self) -> Result<(), DropPrivilegesError> {
+        let check = |code: libc::c_int, call| match code {
+            -1 => Err(DropPrivilegesError::CallFailed {
+                source: io::Error::last_os_error(),
+                call,
+            }),
+            _ => Ok(()),
+        };
+
+        // The groups must be changed while the process is still privileged
+        check(
+            unsafe { libc::initgroups(self.name.as_ptr(), self.gid) },
+            "initgroups()",
+        )?;
+        check(unsafe { libc::setgid(self.gid) }, "setgid()")?;
+        check(unsafe { libc::setuid(self.uid) }, "setuid()")?;
+
+        if self.uid != 0 && unsafe { libc::setuid(0) } == 0 {
+            return Err(DropPrivilegesError::NotDropped);
+        }
+        Ok(())
+    }
+
+    pub fn name(&self) -> &str {
+        self.name.to_str().unwrap_or("?")
+    }
+}
+
+/// Holds testers back after they've created their sockets, so the privileges
+/// can be dropped before any packet is sent.
+///
+/// Each tester takes a `GateTicket` before creating its sockets and calls
+/// `GateTicket::arrive` after that. A tester failing before it arrives just
+/// drops its ticket, so it's never waited for.
+#[derive(Debug, Default)]
+pub struct StartGate {
+    state: Mutex<GateState>,
+    changed: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct GateState {
+    /// A number of the testers which have arrived or dropped their tickets.
+    settled: usize,
+
+    /// Whether the privileges have been dropped, or `None` if the gate is
+    /// still closed.
+    outcome: Option<bool>,
+}
+
+/// A tester's place at `StartGate`.
+pub struct GateTicket<'g> {
+    gate: &'g StartGate,
+    settled: bool,
+}
+
+impl StartGate {
+    pub fn ticket(&self) -> GateTicket<'_> {
+        GateTicket {
+            gate: self,
+            settled: false,
+        }
+    }
+
+    /// Blocks until `testers` tickets are settled.
+    pub fn wait_for(&self, testers: usize) {
+        let mut state = self.state.lock().expect("The start gate is poisoned");
+        while state.settled < testers {
+            state = self
+                .changed
+                .wait(state)
+                .expect("The start gate is poisoned");
+        }
+    }
+
+    /// Lets the waiting testers go on. `dropped` tells them whether the
+    /// privileges have been dropped.
+    pub fn open(&self, dropped: bool) {
+        self.state
+            .lock()
+            .expect("The start gate is poisoned")
+            .outcome = Some(dropped);
+        self.changed.notify_all();
+    }
+
+    /// Counts one more settled ticket and returns the locked state.
+    fn settle(&self) -> MutexGuard<'_, GateState> {
+        let mut state = self.state.lock().expect("The start gate is poisoned");
+        state.settled += 1;
+        self.changed.notify_all();
+        state
+    }
+}
+
+impl<'g> GateTicket<'g> {
+    /// Blocks until the gate is opened and returns whether the privileges
+    /// have been dropped.
+    pub fn arrive(mut self) -> bool {
+        self.settled = true;
+
+        let mut state = self.gate.settle();
+        loop {
+            match state.outcome {
+                Some(dropped) => return dropped,
+                None => {
+                    state = self
+                        .gate
+                        .changed
+                        .wait(state)
+                        .expect("The start gate is poisoned")
+                }
+            }
+        }
+    }
+}
+
+impl<'g> Drop for GateTicket<'g> {
+    fn drop(&mut self) {
+        if !self.settled {
+            drop(self.gate.settle());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn looks_up_accounts() {
+        let root = Account::lookup("root").expect("Account::lookup() has failed");
+        assert_eq!((root.uid, root.gid, root.name()), (0, 0, "root"));
+
+        match Account::lookup("no-such-user-here") {
+            Err(DropPrivilegesError::UnknownUser(user)) => assert_eq!(user, "no-such-user-here"),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn waits_for_settled_testers() {
+        let gate = Arc::new(StartGate::default());
+
+        let testers = (0..3)
+            .map(|index| {
+                let gate = gate.clone();
+                thread::spawn(move || {
+                    let ticket = gate.ticket();
+                    // A failed tester leaves without waiting
+                    if index == 0 {
+                        return None;
+                    }
+                    Some(ticket.arrive())
+                })
+            })
+            .collect::<Vec<_>>();
+
+        gate.wait_for(3);
+        gate.open(true);
+
+        let outcomes = testers
+            .into_iter()
+            .map(|tester| tester.join().unwrap())
+            .collect::<Vec<Option<bool>>>();
+        assert_eq!(outcomes, vec![None, Some(true), Some(true)]);
+    }
+}
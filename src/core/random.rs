@@ -0,0 +1,79 @@
+// anevicon: A high-performant UDP-based load generator, written in Rust.
+// Copyright (C) 2019  Temirkhan Myrzamadi <gymmasssorla@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// For more information see <https://github.com/Gymmasssorla/anevicon>.
+
+//! The random number generator of each thread, which can be seeded by
+//! `--random-seed` to make tests reproducible.
+//!
+//! The main thread, which crafts datagrams and orders the endpoints, is seeded
+//! by the seed itself. The worker of the endpoint `i` is seeded by
+//! `seed ^ i`, so each endpoint gets the same random stream in each run
+//! regardless of how the workers are scheduled. A single thread sending to
+//! all the endpoints (`--fair-interleave`) is seeded by the seed itself, and
+//! its endpoints share the stream in their fixed order.
+//!
+//! Streams are only reproducible by the same build of anevicon, since the
+//! algorithm of `StdRng` might change between versions of `rand`.
+
+use std::cell::RefCell;
+
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+
+thread_local! {
+    static GENERATOR: RefCell<Option<StdRng>> = const { RefCell::new(None) };
+}
+
+/// Seeds the generator of the current thread, so it produces the same
+/// numbers each time it's seeded by the same `seed`.
+pub fn seed(seed: u64) {
+    GENERATOR.with(|generator| *generator.borrow_mut() = Some(StdRng::seed_from_u64(seed)));
+}
+
+/// Returns the seed of the worker of the endpoint `index`.
+pub fn worker_seed(seed: u64, index: usize) -> u64 {
+    seed ^ index as u64
+}
+
+/// Calls `f` with the generator of the current thread: the seeded one, or
+/// `rand::thread_rng()` if `seed` hasn't been called.
+pub fn with<T, F: FnOnce(&mut dyn RngCore) -> T>(f: F) -> T {
+    GENERATOR.with(|generator| match *generator.borrow_mut() {
+        Some(ref mut generator) => f(generator),
+        None => f(&mut rand::thread_rng()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+
+    #[test]
+    fn reproduces_seeded_streams() {
+        let numbers = |seed_value| {
+            seed(seed_value);
+            (0..8)
+                .map(|_| with(|generator| generator.gen::<u64>()))
+                .collect::<Vec<u64>>()
+        };
+
+        assert_eq!(numbers(42), numbers(42));
+        assert_ne!(numbers(42), numbers(worker_seed(42, 1)));
+    }
+}
@@ -0,0 +1,258 @@
+// anevicon: A high-performant UDP-based load generator, written in Rust.
+// Copyright (C) 2019  Temirkhan Myrzamadi <gymmasssorla@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// For more information see <https://github.com/Gymmasssorla/anevicon>.
+
+//! Counting of the datagrams replied by a receiver and the ICMP errors
+//! caused by the sent ones (`--count-responses`).
+
+use std::collections::HashSet;
+use std::io;
+use std::net::{IpAddr, UdpSocket};
+use std::os::unix::io::AsRawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::config::Endpoints;
+use crate::core::udp_sender::icmp;
+
+/// How often the receiving thread checks whether it must stop.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long responses are still awaited after the last packet is sent.
+const RESPONSE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Counts the datagrams sent back by a receiver on a separate thread.
+///
+/// Packets are sent through raw sockets, which don't receive anything, so the
+/// replies are received by a regular UDP socket bound to the sender address
+/// and connected to the receiver one. Thus, only the replies to the same
+/// 4-tuple are counted, and the sender address must be a fixed local one.
+///
+/// The kernel also queues the ICMP errors caused by the sent datagrams on
+/// this socket, so they are counted together with their sources.
+pub struct ResponseCounter {
+    stopped: Arc<AtomicBool>,
+
+    /// Whether the last packet has been sent, see `ResponseCounter::finish`.
+    cooling_down: Arc<AtomicBool>,
+    thread: Option<JoinHandle<Responses>>,
+}
+
+/// Everything received by `ResponseCounter` during a test.
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct Responses {
+    pub datagrams: u64,
+    pub icmp_errors: u64,
+
+    /// The distinct hosts which have sent ICMP errors: a receiver itself or
+    /// intermediate routers.
+    pub icmp_sources: HashSet<IpAddr>,
+
+    /// The ICMP errors (included into `icmp_errors`) received after the last
+    /// packet has been sent, while `ResponseCounter::finish` was waiting.
+    pub cooldown_icmp_errors: u64,
+}
+
+impl ResponseCounter {
+    /// Binds a socket to the sender of `endpoints`, connects it to the
+    /// receiver, and starts counting the datagrams and ICMP errors received
+    /// by it.
+    pub fn spawn(endpoints: &Endpoints) -> io::Result<ResponseCounter> {
+        let socket = UdpSocket::bind(endpoints.sender())?;
+        socket.connect(endpoints.receiver())?;
+        socket.set_read_timeout(Some(POLL_INTERVAL))?;
+        icmp::enable(socket.as_raw_fd(), endpoints.receiver().is_ipv6())?;
+
+        let stopped = Arc::new(AtomicBool::new(false));
+        let cooling_down = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let (stopped, cooling_down) = (stopped.clone(), cooling_down.clone());
+            thread::spawn(move || receive(&socket, &stopped, &cooling_down))
+        };
+
+        Ok(ResponseCounter {
+            stopped,
+            cooling_down,
+            thread: Some(thread),
+        })
+    }
+
+    /// Waits for late responses during `cooldown` (`--cooldown`, or 500
+    /// milliseconds if it's `None`), stops counting, and returns everything
+    /// received. It must be called right after the last packet is sent.
+    pub fn finish(mut self, cooldown: Option<Duration>) -> Responses {
+        self.cooling_down.store(true, Ordering::Relaxed);
+        thread::sleep(cooldown.unwrap_or(RESPONSE_TIMEOUT));
+        self.stop().unwrap_or_default()
+    }
+
+    fn stop(&mut self) -> Option<Responses> {
+        self.stopped.store(true, Ordering::Relaxed);
+        self.thread.take().map(|thread| {
+            thread
+                .join()
+                .expect("The response counting thread has panicked")
+        })
+    }
+}
+
+impl Drop for ResponseCounter {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn receive(socket: &UdpSocket, stopped: &AtomicBool, cooling_down: &AtomicBool) -> Responses {
+    let mut responses = Responses::default();
+    let mut buffer = [0u8; 1];
+
+    while !stopped.load(Ordering::Relaxed) {
+        // A datagram is counted even if it's truncated by the small buffer
+        let error = match socket.recv(&mut buffer) {
+            Ok(_) => {
+                responses.datagrams += 1;
+                continue;
+            }
+            Err(error) => error,
+        };
+
+        // A queued ICMP error is also reported by `recv` as a pending one
+        let icmp_errors = icmp::read_all(socket.as_raw_fd()).unwrap_or_default();
+        let reported = !icmp_errors.is_empty();
+        let cooling_down = cooling_down.load(Ordering::Relaxed);
+        for icmp_error in icmp_errors {
+            responses.icmp_errors += 1;
+            if cooling_down {
+                responses.cooldown_icmp_errors += 1;
+            }
+            responses.icmp_sources.extend(icmp_error.source);
+        }
+
+        match error.kind() {
+            io::ErrorKind::WouldBlock
+            | io::ErrorKind::TimedOut
+            | io::ErrorKind::ConnectionRefused => (),
+            _ if reported => (),
+            _ => {
+                log::warn!(
+                    "stopped counting responses from {receiver} because of an error: \
+                         {error}!",
+                    receiver = socket
+                        .peer_addr()
+                        .map_or_else(|_| String::from("a receiver"), |addr| addr.to_string()),
+                    error = error,
+                );
+                return responses;
+            }
+        }
+    }
+
+    responses
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use structopt::StructOpt;
+
+    use crate::config::SendConfig;
+    use crate::core::craft_datagrams;
+    use crate::core::statistics::TestSummary;
+    use crate::core::udp_sender::UdpSender;
+
+    use super::*;
+
+    #[test]
+    fn counts_responses() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").expect("UdpSocket::bind() has failed");
+        let sender = UdpSocket::bind("127.0.0.1:0").expect("UdpSocket::bind() has failed");
+        let sender_addr = sender.local_addr().unwrap();
+        drop(sender);
+
+        let endpoints = format!(
+            "{sender}&{receiver}",
+            sender = sender_addr,
+            receiver = receiver.local_addr().unwrap()
+        )
+        .parse::<Endpoints>()
+        .expect("Failed to parse endpoints");
+        let counter = ResponseCounter::spawn(&endpoints).expect("ResponseCounter::spawn() failed");
+
+        for _ in 0..3 {
+            receiver
+                .send_to(b"Reply", sender_addr)
+                .expect("UdpSocket::send_to() failed");
+        }
+
+        // Datagrams from other addresses aren't responses
+        let stranger = UdpSocket::bind("127.0.0.1:0").expect("UdpSocket::bind() has failed");
+        stranger
+            .send_to(b"Noise", sender_addr)
+            .expect("UdpSocket::send_to() failed");
+
+        assert_eq!(counter.finish(None).datagrams, 3);
+    }
+
+    #[test]
+    fn counts_cooldown_icmp_errors() {
+        let closed = UdpSocket::bind("127.0.0.1:0").expect("UdpSocket::bind() has failed");
+        let closed_addr = closed.local_addr().unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").expect("UdpSocket::bind() has failed");
+        let sender_addr = sender.local_addr().unwrap();
+        drop((closed, sender));
+
+        let config = SendConfig::from_iter(&[
+            "send",
+            "--endpoints",
+            &format!("{}&{}", sender_addr, closed_addr),
+            "--send-message",
+            "Is anybody there?",
+        ]);
+        let endpoints = config.packets_config.endpoints[0];
+        let datagram = craft_datagrams::craft_all(&config.packets_config)
+            .expect("craft_all() failed")
+            .remove(0)
+            .remove(0);
+        let send = move |delay| {
+            thread::sleep(delay);
+            UdpSender::new(NonZeroUsize::new(1).unwrap(), &closed_addr, false)
+                .expect("UdpSender::new(...) failed")
+                .send_datagram(&mut TestSummary::default(), &datagram)
+                .expect("sender.send_datagram(...) failed");
+        };
+
+        let counter = ResponseCounter::spawn(&endpoints).expect("ResponseCounter::spawn() failed");
+
+        // One error arrives during the test, and another one during the cooldown
+        send(Duration::from_millis(0));
+        thread::sleep(Duration::from_millis(200));
+        let late = thread::spawn({
+            let send = send.clone();
+            move || send(Duration::from_millis(200))
+        });
+
+        let responses = counter.finish(Some(Duration::from_millis(800)));
+        late.join().expect("The sending thread has panicked");
+
+        assert_eq!(responses.icmp_errors, 2);
+        assert_eq!(responses.cooldown_icmp_errors, 1);
+        assert!(responses.icmp_sources.contains(&closed_addr.ip()));
+    }
+}
@@ -0,0 +1,125 @@
+// anevicon: A high-performant UDP-based load generator, written in Rust.
+// Copyright (C) 2019  Temirkhan Myrzamadi <gymmasssorla@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// For more information see <https://github.com/Gymmasssorla/anevicon>.
+
+//! The implementation of the `self-test` subcommand.
+
+use std::net::UdpSocket;
+use std::num::NonZeroUsize;
+use std::time::Duration;
+
+use failure::Fallible;
+
+use crate::config::{Endianness, Endpoints, PacketsConfig, PayloadConfig};
+use crate::core::craft_datagrams;
+use crate::core::statistics::TestSummary;
+use crate::core::udp_sender::UdpSender;
+
+const SELF_TEST_MESSAGE: &str = "anevicon self-test";
+const SELF_TEST_PACKETS: usize = 8;
+
+#[derive(Debug, Fail)]
+pub enum SelfTestError {
+    #[fail(display = "Failed to setup a loopback receiver")]
+    SetupReceiver(#[fail(cause)] std::io::Error),
+
+    #[fail(display = "Failed to receive a datagram from the loopback receiver")]
+    Receive(#[fail(cause)] std::io::Error),
+
+    #[fail(display = "The loopback receiver has got a corrupted datagram")]
+    CorruptedDatagram,
+}
+
+/// Sends several raw UDP/IPv4 datagrams to a loopback `UdpSocket` using the
+/// same code paths as the `send` subcommand, and checks that all of them are
+/// received intact.
+pub fn run_self_test() -> Fallible<()> {
+    let receiver = UdpSocket::bind("127.0.0.1:0").map_err(SelfTestError::SetupReceiver)?;
+    receiver
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .map_err(SelfTestError::SetupReceiver)?;
+
+    let local_addr = receiver
+        .local_addr()
+        .map_err(SelfTestError::SetupReceiver)?;
+    let endpoints = format!("{0}&{0}", local_addr)
+        .parse::<Endpoints>()
+        .expect("Failed to parse loopback endpoints");
+
+    let datagram = craft_datagrams::craft_all(&PacketsConfig {
+        endpoints: vec![endpoints],
+        ip_ttl: 64,
+        multicast_ttl: None,
+        receiver_port_range: None,
+        randomize_ip_id: false,
+        randomize_flow_label: false,
+        vary_per_endpoint: false,
+        payload_weights: Vec::new(),
+        aggregate: None,
+        set_reserved_flag: false,
+        ip_option: None,
+        mutate_bits: None,
+        sequence_number: false,
+        seq_offset: 0,
+        seq_width: 4,
+        seq_endian: Endianness::Big,
+        payload_config: PayloadConfig {
+            random_packets: Vec::new(),
+            send_files: Vec::new(),
+            send_dirs: Vec::new(),
+            send_messages: vec![String::from(SELF_TEST_MESSAGE)],
+            send_commands: Vec::new(),
+            send_command_each: false,
+            mmap_files: false,
+            fill_pattern: None,
+            repeat_file: NonZeroUsize::new(1).unwrap(),
+            warn_entropy: None,
+        },
+    })?
+    .remove(0)
+    .remove(0);
+
+    let mut summary = TestSummary::default();
+    let mut sender = UdpSender::new(NonZeroUsize::new(1).unwrap(), &local_addr, false)?;
+
+    let mut buffer = [0u8; 512];
+    for _ in 0..SELF_TEST_PACKETS {
+        sender.send_datagram(&mut summary, &datagram)?;
+
+        let received = receiver.recv(&mut buffer).map_err(SelfTestError::Receive)?;
+        if &buffer[..received] != SELF_TEST_MESSAGE.as_bytes() {
+            return Err(SelfTestError::CorruptedDatagram.into());
+        }
+    }
+
+    log::debug!(
+        "{packets} self-test datagrams have been received by {receiver}.",
+        packets = summary.packets_sent(),
+        receiver = local_addr,
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn self_test_passes() {
+        run_self_test().expect("run_self_test() failed");
+    }
+}
@@ -0,0 +1,171 @@
+// anevicon: A high-performant UDP-based load generator, written in Rust.
+// Copyright (C) 2019  Temirkhan Myrzamadi <gymmasssorla@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// For more information see <https://github.com/Gymmasssorla/anevicon>.
+
+//! The per-interval records streamed by `--json-lines`.
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use serde::Serialize;
+
+use super::TestSummary;
+
+/// A snapshot of `TestSummary` of a single endpoint taken right after a
+/// reporting interval is finished. The counters are cumulative, except those
+/// prefixed with `interval_`, and the rates are of the last interval only.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct IntervalRecord {
+    pub sender: String,
+    pub receiver: String,
+    pub interval: usize,
+    pub seconds_passed: f64,
+    pub bytes_expected: u64,
+    pub bytes_sent: u64,
+    pub packets_expected: u64,
+    pub packets_sent: u64,
+    pub interval_bytes_sent: u64,
+    pub interval_packets_sent: u64,
+    pub interval_seconds: f64,
+    pub packets_per_sec: f64,
+    pub bits_per_sec: f64,
+}
+
+/// Writes `IntervalRecord`s into a file as newline-delimited JSON, one object
+/// per line. Testers running on different threads share a single writer.
+pub struct JsonLinesWriter {
+    file: Mutex<File>,
+    failed: AtomicBool,
+}
+
+impl IntervalRecord {
+    /// Returns a record of the last finished interval of `summary`, or `None`
+    /// if no interval is finished yet.
+    pub fn new(sender: String, receiver: String, summary: &TestSummary) -> Option<IntervalRecord> {
+        let last_interval = summary.last_interval()?;
+        let interval_seconds = last_interval.duration.as_secs_f64();
+        let per_sec = |value: u64| {
+            if interval_seconds == 0.0 {
+                0.0
+            } else {
+                value as f64 / interval_seconds
+            }
+        };
+
+        Some(IntervalRecord {
+            sender,
+            receiver,
+            interval: summary.intervals_finished() - 1,
+            seconds_passed: summary.time_passed().as_secs_f64(),
+            bytes_expected: summary.bytes_expected(),
+            bytes_sent: summary.bytes_sent(),
+            packets_expected: summary.packets_expected(),
+            packets_sent: summary.packets_sent(),
+            interval_bytes_sent: last_interval.bytes_sent,
+            interval_packets_sent: last_interval.packets_sent,
+            interval_seconds,
+            packets_per_sec: per_sec(last_interval.packets_sent),
+            bits_per_sec: per_sec(last_interval.bytes_sent * 8),
+        })
+    }
+}
+
+impl JsonLinesWriter {
+    /// Creates the file located at `path`, replacing its content.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<JsonLinesWriter> {
+        Ok(JsonLinesWriter {
+            file: Mutex::new(File::create(path)?),
+            failed: AtomicBool::new(false),
+        })
+    }
+
+    /// Appends `record` as a single line and flushes the file, so a consumer
+    /// sees each record as soon as it's written.
+    ///
+    /// A failed write doesn't stop a test: the first failure is logged, and
+    /// then all the next records are discarded.
+    pub fn write(&self, record: &IntervalRecord) {
+        if self.failed.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let mut line = serde_json::to_vec(record).expect("An interval record is always encodable");
+        line.push(b'\n');
+
+        let mut file = self.file.lock().expect("The --json-lines file is poisoned");
+        if let Err(error) = file.write_all(&line).and_then(|_| file.flush()) {
+            if !self.failed.swap(true, Ordering::Relaxed) {
+                log::error!(
+                    "failed to write into the --json-lines file, the next records are \
+                     discarded!\n    Caused by: {error}",
+                    error = error,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::core::statistics::SummaryPortion;
+
+    #[test]
+    fn writes_interval_records() {
+        let mut summary = TestSummary::default();
+        let record = |summary: &TestSummary| {
+            IntervalRecord::new(
+                String::from("127.0.0.1:17333"),
+                String::from("127.0.0.1:80"),
+                summary,
+            )
+        };
+        assert_eq!(record(&summary), None);
+
+        summary.update(SummaryPortion::new(3000, 3000, 3, 3));
+        thread::sleep(Duration::from_millis(10));
+        summary.finish_interval();
+        summary.update(SummaryPortion::new(1000, 1000, 1, 1));
+        thread::sleep(Duration::from_millis(10));
+        summary.finish_interval();
+
+        let path = std::env::temp_dir().join(format!("anevicon-{}.ndjson", std::process::id()));
+        let writer = JsonLinesWriter::create(&path).expect("JsonLinesWriter::create() failed");
+        writer.write(&record(&summary).unwrap());
+        writer.write(&record(&summary).unwrap());
+
+        let content = fs::read_to_string(&path).expect("fs::read_to_string() failed");
+        fs::remove_file(&path).unwrap();
+        let lines = content.lines().collect::<Vec<&str>>();
+        assert_eq!(lines.len(), 2);
+
+        let value = serde_json::from_str::<serde_json::Value>(lines[0]).unwrap();
+        assert_eq!(value["receiver"], "127.0.0.1:80");
+        assert_eq!(value["interval"], 1);
+        assert_eq!(value["packets_sent"], 4);
+        assert_eq!(value["interval_packets_sent"], 1);
+        assert_eq!(value["interval_bytes_sent"], 1000);
+        assert!(value["packets_per_sec"].as_f64().unwrap() > 0.0);
+    }
+}
@@ -0,0 +1,150 @@
+// anevicon: A high-performant UDP-based load generator, written in Rust.
+// Copyright (C) 2019  Temirkhan Myrzamadi <gymmasssorla@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// For more information see <https://github.com/Gymmasssorla/anevicon>.
+
+use std::convert::TryFrom;
+use std::time::Duration;
+
+const BUCKETS: usize = 64;
+
+/// A histogram of latencies with power-of-two buckets (in nanoseconds), so it
+/// occupies a constant amount of memory regardless of a test duration.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct LatencyHistogram {
+    buckets: [usize; BUCKETS],
+    count: usize,
+    min: Duration,
+    max: Duration,
+}
+
+/// The statistics of recorded latencies. `min` and `max` are exact, while
+/// `median` and `p95` are upper bounds of the buckets containing them.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct LatencyStats {
+    pub min: Duration,
+    pub max: Duration,
+    pub median: Duration,
+    pub p95: Duration,
+}
+
+impl LatencyHistogram {
+    pub fn record(&mut self, latency: Duration) {
+        let nanos = u64::try_from(latency.as_nanos()).unwrap_or(u64::MAX);
+        self.buckets[bucket_of(nanos)] += 1;
+
+        if self.count == 0 || latency < self.min {
+            self.min = latency;
+        }
+        if latency > self.max {
+            self.max = latency;
+        }
+        self.count += 1;
+    }
+
+    /// Returns the statistics of all the recorded latencies, or `None` if
+    /// there weren't any. The percentiles are computed using the nearest-rank
+    /// method.
+    pub fn stats(&self) -> Option<LatencyStats> {
+        if self.count == 0 {
+            return None;
+        }
+
+        let percentile = |percent: usize| {
+            let rank = (percent * self.count).div_ceil(100).max(1);
+            let mut seen = 0;
+
+            for (index, &count) in self.buckets.iter().enumerate() {
+                seen += count;
+                if seen >= rank {
+                    return bucket_upper_bound(index).clamp(self.min, self.max);
+                }
+            }
+
+            self.max
+        };
+
+        Some(LatencyStats {
+            min: self.min,
+            max: self.max,
+            median: percentile(50),
+            p95: percentile(95),
+        })
+    }
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> LatencyHistogram {
+        LatencyHistogram {
+            buckets: [0; BUCKETS],
+            count: 0,
+            min: Duration::from_secs(0),
+            max: Duration::from_secs(0),
+        }
+    }
+}
+
+/// The bucket `index` holds latencies of `[2^index, 2^(index + 1))`
+/// nanoseconds, except the first one which also holds zero.
+fn bucket_of(nanos: u64) -> usize {
+    (63 - nanos.max(1).leading_zeros()) as usize
+}
+
+fn bucket_upper_bound(index: usize) -> Duration {
+    Duration::from_nanos(
+        1u64.checked_shl(index as u32 + 1)
+            .map_or(u64::MAX, |bound| bound - 1),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_histogram_has_no_stats() {
+        assert_eq!(LatencyHistogram::default().stats(), None);
+    }
+
+    #[test]
+    fn computes_latency_stats() {
+        let mut histogram = LatencyHistogram::default();
+        for micros in 1..=100 {
+            histogram.record(Duration::from_micros(micros));
+        }
+
+        let stats = histogram.stats().unwrap();
+        assert_eq!(stats.min, Duration::from_micros(1));
+        assert_eq!(stats.max, Duration::from_micros(100));
+
+        // The exact values are 50us and 95us, but the buckets are
+        // [32.768us, 65.536us) and [65.536us, 131.072us)
+        assert_eq!(stats.median, Duration::from_nanos(65_535));
+        assert_eq!(stats.p95, Duration::from_micros(100));
+    }
+
+    #[test]
+    fn handles_extreme_latencies() {
+        let mut histogram = LatencyHistogram::default();
+        histogram.record(Duration::from_secs(0));
+        histogram.record(Duration::from_secs(u64::MAX));
+
+        let stats = histogram.stats().unwrap();
+        assert_eq!(stats.min, Duration::from_secs(0));
+        assert_eq!(stats.max, Duration::from_secs(u64::MAX));
+        assert_eq!(stats.median, Duration::from_nanos(1));
+    }
+}
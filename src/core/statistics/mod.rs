@@ -18,8 +18,15 @@
 
 //! The module containing abstractions to analyse test execution results.
 
+pub use json_lines::{IntervalRecord, JsonLinesWriter};
+pub use shared_counters::SharedCounters;
 pub use summary_portion::SummaryPortion;
+pub use summary_report::{write_reports, SummaryReport};
 pub use test_summary::TestSummary;
 
+mod json_lines;
+mod latency_histogram;
+mod shared_counters;
 mod summary_portion;
+mod summary_report;
 mod test_summary;
@@ -0,0 +1,76 @@
+// anevicon: A high-performant UDP-based load generator, written in Rust.
+// Copyright (C) 2019  Temirkhan Myrzamadi <gymmasssorla@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// For more information see <https://github.com/Gymmasssorla/anevicon>.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::SummaryPortion;
+
+/// Counters of a single endpoint which its tester updates after each flush,
+/// while other threads (such as the `--tui` dashboard) read them during a
+/// test. Unlike `TestSummary`, they can be shared between threads.
+#[derive(Debug, Default)]
+pub struct SharedCounters {
+    packets_sent: AtomicU64,
+    bytes_sent: AtomicU64,
+    packets_failed: AtomicU64,
+}
+
+impl SharedCounters {
+    /// Adds `portion` produced by a flush of the endpoint's tester.
+    pub fn record(&self, portion: &SummaryPortion) {
+        self.packets_sent
+            .fetch_add(portion.packets_sent() as u64, Ordering::Relaxed);
+        self.bytes_sent
+            .fetch_add(portion.bytes_sent() as u64, Ordering::Relaxed);
+        self.packets_failed.fetch_add(
+            (portion.packets_expected() - portion.packets_sent()) as u64,
+            Ordering::Relaxed,
+        );
+    }
+
+    #[inline]
+    pub fn packets_sent(&self) -> u64 {
+        self.packets_sent.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    pub fn bytes_sent(&self) -> u64 {
+        self.bytes_sent.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    pub fn packets_failed(&self) -> u64 {
+        self.packets_failed.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_flushed_portions() {
+        let counters = SharedCounters::default();
+
+        counters.record(&SummaryPortion::new(300, 200, 3, 2));
+        counters.record(&SummaryPortion::new(100, 100, 1, 1));
+        assert_eq!(counters.packets_sent(), 3);
+        assert_eq!(counters.bytes_sent(), 300);
+        assert_eq!(counters.packets_failed(), 1);
+    }
+}
@@ -78,12 +78,53 @@ impl SummaryPortion {
     pub fn packets_sent(&self) -> usize {
         self.packets_sent
     }
+
+    /// Returns an average number of bytes per a sent packet, or zero if
+    /// nothing has been sent.
+    #[inline]
+    #[allow(dead_code)]
+    pub fn average_packet_size(&self) -> f64 {
+        if self.packets_sent == 0 {
+            0.0
+        } else {
+            self.bytes_sent as f64 / self.packets_sent as f64
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use proptest::prelude::*;
+
     use super::*;
 
+    /// Generates valid arguments of `SummaryPortion::new`.
+    fn valid_parts() -> impl Strategy<Value = (usize, usize, usize, usize)> {
+        (any::<usize>(), any::<usize>()).prop_flat_map(|(bytes_expected, packets_expected)| {
+            (
+                Just(bytes_expected),
+                0..=bytes_expected,
+                Just(packets_expected),
+                0..=packets_expected,
+            )
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn getters_return_constructed_parts(
+            (bytes_expected, bytes_sent, packets_expected, packets_sent) in valid_parts()
+        ) {
+            let portion =
+                SummaryPortion::new(bytes_expected, bytes_sent, packets_expected, packets_sent);
+
+            prop_assert_eq!(portion.bytes_expected(), bytes_expected);
+            prop_assert_eq!(portion.bytes_sent(), bytes_sent);
+            prop_assert_eq!(portion.packets_expected(), packets_expected);
+            prop_assert_eq!(portion.packets_sent(), packets_sent);
+        }
+    }
+
     #[test]
     fn summary_portion_valid_works() {
         let (bytes_expected, bytes_sent, packets_expected, packets_sent) = (18394, 1223, 94, 74);
@@ -96,6 +137,18 @@ mod tests {
         assert_eq!(portion.packets_sent(), packets_sent);
     }
 
+    #[test]
+    fn computes_average_packet_size() {
+        assert_eq!(
+            SummaryPortion::new(3000, 2500, 5, 4).average_packet_size(),
+            625.0
+        );
+        assert_eq!(
+            SummaryPortion::new(3000, 0, 5, 0).average_packet_size(),
+            0.0
+        );
+    }
+
     #[test]
     #[should_panic(expected = "bytes_sent cannot be higher than bytes_expected")]
     fn summary_portion_panics_bytes() {
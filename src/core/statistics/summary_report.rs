@@ -0,0 +1,233 @@
+// anevicon: A high-performant UDP-based load generator, written in Rust.
+// Copyright (C) 2019  Temirkhan Myrzamadi <gymmasssorla@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// For more information see <https://github.com/Gymmasssorla/anevicon>.
+
+//! The final summaries written by `--output-file`.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::Serialize;
+
+use super::TestSummary;
+use crate::config::{Endpoints, OutputFormat};
+
+/// A snapshot of `TestSummary` of a single endpoint taken right after its
+/// test is finished. All the formats encode it as a map with the field names
+/// as keys, so consumers don't depend on the field order.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SummaryReport {
+    pub sender: String,
+    pub receiver: String,
+    pub bytes_expected: u64,
+    pub bytes_sent: u64,
+    pub packets_expected: u64,
+    pub packets_sent: u64,
+    pub seconds_passed: f64,
+    pub packets_per_sec: f64,
+    pub bits_per_sec: f64,
+    pub retransmissions: u64,
+
+    /// Written only if `--count-responses` is specified.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub responses_received: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_rate: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icmp_errors: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icmp_sources: Option<usize>,
+
+    /// Written only if `--cooldown` is specified.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cooldown_icmp_errors: Option<u64>,
+
+    /// Written only if `--wire-accounting` is specified.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wire_bytes_sent: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wire_bits_per_sec: Option<f64>,
+}
+
+#[derive(Debug, Fail)]
+pub enum WriteReportError {
+    #[fail(display = "Failed to encode the summary as {}: {}", format, message)]
+    EncodeFailed {
+        format: OutputFormat,
+        message: String,
+    },
+
+    #[fail(display = "Failed to write the summary into {}", filename)]
+    WriteFailed {
+        #[fail(cause)]
+        source: io::Error,
+        filename: String,
+    },
+
+    #[fail(
+        display = "--output-format={} requires anevicon to be built with the `{}` feature",
+        _0, _0
+    )]
+    Unsupported(OutputFormat),
+}
+
+impl SummaryReport {
+    pub fn new(endpoints: &Endpoints, summary: &TestSummary) -> SummaryReport {
+        SummaryReport {
+            sender: endpoints.sender().to_string(),
+            receiver: endpoints.receiver().to_string(),
+            bytes_expected: summary.bytes_expected(),
+            bytes_sent: summary.bytes_sent(),
+            packets_expected: summary.packets_expected(),
+            packets_sent: summary.packets_sent(),
+            seconds_passed: summary.time_passed().as_secs_f64(),
+            packets_per_sec: summary.packets_per_sec_f64(),
+            bits_per_sec: summary.bits_per_sec_f64(),
+            retransmissions: summary.retransmissions(),
+            responses_received: summary.responses_received(),
+            response_rate: summary.response_rate(),
+            icmp_errors: summary.icmp_errors(),
+            icmp_sources: summary.icmp_errors().map(|_| summary.icmp_sources().len()),
+            cooldown_icmp_errors: summary.cooldown_icmp_errors(),
+            wire_bytes_sent: summary.wire_bytes_sent(),
+            wire_bits_per_sec: summary.wire_bits_per_sec_f64(),
+        }
+    }
+}
+
+/// Encodes `reports` as a sequence in the specified `format`.
+pub fn encode_reports(
+    reports: &[SummaryReport],
+    format: OutputFormat,
+) -> Result<Vec<u8>, WriteReportError> {
+    let encode_failed = |message: String| WriteReportError::EncodeFailed { format, message };
+
+    match format {
+        OutputFormat::Json => {
+            serde_json::to_vec_pretty(reports).map_err(|error| encode_failed(error.to_string()))
+        }
+        #[cfg(feature = "cbor")]
+        OutputFormat::Cbor => {
+            let mut bytes = Vec::new();
+            ciborium::ser::into_writer(reports, &mut bytes)
+                .map_err(|error| encode_failed(error.to_string()))?;
+            Ok(bytes)
+        }
+        #[cfg(feature = "msgpack")]
+        OutputFormat::Msgpack => {
+            rmp_serde::to_vec_named(reports).map_err(|error| encode_failed(error.to_string()))
+        }
+        #[allow(unreachable_patterns)]
+        _ => Err(WriteReportError::Unsupported(format)),
+    }
+}
+
+/// Encodes `reports` in the specified `format` and writes them into the file
+/// located at `path`, replacing its content.
+pub fn write_reports<P: AsRef<Path>>(
+    reports: &[SummaryReport],
+    format: OutputFormat,
+    path: P,
+) -> Result<(), WriteReportError> {
+    fs::write(path.as_ref(), encode_reports(reports, format)?).map_err(|error| {
+        WriteReportError::WriteFailed {
+            source: error,
+            filename: path.as_ref().to_string_lossy().into_owned(),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report() -> SummaryReport {
+        SummaryReport {
+            sender: String::from("127.0.0.1:17333"),
+            receiver: String::from("127.0.0.1:80"),
+            bytes_expected: 2048,
+            bytes_sent: 1024,
+            packets_expected: 2,
+            packets_sent: 1,
+            seconds_passed: 0.5,
+            packets_per_sec: 2.0,
+            bits_per_sec: 16384.0,
+            retransmissions: 0,
+            responses_received: None,
+            response_rate: None,
+            icmp_errors: None,
+            icmp_sources: None,
+            cooldown_icmp_errors: None,
+            wire_bytes_sent: None,
+            wire_bits_per_sec: None,
+        }
+    }
+
+    #[test]
+    fn encodes_json() {
+        let bytes = encode_reports(&[report()], OutputFormat::Json).unwrap();
+        let value = serde_json::from_slice::<serde_json::Value>(&bytes).unwrap();
+
+        assert_eq!(value[0]["receiver"], "127.0.0.1:80");
+        assert_eq!(value[0]["bytes_sent"], 1024);
+        assert_eq!(value[0]["seconds_passed"], 0.5);
+        assert_eq!(value[0]["retransmissions"], 0);
+        assert!(value[0].get("responses_received").is_none());
+
+        let mut with_responses = report();
+        with_responses.responses_received = Some(1);
+        with_responses.response_rate = Some(1.0);
+        let bytes = encode_reports(&[with_responses], OutputFormat::Json).unwrap();
+        let value = serde_json::from_slice::<serde_json::Value>(&bytes).unwrap();
+        assert_eq!(value[0]["responses_received"], 1);
+        assert_eq!(value[0]["response_rate"], 1.0);
+        assert!(value[0].get("wire_bytes_sent").is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "cbor")]
+    fn encodes_cbor() {
+        let bytes = encode_reports(&[report()], OutputFormat::Cbor).unwrap();
+
+        // An array of one item, then a map of ten pairs
+        assert_eq!(&bytes[..2], &[0x81, 0xAA]);
+        let value = ciborium::de::from_reader::<ciborium::value::Value, _>(bytes.as_slice());
+        assert!(value.is_ok());
+    }
+
+    #[test]
+    #[cfg(feature = "msgpack")]
+    fn encodes_msgpack() {
+        let bytes = encode_reports(&[report()], OutputFormat::Msgpack).unwrap();
+
+        // A fixarray of one item, then a fixmap of ten pairs
+        assert_eq!(&bytes[..2], &[0x91, 0x8A]);
+    }
+
+    #[test]
+    #[cfg(not(feature = "cbor"))]
+    fn rejects_unsupported_formats() {
+        match encode_reports(&[report()], OutputFormat::Cbor) {
+            Err(WriteReportError::Unsupported(OutputFormat::Cbor)) => (),
+            result => panic!(
+                "WriteReportError::Unsupported must be returned: {:?}",
+                result
+            ),
+        }
+    }
+}
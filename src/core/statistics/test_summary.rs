@@ -16,58 +16,400 @@
 //
 // For more information see <https://github.com/Gymmasssorla/anevicon>.
 
+use std::collections::HashSet;
+use std::fmt::Debug;
+use std::net::IpAddr;
 use std::ops::{Add, AddAssign};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
+use super::latency_histogram::{LatencyHistogram, LatencyStats};
 use crate::core::statistics::SummaryPortion;
 
+/// A source of the current time for `TestSummary`, which measures all the
+/// durations (and so the rates) by it. Tests replace the system clock by one
+/// they advance by themselves, so they don't depend on real time passing.
+pub trait Clock: Debug + Send + Sync {
+    fn now(&self) -> Instant;
+}
+
+/// The clock used by default, which returns `Instant::now()`.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    #[inline]
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
 /// The structure which represents a whole test execution result by
 /// concatenating `SummaryPortion` instances.
-#[derive(Debug, Eq, PartialEq, Clone)]
+#[derive(Debug, Clone)]
 pub struct TestSummary {
-    bytes_expected: usize,
-    bytes_sent: usize,
-    packets_expected: usize,
-    packets_sent: usize,
+    // The counters are `u64` even on 32-bit platforms, where `usize` would
+    // overflow after sending only 4 GiB
+    bytes_expected: u64,
+    bytes_sent: u64,
+    packets_expected: u64,
+    packets_sent: u64,
     initial_time: Instant,
+
+    /// Whether one of the counters has reached `u64::MAX`, see
+    /// `TestSummary::update`.
+    saturated: bool,
+
+    /// Packets sent during each finished reporting interval, see
+    /// `TestSummary::finish_interval`.
+    interval_packets: Vec<u64>,
+    packets_sent_before_interval: u64,
+    bytes_sent_before_interval: u64,
+    interval_start: Instant,
+    last_interval: Option<IntervalDelta>,
+
+    /// Delays between passing packets to the kernel and their transmission,
+    /// recorded only if `--tx-timestamps` is specified.
+    tx_latencies: LatencyHistogram,
+
+    /// A number of datagrams replied by a receiver, recorded only if
+    /// `--count-responses` is specified.
+    responses_received: Option<u64>,
+
+    /// A number of ICMP errors caused by the sent packets and the distinct
+    /// hosts which have sent them, recorded only if `--count-responses` is
+    /// specified.
+    icmp_errors: Option<u64>,
+    icmp_sources: HashSet<IpAddr>,
+
+    /// A number of the ICMP errors received after the last packet has been
+    /// sent, recorded only if `--cooldown` is specified.
+    cooldown_icmp_errors: Option<u64>,
+
+    /// A number of bytes added to each sent packet on the wire, specified
+    /// only if `--wire-accounting` is.
+    wire_overhead: Option<u64>,
+
+    /// How many times the unsent packets have been sent again, see
+    /// `TestSummary::record_retransmission`.
+    retransmissions: u64,
+
+    /// Measures `time_passed` and the reporting intervals, see
+    /// `TestSummary::with_clock`.
+    clock: Arc<dyn Clock>,
+}
+
+/// Data sent during a single reporting interval.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct IntervalDelta {
+    pub packets_sent: u64,
+    pub bytes_sent: u64,
+    pub duration: Duration,
+}
+
+impl IntervalDelta {
+    /// Returns a number of megabits sent per a second during this interval.
+    pub fn megabits_per_sec(&self) -> f64 {
+        let secs = self.duration.as_secs_f64();
+
+        if secs == 0.0 {
+            0.0
+        } else {
+            (self.bytes_sent as f64 * 8.0) / 1024.0 / 1024.0 / secs
+        }
+    }
+}
+
+/// Statistics of packets sent per a reporting interval, which show how stable
+/// a test rate was (unlike the average speed).
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct IntervalStats {
+    pub min: u64,
+    pub max: u64,
+    pub median: u64,
+    pub p95: u64,
 }
 
 impl TestSummary {
+    /// Creates an empty summary just like `TestSummary::default()`, but
+    /// measuring time by `clock` instead of the system clock.
+    pub fn with_clock(clock: Arc<dyn Clock>) -> TestSummary {
+        let now = clock.now();
+
+        TestSummary {
+            bytes_expected: 0,
+            bytes_sent: 0,
+            packets_expected: 0,
+            packets_sent: 0,
+            initial_time: now,
+            saturated: false,
+            interval_packets: Vec::new(),
+            packets_sent_before_interval: 0,
+            bytes_sent_before_interval: 0,
+            interval_start: now,
+            last_interval: None,
+            tx_latencies: LatencyHistogram::default(),
+            responses_received: None,
+            icmp_errors: None,
+            icmp_sources: HashSet::new(),
+            cooldown_icmp_errors: None,
+            wire_overhead: None,
+            retransmissions: 0,
+            clock,
+        }
+    }
+
     /// Updates the test summary by an performing an addition of the specified
     /// `SummaryPortion` to itself. You can also consider the addition operators
     /// defined as `summary += portion` and `summary + portion`.
+    ///
+    /// The counters saturate at `u64::MAX` instead of wrapping around, which is
+    /// logged only once.
     pub fn update(&mut self, portion: SummaryPortion) {
-        self.bytes_expected += portion.bytes_expected();
-        self.bytes_sent += portion.bytes_sent();
+        let mut saturated = false;
+        let mut add = |counter: &mut u64, value: usize| {
+            let (sum, overflowed) = counter.overflowing_add(value as u64);
+            *counter = if overflowed { u64::MAX } else { sum };
+            saturated |= overflowed;
+        };
+
+        add(&mut self.bytes_expected, portion.bytes_expected());
+        add(&mut self.bytes_sent, portion.bytes_sent());
+
+        add(&mut self.packets_expected, portion.packets_expected());
+        add(&mut self.packets_sent, portion.packets_sent());
+
+        if saturated && !self.saturated {
+            self.saturated = true;
+            log::warn!("the test statistics have saturated, so they aren't exact anymore!");
+        }
+    }
+
+    /// Finishes the current reporting interval, recording data sent since the
+    /// previous call (or since the summary creation).
+    pub fn finish_interval(&mut self) {
+        let now = self.clock.now();
+        let delta = IntervalDelta {
+            packets_sent: self.packets_sent - self.packets_sent_before_interval,
+            bytes_sent: self.bytes_sent - self.bytes_sent_before_interval,
+            duration: now - self.interval_start,
+        };
+
+        self.interval_packets.push(delta.packets_sent);
+        self.last_interval = Some(delta);
+
+        self.packets_sent_before_interval = self.packets_sent;
+        self.bytes_sent_before_interval = self.bytes_sent;
+        self.interval_start = now;
+    }
+
+    /// Returns a number of the reporting intervals finished so far.
+    #[inline]
+    pub fn intervals_finished(&self) -> usize {
+        self.interval_packets.len()
+    }
+
+    /// Returns data sent during the last finished reporting interval, or
+    /// `None` if there weren't any.
+    #[inline]
+    pub fn last_interval(&self) -> Option<IntervalDelta> {
+        self.last_interval
+    }
+
+    /// Records a delay between passing a packet to the kernel and its
+    /// transmission, reported by the kernel (`--tx-timestamps`).
+    #[inline]
+    pub fn record_tx_latency(&mut self, latency: Duration) {
+        self.tx_latencies.record(latency);
+    }
+
+    /// Returns the statistics of all the recorded transmission latencies, or
+    /// `None` if there weren't any.
+    #[inline]
+    pub fn tx_latency_stats(&self) -> Option<LatencyStats> {
+        self.tx_latencies.stats()
+    }
 
-        self.packets_expected += portion.packets_expected();
-        self.packets_sent += portion.packets_sent();
+    /// Records that the packets which haven't been sent (because of send
+    /// errors or partial sends) are about to be sent again.
+    #[inline]
+    pub fn record_retransmission(&mut self) {
+        self.retransmissions += 1;
+    }
+
+    /// Returns how many times the unsent packets have been sent again. It's
+    /// zero for a clean test.
+    #[inline]
+    pub fn retransmissions(&self) -> u64 {
+        self.retransmissions
+    }
+
+    /// Records a number of datagrams replied by a receiver during the whole
+    /// test (`--count-responses`).
+    #[inline]
+    pub fn record_responses(&mut self, responses_received: u64) {
+        self.responses_received = Some(responses_received);
+    }
+
+    /// Returns a number of datagrams replied by a receiver, or `None` if they
+    /// haven't been counted.
+    #[inline]
+    pub fn responses_received(&self) -> Option<u64> {
+        self.responses_received
+    }
+
+    /// Records a number of ICMP errors caused by the sent packets during the
+    /// whole test and the distinct hosts which have sent them
+    /// (`--count-responses`).
+    #[inline]
+    pub fn record_icmp_errors(&mut self, icmp_errors: u64, icmp_sources: HashSet<IpAddr>) {
+        self.icmp_errors = Some(icmp_errors);
+        self.icmp_sources = icmp_sources;
+    }
+
+    /// Records a number of the ICMP errors (included into `icmp_errors`)
+    /// received after the last packet has been sent (`--cooldown`).
+    #[inline]
+    pub fn record_cooldown_icmp_errors(&mut self, cooldown_icmp_errors: u64) {
+        self.cooldown_icmp_errors = Some(cooldown_icmp_errors);
+    }
+
+    /// Returns a number of the ICMP errors received after the last packet has
+    /// been sent, or `None` if they haven't been counted separately.
+    #[inline]
+    pub fn cooldown_icmp_errors(&self) -> Option<u64> {
+        self.cooldown_icmp_errors
+    }
+
+    /// Returns a number of ICMP errors caused by the sent packets, or `None`
+    /// if they haven't been counted.
+    #[inline]
+    pub fn icmp_errors(&self) -> Option<u64> {
+        self.icmp_errors
+    }
+
+    /// Returns the distinct hosts which have sent ICMP errors (empty if they
+    /// haven't been counted).
+    #[inline]
+    pub fn icmp_sources(&self) -> &HashSet<IpAddr> {
+        &self.icmp_sources
+    }
+
+    /// Returns a number of responses received per a sent packet, or `None` if
+    /// they haven't been counted. It's zero if nothing has been sent.
+    pub fn response_rate(&self) -> Option<f64> {
+        self.responses_received.map(|responses| {
+            if self.packets_sent == 0 {
+                0.0
+            } else {
+                responses as f64 / self.packets_sent as f64
+            }
+        })
+    }
+
+    /// Returns the statistics of all the finished reporting intervals, or
+    /// `None` if there weren't any. The percentiles are computed using the
+    /// nearest-rank method.
+    pub fn interval_stats(&self) -> Option<IntervalStats> {
+        if self.interval_packets.is_empty() {
+            return None;
+        }
+
+        let mut sorted = self.interval_packets.clone();
+        sorted.sort_unstable();
+
+        let percentile = |percent: usize| {
+            let rank = (percent * sorted.len()).div_ceil(100);
+            sorted[rank.max(1) - 1]
+        };
+
+        Some(IntervalStats {
+            min: sorted[0],
+            max: sorted[sorted.len() - 1],
+            median: percentile(50),
+            p95: percentile(95),
+        })
     }
 
     #[inline]
     #[allow(dead_code)]
-    pub fn megabytes_expected(&self) -> usize {
+    pub fn megabytes_expected(&self) -> u64 {
         self.bytes_expected / 1024 / 1024
     }
 
     #[inline]
-    pub fn megabytes_sent(&self) -> usize {
+    pub fn megabytes_sent(&self) -> u64 {
         self.bytes_sent / 1024 / 1024
     }
 
     #[inline]
-    pub fn packets_expected(&self) -> usize {
+    pub fn bytes_expected(&self) -> u64 {
+        self.bytes_expected
+    }
+
+    #[inline]
+    pub fn bytes_sent(&self) -> u64 {
+        self.bytes_sent
+    }
+
+    #[inline]
+    pub fn packets_expected(&self) -> u64 {
         self.packets_expected
     }
 
     #[inline]
-    pub fn packets_sent(&self) -> usize {
+    pub fn packets_sent(&self) -> u64 {
         self.packets_sent
     }
 
+    /// Makes `wire_bytes_sent` count `overhead` more bytes per each sent
+    /// packet (`--wire-accounting`).
+    #[inline]
+    pub fn set_wire_overhead(&mut self, overhead: u64) {
+        self.wire_overhead = Some(overhead);
+    }
+
+    /// Returns a number of bytes sent on the wire, which is `bytes_sent` plus
+    /// the overhead of each sent packet, or `None` if it isn't counted.
+    #[inline]
+    pub fn wire_bytes_sent(&self) -> Option<u64> {
+        self.wire_overhead.map(|overhead| {
+            self.bytes_sent
+                .saturating_add(self.packets_sent.saturating_mul(overhead))
+        })
+    }
+
+    /// Returns a number of bits sent on the wire per a second, or `None` if
+    /// they aren't counted.
     #[inline]
-    pub fn megabites_per_sec(&self) -> usize {
-        let secs_passed = self.time_passed().as_secs() as usize;
+    pub fn wire_bits_per_sec_f64(&self) -> Option<f64> {
+        let secs_passed = self.time_passed().as_secs_f64();
+
+        self.wire_bytes_sent().map(|bytes| {
+            if secs_passed == 0.0 {
+                0.0
+            } else {
+                (bytes as f64 * 8.0) / secs_passed
+            }
+        })
+    }
+
+    /// Returns an average number of bytes per a sent packet, which isn't
+    /// obvious when payloads of different sizes are sent, or zero if nothing
+    /// has been sent.
+    #[inline]
+    pub fn average_packet_size(&self) -> f64 {
+        if self.packets_sent == 0 {
+            0.0
+        } else {
+            self.bytes_sent as f64 / self.packets_sent as f64
+        }
+    }
+
+    #[inline]
+    #[allow(dead_code)]
+    pub fn megabites_per_sec(&self) -> u64 {
+        let secs_passed = self.time_passed().as_secs();
 
         if secs_passed == 0 {
             0
@@ -77,8 +419,9 @@ impl TestSummary {
     }
 
     #[inline]
-    pub fn packets_per_sec(&self) -> usize {
-        let secs_passed = self.time_passed().as_secs() as usize;
+    #[allow(dead_code)]
+    pub fn packets_per_sec(&self) -> u64 {
+        let secs_passed = self.time_passed().as_secs();
 
         if secs_passed == 0 {
             0
@@ -87,14 +430,42 @@ impl TestSummary {
         }
     }
 
+    /// Returns a number of packets sent per a second as a floating-point number,
+    /// which (unlike `packets_per_sec`) isn't truncated to zero for tests
+    /// shorter than a second.
+    #[inline]
+    pub fn packets_per_sec_f64(&self) -> f64 {
+        let secs_passed = self.time_passed().as_secs_f64();
+
+        if secs_passed == 0.0 {
+            0.0
+        } else {
+            self.packets_sent as f64 / secs_passed
+        }
+    }
+
+    /// Returns a number of bits sent per a second as a floating-point number.
+    #[inline]
+    pub fn bits_per_sec_f64(&self) -> f64 {
+        let secs_passed = self.time_passed().as_secs_f64();
+
+        if secs_passed == 0.0 {
+            0.0
+        } else {
+            (self.bytes_sent as f64 * 8.0) / secs_passed
+        }
+    }
+
     /// Returns a passed time interval since a test summary creation. Note
     /// that this method uses the monotonically non-decreasing time
-    /// structure [`Instant`].
+    /// structure [`Instant`], as returned by the summary's `Clock`.
     ///
     /// [`Instant`]: https://doc.rust-lang.org/std/time/struct.Instant.html
     #[inline]
     pub fn time_passed(&self) -> Duration {
-        self.initial_time.elapsed()
+        self.clock
+            .now()
+            .saturating_duration_since(self.initial_time)
     }
 }
 
@@ -117,22 +488,123 @@ impl AddAssign<SummaryPortion> for TestSummary {
 
 impl Default for TestSummary {
     fn default() -> TestSummary {
-        TestSummary {
-            bytes_expected: 0,
-            bytes_sent: 0,
-            packets_expected: 0,
-            packets_sent: 0,
-            initial_time: Instant::now(),
-        }
+        TestSummary::with_clock(Arc::new(SystemClock))
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Mutex;
     use std::thread::sleep;
 
+    use proptest::prelude::*;
+
     use super::*;
 
+    /// The largest value of a portion counter, small enough to never saturate
+    /// the counters of a summary in the property tests below.
+    const MAX_PORTION_COUNTER: usize = u32::MAX as usize;
+
+    fn portion() -> impl Strategy<Value = SummaryPortion> {
+        (0..=MAX_PORTION_COUNTER, 0..=MAX_PORTION_COUNTER)
+            .prop_flat_map(|(bytes_expected, packets_expected)| {
+                (
+                    Just(bytes_expected),
+                    0..=bytes_expected,
+                    Just(packets_expected),
+                    0..=packets_expected,
+                )
+            })
+            .prop_map(
+                |(bytes_expected, bytes_sent, packets_expected, packets_sent)| {
+                    SummaryPortion::new(bytes_expected, bytes_sent, packets_expected, packets_sent)
+                },
+            )
+    }
+
+    /// A clock which stands still until a test advances it.
+    #[derive(Debug)]
+    struct MockClock {
+        now: Mutex<Instant>,
+    }
+
+    impl MockClock {
+        fn new() -> Arc<MockClock> {
+            Arc::new(MockClock {
+                now: Mutex::new(Instant::now()),
+            })
+        }
+
+        fn advance(&self, duration: Duration) {
+            *self.now.lock().unwrap() += duration;
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> Instant {
+            *self.now.lock().unwrap()
+        }
+    }
+
+    fn counters(summary: &TestSummary) -> [u64; 4] {
+        [
+            summary.bytes_expected,
+            summary.bytes_sent,
+            summary.packets_expected,
+            summary.packets_sent,
+        ]
+    }
+
+    fn summarize<'a, I: IntoIterator<Item = &'a SummaryPortion>>(portions: I) -> TestSummary {
+        portions
+            .into_iter()
+            .fold(TestSummary::default(), |summary, &portion| {
+                summary + portion
+            })
+    }
+
+    proptest! {
+        #[test]
+        fn totals_equal_sums_of_portions(portions in prop::collection::vec(portion(), 0..64)) {
+            let sum = |part: fn(&SummaryPortion) -> usize| {
+                portions.iter().map(|portion| part(portion) as u64).sum::<u64>()
+            };
+
+            prop_assert_eq!(
+                counters(&summarize(&portions)),
+                [
+                    sum(SummaryPortion::bytes_expected),
+                    sum(SummaryPortion::bytes_sent),
+                    sum(SummaryPortion::packets_expected),
+                    sum(SummaryPortion::packets_sent),
+                ]
+            );
+        }
+
+        #[test]
+        fn updates_are_commutative(
+            (portions, shuffled) in prop::collection::vec(portion(), 0..64)
+                .prop_flat_map(|portions| (Just(portions.clone()), Just(portions).prop_shuffle()))
+        ) {
+            prop_assert_eq!(counters(&summarize(&portions)), counters(&summarize(&shuffled)));
+        }
+
+        #[test]
+        fn updates_are_associative(first in portion(), second in portion()) {
+            let combined = SummaryPortion::new(
+                first.bytes_expected() + second.bytes_expected(),
+                first.bytes_sent() + second.bytes_sent(),
+                first.packets_expected() + second.packets_expected(),
+                first.packets_sent() + second.packets_sent(),
+            );
+
+            prop_assert_eq!(
+                counters(&summarize(&[first, second])),
+                counters(&summarize(&[combined]))
+            );
+        }
+    }
+
     #[test]
     fn is_nondecreasing_clock() {
         let summary = TestSummary::default();
@@ -165,6 +637,7 @@ mod tests {
     #[test]
     fn ordinary_updates_work() {
         let mut summary = TestSummary::default();
+        assert_eq!(summary.average_packet_size(), 0.0);
 
         summary.update(SummaryPortion::new(
             1024 * 1024 * 24,
@@ -187,6 +660,21 @@ mod tests {
         ));
         assert_eq!(summary.megabytes_sent(), 85 + 23);
         assert_eq!(summary.packets_sent(), 2698 + 4258);
+        assert_eq!(
+            summary.average_packet_size(),
+            (1024 * 1024 * (85 + 23)) as f64 / (2698 + 4258) as f64
+        );
+    }
+
+    #[test]
+    fn counts_wire_bytes() {
+        let mut summary = TestSummary::default();
+        summary.update(SummaryPortion::new(3000, 2000, 30, 20));
+        assert_eq!(summary.wire_bytes_sent(), None);
+        assert_eq!(summary.wire_bits_per_sec_f64(), None);
+
+        summary.set_wire_overhead(38);
+        assert_eq!(summary.wire_bytes_sent(), Some(2000 + 20 * 38));
     }
 
     #[test]
@@ -241,6 +729,110 @@ mod tests {
         );
     }
 
+    #[test]
+    fn saturates_instead_of_wrapping() {
+        let mut summary = TestSummary {
+            bytes_sent: u64::MAX - 10,
+            packets_sent: u64::MAX - 1,
+            ..TestSummary::default()
+        };
+
+        summary.update(SummaryPortion::new(usize::MAX, usize::MAX, 5, 5));
+        assert_eq!(summary.bytes_sent, u64::MAX);
+        assert_eq!(summary.packets_sent(), u64::MAX);
+        assert!(summary.saturated);
+
+        // Further updates must neither panic nor wrap around
+        summary.update(SummaryPortion::new(100, 100, 1, 1));
+        assert_eq!(summary.bytes_sent, u64::MAX);
+        assert_eq!(summary.packets_sent(), u64::MAX);
+        assert_eq!(summary.packets_expected(), 6);
+    }
+
+    #[test]
+    fn fractional_rates_work() {
+        let clock = MockClock::new();
+        let mut summary = TestSummary::with_clock(clock.clone());
+        summary.update(SummaryPortion::new(1024, 1024, 10, 10));
+
+        // No time has passed yet
+        assert_eq!(summary.packets_per_sec_f64(), 0.0);
+        assert_eq!(summary.bits_per_sec_f64(), 0.0);
+
+        // A test shorter than a second must not be reported as a zero-rate one
+        clock.advance(Duration::from_millis(250));
+        assert_eq!(summary.packets_per_sec(), 0);
+        assert_eq!(summary.packets_per_sec_f64(), 40.0);
+        assert_eq!(summary.bits_per_sec_f64(), 32768.0);
+
+        summary.set_wire_overhead(38);
+        assert_eq!(
+            summary.wire_bits_per_sec_f64(),
+            Some((1024.0 + 380.0) * 8.0 * 4.0)
+        );
+
+        clock.advance(Duration::from_millis(1750));
+        assert_eq!(summary.packets_per_sec(), 5);
+        assert_eq!(summary.packets_per_sec_f64(), 5.0);
+    }
+
+    #[test]
+    fn computes_interval_stats() {
+        let mut summary = TestSummary::default();
+        assert_eq!(summary.interval_stats(), None);
+
+        for packets in &[50, 10, 40, 20, 30, 100, 60, 90, 70, 80] {
+            summary.update(SummaryPortion::new(*packets, *packets, *packets, *packets));
+            summary.finish_interval();
+        }
+
+        assert_eq!(
+            summary.interval_stats(),
+            Some(IntervalStats {
+                min: 10,
+                max: 100,
+                median: 50,
+                p95: 100,
+            })
+        );
+    }
+
+    #[test]
+    fn single_interval_stats() {
+        let mut summary = TestSummary::default();
+        summary.update(SummaryPortion::new(700, 700, 7, 7));
+        summary.finish_interval();
+
+        let stats = summary.interval_stats().unwrap();
+        assert_eq!(
+            (stats.min, stats.max, stats.median, stats.p95),
+            (7, 7, 7, 7)
+        );
+    }
+
+    #[test]
+    fn tracks_last_interval() {
+        let clock = MockClock::new();
+        let mut summary = TestSummary::with_clock(clock.clone());
+        assert_eq!(summary.last_interval(), None);
+
+        summary.update(SummaryPortion::new(3000, 3000, 30, 30));
+        clock.advance(Duration::from_secs(1));
+        summary.finish_interval();
+        summary.update(SummaryPortion::new(500, 400, 5, 4));
+        summary.update(SummaryPortion::new(600, 600, 6, 6));
+        clock.advance(Duration::from_millis(500));
+        summary.finish_interval();
+
+        let delta = summary.last_interval().unwrap();
+        assert_eq!(delta.packets_sent, 10);
+        assert_eq!(delta.bytes_sent, 1000);
+        assert_eq!(delta.duration, Duration::from_millis(500));
+
+        // The cumulative figures must stay untouched
+        assert_eq!(summary.packets_sent(), 40);
+    }
+
     #[test]
     fn time_passed_works() {
         let mut summary = TestSummary::default();
@@ -16,74 +16,817 @@
 //
 // For more information see <https://github.com/Gymmasssorla/anevicon>.
 
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::io;
 use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 
-use failure::Fallible;
+use rand::Rng;
 use termion::color;
 
-use crate::config::{ArgsConfig, Endpoints};
-use crate::core::statistics::TestSummary;
-use crate::core::udp_sender::{SupplyResult, UdpSender};
+use crate::config::{Endpoints, SendConfig};
+use crate::core::craft_datagrams::{self, CraftPayloadError, Datagram, Payload};
+use crate::core::privileges::StartGate;
+use crate::core::random;
+use crate::core::responses::ResponseCounter;
+use crate::core::statistics::{IntervalRecord, JsonLinesWriter, TestSummary};
+use crate::core::udp_sender::{
+    errno, CreateUdpSenderError, FlushHook, PooledSender, RetryPolicy, SocketPool, SupplyResult,
+    UdpSender,
+};
 use crate::helpers;
 
+/// How often `--keepalive` checks whether a test is aborted between packets.
+const KEEPALIVE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The reason why a tester has finished without an error.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TesterStatus {
+    /// All the required packets have been sent (`--packets-count`).
+    Completed,
+
+    /// The allotted time has passed (`--test-duration`).
+    TimeExpired,
+
+    /// Another endpoint has failed (`--abort-on-error`).
+    Aborted,
+}
+
+impl Display for TesterStatus {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match self {
+            TesterStatus::Completed => "completed",
+            TesterStatus::TimeExpired => "time expired",
+            TesterStatus::Aborted => "aborted",
+        })
+    }
+}
+
+/// An error which has stopped a tester.
+#[derive(Debug)]
+pub enum TesterError {
+    /// A socket has failed to be created or configured.
+    CreateSender(CreateUdpSenderError),
+
+    /// A datagram is larger than the path MTU allows (`EMSGSIZE`).
+    MessageTooLarge(io::Error),
+
+    /// Sending has failed with a fatal error other than `EMSGSIZE`, such as
+    /// `EPERM` or `EACCES`.
+    SendFailed {
+        errno: &'static str,
+        source: io::Error,
+    },
+
+    /// A command has failed to print a payload again (`--send-command-each`),
+    /// or a sequence number doesn't fit into the printed one.
+    RunCommand(CraftPayloadError),
+
+    /// A socket receiving responses has failed to be bound to the sender
+    /// address (`--count-responses`).
+    CountResponses(io::Error),
+
+    /// A worker thread has failed to be spawned, for example, because its
+    /// stack is too large (`--worker-stack-size`).
+    SpawnWorker(io::Error),
+
+    /// The privileges have failed to be dropped, so the test hasn't started
+    /// (`--drop-privileges`).
+    PrivilegesNotDropped,
+}
+
+impl Display for TesterError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            TesterError::CreateSender(error) => write!(f, "{}", error),
+            TesterError::MessageTooLarge(_) => write!(
+                f,
+                "Sending has failed with EMSGSIZE, which is fatal (a datagram is too large)"
+            ),
+            TesterError::SendFailed { errno, .. } => {
+                write!(f, "Sending has failed with {}, which is fatal", errno)
+            }
+            TesterError::RunCommand(error) => write!(f, "{}", error),
+            TesterError::CountResponses(_) => write!(
+                f,
+                "Failed to bind a socket receiving responses to the sender address"
+            ),
+            TesterError::SpawnWorker(_) => write!(f, "Failed to spawn a worker thread"),
+            TesterError::PrivilegesNotDropped => write!(
+                f,
+                "The test hasn't started, because the privileges haven't been dropped"
+            ),
+        }
+    }
+}
+
+impl Error for TesterError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TesterError::CreateSender(error) => Some(error.io_error()),
+            TesterError::MessageTooLarge(source) => Some(source),
+            TesterError::SendFailed { source, .. } => Some(source),
+            TesterError::RunCommand(CraftPayloadError::CommandNotRun { source, .. }) => {
+                Some(source)
+            }
+            TesterError::RunCommand(_) => None,
+            TesterError::CountResponses(source) => Some(source),
+            TesterError::SpawnWorker(source) => Some(source),
+            TesterError::PrivilegesNotDropped => None,
+        }
+    }
+}
+
+impl From<CreateUdpSenderError> for TesterError {
+    fn from(error: CreateUdpSenderError) -> Self {
+        TesterError::CreateSender(error)
+    }
+}
+
+/// The optional settings of `run_tester`, all of which are off by default.
+#[derive(Default)]
+pub struct TesterOptions<'a> {
+    /// A number of packets per a system call chosen by `--auto-batch`.
+    pub batch_size: Option<NonZeroUsize>,
+
+    /// A hook invoked after each flush, see `UdpSender::on_flush`.
+    pub flush_hook: Option<FlushHook<'static>>,
+
+    /// A writer of `--json-lines` records.
+    pub json_lines: Option<&'a JsonLinesWriter>,
+
+    /// A gate which the tester passes before sending (`--drop-privileges`).
+    pub start_gate: Option<&'a StartGate>,
+}
+
 pub fn run_tester(
-    config: Arc<ArgsConfig>,
-    datagrams: Vec<Vec<u8>>,
+    config: Arc<SendConfig>,
+    datagrams: Vec<Datagram>,
     endpoints: Endpoints,
-) -> Fallible<TestSummary> {
+    packets_count: NonZeroUsize,
+    test_intensity: NonZeroUsize,
+    options: TesterOptions,
+    aborted: &AtomicBool,
+) -> Result<(TestSummary, TesterStatus), TesterError> {
+    let TesterOptions {
+        batch_size,
+        flush_hook,
+        json_lines,
+        start_gate,
+    } = options;
+    let ticket = start_gate.map(StartGate::ticket);
+    let mut summary = create_summary(&config);
+    let (buffer_capacity, batches_per_sec) = match (config.keepalive, config.burst, batch_size) {
+        (Some(_), _, _) => (NonZeroUsize::new(1).unwrap(), 1),
+        (None, Some(burst), _) => (burst, 1),
+        (None, None, Some(batch_size)) => split_intensity(test_intensity, batch_size),
+        (None, None, None) => (test_intensity, 1),
+    };
+    let mut sender = acquire_sender(&config, &endpoints, buffer_capacity, flush_hook)?;
+    if config.burst.is_some() || config.keepalive.is_some() {
+        sender.disable_pacing();
+    } else if batches_per_sec > 1 {
+        sender.set_flush_period(Duration::from_secs(1) / batches_per_sec);
+    }
+
+    let responses = if config.sockets_config.count_responses {
+        Some(ResponseCounter::spawn(&endpoints).map_err(TesterError::CountResponses)?)
+    } else {
+        None
+    };
+    if let Some(ticket) = ticket {
+        if !ticket.arrive() {
+            return Err(TesterError::PrivilegesNotDropped);
+        }
+    }
+
+    let status = match config.keepalive {
+        Some(interval) => send_keepalives(
+            &mut sender,
+            &mut summary,
+            &datagrams,
+            packets_count,
+            interval,
+            &config,
+            aborted,
+        )?,
+        None => send_all(
+            &mut sender,
+            &mut summary,
+            &datagrams,
+            packets_count,
+            &config,
+            json_lines,
+            aborted,
+        )?,
+    };
+
+    if let Some(threshold) = config.loss_alert {
+        alert_shortfall(&summary, threshold);
+    }
+    if let Some(responses) = responses {
+        record_responses(&mut summary, responses, config.sockets_config.cooldown);
+    }
+    display_final_stats(&summary);
+    Ok((summary, status))
+}
+
+/// Splits `test_intensity` into equal batches of at most `batch_size` packets
+/// (see `--auto-batch`) and returns the size of a batch together with a number
+/// of batches sent per a second. The batches might sum up to slightly less
+/// than `test_intensity`, but never to more.
+fn split_intensity(test_intensity: NonZeroUsize, batch_size: NonZeroUsize) -> (NonZeroUsize, u32) {
+    let batches = test_intensity.get().div_ceil(batch_size.get());
+    (
+        NonZeroUsize::new(test_intensity.get() / batches).unwrap(),
+        u32::try_from(batches).unwrap_or(u32::MAX),
+    )
+}
+
+/// Returns an empty summary counting the bytes on the wire if
+/// `--wire-accounting` is specified.
+fn create_summary(config: &SendConfig) -> TestSummary {
     let mut summary = TestSummary::default();
-    let current_receiver = endpoints.receiver();
-    let mut sender = UdpSender::new(
-        config.test_intensity,
-        &current_receiver,
-        config.sockets_config.broadcast,
-    )?;
-
-    // Run the main cycle for the current worker, and exit if the allotted time
-    // expires or all required packets will be sent (whichever happens first)
-    let mut packets_to_send = config.exit_config.packets_count.get();
-    loop {
-        for (datagram, _) in datagrams.iter().cycle().zip(0..packets_to_send) {
-            match sender.supply(&mut summary, datagram) {
-                Err(error) => {
-                    // If EMSGSIZE has occurred, then exit the current tester because next calls to
-                    // the OS will return the same error
-                    if error.raw_os_error().expect("Cannot get an errno's code") == libc::EMSGSIZE {
-                        return Err(error.into());
-                    }
+    if config.wire_accounting {
+        summary.set_wire_overhead(config.wire_overhead.into());
+    }
+    summary
+}
 
-                    send_multiple_error(&error.into());
-                }
+/// Returns a sender connected to the receiver of `endpoints` and configured
+/// according to `config`, with a buffer of `buffer_capacity` packets.
+fn acquire_sender<'a>(
+    config: &SendConfig,
+    endpoints: &Endpoints,
+    buffer_capacity: NonZeroUsize,
+    flush_hook: Option<FlushHook<'static>>,
+) -> Result<PooledSender<'static, 'a>, TesterError> {
+    let mut sender = match config.sockets_config.fd {
+        Some(fd) => {
+            let sender = UdpSender::from_inherited(fd, buffer_capacity, &endpoints.receiver())?;
+            if !sender.sends_headers() {
+                log::warn!(
+                    "--fd {fd} is a UDP socket, so the kernel builds the IP/UDP headers, and the \
+                     sender address and the crafted header fields are ignored.",
+                    fd = fd
+                );
+            }
+            PooledSender::unpooled(sender)
+        }
+        None => SocketPool::global().acquire(
+            buffer_capacity,
+            &endpoints.receiver(),
+            config.sockets_config.broadcast,
+            config.packets_config.ip_ttl,
+        )?,
+    };
+    if let Some(deadline) = Instant::now().checked_add(config.exit_config.test_duration) {
+        sender.set_deadline(deadline);
+    }
+    if let Some(hook) = flush_hook {
+        sender.on_flush(hook);
+    }
+    if config.sockets_config.tx_timestamps {
+        sender.enable_tx_timestamps()?;
+    }
+    if let Some(mark) = config.sockets_config.so_mark {
+        sender.set_mark(mark)?;
+    }
+    let receiver = endpoints.receiver().ip();
+    if receiver.is_multicast() {
+        sender.set_multicast(
+            &receiver,
+            config
+                .packets_config
+                .multicast_ttl
+                .unwrap_or(config.packets_config.ip_ttl),
+            config.sockets_config.multicast_if.as_deref(),
+        )?;
+    }
+    if config.sockets_config.show_sndq {
+        sender.enable_send_queue();
+    }
+    if config.sockets_config.warn_short_send {
+        sender.enable_short_send_warnings();
+    }
+    sender.set_retry_policy(RetryPolicy {
+        max_retries: config.sockets_config.send_retries,
+        backoff: config.sockets_config.retry_backoff,
+        partial_retries: config.sockets_config.partial_retries,
+    });
+
+    Ok(sender)
+}
+
+/// Runs the main cycle for the current worker, and exits if the allotted time
+/// expires or all required packets will be sent (whichever happens first).
+fn send_all<'a>(
+    sender: &mut UdpSender<'a>,
+    summary: &mut TestSummary,
+    datagrams: &'a [Datagram],
+    packets_count: NonZeroUsize,
+    config: &SendConfig,
+    json_lines: Option<&JsonLinesWriter>,
+    aborted: &AtomicBool,
+) -> Result<TesterStatus, TesterError> {
+    let mut packets_to_send = packets_count.get();
+
+    // The datagrams sent before the main cycle, so it continues after them
+    let mut datagrams_sent = 0;
+    if let Some(delay) = config.first_packet_delay {
+        send_first_packet(sender, summary, &datagrams[0], delay, config)?;
+        packets_to_send -= 1;
+        datagrams_sent = 1;
+    }
+
+    loop {
+        for (datagram, _) in datagrams
+            .iter()
+            .cycle()
+            .skip(datagrams_sent)
+            .zip(0..packets_to_send)
+        {
+            match supply(sender, summary, datagram, config)? {
+                Err(error) => handle_send_error(error)?,
                 Ok(result) => {
                     if result == SupplyResult::Flushed {
-                        display_summary(&summary);
+                        summary.finish_interval();
+                        display_summary(summary, sender.send_queue());
+                        write_interval(json_lines, summary);
+
+                        if config.burst.is_some() {
+                            pause_burst(summary, config);
+                        }
                     }
                 }
             }
 
             if summary.time_passed() >= config.exit_config.test_duration {
                 display_expired_time();
-                return Ok(summary);
+                return Ok(TesterStatus::TimeExpired);
+            }
+            if aborted.load(Ordering::Relaxed) {
+                display_aborted();
+                return Ok(TesterStatus::Aborted);
             }
         }
 
-        if let Err(error) = sender.flush(&mut summary) {
-            send_multiple_error(&error.into());
+        if let Err(error) = sender.flush(summary) {
+            handle_send_error(error)?;
         }
 
         // We might have a situation when not all the required packets are sent, so
         // resend them again
         let unsent = summary.packets_expected() - summary.packets_sent();
-        if unsent != 0 {
-            packets_to_send = unsent;
+        if unsent != 0 && config.simulate_loss.is_none() {
+            if let Some(threshold) = config.loss_alert {
+                alert_shortfall(summary, threshold);
+            }
+            summary.record_retransmission();
+            packets_to_send = usize::try_from(unsent).unwrap_or(usize::MAX);
+            datagrams_sent = 0;
         } else {
-            display_packets_sent(config.exit_config.packets_count);
+            display_packets_sent(packets_count);
+            return Ok(TesterStatus::Completed);
+        }
+    }
+}
+
+/// Sends `datagram` immediately and sleeps `delay` (`--first-packet-delay`),
+/// but not longer than `--test-duration`, so a receiver warms up before the
+/// main cycle starts.
+fn send_first_packet(
+    sender: &mut UdpSender,
+    summary: &mut TestSummary,
+    datagram: &Datagram,
+    delay: Duration,
+    config: &SendConfig,
+) -> Result<(), TesterError> {
+    let result = match regenerate(datagram, summary.packets_expected(), config)? {
+        Some(fresh) => sender.send_datagram(summary, &fresh),
+        None => sender.send_datagram(summary, datagram),
+    };
+    if let Err(error) = result {
+        handle_send_error(error)?;
+    }
+
+    log::info!(
+        "the first packet has been sent to {receiver}, waiting {delay} before the main run...",
+        receiver = super::current_receiver(),
+        delay = humantime::format_duration(delay),
+    );
+    let remaining = config
+        .exit_config
+        .test_duration
+        .checked_sub(summary.time_passed())
+        .unwrap_or_default();
+    thread::sleep(delay.min(remaining));
+
+    log::info!(
+        "the first packet delay has elapsed, starting the main run for {receiver}.",
+        receiver = super::current_receiver(),
+    );
+    Ok(())
+}
+
+/// Sends a single datagram per `interval` (`--keepalive`) until the allotted
+/// time passes or all the required packets are sent, whichever happens first.
+fn send_keepalives<'a>(
+    sender: &mut UdpSender<'a>,
+    summary: &mut TestSummary,
+    datagrams: &'a [Datagram],
+    packets_count: NonZeroUsize,
+    interval: Duration,
+    config: &SendConfig,
+    aborted: &AtomicBool,
+) -> Result<TesterStatus, TesterError> {
+    for (number, datagram) in (1..=packets_count.get()).zip(datagrams.iter().cycle()) {
+        let next_send = Instant::now() + interval;
+
+        let result = supply(sender, summary, datagram, config)?;
+        if let Err(error) = result.and_then(|_| sender.flush(summary)) {
+            handle_send_error(error)?;
+        }
+        summary.finish_interval();
+        log::info!(
+            "keepalive #{number} has been sent to {receiver} from {sender}.",
+            number = number,
+            receiver = super::current_receiver(),
+            sender = super::current_sender(),
+        );
+
+        if number == packets_count.get() {
             break;
         }
+
+        // Sleep in short steps to notice an abort, but not beyond the deadline
+        loop {
+            if summary.time_passed() >= config.exit_config.test_duration {
+                display_expired_time();
+                return Ok(TesterStatus::TimeExpired);
+            }
+            if aborted.load(Ordering::Relaxed) {
+                display_aborted();
+                return Ok(TesterStatus::Aborted);
+            }
+
+            let now = Instant::now();
+            if now >= next_send {
+                break;
+            }
+            let remaining = config
+                .exit_config
+                .test_duration
+                .checked_sub(summary.time_passed())
+                .unwrap_or_default();
+            thread::sleep(
+                (next_send - now)
+                    .min(remaining)
+                    .min(KEEPALIVE_POLL_INTERVAL),
+            );
+        }
+    }
+
+    display_packets_sent(packets_count);
+    Ok(TesterStatus::Completed)
+}
+
+/// The state of a single endpoint tested by `run_interleaved`.
+struct Lane<'a> {
+    endpoints: Endpoints,
+    sender: PooledSender<'static, 'a>,
+    summary: TestSummary,
+    datagrams: &'a [Datagram],
+    packets_count: NonZeroUsize,
+    packets_supplied: usize,
+    responses: Option<ResponseCounter>,
+    outcome: Option<Result<TesterStatus, TesterError>>,
+}
+
+/// Tests all the endpoints from the current thread (`--fair-interleave`).
+/// Each cycle sends a single packet to each endpoint which isn't finished
+/// yet, and `--test-intensity` cycles are made per second.
+///
+/// Returns the results in the order of `datagrams`, which are the datagrams
+/// of each endpoint of `config`, just like those of separate `run_tester`
+/// calls.
+pub fn run_interleaved(
+    config: Arc<SendConfig>,
+    datagrams: Vec<Vec<Datagram>>,
+    packets_counts: Vec<NonZeroUsize>,
+    flush_hooks: Vec<Option<FlushHook<'static>>>,
+    json_lines: Option<&JsonLinesWriter>,
+    start_gate: Option<&StartGate>,
+    aborted: &AtomicBool,
+) -> Vec<Result<(TestSummary, TesterStatus), TesterError>> {
+    let ticket = start_gate.map(StartGate::ticket);
+    let single_packet = NonZeroUsize::new(1).unwrap();
+    let mut lanes = config
+        .packets_config
+        .endpoints
+        .iter()
+        .zip(&datagrams)
+        .zip(packets_counts)
+        .zip(flush_hooks)
+        .map(|(((&endpoints, datagrams), packets_count), flush_hook)| {
+            let mut sender = acquire_sender(&config, &endpoints, single_packet, flush_hook)?;
+            sender.disable_pacing();
+
+            let responses = if config.sockets_config.count_responses {
+                Some(ResponseCounter::spawn(&endpoints).map_err(TesterError::CountResponses)?)
+            } else {
+                None
+            };
+
+            Ok(Lane {
+                endpoints,
+                sender,
+                summary: create_summary(&config),
+                datagrams,
+                packets_count,
+                packets_supplied: 0,
+                responses,
+                outcome: None,
+            })
+        })
+        .collect::<Vec<Result<Lane, TesterError>>>();
+    if let Some(ticket) = ticket {
+        if !ticket.arrive() {
+            return lanes
+                .into_iter()
+                .map(|lane| lane.and(Err(TesterError::PrivilegesNotDropped)))
+                .collect();
+        }
+    }
+
+    let test_intensity = config.test_intensity.get();
+    let start = Instant::now();
+    let mut cycles = 0usize;
+
+    loop {
+        let mut active = lanes
+            .iter_mut()
+            .filter_map(|lane| lane.as_mut().ok())
+            .filter(|lane| lane.outcome.is_none())
+            .peekable();
+        if active.peek().is_none() {
+            break;
+        }
+
+        for lane in active {
+            super::init_endpoints(lane.endpoints);
+
+            if let Err(error) = send_next(lane, &config) {
+                if config.abort_on_error {
+                    aborted.store(true, Ordering::Relaxed);
+                }
+                lane.outcome = Some(Err(error));
+            } else if lane.summary.packets_sent() >= lane.packets_count.get() as u64 {
+                display_packets_sent(lane.packets_count);
+                lane.outcome = Some(Ok(TesterStatus::Completed));
+            }
+        }
+        cycles += 1;
+
+        let elapsed = start.elapsed();
+        let status = if elapsed >= config.exit_config.test_duration {
+            Some(TesterStatus::TimeExpired)
+        } else if aborted.load(Ordering::Relaxed) {
+            Some(TesterStatus::Aborted)
+        } else {
+            None
+        };
+
+        for lane in lanes.iter_mut().filter_map(|lane| lane.as_mut().ok()) {
+            if lane.outcome.is_some() {
+                continue;
+            }
+            super::init_endpoints(lane.endpoints);
+
+            match status {
+                Some(TesterStatus::TimeExpired) => display_expired_time(),
+                Some(_) => display_aborted(),
+                None if cycles.is_multiple_of(test_intensity) => {
+                    lane.summary.finish_interval();
+                    display_summary(&lane.summary, lane.sender.send_queue());
+                    write_interval(json_lines, &lane.summary);
+                }
+                None => {}
+            }
+            lane.outcome = status.map(Ok);
+        }
+
+        // Sleep until the next cycle is due according to `--test-intensity`,
+        // but not beyond the deadline
+        let next_cycle = Duration::from_secs_f64(cycles as f64 / test_intensity as f64)
+            .min(config.exit_config.test_duration);
+        if let Some(wait) = next_cycle.checked_sub(elapsed) {
+            thread::sleep(wait);
+        }
+    }
+
+    display_fairness(&lanes, cycles);
+    lanes
+        .into_iter()
+        .map(|lane| {
+            let mut lane = lane?;
+            super::init_endpoints(lane.endpoints);
+            let status = lane.outcome.expect("Each lane must be finished")?;
+
+            if let Some(threshold) = config.loss_alert {
+                alert_shortfall(&lane.summary, threshold);
+            }
+            if let Some(responses) = lane.responses {
+                record_responses(&mut lane.summary, responses, config.sockets_config.cooldown);
+            }
+            display_final_stats(&lane.summary);
+            Ok((lane.summary, status))
+        })
+        .collect()
+}
+
+/// Sends the next datagram of `lane` and waits until it's sent.
+fn send_next(lane: &mut Lane, config: &SendConfig) -> Result<(), TesterError> {
+    let datagram = &lane.datagrams[lane.packets_supplied % lane.datagrams.len()];
+    lane.packets_supplied += 1;
+
+    let result = supply(&mut lane.sender, &mut lane.summary, datagram, config)?;
+    match result.and_then(|_| lane.sender.flush(&mut lane.summary)) {
+        Err(error) => handle_send_error(error),
+        Ok(()) => Ok(()),
+    }
+}
+
+/// Logs the least and the most packets sent to a single endpoint by
+/// `run_interleaved`, which differ at most by one if no packets were lost.
+fn display_fairness(lanes: &[Result<Lane, TesterError>], cycles: usize) {
+    let packets_sent = lanes
+        .iter()
+        .filter_map(|lane| lane.as_ref().ok())
+        .map(|lane| lane.summary.packets_sent());
+
+    if let (Some(min), Some(max)) = (packets_sent.clone().min(), packets_sent.max()) {
+        log::info!(
+            "{cyan}{cycles}{reset} interleaved cycles have sent from {cyan}{min}{reset} to \
+             {cyan}{max}{reset} packets per endpoint.",
+            cycles = cycles,
+            min = min,
+            max = max,
+            cyan = color::Fg(color::Cyan),
+            reset = color::Fg(color::Reset),
+        );
+    }
+}
+
+/// Returns `datagram` carrying a payload changed for a single packet: printed
+/// by its command again (`--send-command-each`), then with random bits
+/// flipped (`--mutate-bits`), and then with `sequence` written into it
+/// (`--sequence-number`). Returns `None` if the payload is sent as is.
+fn regenerate(
+    datagram: &Datagram,
+    sequence: u64,
+    config: &SendConfig,
+) -> Result<Option<Datagram>, TesterError> {
+    let packets_config = &config.packets_config;
+    let command = datagram
+        .payload
+        .command()
+        .filter(|_| packets_config.payload_config.send_command_each);
+    let mutate_bits = packets_config.mutate_bits;
+    if command.is_none() && mutate_bits.is_none() && !packets_config.sequence_number {
+        return Ok(None);
+    }
+
+    let mut output = match command {
+        Some(command) => craft_datagrams::run_command(command).map_err(TesterError::RunCommand)?,
+        None => datagram.payload.to_vec(),
+    };
+    if let Some(bits) = mutate_bits {
+        craft_datagrams::flip_random_bits(&mut output, bits);
+    }
+    if packets_config.sequence_number {
+        // A command might print a shorter payload than it did before a test
+        craft_datagrams::write_sequence_number(
+            &mut output,
+            sequence,
+            packets_config.seq_offset,
+            packets_config.seq_width,
+            packets_config.seq_endian,
+        )
+        .map_err(TesterError::RunCommand)?;
+    }
+    // A command might print a payload leaving no room for IPv4 options
+    if packets_config.ip_option.is_some() && datagram.header[0] >> 4 == 4 {
+        craft_datagrams::check_size_with_ip_option(output.len())
+            .map_err(TesterError::RunCommand)?;
+    }
+
+    Ok(Some(datagram.with_payload(Arc::new(match command {
+        Some(command) => Payload::Command {
+            output,
+            command: command.to_owned(),
+        },
+        None => Payload::Owned(output),
+    }))))
+}
+
+/// Supplies `datagram` to `sender`, regenerating it first if necessary (see
+/// `regenerate`), or counts it as lost without sending (`--simulate-loss`).
+fn supply<'a>(
+    sender: &mut UdpSender<'a>,
+    summary: &mut TestSummary,
+    datagram: &'a Datagram,
+    config: &SendConfig,
+) -> Result<io::Result<SupplyResult>, TesterError> {
+    if let Some(percent) = config.simulate_loss {
+        if random::with(|generator| generator.gen_range(0, 100)) < percent {
+            sender.skip(summary, datagram);
+            return Ok(Ok(SupplyResult::NotFlushed));
+        }
     }
 
-    Ok(summary)
+    Ok(
+        match regenerate(datagram, summary.packets_expected(), config)? {
+            Some(fresh) => send_fresh(sender, summary, &fresh),
+            None => sender.supply(summary, datagram),
+        },
+    )
+}
+
+/// Sends `datagram` crafted during a test right after the buffered ones,
+/// because it doesn't live long enough to be buffered itself.
+fn send_fresh(
+    sender: &mut UdpSender,
+    summary: &mut TestSummary,
+    datagram: &Datagram,
+) -> io::Result<SupplyResult> {
+    sender.flush(summary)?;
+    sender.send_datagram(summary, datagram)?;
+    Ok(SupplyResult::NotFlushed)
+}
+
+/// Warns if the packets sent so far fall short of the expected ones by more
+/// than `threshold` percent (`--loss-alert`).
+fn alert_shortfall(summary: &TestSummary, threshold: u8) {
+    let unsent = summary.packets_expected() - summary.packets_sent();
+    if unsent == 0 {
+        return;
+    }
+
+    let shortfall = unsent as f64 * 100.0 / summary.packets_expected() as f64;
+    if shortfall > f64::from(threshold) {
+        log::warn!(
+            "only {sent} of {expected} packets have been sent to {receiver} from {sender} \
+             ({shortfall:.2}% are unsent, which exceeds --loss-alert={threshold}%)!",
+            sent = summary.packets_sent(),
+            expected = summary.packets_expected(),
+            receiver = super::current_receiver(),
+            sender = super::current_sender(),
+            shortfall = shortfall,
+            threshold = threshold,
+        );
+    }
+}
+
+/// Returns how many times each of `datagrams` datagrams will be sent by
+/// `run_tester` to send `packets_count` packets in total. Datagrams are sent
+/// cyclically, so the first `packets_count % datagrams` of them are sent once
+/// more than the rest.
+pub fn datagram_counts(datagrams: usize, packets_count: NonZeroUsize) -> Vec<usize> {
+    let (quotient, remainder) = (
+        packets_count.get() / datagrams,
+        packets_count.get() % datagrams,
+    );
+
+    (0..datagrams)
+        .map(|index| quotient + if index < remainder { 1 } else { 0 })
+        .collect()
+}
+
+/// Logs a number of packets sent by the last burst and sleeps `--burst-pause`,
+/// but not longer than the rest of `--test-duration`.
+fn pause_burst(summary: &TestSummary, config: &SendConfig) {
+    let last_interval = summary
+        .last_interval()
+        .expect("An interval must be finished before pausing a burst");
+    log::trace!(
+        "a burst of {packets} packets has been sent to {receiver}, pausing for {pause}.",
+        packets = last_interval.packets_sent,
+        receiver = super::current_receiver(),
+        pause = humantime::format_duration(config.burst_pause),
+    );
+
+    let remaining = config
+        .exit_config
+        .test_duration
+        .checked_sub(summary.time_passed())
+        .unwrap_or_default();
+    thread::sleep(config.burst_pause.min(remaining));
 }
 
 fn display_expired_time() {
@@ -94,6 +837,15 @@ fn display_expired_time() {
     );
 }
 
+fn display_aborted() {
+    log::warn!(
+        "the test of {receiver} receiver and {sender} sender has been aborted because another \
+         endpoint has failed.",
+        receiver = super::current_receiver(),
+        sender = super::current_sender(),
+    );
+}
+
 fn display_packets_sent(packets_count: NonZeroUsize) {
     log::info!(
         "{cyan}{packets_count}{reset} packets have been sent to {receiver} from {sender}.",
@@ -105,34 +857,192 @@ fn display_packets_sent(packets_count: NonZeroUsize) {
     );
 }
 
-fn display_summary(summary: &TestSummary) {
+fn display_summary(summary: &TestSummary, send_queue: Option<usize>) {
+    let last_interval = summary
+        .last_interval()
+        .expect("An interval must be finished before displaying a summary");
+    let send_queue = match send_queue {
+        Some(bytes) => format!(
+            "\n\tSend Queue:    {cyan}{bytes} bytes{reset}",
+            bytes = bytes,
+            cyan = color::Fg(color::Cyan),
+            reset = color::Fg(color::Reset),
+        ),
+        None => String::new(),
+    };
+    let wire = match (summary.wire_bytes_sent(), summary.wire_bits_per_sec_f64()) {
+        (Some(bytes), Some(bits_per_sec)) => format!(
+            "\n\tOn the Wire:   {cyan}{megabytes} MB ({mbps:.2} Mbps){reset}",
+            megabytes = bytes / 1024 / 1024,
+            mbps = bits_per_sec / 1024.0 / 1024.0,
+            cyan = color::Fg(color::Cyan),
+            reset = color::Fg(color::Reset),
+        ),
+        _ => String::new(),
+    };
+
     log::info!(
-        "stats for {endpoints}:\n\tData Sent:     {cyan}{data_sent}{reset}\n\tAverage Speed: \
-         {cyan}{average_speed}{reset}\n\tTime Passed:   {cyan}{time_passed}{reset}",
+        "stats for {endpoints}:\n\tData Sent:     {cyan}{data_sent}{reset}{wire}\n\tAverage \
+         Size:  {cyan}{average_size:.2} bytes{reset}\n\tAverage Speed: \
+         {cyan}{average_speed}{reset}\n\tLast Interval: {cyan}{last_interval}{reset}\n\tTime \
+         Passed:   {cyan}{time_passed}{reset}\n\tRetransmitted: \
+         {cyan}{retransmissions} times{reset}{send_queue}",
         endpoints = super::current_endpoints_colored(),
         data_sent = format!(
             "{packets} packets ({megabytes} MB)",
             packets = summary.packets_sent(),
             megabytes = summary.megabytes_sent(),
         ),
+        wire = wire,
+        average_size = summary.average_packet_size(),
         average_speed = format!(
-            "{packets_per_sec} packets/sec ({mbps} Mbps)",
-            packets_per_sec = summary.packets_per_sec(),
-            mbps = summary.megabites_per_sec(),
+            "{packets_per_sec:.2} packets/sec ({mbps:.2} Mbps)",
+            packets_per_sec = summary.packets_per_sec_f64(),
+            mbps = summary.bits_per_sec_f64() / 1024.0 / 1024.0,
+        ),
+        last_interval = format!(
+            "{packets} packets ({mbps:.2} Mbps)",
+            packets = last_interval.packets_sent,
+            mbps = last_interval.megabits_per_sec(),
         ),
         time_passed = humantime::format_duration(summary.time_passed()),
+        retransmissions = summary.retransmissions(),
+        send_queue = send_queue,
         cyan = color::Fg(color::Cyan),
         reset = color::Fg(color::Reset),
     );
 }
 
-fn send_multiple_error(error: &failure::Error) {
-    log::error!(
-        "failed to send packets to {receiver} from {sender}!\n{causes}",
-        receiver = super::current_receiver(),
-        sender = super::current_sender(),
-        causes = helpers::format_failure(&error),
-    );
+/// Writes the last finished interval of `summary` as a line of `--json-lines`.
+fn write_interval(json_lines: Option<&JsonLinesWriter>, summary: &TestSummary) {
+    if let Some(writer) = json_lines {
+        if let Some(record) =
+            IntervalRecord::new(super::current_sender(), super::current_receiver(), summary)
+        {
+            writer.write(&record);
+        }
+    }
+}
+
+fn display_final_stats(summary: &TestSummary) {
+    display_interval_stats(summary);
+    display_tx_latency_stats(summary);
+    display_responses(summary);
+}
+
+fn display_interval_stats(summary: &TestSummary) {
+    if let Some(stats) = summary.interval_stats() {
+        log::info!(
+            "packets sent per interval for {endpoints}:\n\tMinimum: {cyan}{min}{reset}\n\tMaximum: \
+             {cyan}{max}{reset}\n\tMedian:  {cyan}{median}{reset}\n\tP95:     {cyan}{p95}{reset}",
+            endpoints = super::current_endpoints_colored(),
+            min = stats.min,
+            max = stats.max,
+            median = stats.median,
+            p95 = stats.p95,
+            cyan = color::Fg(color::Cyan),
+            reset = color::Fg(color::Reset),
+        );
+    }
+}
+
+/// Waits for `counter` to finish during `cooldown` (`--cooldown`) and records
+/// everything it has received into `summary` (`--count-responses`).
+fn record_responses(
+    summary: &mut TestSummary,
+    counter: ResponseCounter,
+    cooldown: Option<Duration>,
+) {
+    let responses = counter.finish(cooldown);
+    summary.record_responses(responses.datagrams);
+    summary.record_icmp_errors(responses.icmp_errors, responses.icmp_sources);
+    if cooldown.is_some() {
+        summary.record_cooldown_icmp_errors(responses.cooldown_icmp_errors);
+    }
+}
+
+fn display_responses(summary: &TestSummary) {
+    if let (Some(received), Some(rate)) = (summary.responses_received(), summary.response_rate()) {
+        let cooldown = match summary.cooldown_icmp_errors() {
+            Some(icmp_errors) => format!(
+                "\n\tCooldown: {cyan}{icmp_errors}{reset} ICMP errors after sending has stopped",
+                icmp_errors = icmp_errors,
+                cyan = color::Fg(color::Cyan),
+                reset = color::Fg(color::Reset),
+            ),
+            None => String::new(),
+        };
+
+        log::info!(
+            "responses for {endpoints}:\n\tReceived: {cyan}{received}{reset}\n\tRate:     \
+             {cyan}{percent:.2}%{reset} of the sent packets\n\tICMP:     \
+             {cyan}{icmp_errors}{reset} errors from {cyan}{icmp_sources}{reset} distinct \
+             sources{cooldown}",
+            endpoints = super::current_endpoints_colored(),
+            cooldown = cooldown,
+            received = received,
+            percent = rate * 100.0,
+            icmp_errors = summary.icmp_errors().unwrap_or(0),
+            icmp_sources = summary.icmp_sources().len(),
+            cyan = color::Fg(color::Cyan),
+            reset = color::Fg(color::Reset),
+        );
+    }
+}
+
+fn display_tx_latency_stats(summary: &TestSummary) {
+    if let Some(stats) = summary.tx_latency_stats() {
+        log::info!(
+            "transmission latencies for {endpoints}:\n\tMinimum: {cyan}{min:?}{reset}\n\tMaximum: \
+             {cyan}{max:?}{reset}\n\tMedian:  {cyan}{median:?}{reset}\n\tP95:     \
+             {cyan}{p95:?}{reset}",
+            endpoints = super::current_endpoints_colored(),
+            min = stats.min,
+            max = stats.max,
+            median = stats.median,
+            p95 = stats.p95,
+            cyan = color::Fg(color::Cyan),
+            reset = color::Fg(color::Reset),
+        );
+    }
+}
+
+/// Returns `error` if it's fatal (see `errno::is_fatal`), because the next
+/// sends will fail with the same error. Otherwise, just reports it: retryable
+/// errors are expected under a high load, so they're printed only at the
+/// debug level.
+fn handle_send_error(error: io::Error) -> Result<(), TesterError> {
+    let errno = errno::name(&error).unwrap_or("an unknown error");
+
+    if errno::is_fatal(&error) {
+        return Err(match error.raw_os_error() {
+            Some(libc::EMSGSIZE) => TesterError::MessageTooLarge(error),
+            _ => TesterError::SendFailed {
+                errno,
+                source: error,
+            },
+        });
+    }
+
+    if errno::is_retryable(&error) {
+        log::debug!(
+            "sending packets to {receiver} from {sender} has temporarily failed with {errno}, \
+             they will be sent again.",
+            receiver = super::current_receiver(),
+            sender = super::current_sender(),
+            errno = errno,
+        );
+    } else {
+        log::error!(
+            "failed to send packets to {receiver} from {sender} with {errno}!\n{causes}",
+            receiver = super::current_receiver(),
+            sender = super::current_sender(),
+            errno = errno,
+            causes = helpers::format_failure(&error.into()),
+        );
+    }
+
+    Ok(())
 }
 
 #[cfg(test)]
@@ -145,41 +1055,357 @@ mod tests {
 
     use super::*;
 
+    /// Runs a tester sending to `receiver` with `args` (the options of `send`
+    /// except `--endpoints`) and returns its results.
+    fn run_single(receiver: &UdpSocket, args: &[&str]) -> (TestSummary, TesterStatus) {
+        let endpoints = format!("{0}&{0}", receiver.local_addr().unwrap());
+        let config = SendConfig::from_iter(["send", "--endpoints", &endpoints].iter().chain(args));
+
+        let datagrams = craft_datagrams::craft_all(&config.packets_config)
+            .expect("Cannot construct datagrams")
+            .remove(0);
+        let endpoints = config.packets_config.endpoints[0];
+        let (packets_count, test_intensity) =
+            (config.exit_config.packets_count, config.test_intensity);
+
+        run_tester(
+            Arc::new(config),
+            datagrams,
+            endpoints,
+            packets_count,
+            test_intensity,
+            TesterOptions::default(),
+            &AtomicBool::new(false),
+        )
+        .expect("Failed to run a tester")
+    }
+
+    #[test]
+    fn divides_packets_count() {
+        let count =
+            |datagrams, packets| datagram_counts(datagrams, NonZeroUsize::new(packets).unwrap());
+
+        assert_eq!(count(3, 9), vec![3, 3, 3]);
+        assert_eq!(count(3, 10), vec![4, 3, 3]);
+        assert_eq!(count(4, 2), vec![1, 1, 0, 0]);
+        assert_eq!(count(1, 7), vec![7]);
+    }
+
+    #[test]
+    fn splits_intensity_into_batches() {
+        let split = |test_intensity, batch_size| {
+            let (size, batches) = split_intensity(
+                NonZeroUsize::new(test_intensity).unwrap(),
+                NonZeroUsize::new(batch_size).unwrap(),
+            );
+            (size.get(), batches)
+        };
+
+        assert_eq!(split(1000, 1024), (1000, 1));
+        assert_eq!(split(1000, 1000), (1000, 1));
+        assert_eq!(split(1024, 256), (256, 4));
+        assert_eq!(split(800, 600), (400, 2));
+        assert_eq!(split(1000, 64), (62, 16));
+        assert_eq!(split(3, 1), (1, 3));
+    }
+
+    #[test]
+    fn classifies_fatal_send_errors() {
+        let error = io::Error::from_raw_os_error;
+
+        match handle_send_error(error(libc::EMSGSIZE)) {
+            Err(TesterError::MessageTooLarge(source)) => {
+                assert_eq!(source.raw_os_error(), Some(libc::EMSGSIZE))
+            }
+            result => panic!(
+                "TesterError::MessageTooLarge must be returned: {:?}",
+                result
+            ),
+        }
+
+        match handle_send_error(error(libc::EPERM)) {
+            Err(error @ TesterError::SendFailed { errno: "EPERM", .. }) => assert_eq!(
+                error.source().unwrap().to_string(),
+                io::Error::from_raw_os_error(libc::EPERM).to_string()
+            ),
+            result => panic!("TesterError::SendFailed must be returned: {:?}", result),
+        }
+
+        assert!(handle_send_error(error(libc::ENOBUFS)).is_ok());
+    }
+
     #[test]
     fn test_run_tester() {
         let socket = UdpSocket::bind("127.0.0.1:0").expect("UdpSocket::bind(...) failed");
 
-        let config = ArgsConfig::from_iter(&[
-            "anevicon",
-            "--endpoints",
-            &format!("{0}&{0}", socket.local_addr().unwrap()),
-            "--packets-count",
-            "1000",
-            "--test-intensity",
-            "42",
-            "--send-message",
-            "My first message",
-            "--send-message",
-            "My second message",
-            "--send-message",
-            "My third message",
-            "--send-file",
-            "files/packet.txt",
-            "--wait",
-            "0secs",
-        ]);
-
-        let packets_expected = config.exit_config.packets_count.get();
-        let datagrams = craft_datagrams::craft_all(&config.packets_config)
-            .expect("Cannot construct datagarms")
-            .remove(0)
+        let (summary, status) = run_single(
+            &socket,
+            &[
+                "--packets-count",
+                "1000",
+                "--test-intensity",
+                "42",
+                "--send-message",
+                "My first message",
+                "--send-message",
+                "My second message",
+                "--send-message",
+                "My third message",
+                "--send-file",
+                "files/packet.txt",
+                "--wait",
+                "0secs",
+            ],
+        );
+
+        assert_eq!(summary.packets_expected(), 1000);
+        assert_eq!(summary.packets_sent(), 1000);
+        assert_eq!(summary.retransmissions(), 0);
+        assert_eq!(status, TesterStatus::Completed);
+    }
+
+    #[test]
+    fn delays_after_first_packet() {
+        let socket = UdpSocket::bind("127.0.0.1:0").expect("UdpSocket::bind(...) failed");
+        socket
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .expect("socket.set_read_timeout(...) failed");
+
+        let start = Instant::now();
+        let (summary, status) = run_single(
+            &socket,
+            &[
+                "--packets-count",
+                "3",
+                "--send-message",
+                "first",
+                "--send-message",
+                "second",
+                "--first-packet-delay",
+                "300ms",
+                "--wait",
+                "0secs",
+            ],
+        );
+
+        assert!(start.elapsed() >= Duration::from_millis(300));
+        assert_eq!(summary.packets_sent(), 3);
+        assert_eq!(status, TesterStatus::Completed);
+
+        // The main cycle continues after the first packet
+        let mut buffer = [0u8; 64];
+        let received = (0..3)
+            .map(|_| {
+                let length = socket.recv(&mut buffer).expect("socket.recv(...) failed");
+                buffer[..length].to_vec()
+            })
             .collect::<Vec<Vec<u8>>>();
+        assert_eq!(
+            received,
+            vec![b"first".to_vec(), b"second".to_vec(), b"first".to_vec()]
+        );
+    }
 
-        let endpoints = config.packets_config.endpoints[0];
-        let summary =
-            run_tester(Arc::new(config), datagrams, endpoints).expect("Failed to run a tester");
+    #[test]
+    fn simulates_loss() {
+        let socket = UdpSocket::bind("127.0.0.1:0").expect("UdpSocket::bind(...) failed");
+
+        let (summary, status) = run_single(
+            &socket,
+            &[
+                "--packets-count",
+                "10",
+                "--send-message",
+                "Nobody will receive me",
+                "--simulate-loss",
+                "100",
+            ],
+        );
+
+        // The lost packets are expected, but neither sent nor resent
+        assert_eq!(summary.packets_expected(), 10);
+        assert_eq!(summary.packets_sent(), 0);
+        assert_eq!(status, TesterStatus::Completed);
+        socket
+            .set_nonblocking(true)
+            .expect("socket.set_nonblocking(...) failed");
+        assert!(socket.recv(&mut [0u8; 64]).is_err());
+    }
 
-        assert_eq!(summary.packets_expected(), packets_expected);
-        assert_eq!(summary.packets_sent(), packets_expected);
+    #[test]
+    fn sends_keepalives() {
+        let socket = UdpSocket::bind("127.0.0.1:0").expect("UdpSocket::bind(...) failed");
+
+        let start = Instant::now();
+        let (summary, status) = run_single(
+            &socket,
+            &[
+                "--packets-count",
+                "3",
+                "--keepalive",
+                "50ms",
+                "--send-message",
+                "Are you still there?",
+            ],
+        );
+
+        // Each packet is sent separately, and there are two intervals between them
+        assert_eq!(summary.packets_sent(), 3);
+        assert_eq!(status, TesterStatus::Completed);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn interleaves_endpoints() {
+        let sockets = (0..3)
+            .map(|_| UdpSocket::bind("127.0.0.1:0").expect("UdpSocket::bind(...) failed"))
+            .collect::<Vec<UdpSocket>>();
+
+        let mut args = vec![String::from("send")];
+        for socket in &sockets {
+            args.push(String::from("--endpoints"));
+            args.push(format!("{0}&{0}", socket.local_addr().unwrap()));
+        }
+        args.extend(
+            [
+                "--packets-count",
+                "7",
+                "--test-intensity",
+                "1000",
+                "--send-message",
+                "Fair enough",
+                "--fair-interleave",
+            ]
+            .iter()
+            .map(|&arg| String::from(arg)),
+        );
+        let config = SendConfig::from_iter(&args);
+
+        let datagrams =
+            craft_datagrams::craft_all(&config.packets_config).expect("Cannot construct datagrams");
+        let packets_counts = vec![config.exit_config.packets_count; 3];
+        let results = run_interleaved(
+            Arc::new(config),
+            datagrams,
+            packets_counts,
+            vec![None, None, None],
+            None,
+            None,
+            &AtomicBool::new(false),
+        );
+
+        assert_eq!(results.len(), 3);
+        for result in results {
+            let (summary, status) = result.expect("Failed to run a tester");
+            assert_eq!(summary.packets_sent(), 7);
+            assert_eq!(status, TesterStatus::Completed);
+        }
+    }
+
+    #[test]
+    fn runs_commands_for_each_packet() {
+        let socket = UdpSocket::bind("127.0.0.1:0").expect("UdpSocket::bind(...) failed");
+        socket
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .expect("UdpSocket::set_read_timeout(...) failed");
+
+        // Each shell has its own PID, so each packet carries a fresh output
+        let (summary, _) = run_single(
+            &socket,
+            &[
+                "--packets-count",
+                "3",
+                "--send-command",
+                "echo $$",
+                "--send-command-each",
+            ],
+        );
+        assert_eq!(summary.packets_sent(), 3);
+
+        let mut buffer = [0u8; 64];
+        let mut outputs = (0..3)
+            .map(|_| {
+                let length = socket
+                    .recv(&mut buffer)
+                    .expect("UdpSocket::recv(...) failed");
+                buffer[..length].to_vec()
+            })
+            .collect::<Vec<Vec<u8>>>();
+        outputs.dedup();
+        assert_eq!(outputs.len(), 3);
+    }
+
+    #[test]
+    fn mutates_payload_bits() {
+        let socket = UdpSocket::bind("127.0.0.1:0").expect("UdpSocket::bind(...) failed");
+        socket
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .expect("UdpSocket::set_read_timeout(...) failed");
+
+        let message = "A payload to be fuzzed by flipping bits";
+        run_single(
+            &socket,
+            &[
+                "--packets-count",
+                "5",
+                "--send-message",
+                message,
+                "--mutate-bits",
+                "3",
+            ],
+        );
+
+        // Each received packet differs from the original payload by three bits
+        let mut buffer = [0u8; 64];
+        for _ in 0..5 {
+            let length = socket
+                .recv(&mut buffer)
+                .expect("UdpSocket::recv(...) failed");
+            let flipped = message
+                .as_bytes()
+                .iter()
+                .zip(&buffer[..length])
+                .map(|(a, b)| (a ^ b).count_ones())
+                .sum::<u32>();
+
+            assert_eq!(length, message.len());
+            assert_eq!(flipped, 3);
+        }
+    }
+
+    #[test]
+    fn writes_sequence_numbers() {
+        let socket = UdpSocket::bind("127.0.0.1:0").expect("UdpSocket::bind(...) failed");
+        socket
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .expect("UdpSocket::set_read_timeout(...) failed");
+
+        run_single(
+            &socket,
+            &[
+                "--packets-count",
+                "3",
+                "--send-message",
+                "Counter: ..",
+                "--sequence-number",
+                "--seq-offset",
+                "9",
+                "--seq-width",
+                "2",
+                "--seq-endian",
+                "little",
+            ],
+        );
+
+        let mut buffer = [0u8; 64];
+        for number in 0..3u8 {
+            let length = socket
+                .recv(&mut buffer)
+                .expect("UdpSocket::recv(...) failed");
+            assert_eq!(
+                &buffer[..length],
+                &[b"Counter: ".as_ref(), &[number, 0]].concat()[..]
+            );
+        }
     }
 }
@@ -0,0 +1,97 @@
+// anevicon: A high-performant UDP-based load generator, written in Rust.
+// Copyright (C) 2019  Temirkhan Myrzamadi <gymmasssorla@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// For more information see <https://github.com/Gymmasssorla/anevicon>.
+
+//! Classification of errors returned by the system calls sending packets.
+
+use std::io;
+
+/// Returns `true` if the next sends through the same socket will fail with
+/// the same `error` (a packet is too large or sending is forbidden), so there
+/// is no point in continuing a test.
+pub fn is_fatal(error: &io::Error) -> bool {
+    matches!(
+        error.raw_os_error(),
+        Some(libc::EMSGSIZE) | Some(libc::EPERM) | Some(libc::EACCES)
+    )
+}
+
+/// Returns `true` if `error` is caused by a temporary condition (full socket
+/// or device buffers, an interrupted system call), so a send is expected to
+/// succeed later.
+pub fn is_retryable(error: &io::Error) -> bool {
+    matches!(
+        error.raw_os_error(),
+        Some(libc::EAGAIN) | Some(libc::ENOBUFS) | Some(libc::EINTR)
+    )
+}
+
+/// Returns the symbolic name of an errno code of `error` (such as `ENOBUFS`),
+/// or `None` if it isn't an OS error or the code is unusual for sending.
+pub fn name(error: &io::Error) -> Option<&'static str> {
+    Some(match error.raw_os_error()? {
+        libc::EMSGSIZE => "EMSGSIZE",
+        libc::EPERM => "EPERM",
+        libc::EACCES => "EACCES",
+        libc::EAGAIN => "EAGAIN",
+        libc::ENOBUFS => "ENOBUFS",
+        libc::EINTR => "EINTR",
+        libc::EINVAL => "EINVAL",
+        libc::ENETDOWN => "ENETDOWN",
+        libc::ENETUNREACH => "ENETUNREACH",
+        libc::EHOSTUNREACH => "EHOSTUNREACH",
+        libc::ECONNREFUSED => "ECONNREFUSED",
+        libc::EDESTADDRREQ => "EDESTADDRREQ",
+        libc::EADDRNOTAVAIL => "EADDRNOTAVAIL",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_errors() {
+        let error = io::Error::from_raw_os_error;
+
+        for &errno in &[libc::EMSGSIZE, libc::EPERM, libc::EACCES] {
+            assert!(is_fatal(&error(errno)));
+            assert!(!is_retryable(&error(errno)));
+        }
+        for &errno in &[libc::EAGAIN, libc::ENOBUFS, libc::EINTR] {
+            assert!(!is_fatal(&error(errno)));
+            assert!(is_retryable(&error(errno)));
+        }
+
+        let custom = io::Error::other("Not an OS error");
+        assert!(!is_fatal(&custom) && !is_retryable(&custom));
+    }
+
+    #[test]
+    fn names_errno_codes() {
+        assert_eq!(
+            name(&io::Error::from_raw_os_error(libc::ENOBUFS)),
+            Some("ENOBUFS")
+        );
+        assert_eq!(
+            name(&io::Error::from_raw_os_error(libc::EMSGSIZE)),
+            Some("EMSGSIZE")
+        );
+        assert_eq!(name(&io::Error::other("Oops")), None);
+    }
+}
@@ -0,0 +1,132 @@
+// anevicon: A high-performant UDP-based load generator, written in Rust.
+// Copyright (C) 2019  Temirkhan Myrzamadi <gymmasssorla@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// For more information see <https://github.com/Gymmasssorla/anevicon>.
+
+//! Minimal safe bindings to ICMP errors queued on a UDP socket
+//! (`IP_RECVERR`, `IPV6_RECVERR`).
+
+use std::io;
+use std::mem;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use crate::core::error_queue::{self, ControlMessage};
+
+/// An ICMP (ICMPv6) error received in reply to a sent datagram.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct IcmpError {
+    /// The host which has sent the error: a receiver itself or an
+    /// intermediate router. `None` if the kernel doesn't know it.
+    pub source: Option<IpAddr>,
+}
+
+/// Makes the kernel queue the ICMP errors caused by datagrams of `fd` (an
+/// IPv6 socket if `ipv6`), so `read_all` can read them.
+pub fn enable(fd: libc::c_int, ipv6: bool) -> io::Result<()> {
+    if ipv6 {
+        error_queue::set_int_option(fd, libc::IPPROTO_IPV6, libc::IPV6_RECVERR, 1)
+    } else {
+        error_queue::set_int_option(fd, libc::IPPROTO_IP, libc::IP_RECVERR, 1)
+    }
+}
+
+/// Reads all the ICMP errors currently queued on `fd` without blocking.
+/// Errors generated by the local host (e.g., `EMSGSIZE`) are skipped.
+///
+/// # References
+/// For more information please read `ip(7)` (`IP_RECVERR`).
+pub fn read_all(fd: libc::c_int) -> io::Result<Vec<IcmpError>> {
+    let mut errors = Vec::new();
+
+    error_queue::read_all(fd, |message| {
+        if let Some(extended) = message.extended_error() {
+            if extended.ee_origin == libc::SO_EE_ORIGIN_ICMP
+                || extended.ee_origin == libc::SO_EE_ORIGIN_ICMP6
+            {
+                errors.push(IcmpError {
+                    source: offender(message),
+                });
+            }
+        }
+    })?;
+
+    Ok(errors)
+}
+
+/// Returns the address of the host which has sent the extended error carried
+/// by `message`.
+///
+/// Linux places the offender right after `struct sock_extended_err` in the
+/// same control message (`SO_EE_OFFENDER`): `struct sockaddr_in` for ICMP and
+/// `struct sockaddr_in6` for ICMPv6 errors, or just `sa_family` equal to
+/// `AF_UNSPEC` if the offender is unknown.
+fn offender(message: &ControlMessage) -> Option<IpAddr> {
+    let offset = mem::size_of::<libc::sock_extended_err>();
+
+    match libc::c_int::from(unsafe { message.read::<libc::sa_family_t>(offset) }?) {
+        libc::AF_INET => {
+            let address = unsafe { message.read::<libc::sockaddr_in>(offset) }?;
+            Some(IpAddr::V4(Ipv4Addr::from(u32::from_be(
+                address.sin_addr.s_addr,
+            ))))
+        }
+        libc::AF_INET6 => {
+            let address = unsafe { message.read::<libc::sockaddr_in6>(offset) }?;
+            Some(IpAddr::V6(Ipv6Addr::from(address.sin6_addr.s6_addr)))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::UdpSocket;
+    use std::os::unix::io::AsRawFd;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn reads_port_unreachable_errors() {
+        let closed = UdpSocket::bind("127.0.0.1:0").expect("UdpSocket::bind() has failed");
+        let closed_addr = closed.local_addr().unwrap();
+        drop(closed);
+
+        let socket = UdpSocket::bind("127.0.0.1:0").expect("UdpSocket::bind() has failed");
+        socket
+            .connect(closed_addr)
+            .expect("socket.connect() has failed");
+        enable(socket.as_raw_fd(), false).expect("enable() has failed");
+
+        let mut errors = Vec::new();
+        for _ in 0..100 {
+            // Each failed send reports the previous error, so keep sending
+            let _ = socket.send(b"Is anybody there?");
+            thread::sleep(Duration::from_millis(10));
+
+            errors.extend(read_all(socket.as_raw_fd()).expect("read_all() has failed"));
+            if !errors.is_empty() {
+                break;
+            }
+        }
+
+        assert!(!errors.is_empty());
+        assert!(errors
+            .iter()
+            .all(|error| error.source == Some(IpAddr::V4(Ipv4Addr::LOCALHOST))));
+    }
+}
@@ -19,27 +19,35 @@
 //! This file is used to send raw UDP/IP messages to a web server.
 
 use std::convert::TryInto;
+use std::ffi::CString;
 use std::io::IoSlice;
 use std::net::{IpAddr, SocketAddr};
 use std::num::NonZeroUsize;
 use std::os::raw::c_void;
-use std::os::unix::io::RawFd;
-use std::time::{Duration, Instant};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::time::{Duration, Instant, SystemTime};
 use std::{io, mem, thread};
 
-use failure::Fallible;
-
+use crate::core::craft_datagrams::Datagram;
 use crate::core::statistics::{SummaryPortion, TestSummary};
 
+pub use retry::RetryPolicy;
+pub use sendmmsg_wrapper::DataPortion;
+pub use socket_pool::{PooledSender, SocketPool};
+
+pub mod errno;
+pub mod icmp;
+mod retry;
 mod sendmmsg_wrapper;
+mod socket_pool;
+mod tx_timestamps;
 
-/// A type alias that represents a portion to be sent. `transmitted` is a
-/// number of bytes sent, and `slice` is a packet to be sent.
-#[derive(Debug)]
-pub struct DataPortion<'a> {
-    pub transmitted: usize,
-    pub slice: IoSlice<'a>,
-}
+/// A weight of the previous flushes in the moving average of time taken to send
+/// a packet, so a single slow flush doesn't shrink the next batches too much.
+const PACKET_SEND_TIME_WEIGHT: u32 = 8;
+
+/// A hook invoked with a result of each flush, see `UdpSender::on_flush`.
+pub type FlushHook<'a> = Box<dyn FnMut(&SummaryPortion) + 'a>;
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum SupplyResult {
@@ -59,12 +67,38 @@ pub enum CreateUdpSenderError {
     #[fail(display = "Failed to create a socket")]
     CreateSocket(#[fail(cause)] io::Error),
 
+    #[fail(
+        display = "Failed to set the 'SO_MARK' socket option: it requires the CAP_NET_ADMIN \
+                   capability"
+    )]
+    MarkNotPermitted(#[fail(cause)] io::Error),
+
     #[fail(display = "Failed to connect a socket to {}", _1)]
     ConnectSocket {
         #[fail(cause)]
         error: io::Error,
         address: SocketAddr,
     },
+
+    #[fail(display = "File descriptor {} cannot be used to send datagrams", _1)]
+    InheritSocket {
+        #[fail(cause)]
+        error: io::Error,
+        fd: RawFd,
+    },
+}
+
+impl CreateUdpSenderError {
+    /// Returns the system call error that has caused the current one.
+    pub fn io_error(&self) -> &io::Error {
+        match self {
+            CreateUdpSenderError::SetSocketOption { error, .. } => error,
+            CreateUdpSenderError::CreateSocket(error) => error,
+            CreateUdpSenderError::MarkNotPermitted(error) => error,
+            CreateUdpSenderError::ConnectSocket { error, .. } => error,
+            CreateUdpSenderError::InheritSocket { error, .. } => error,
+        }
+    }
 }
 
 /// A structure representing a raw IPv4/IPv6 socket with a buffer. The buffer is
@@ -76,6 +110,66 @@ pub struct UdpSender<'a> {
     /// system call (`--test-intensity`). When this buffer is full, then it
     /// will be flushed to an endpoint using `libc::sendmmsg`.
     buffer: Vec<DataPortion<'a>>,
+
+    /// A hook invoked with a result of each non-empty `flush`, see
+    /// `UdpSender::on_flush`.
+    flush_hook: Option<FlushHook<'a>>,
+
+    /// Whether `flush` records transmission latencies, see
+    /// `UdpSender::enable_tx_timestamps`.
+    tx_timestamps: bool,
+
+    /// The multicast group which the socket has been configured for, see
+    /// `UdpSender::set_multicast`.
+    multicast_group: Option<IpAddr>,
+
+    /// Whether the socket marks packets, see `UdpSender::set_mark`.
+    marked: bool,
+
+    /// Specifies how failed system calls are retried, see
+    /// `UdpSender::set_retry_policy`.
+    retry_policy: RetryPolicy,
+
+    /// Whether `flush` reads the send queue occupancy, see
+    /// `UdpSender::enable_send_queue`.
+    read_send_queue: bool,
+
+    /// A number of bytes queued in the socket after the last `flush`, see
+    /// `UdpSender::send_queue`.
+    send_queue: Option<usize>,
+
+    /// Whether `flush` warns about packets sent partially, see
+    /// `UdpSender::enable_short_send_warnings`.
+    warn_short_sends: bool,
+
+    /// Whether `flush` sleeps the rest of `flush_period`, see
+    /// `UdpSender::disable_pacing`.
+    pacing: bool,
+
+    /// A period of time taken by each paced `flush`, see
+    /// `UdpSender::set_flush_period`.
+    flush_period: Duration,
+
+    /// Whether the socket is connected to a receiver, see
+    /// `UdpSender::is_connected`.
+    connected: bool,
+
+    /// Whether the crafted IP/UDP headers are sent together with payloads (a
+    /// raw socket), or only payloads are (a UDP socket), see
+    /// `UdpSender::from_inherited`.
+    sends_headers: bool,
+
+    /// An instant by which the last `flush` must finish, see
+    /// `UdpSender::set_deadline`.
+    deadline: Option<Instant>,
+
+    /// A moving average of time taken by `sendmmsg` per packet, measured by
+    /// the recent flushes.
+    packet_send_time: Option<Duration>,
+
+    /// A number of buffered packets which makes `supply` flush the buffer. It
+    /// equals to the buffer capacity unless the deadline is near.
+    batch_limit: usize,
 }
 
 impl<'a> UdpSender<'a> {
@@ -89,74 +183,273 @@ impl<'a> UdpSender<'a> {
         test_intensity: NonZeroUsize,
         dest: &SocketAddr,
         broadcast: bool,
-    ) -> Fallible<UdpSender> {
-        let fd = match unsafe {
-            libc::socket(
-                match dest.ip() {
-                    IpAddr::V4(_) => libc::AF_INET,
-                    IpAddr::V6(_) => libc::AF_INET6,
-                },
-                libc::SOCK_RAW,
-                libc::IPPROTO_RAW,
-            )
-        } {
+    ) -> Result<UdpSender, CreateUdpSenderError> {
+        let mut sender = UdpSender::new_unconnected(test_intensity, &dest.ip(), broadcast)?;
+        sender.connect(dest)?;
+
+        log::trace!("UdpSender::new has succeed (fd = {fd}).", fd = sender.fd);
+        Ok(sender)
+    }
+
+    /// Creates a raw socket of the same address family as `family` without
+    /// connecting it to any endpoint. Such a sender can transmit packets to
+    /// arbitrary destinations only by `UdpSender::send_to`, since `supply`,
+    /// `flush`, and `send_datagram` require a connected socket.
+    pub fn new_unconnected(
+        test_intensity: NonZeroUsize,
+        family: &IpAddr,
+        broadcast: bool,
+    ) -> Result<UdpSender<'a>, CreateUdpSenderError> {
+        Ok(UdpSender::from_socket(
+            create_socket(family, broadcast)?,
+            test_intensity,
+        ))
+    }
+
+    /// Wraps an unconnected raw socket `fd` (created by `create_socket`) into
+    /// a sender with an empty buffer and the default settings. The sender owns
+    /// `fd` and closes it when it's dropped.
+    fn from_socket(fd: RawFd, test_intensity: NonZeroUsize) -> UdpSender<'a> {
+        UdpSender {
+            fd,
+            buffer: {
+                let mut packets = Vec::new();
+                packets.reserve_exact(test_intensity.get());
+                packets
+            },
+            flush_hook: None,
+            tx_timestamps: false,
+            marked: false,
+            multicast_group: None,
+            retry_policy: RetryPolicy::default(),
+            read_send_queue: false,
+            send_queue: None,
+            warn_short_sends: false,
+            pacing: true,
+            flush_period: Duration::from_secs(1),
+            connected: false,
+            sends_headers: true,
+            deadline: None,
+            packet_send_time: None,
+            batch_limit: test_intensity.get(),
+        }
+    }
+
+    /// Wraps a socket `fd` created by someone else (e.g., inherited from a
+    /// parent process in another network namespace) into a sender connected
+    /// to `dest`. The sender owns a duplicate of `fd`, so `fd` itself stays
+    /// open and can be wrapped again.
+    ///
+    /// `fd` must be a socket of the same address family as `dest`: either a
+    /// raw one with `IPPROTO_RAW`, which sends the crafted IP/UDP headers, or
+    /// a UDP one, which sends only payloads, leaving the headers to the kernel.
+    pub fn from_inherited(
+        fd: RawFd,
+        test_intensity: NonZeroUsize,
+        dest: &SocketAddr,
+    ) -> Result<UdpSender<'a>, CreateUdpSenderError> {
+        let sends_headers = inherited_socket_kind(fd, dest)
+            .map_err(|error| CreateUdpSenderError::InheritSocket { error, fd })?;
+        let duplicate = match unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 0) } {
             -1 => {
-                return Err(CreateUdpSenderError::CreateSocket(io::Error::last_os_error()).into());
+                return Err(CreateUdpSenderError::InheritSocket {
+                    error: io::Error::last_os_error(),
+                    fd,
+                })
             }
-            value => value,
+            duplicate => duplicate,
         };
 
-        set_socket_option_safe(
-            fd,
-            libc::SOL_SOCKET,
-            libc::SO_SNDTIMEO,
-            &libc::timeval {
-                tv_sec: 1,
-                tv_usec: 0,
-            },
-        )
-        .map_err(|error| CreateUdpSenderError::SetSocketOption {
-            error,
-            option: String::from("SO_SNDTIMEO"),
-        })?;
+        let mut sender = UdpSender::from_socket(duplicate, test_intensity);
+        sender.sends_headers = sends_headers;
+        sender.connect(dest)?;
 
-        set_socket_option_safe(
-            fd,
-            libc::SOL_SOCKET,
-            libc::SO_BROADCAST,
-            if broadcast { &1 } else { &0 },
-        )
-        .map_err(|error| CreateUdpSenderError::SetSocketOption {
-            error,
-            option: String::from("SO_BROADCAST"),
+        log::trace!(
+            "UdpSender::from_inherited has succeed (fd = {fd}, sends_headers = {sends_headers}).",
+            fd = fd,
+            sends_headers = sends_headers,
+        );
+        Ok(sender)
+    }
+
+    /// Returns `false` if the socket sends only payloads, and the kernel
+    /// builds the IP/UDP headers (see `UdpSender::from_inherited`).
+    pub fn sends_headers(&self) -> bool {
+        self.sends_headers
+    }
+
+    /// Connects the socket to `dest`. A socket which is already connected is
+    /// reconnected to `dest`.
+    fn connect(&mut self, dest: &SocketAddr) -> Result<(), CreateUdpSenderError> {
+        connect_socket_safe(self.fd, dest).map_err(|error| {
+            CreateUdpSenderError::ConnectSocket {
+                error,
+                address: *dest,
+            }
         })?;
 
-        connect_socket_safe(fd, dest).map_err(|error| CreateUdpSenderError::ConnectSocket {
+        self.connected = true;
+        Ok(())
+    }
+
+    /// Takes the socket out of the sender without closing it, so it can be
+    /// reused by another sender (see `SocketPool`). Transmission timestamps,
+    /// the mark, and the multicast settings are reset, and the queued
+    /// timestamps are discarded.
+    fn into_socket(mut self) -> RawFd {
+        if self.tx_timestamps {
+            let _ = tx_timestamps::disable(self.fd);
+            let _ = tx_timestamps::read_all(self.fd);
+        }
+        if self.marked {
+            let _ = set_socket_option_safe(self.fd, libc::SOL_SOCKET, libc::SO_MARK, &0u32);
+        }
+        if let Some(group) = self.multicast_group {
+            let _ = set_multicast_options(self.fd, &group, 1, 0);
+        }
+
+        mem::replace(&mut self.fd, -1)
+    }
+
+    /// Enables kernel transmission timestamps (`SO_TIMESTAMPING`), so each
+    /// `flush` records delays between passing packets to the kernel and their
+    /// handoff to a network device into `TestSummary`. It adds a control
+    /// message per packet, so it's disabled by default.
+    pub fn enable_tx_timestamps(&mut self) -> Result<(), CreateUdpSenderError> {
+        tx_timestamps::enable(self.fd).map_err(|error| CreateUdpSenderError::SetSocketOption {
             error,
-            address: *dest,
+            option: String::from("SO_TIMESTAMPING"),
         })?;
 
-        let result = Ok(UdpSender {
-            fd,
-            buffer: {
-                let mut packets = Vec::new();
-                packets.reserve_exact(test_intensity.get());
-                packets
+        self.tx_timestamps = true;
+        Ok(())
+    }
+
+    /// Marks each packet sent through the socket with `mark` (`SO_MARK`), so
+    /// it can be matched by policy routing rules (`ip rule add fwmark ...`) and
+    /// packet filters. It requires the `CAP_NET_ADMIN` capability.
+    pub fn set_mark(&mut self, mark: u32) -> Result<(), CreateUdpSenderError> {
+        set_socket_option_safe(self.fd, libc::SOL_SOCKET, libc::SO_MARK, &mark).map_err(
+            |error| match error.raw_os_error() {
+                Some(libc::EPERM) => CreateUdpSenderError::MarkNotPermitted(error),
+                _ => CreateUdpSenderError::SetSocketOption {
+                    error,
+                    option: String::from("SO_MARK"),
+                },
             },
-        });
+        )?;
+
+        self.marked = mark != 0;
+        Ok(())
+    }
+
+    /// Configures the socket to send to the multicast `group`: sets the TTL
+    /// (hop limit) of multicast datagrams to `ttl` and sends them through the
+    /// network `interface` instead of the one chosen by the routing table.
+    ///
+    /// Unlike regular UDP sockets, raw ones take the TTL from the crafted IP
+    /// headers, so it must be written there as well (see `--multicast-ttl`).
+    /// The interface is respected as for regular sockets.
+    pub fn set_multicast(
+        &mut self,
+        group: &IpAddr,
+        ttl: u8,
+        interface: Option<&str>,
+    ) -> Result<(), CreateUdpSenderError> {
+        let if_index = match interface {
+            Some(name) => {
+                interface_index(name).map_err(|error| CreateUdpSenderError::SetSocketOption {
+                    error,
+                    option: format!("{} ({})", multicast_if_option(group), name),
+                })?
+            }
+            None => 0,
+        };
+
+        // Mark the socket first, so the settings are reset even if only one
+        // of them has been set
+        self.multicast_group = Some(*group);
+        set_multicast_options(self.fd, group, ttl, if_index)
+    }
+
+    /// Makes each `flush` read a number of bytes queued in the socket send
+    /// buffer (the Linux-specific `SIOCOUTQ`), which is returned by
+    /// `UdpSender::send_queue`. It adds a system call per flush, so it's
+    /// disabled by default.
+    pub fn enable_send_queue(&mut self) {
+        self.read_send_queue = true;
+    }
+
+    /// Makes each `flush` log a warning for every packet sent by fewer bytes
+    /// than it has (e.g., because of the MTU or the socket buffer), which is
+    /// otherwise visible only as a gap between the expected and sent bytes.
+    pub fn enable_short_send_warnings(&mut self) {
+        self.warn_short_sends = true;
+    }
+
+    /// Returns a number of bytes that were queued in the socket send buffer
+    /// right after the last `flush`, or `None` if it hasn't been read (see
+    /// `UdpSender::enable_send_queue`).
+    pub fn send_queue(&self) -> Option<usize> {
+        self.send_queue
+    }
 
-        log::trace!("UdpSender::new has succeed (fd = {fd}).", fd = fd);
-        result
+    /// Returns `true` if the socket is connected to a receiver (created by
+    /// `UdpSender::new`), so `supply`, `flush`, and `send_datagram` can be used.
+    #[allow(dead_code)]
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    /// Makes `flush` return right after sending a buffer instead of sleeping
+    /// the rest of a second, so a caller paces packets by itself (such as
+    /// `--burst` does by `--burst-pause`).
+    pub fn disable_pacing(&mut self) {
+        self.pacing = false;
+    }
+
+    /// Makes `flush` sleep the rest of `period` instead of a second, so a
+    /// buffer smaller than `--test-intensity` (see `--auto-batch`) keeps the
+    /// same rate by being flushed more often.
+    pub fn set_flush_period(&mut self, period: Duration) {
+        self.flush_period = period;
+    }
+
+    /// Makes the sender finish sending near `deadline` (such as the end of
+    /// `--test-duration`). When the deadline is closer than a full buffer
+    /// would take to send (estimated by the recent flushes), `supply` flushes
+    /// fewer packets, and `flush` doesn't sleep beyond the deadline.
+    pub fn set_deadline(&mut self, deadline: Instant) {
+        self.deadline = Some(deadline);
     }
 
-    /// Puts `packet` into an inner buffer. If a buffer is full, then all its
+    /// Makes `flush`, `send_datagram`, and `send_to` retry failed system calls
+    /// according to `policy` before returning an error. By default, nothing
+    /// is retried.
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry_policy = policy;
+    }
+
+    /// Sets `hook` to be invoked with `SummaryPortion` produced by each
+    /// `flush` of a non-empty buffer, right before it is added to a summary.
+    /// It allows to push metrics to somewhere else without polling
+    /// `TestSummary`. The previous hook, if any, is replaced.
+    ///
+    /// A sender is used by a single thread, so `hook` is always called on the
+    /// thread owning this `UdpSender` and doesn't need to be `Send`, but it
+    /// must synchronise access to data shared with other threads by itself.
+    pub fn on_flush<F: FnMut(&SummaryPortion) + 'a>(&mut self, hook: F) {
+        self.flush_hook = Some(Box::new(hook));
+    }
+
+    /// Puts `datagram` into an inner buffer. If a buffer is full, then all its
     /// content will be flushed and a specified `summary` will be updated.
     pub fn supply(
         &mut self,
         summary: &mut TestSummary,
-        packet: &'a [u8],
+        datagram: &'a Datagram,
     ) -> io::Result<SupplyResult> {
-        let result = if self.buffer.len() == self.buffer.capacity() {
+        let result = if self.buffer.len() >= self.batch_limit {
             self.flush(summary)?;
             SupplyResult::Flushed
         } else {
@@ -165,37 +458,138 @@ impl<'a> UdpSender<'a> {
 
         self.buffer.push(DataPortion {
             transmitted: 0,
-            slice: IoSlice::new(packet),
+            slices: [
+                IoSlice::new(self.header_of(datagram)),
+                IoSlice::new(&datagram.payload),
+            ],
         });
         Ok(result)
     }
 
-    /// Sends the a specified `packet` immediately (without buffering),
-    /// returning a number of bytes send successfully, or `io::Error`.
+    /// Counts `datagram` as expected but not sent without sending it, just as
+    /// a failed `flush` would do (`--simulate-loss`).
+    pub fn skip(&mut self, summary: &mut TestSummary, datagram: &Datagram) {
+        let size = self.header_of(datagram).len() + datagram.payload.len();
+        let portion = SummaryPortion::new(size, 0, 1, 0);
+
+        if let Some(ref mut hook) = self.flush_hook {
+            hook(&portion);
+        }
+        *summary += portion;
+    }
+
+    /// Sends `datagram` immediately (without buffering), returning a number of
+    /// bytes send successfully, or `io::Error`. Its headers and payload aren't
+    /// concatenated, but written by a single `writev` as separate buffers.
+    pub fn send_datagram(
+        &mut self,
+        summary: &mut TestSummary,
+        datagram: &Datagram,
+    ) -> io::Result<usize> {
+        let header = self.header_of(datagram);
+        self.send_vectored(
+            summary,
+            &[IoSlice::new(header), IoSlice::new(&datagram.payload)],
+        )
+    }
+
+    /// Returns the headers of `datagram` to be sent, which are empty if the
+    /// kernel builds them by itself.
+    fn header_of<'d>(&self, datagram: &'d Datagram) -> &'d [u8] {
+        if self.sends_headers {
+            &datagram.header
+        } else {
+            &[]
+        }
+    }
+
+    /// Sends `slices` immediately as a single packet, see `send_datagram`.
+    fn send_vectored(
+        &mut self,
+        summary: &mut TestSummary,
+        slices: &[IoSlice],
+    ) -> io::Result<usize> {
+        let fd = self.fd;
+        let length = slices.iter().map(|slice| slice.len()).sum();
+
+        match self.retry_policy.run(|| {
+            match unsafe {
+                libc::writev(
+                    fd,
+                    slices.as_ptr() as *const libc::iovec,
+                    slices.len() as libc::c_int,
+                )
+            } {
+                -1 => Err(io::Error::last_os_error()),
+                result => Ok(result as usize),
+            }
+        }) {
+            Err(error) => {
+                summary.update(SummaryPortion::new(length, 0, 1, 0));
+                log::trace!(
+                    "UdpSender::send_vectored has failed (fd = {fd}, error = {error}).",
+                    fd = self.fd,
+                    error = error
+                );
+                Err(error)
+            }
+            Ok(result) => {
+                summary.update(SummaryPortion::new(length, result, 1, 1));
+                log::trace!(
+                    "UdpSender::send_vectored has succeed (fd = {fd}).",
+                    fd = self.fd
+                );
+                Ok(result)
+            }
+        }
+    }
+
+    /// Sends a specified `packet` immediately to `dest`, returning a number of
+    /// bytes sent successfully, or `io::Error`. It's used to fan out packets
+    /// to many destinations through a single socket, so the socket must be
+    /// created by `UdpSender::new_unconnected` (not connected to anything).
     #[allow(dead_code)]
-    pub fn send_one(&mut self, summary: &mut TestSummary, packet: &[u8]) -> io::Result<usize> {
-        match unsafe {
-            libc::send(
-                self.fd,
-                packet as *const _ as *const c_void,
-                packet.len(),
-                0,
-            )
-        } {
-            -1 => {
+    pub fn send_to(
+        &mut self,
+        summary: &mut TestSummary,
+        packet: &[u8],
+        dest: &SocketAddr,
+    ) -> io::Result<usize> {
+        // Raw sockets take destination ports from the crafted UDP header, and
+        // Linux interprets a non-zero IPv6 port as a protocol number, so clear it
+        let mut dest = *dest;
+        dest.set_port(0);
+
+        let (address, length) = socket_address(&dest);
+        let fd = self.fd;
+
+        match self.retry_policy.run(|| {
+            match unsafe {
+                libc::sendto(
+                    fd,
+                    packet as *const _ as *const c_void,
+                    packet.len(),
+                    0,
+                    &address as *const _ as *const libc::sockaddr,
+                    length,
+                )
+            } {
+                -1 => Err(io::Error::last_os_error()),
+                result => Ok(result as usize),
+            }
+        }) {
+            Err(error) => {
                 summary.update(SummaryPortion::new(packet.len(), 0, 1, 0));
-                let error = io::Error::last_os_error();
                 log::trace!(
-                    "UdpSender::send_one has failed (fd = {fd}, error = {error}).",
+                    "UdpSender::send_to has failed (fd = {fd}, error = {error}).",
                     fd = self.fd,
                     error = error
                 );
                 Err(error)
             }
-            result => {
-                let result = result as usize;
+            Ok(result) => {
                 summary.update(SummaryPortion::new(packet.len(), result, 1, 1));
-                log::trace!("UdpSender::send_one has succeed (fd = {fd}).", fd = self.fd);
+                log::trace!("UdpSender::send_to has succeed (fd = {fd}).", fd = self.fd);
                 Ok(result)
             }
         }
@@ -207,34 +601,170 @@ impl<'a> UdpSender<'a> {
     pub fn flush(&mut self, summary: &mut TestSummary) -> io::Result<()> {
         if !self.buffer.is_empty() {
             let start = Instant::now();
+            let sent_at = SystemTime::now();
 
-            let packets_sent = sendmmsg_wrapper::sendmmsg(self.fd, self.buffer.as_mut_slice())?;
+            let (fd, buffer) = (self.fd, &mut self.buffer);
+            let packets_sent = self
+                .retry_policy
+                .run_partial(buffer.as_mut_slice(), |packets| {
+                    sendmmsg_wrapper::sendmmsg(fd, packets)
+                })?;
+            self.update_packet_send_time(start.elapsed());
+
+            if self.tx_timestamps {
+                self.record_tx_latencies(summary, sent_at);
+            }
+            if self.read_send_queue {
+                self.send_queue = self.read_send_queue();
+            }
 
             let mut bytes_expected = 0usize;
             let mut bytes_sent = 0usize;
             for packet in &self.buffer {
-                bytes_expected += packet.slice.len();
+                bytes_expected += packet.size();
                 bytes_sent += packet.transmitted;
             }
+            if self.warn_short_sends {
+                for (index, packet) in short_sends(&self.buffer, packets_sent) {
+                    log::warn!(
+                        "a short send to {endpoints}: packet #{index} of a batch of {batch} has \
+                         transmitted {transmitted} of {size} bytes!",
+                        endpoints = super::current_endpoints_colored(),
+                        index = index,
+                        batch = self.buffer.len(),
+                        transmitted = packet.transmitted,
+                        size = packet.size(),
+                    );
+                }
+            }
 
-            *summary +=
+            let portion =
                 SummaryPortion::new(bytes_expected, bytes_sent, self.buffer.len(), packets_sent);
+            if let Some(ref mut hook) = self.flush_hook {
+                hook(&portion);
+            }
+
+            *summary += portion;
             self.buffer.clear();
 
-            // If the operation took less than a second, then sleep the rest of time
-            // according to `--test-intensity`:
-            if let Some(wait) = Duration::from_secs(1).checked_sub(start.elapsed()) {
-                thread::sleep(wait);
+            // If the operation took less than a flush period, then sleep the rest of
+            // time according to `--test-intensity`:
+            if self.pacing {
+                if let Some(mut wait) = self.flush_period.checked_sub(start.elapsed()) {
+                    if let Some(deadline) = self.deadline {
+                        wait = wait.min(deadline.saturating_duration_since(Instant::now()));
+                    }
+                    thread::sleep(wait);
+                }
             }
+
+            self.batch_limit = self.next_batch_limit();
         }
 
         log::trace!("UdpSender::flush has succeed (fd = {fd}).", fd = self.fd);
         Ok(())
     }
+
+    /// Adds `elapsed`, taken by `sendmmsg` of the whole buffer, to the moving
+    /// average of time taken per packet.
+    fn update_packet_send_time(&mut self, elapsed: Duration) {
+        let sample = elapsed / self.buffer.len().try_into().unwrap_or(u32::MAX);
+
+        self.packet_send_time = Some(match self.packet_send_time {
+            Some(average) => {
+                (average * (PACKET_SEND_TIME_WEIGHT - 1) + sample) / PACKET_SEND_TIME_WEIGHT
+            }
+            None => sample,
+        });
+    }
+
+    /// Returns a number of packets which can be sent before the deadline
+    /// according to `packet_send_time`, but at least one and not more than the
+    /// buffer capacity.
+    fn next_batch_limit(&self) -> usize {
+        let capacity = self.buffer.capacity();
+
+        match (self.deadline, self.packet_send_time) {
+            (Some(deadline), Some(packet_send_time)) if packet_send_time > Duration::ZERO => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                let packets = remaining.as_nanos() / packet_send_time.as_nanos();
+                (packets.min(capacity as u128) as usize).max(1)
+            }
+            _ => capacity,
+        }
+    }
+
+    /// Reads a number of bytes queued in the socket send buffer using
+    /// `SIOCOUTQ`, logging a failure at the trace level.
+    fn read_send_queue(&self) -> Option<usize> {
+        let mut queued: libc::c_int = 0;
+
+        // Linux defines `SIOCOUTQ` as `TIOCOUTQ`
+        match unsafe { libc::ioctl(self.fd, libc::TIOCOUTQ, &mut queued) } {
+            -1 => {
+                log::trace!(
+                    "Failed to read the send queue (fd = {fd}, error = {error}).",
+                    fd = self.fd,
+                    error = io::Error::last_os_error()
+                );
+                None
+            }
+            _ => Some(queued as usize),
+        }
+    }
+
+    /// Reads the queued transmission timestamps and records their delays since
+    /// `sent_at` into `summary`. Timestamps of the previous batches that have
+    /// arrived too late precede `sent_at`, so they are skipped.
+    fn record_tx_latencies(&self, summary: &mut TestSummary, sent_at: SystemTime) {
+        match tx_timestamps::read_all(self.fd) {
+            Ok(timestamps) => {
+                for timestamp in timestamps {
+                    if let Ok(latency) = timestamp.duration_since(sent_at) {
+                        summary.record_tx_latency(latency);
+                    }
+                }
+            }
+            Err(error) => log::trace!(
+                "Failed to read transmission timestamps (fd = {fd}, error = {error}).",
+                fd = self.fd,
+                error = error
+            ),
+        }
+    }
+}
+
+/// Returns the packets among the first `packets_sent` of `packets` (the ones
+/// sent by `sendmmsg`) which have been transmitted by fewer bytes than they
+/// have, together with their indices. The rest of `packets` haven't been sent
+/// at all, so they aren't short sends.
+fn short_sends<'p, 'a>(
+    packets: &'p [DataPortion<'a>],
+    packets_sent: usize,
+) -> impl Iterator<Item = (usize, &'p DataPortion<'a>)> {
+    packets
+        .iter()
+        .take(packets_sent)
+        .enumerate()
+        .filter(|(_, packet)| packet.transmitted != packet.size())
+}
+
+/// Exposes the socket to integrate a sender with `poll`/`epoll` loops or to
+/// set additional socket options. The descriptor is still owned by the sender
+/// and closed when it's dropped.
+impl<'a> AsRawFd for UdpSender<'a> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
 }
 
 impl<'a> Drop for UdpSender<'a> {
     fn drop(&mut self) {
+        // The socket has been taken by `UdpSender::into_socket`
+        if self.fd == -1 {
+            return;
+        }
+
         unsafe {
             if libc::close(self.fd) == -1 {
                 panic!("Failed to drop UdpSender");
@@ -243,6 +773,119 @@ impl<'a> Drop for UdpSender<'a> {
     }
 }
 
+/// Creates an unconnected raw socket of the same address family as `family`,
+/// which allows us to transmit raw IPv4/IPv6 packets (IPv4/IPv6 header +
+/// user's data).
+fn create_socket(family: &IpAddr, broadcast: bool) -> Result<RawFd, CreateUdpSenderError> {
+    let fd = match unsafe {
+        libc::socket(
+            match family {
+                IpAddr::V4(_) => libc::AF_INET,
+                IpAddr::V6(_) => libc::AF_INET6,
+            },
+            libc::SOCK_RAW,
+            libc::IPPROTO_RAW,
+        )
+    } {
+        -1 => {
+            return Err(CreateUdpSenderError::CreateSocket(
+                io::Error::last_os_error(),
+            ))
+        }
+        value => value,
+    };
+
+    set_socket_option_safe(
+        fd,
+        libc::SOL_SOCKET,
+        libc::SO_SNDTIMEO,
+        &libc::timeval {
+            tv_sec: 1,
+            tv_usec: 0,
+        },
+    )
+    .map_err(|error| CreateUdpSenderError::SetSocketOption {
+        error,
+        option: String::from("SO_SNDTIMEO"),
+    })?;
+
+    set_socket_option_safe(
+        fd,
+        libc::SOL_SOCKET,
+        libc::SO_BROADCAST,
+        if broadcast { &1 } else { &0 },
+    )
+    .map_err(|error| CreateUdpSenderError::SetSocketOption {
+        error,
+        option: String::from("SO_BROADCAST"),
+    })?;
+
+    Ok(fd)
+}
+
+/// Sets the multicast TTL (hop limit) and the outgoing interface (`0` for
+/// the routing table's choice) of the socket `fd` of the same address family
+/// as `group`.
+fn set_multicast_options(
+    fd: RawFd,
+    group: &IpAddr,
+    ttl: u8,
+    if_index: libc::c_uint,
+) -> Result<(), CreateUdpSenderError> {
+    let result = match group {
+        IpAddr::V4(_) => set_socket_option_safe(
+            fd,
+            libc::IPPROTO_IP,
+            libc::IP_MULTICAST_TTL,
+            &libc::c_int::from(ttl),
+        )
+        .map_err(|error| (error, "IP_MULTICAST_TTL"))
+        .and_then(|_| {
+            let request = libc::ip_mreqn {
+                imr_multiaddr: libc::in_addr { s_addr: 0 },
+                imr_address: libc::in_addr { s_addr: 0 },
+                imr_ifindex: if_index as libc::c_int,
+            };
+            set_socket_option_safe(fd, libc::IPPROTO_IP, libc::IP_MULTICAST_IF, &request)
+                .map_err(|error| (error, "IP_MULTICAST_IF"))
+        }),
+        IpAddr::V6(_) => set_socket_option_safe(
+            fd,
+            libc::IPPROTO_IPV6,
+            libc::IPV6_MULTICAST_HOPS,
+            &libc::c_int::from(ttl),
+        )
+        .map_err(|error| (error, "IPV6_MULTICAST_HOPS"))
+        .and_then(|_| {
+            set_socket_option_safe(fd, libc::IPPROTO_IPV6, libc::IPV6_MULTICAST_IF, &if_index)
+                .map_err(|error| (error, "IPV6_MULTICAST_IF"))
+        }),
+    };
+
+    result.map_err(|(error, option)| CreateUdpSenderError::SetSocketOption {
+        error,
+        option: String::from(option),
+    })
+}
+
+fn multicast_if_option(group: &IpAddr) -> &'static str {
+    match group {
+        IpAddr::V4(_) => "IP_MULTICAST_IF",
+        IpAddr::V6(_) => "IPV6_MULTICAST_IF",
+    }
+}
+
+/// Returns the index of the network interface `name`.
+fn interface_index(name: &str) -> io::Result<libc::c_uint> {
+    let name =
+        CString::new(name).map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+
+    match unsafe { libc::if_nametoindex(name.as_ptr()) } {
+        0 => Err(io::Error::last_os_error()),
+        index => Ok(index),
+    }
+}
+
 fn set_socket_option_safe<T>(
     fd: RawFd,
     level: libc::c_int,
@@ -263,58 +906,97 @@ fn set_socket_option_safe<T>(
     }
 }
 
+/// Checks that `fd` is a socket able to send datagrams to `dest`, and returns
+/// `true` if it's a raw one sending the crafted headers, or `false` if it's a
+/// UDP one.
+fn inherited_socket_kind(fd: RawFd, dest: &SocketAddr) -> io::Result<bool> {
+    let invalid = |message: &str| Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+
+    let domain = get_socket_option_safe(fd, libc::SOL_SOCKET, libc::SO_DOMAIN)?;
+    match (domain, dest) {
+        (libc::AF_INET, SocketAddr::V4(_)) | (libc::AF_INET6, SocketAddr::V6(_)) => (),
+        (libc::AF_INET, _) | (libc::AF_INET6, _) => {
+            return invalid("its address family differs from the receiver's one")
+        }
+        _ => return invalid("it's neither an IPv4 nor an IPv6 socket"),
+    }
+
+    let protocol = get_socket_option_safe(fd, libc::SOL_SOCKET, libc::SO_PROTOCOL)?;
+    match get_socket_option_safe(fd, libc::SOL_SOCKET, libc::SO_TYPE)? {
+        libc::SOCK_RAW if protocol == libc::IPPROTO_RAW => Ok(true),
+        libc::SOCK_RAW => invalid("it's a raw socket without IPPROTO_RAW"),
+        libc::SOCK_DGRAM if protocol == libc::IPPROTO_UDP => Ok(false),
+        _ => invalid("it's neither a raw nor a UDP socket"),
+    }
+}
+
+fn get_socket_option_safe(
+    fd: RawFd,
+    level: libc::c_int,
+    name: libc::c_int,
+) -> io::Result<libc::c_int> {
+    let mut value: libc::c_int = 0;
+    let mut length = mem::size_of_val(&value) as libc::socklen_t;
+
+    match unsafe {
+        libc::getsockopt(
+            fd,
+            level,
+            name,
+            &mut value as *mut _ as *mut c_void,
+            &mut length,
+        )
+    } {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(value),
+    }
+}
+
 fn connect_socket_safe(fd: RawFd, dest: &SocketAddr) -> io::Result<()> {
-    let ret = match dest {
-        SocketAddr::V4(dest_v4) => {
-            let octets = dest_v4.ip().octets();
+    let (address, length) = socket_address(dest);
+
+    match unsafe { libc::connect(fd, &address as *const _ as *const libc::sockaddr, length) } {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+/// Converts `address` into `libc::sockaddr_in` or `libc::sockaddr_in6`
+/// (stored inside `libc::sockaddr_storage`) together with its length.
+fn socket_address(address: &SocketAddr) -> (libc::sockaddr_storage, libc::socklen_t) {
+    let mut storage = unsafe { mem::zeroed::<libc::sockaddr_storage>() };
 
+    let length = match address {
+        SocketAddr::V4(address_v4) => {
             let addr_v4 = libc::sockaddr_in {
                 sin_family: libc::AF_INET.try_into().unwrap(),
-                sin_port: dest.port().to_be(),
+                sin_port: address_v4.port().to_be(),
                 sin_addr: libc::in_addr {
-                    s_addr: u32::to_be(
-                        (u32::from(octets[0]) << 24)
-                            | (u32::from(octets[1]) << 16)
-                            | (u32::from(octets[2]) << 8)
-                            | u32::from(octets[3]),
-                    ),
+                    s_addr: u32::from(*address_v4.ip()).to_be(),
                 },
                 ..unsafe { mem::zeroed() }
             };
 
-            unsafe {
-                libc::connect(
-                    fd,
-                    &addr_v4 as *const _ as *const libc::sockaddr,
-                    mem::size_of_val(&addr_v4).try_into().unwrap(),
-                )
-            }
+            unsafe { (&mut storage as *mut _ as *mut libc::sockaddr_in).write(addr_v4) };
+            mem::size_of_val(&addr_v4)
         }
-        SocketAddr::V6(dest_v6) => {
+        SocketAddr::V6(address_v6) => {
             let addr_v6 = libc::sockaddr_in6 {
                 sin6_family: libc::AF_INET6.try_into().unwrap(),
-                sin6_port: dest.port().to_be(),
+                sin6_port: address_v6.port().to_be(),
                 sin6_addr: libc::in6_addr {
-                    s6_addr: dest_v6.ip().octets(),
+                    s6_addr: address_v6.ip().octets(),
                 },
-                sin6_flowinfo: dest_v6.flowinfo(),
-                sin6_scope_id: dest_v6.scope_id(),
+                sin6_flowinfo: address_v6.flowinfo(),
+                sin6_scope_id: address_v6.scope_id(),
             };
 
-            unsafe {
-                libc::connect(
-                    fd,
-                    &addr_v6 as *const _ as *const libc::sockaddr,
-                    mem::size_of_val(&addr_v6).try_into().unwrap(),
-                )
-            }
+            unsafe { (&mut storage as *mut _ as *mut libc::sockaddr_in6).write(addr_v6) };
+            mem::size_of_val(&addr_v6)
         }
     };
 
-    match ret {
-        -1 => Err(io::Error::last_os_error()),
-        _ => Ok(()),
-    }
+    (storage, length.try_into().unwrap())
 }
 
 #[cfg(test)]
@@ -322,11 +1004,13 @@ mod tests {
     use std::net::Ipv4Addr;
     use std::net::UdpSocket;
     use std::ops::Deref;
+    use std::sync::Arc;
 
     use etherparse::PacketBuilder;
 
     use lazy_static::lazy_static;
 
+    use crate::core::craft_datagrams::Payload;
     use crate::core::statistics::TestSummary;
 
     use super::*;
@@ -353,6 +1037,10 @@ mod tests {
                 .expect("Failed to serialize a UDP/IPv4 packet into Vec<u8>");
             serialized
         };
+        static ref TEST_DATAGRAM: Datagram = Datagram {
+            header: TEST_UDP_PACKET[..28].to_vec(),
+            payload: Arc::new(Payload::Owned(TEST_UDP_PACKET[28..].to_vec())),
+        };
     }
 
     #[test]
@@ -365,6 +1053,27 @@ mod tests {
         assert_eq!(buffer.buffer.len(), 0);
     }
 
+    #[test]
+    fn reports_connection_state() {
+        let local_addr = UDP_SERVER.local_addr().unwrap();
+
+        let connected = UdpSender::new(NonZeroUsize::new(1).unwrap(), &local_addr, false)
+            .expect("UdpSender::new(...) failed");
+        let unconnected =
+            UdpSender::new_unconnected(NonZeroUsize::new(1).unwrap(), &local_addr.ip(), false)
+                .expect("UdpSender::new_unconnected(...) failed");
+
+        assert!(connected.is_connected());
+        assert!(!unconnected.is_connected());
+
+        for sender in &[connected, unconnected] {
+            assert_ne!(
+                unsafe { libc::fcntl(sender.as_raw_fd(), libc::F_GETFD) },
+                -1
+            );
+        }
+    }
+
     #[test]
     fn packets_buffer_works_correctly() {
         const SUPPLY_COUNT: usize = 6;
@@ -376,15 +1085,15 @@ mod tests {
 
         let check = |buffer: &UdpSender| {
             assert_eq!(buffer.buffer.capacity(), 4);
-            assert_eq!(
-                buffer.buffer.last().unwrap().slice.deref(),
-                TEST_UDP_PACKET.as_slice()
-            );
+            let last = buffer.buffer.last().unwrap();
+            assert_eq!(last.slices[0].deref(), &TEST_UDP_PACKET[..28]);
+            assert_eq!(last.slices[1].deref(), &TEST_UDP_PACKET[28..]);
+            assert_eq!(last.size(), TEST_UDP_PACKET.len());
         };
 
         let mut supply = |buffer: &mut UdpSender| {
             buffer
-                .supply(&mut summary, TEST_UDP_PACKET.as_ref())
+                .supply(&mut summary, &TEST_DATAGRAM)
                 .expect("buffer.supply() failed");
         };
 
@@ -420,14 +1129,303 @@ mod tests {
         // Check that our UdpSender has updates the TestSummary
         assert!(
             summary.megabytes_expected() == summary.megabytes_sent()
-                && summary.megabytes_sent() == (SUPPLY_COUNT * TEST_UDP_PACKET.len()) / 1024 / 1024
+                && summary.megabytes_sent()
+                    == (SUPPLY_COUNT * TEST_UDP_PACKET.len()) as u64 / 1024 / 1024
         );
         assert!(
             summary.packets_expected() == summary.packets_sent()
-                && summary.packets_sent() == SUPPLY_COUNT
+                && summary.packets_sent() == SUPPLY_COUNT as u64
         );
     }
 
+    fn check_send_to(receiver: UdpSocket) {
+        let receiver_addr = receiver.local_addr().unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .expect("UdpSocket::set_read_timeout(...) failed");
+
+        let payload = b"Unconnected";
+        let builder = match receiver_addr {
+            SocketAddr::V4(addr) => PacketBuilder::ipv4(addr.ip().octets(), addr.ip().octets(), 8),
+            SocketAddr::V6(addr) => PacketBuilder::ipv6(addr.ip().octets(), addr.ip().octets(), 8),
+        }
+        .udp(receiver_addr.port(), receiver_addr.port());
+        let mut packet = Vec::<u8>::with_capacity(builder.size(payload.len()));
+        builder
+            .write(&mut packet, payload)
+            .expect("Failed to serialize a UDP/IP packet into Vec<u8>");
+
+        let mut summary = TestSummary::default();
+        let mut sender =
+            UdpSender::new_unconnected(NonZeroUsize::new(1).unwrap(), &receiver_addr.ip(), false)
+                .expect("UdpSender::new_unconnected(...) failed");
+
+        sender
+            .send_to(&mut summary, &packet, &receiver_addr)
+            .expect("sender.send_to(...) failed");
+        assert_eq!(summary.packets_sent(), 1);
+
+        let mut buffer = [0u8; 64];
+        let received = receiver
+            .recv(&mut buffer)
+            .expect("Failed to receive a datagram");
+        assert_eq!(&buffer[..received], payload);
+    }
+
+    #[test]
+    fn sends_to_ipv4_destination() {
+        check_send_to(UdpSocket::bind("127.0.0.1:0").expect("Failed to bind an IPv4 socket"));
+    }
+
+    #[test]
+    fn sends_to_ipv6_destination() {
+        check_send_to(UdpSocket::bind("[::1]:0").expect("Failed to bind an IPv6 socket"));
+    }
+
+    #[test]
+    fn invokes_flush_hook() {
+        let local_addr = UDP_SERVER.local_addr().unwrap();
+        let mut portions = Vec::new();
+
+        {
+            let mut summary = TestSummary::default();
+            let mut sender = UdpSender::new(NonZeroUsize::new(2).unwrap(), &local_addr, false)
+                .expect("UdpSender::new(...) failed");
+            sender.on_flush(|portion| portions.push(*portion));
+
+            for _ in 0..3 {
+                sender
+                    .supply(&mut summary, &TEST_DATAGRAM)
+                    .expect("sender.supply() failed");
+            }
+            sender.flush(&mut summary).expect("sender.flush() failed");
+
+            // Flushing an empty buffer mustn't invoke the hook
+            sender.flush(&mut summary).expect("sender.flush() failed");
+        }
+
+        assert_eq!(portions.len(), 2);
+        assert_eq!(portions[0].packets_sent(), 2);
+        assert_eq!(portions[1].packets_sent(), 1);
+        assert_eq!(portions[1].bytes_sent(), TEST_UDP_PACKET.len());
+    }
+
+    #[test]
+    fn records_tx_latencies() {
+        let local_addr = UDP_SERVER.local_addr().unwrap();
+
+        let mut summary = TestSummary::default();
+        let mut sender = UdpSender::new(NonZeroUsize::new(3).unwrap(), &local_addr, false)
+            .expect("UdpSender::new(...) failed");
+        sender
+            .enable_tx_timestamps()
+            .expect("sender.enable_tx_timestamps() failed");
+
+        for _ in 0..3 {
+            sender
+                .supply(&mut summary, &TEST_DATAGRAM)
+                .expect("sender.supply() failed");
+        }
+        sender.flush(&mut summary).expect("sender.flush() failed");
+
+        assert!(summary.tx_latency_stats().is_some());
+    }
+
+    #[test]
+    fn sends_to_multicast_group() {
+        let group = Ipv4Addr::new(239, 255, 17, 33);
+        let receiver = UdpSocket::bind((group, 0)).expect("UdpSocket::bind(...) failed");
+        receiver
+            .join_multicast_v4(&group, &Ipv4Addr::LOCALHOST)
+            .expect("receiver.join_multicast_v4(...) failed");
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .expect("UdpSocket::set_read_timeout(...) failed");
+        let receiver_addr = receiver.local_addr().unwrap();
+
+        let payload = b"To everyone in the group";
+        let builder = PacketBuilder::ipv4(Ipv4Addr::LOCALHOST.octets(), group.octets(), 1)
+            .udp(receiver_addr.port(), receiver_addr.port());
+        let mut packet = Vec::<u8>::with_capacity(builder.size(payload.len()));
+        builder
+            .write(&mut packet, payload)
+            .expect("Failed to serialize a UDP/IPv4 packet into Vec<u8>");
+
+        let mut summary = TestSummary::default();
+        let mut sender = UdpSender::new(NonZeroUsize::new(1).unwrap(), &receiver_addr, false)
+            .expect("UdpSender::new(...) failed");
+        sender
+            .set_multicast(&receiver_addr.ip(), 1, Some("lo"))
+            .expect("sender.set_multicast(...) failed");
+        sender
+            .send_vectored(&mut summary, &[IoSlice::new(&packet)])
+            .expect("sender.send_vectored(...) failed");
+
+        let mut buffer = [0u8; 64];
+        let received = receiver
+            .recv(&mut buffer)
+            .expect("Failed to receive a multicast datagram");
+        assert_eq!(&buffer[..received], payload);
+
+        match sender.set_multicast(&receiver_addr.ip(), 1, Some("no-such-interface")) {
+            Err(CreateUdpSenderError::SetSocketOption { option, .. }) => {
+                assert_eq!(option, "IP_MULTICAST_IF (no-such-interface)")
+            }
+            result => panic!(
+                "CreateUdpSenderError::SetSocketOption must be returned: {:?}",
+                result.err()
+            ),
+        }
+    }
+
+    #[test]
+    fn marks_packets() {
+        let local_addr = UDP_SERVER.local_addr().unwrap();
+        let mut sender = UdpSender::new(NonZeroUsize::new(1).unwrap(), &local_addr, false)
+            .expect("UdpSender::new(...) failed");
+        let fd = sender.as_raw_fd();
+
+        let mark = || {
+            let mut value: u32 = 0;
+            let mut length = mem::size_of_val(&value) as libc::socklen_t;
+            let result = unsafe {
+                libc::getsockopt(
+                    fd,
+                    libc::SOL_SOCKET,
+                    libc::SO_MARK,
+                    &mut value as *mut _ as *mut c_void,
+                    &mut length,
+                )
+            };
+            assert_ne!(result, -1);
+            value
+        };
+        assert_eq!(mark(), 0);
+
+        match sender.set_mark(0xBEEF) {
+            Ok(()) => assert_eq!(mark(), 0xBEEF),
+            // Tests might be run without CAP_NET_ADMIN
+            Err(CreateUdpSenderError::MarkNotPermitted(_)) => (),
+            Err(error) => panic!("sender.set_mark() failed: {}", error),
+        }
+    }
+
+    #[test]
+    fn reads_send_queue() {
+        let local_addr = UDP_SERVER.local_addr().unwrap();
+
+        let mut summary = TestSummary::default();
+        let mut sender = UdpSender::new(NonZeroUsize::new(3).unwrap(), &local_addr, false)
+            .expect("UdpSender::new(...) failed");
+        assert_eq!(sender.send_queue(), None);
+
+        sender.enable_send_queue();
+        sender
+            .supply(&mut summary, &TEST_DATAGRAM)
+            .expect("sender.supply() failed");
+        sender.flush(&mut summary).expect("sender.flush() failed");
+
+        assert!(sender.send_queue().is_some());
+    }
+
+    #[test]
+    fn finds_short_sends() {
+        let packets = [
+            DataPortion {
+                transmitted: 9,
+                slices: [IoSlice::new(b"head"), IoSlice::new(b"tail!")],
+            },
+            DataPortion {
+                transmitted: 4,
+                slices: [IoSlice::new(b"head"), IoSlice::new(b"tail!")],
+            },
+            DataPortion {
+                transmitted: 0,
+                slices: [IoSlice::new(b"head"), IoSlice::new(b"tail!")],
+            },
+        ];
+
+        // The last packet hasn't been sent at all
+        let short = short_sends(&packets, 2)
+            .map(|(index, packet)| (index, packet.transmitted))
+            .collect::<Vec<(usize, usize)>>();
+        assert_eq!(short, vec![(1, 4)]);
+
+        assert_eq!(short_sends(&packets, 3).count(), 2);
+        assert_eq!(short_sends(&packets[..1], 1).count(), 0);
+    }
+
+    #[test]
+    fn flushes_without_pacing() {
+        let local_addr = UDP_SERVER.local_addr().unwrap();
+
+        let mut summary = TestSummary::default();
+        let mut sender = UdpSender::new(NonZeroUsize::new(1).unwrap(), &local_addr, false)
+            .expect("UdpSender::new(...) failed");
+        sender.disable_pacing();
+
+        let start = Instant::now();
+        for _ in 0..3 {
+            sender
+                .supply(&mut summary, &TEST_DATAGRAM)
+                .expect("sender.supply() failed");
+        }
+        sender.flush(&mut summary).expect("sender.flush() failed");
+
+        assert_eq!(summary.packets_sent(), 3);
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn paces_by_flush_period() {
+        let local_addr = UDP_SERVER.local_addr().unwrap();
+
+        let mut summary = TestSummary::default();
+        let mut sender = UdpSender::new(NonZeroUsize::new(1).unwrap(), &local_addr, false)
+            .expect("UdpSender::new(...) failed");
+        sender.set_flush_period(Duration::from_millis(50));
+
+        let start = Instant::now();
+        for _ in 0..3 {
+            sender
+                .supply(&mut summary, &TEST_DATAGRAM)
+                .expect("sender.supply() failed");
+        }
+        sender.flush(&mut summary).expect("sender.flush() failed");
+
+        assert_eq!(summary.packets_sent(), 3);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn shrinks_batches_near_deadline() {
+        let local_addr = UDP_SERVER.local_addr().unwrap();
+
+        let mut summary = TestSummary::default();
+        let mut sender = UdpSender::new(NonZeroUsize::new(1000).unwrap(), &local_addr, false)
+            .expect("UdpSender::new(...) failed");
+        sender.packet_send_time = Some(Duration::from_millis(1));
+        assert_eq!(sender.next_batch_limit(), 1000);
+
+        sender.set_deadline(Instant::now() + Duration::from_millis(10));
+        assert!((1..=10).contains(&sender.next_batch_limit()));
+
+        sender.set_deadline(Instant::now());
+        assert_eq!(sender.next_batch_limit(), 1);
+
+        // A paced flush mustn't sleep beyond the deadline
+        let start = Instant::now();
+        sender.set_deadline(start + Duration::from_millis(50));
+        sender
+            .supply(&mut summary, &TEST_DATAGRAM)
+            .expect("sender.supply() failed");
+        sender.flush(&mut summary).expect("sender.flush() failed");
+
+        assert!(start.elapsed() < Duration::from_millis(500));
+        assert_eq!(sender.batch_limit, 1);
+    }
+
     #[test]
     fn transmits_one_datagram_corectly() {
         let local_addr = UDP_SERVER.local_addr().unwrap();
@@ -441,16 +1439,63 @@ mod tests {
         assert_eq!(summary.packets_sent(), 0);
 
         sender
-            .send_one(&mut summary, TEST_UDP_PACKET.as_slice())
-            .expect("sender.send_one(...) failed");
+            .send_vectored(&mut summary, &[IoSlice::new(&TEST_UDP_PACKET)])
+            .expect("sender.send_vectored(...) failed");
 
         // Check that our UdpSender has updates the TestSummary
         assert!(
             summary.megabytes_expected() == summary.megabytes_sent()
-                && summary.megabytes_sent() == TEST_UDP_PACKET.len() / 1024 / 1024
+                && summary.megabytes_sent() == TEST_UDP_PACKET.len() as u64 / 1024 / 1024
         );
         assert!(
             summary.packets_expected() == summary.packets_sent() && summary.packets_sent() == 1
         );
     }
+
+    #[test]
+    fn sends_payloads_through_inherited_udp_sockets() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").expect("UdpSocket::bind() has failed");
+        let receiver_addr = receiver.local_addr().unwrap();
+        let socket = UdpSocket::bind("127.0.0.1:0").expect("UdpSocket::bind() has failed");
+
+        let mut summary = TestSummary::default();
+        let mut sender = UdpSender::from_inherited(
+            socket.as_raw_fd(),
+            NonZeroUsize::new(1).unwrap(),
+            &receiver_addr,
+        )
+        .expect("UdpSender::from_inherited(...) failed");
+        assert!(!sender.sends_headers());
+        sender
+            .send_datagram(&mut summary, &TEST_DATAGRAM)
+            .expect("sender.send_datagram(...) failed");
+
+        // The kernel has built the headers for the payload only
+        let mut buffer = [0u8; 64];
+        let (received, source) = receiver
+            .recv_from(&mut buffer)
+            .expect("Failed to receive a datagram");
+        assert_eq!(&buffer[..received], &TEST_DATAGRAM.payload[..]);
+        assert_eq!(source, socket.local_addr().unwrap());
+
+        // The sender has closed only its duplicate
+        drop(sender);
+        assert!(socket.local_addr().is_ok());
+    }
+
+    #[test]
+    fn rejects_unusable_inherited_sockets() {
+        let local_addr = UDP_SERVER.local_addr().unwrap();
+        let file = std::fs::File::open("/dev/null").expect("Failed to open /dev/null");
+        let ipv6 = UdpSocket::bind("[::1]:0").expect("UdpSocket::bind() has failed");
+
+        for fd in [file.as_raw_fd(), ipv6.as_raw_fd()] {
+            match UdpSender::from_inherited(fd, NonZeroUsize::new(1).unwrap(), &local_addr) {
+                Err(CreateUdpSenderError::InheritSocket { fd: failed, .. }) => {
+                    assert_eq!(failed, fd)
+                }
+                _ => panic!("The socket of fd {} must be rejected", fd),
+            }
+        }
+    }
 }
@@ -0,0 +1,275 @@
+// anevicon: A high-performant UDP-based load generator, written in Rust.
+// Copyright (C) 2019  Temirkhan Myrzamadi <gymmasssorla@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// For more information see <https://github.com/Gymmasssorla/anevicon>.
+
+//! Retrying of failed system calls with an exponential backoff.
+
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+use super::errno;
+
+/// Specifies how many times a failed send is retried (`--send-retries`) and
+/// how long to wait before the first retry (`--retry-backoff`). The waiting
+/// time doubles after each next retry.
+///
+/// `partial_retries` is how many times the unsent rest of packets is sent
+/// again after a send has transmitted only a part of them
+/// (`--partial-retries`), see `RetryPolicy::run_partial`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct RetryPolicy {
+    pub max_retries: usize,
+    pub backoff: Duration,
+    pub partial_retries: usize,
+}
+
+impl RetryPolicy {
+    /// Calls `send` until it succeeds, fails with a fatal error (such as
+    /// `EMSGSIZE`, see `errno::is_fatal`), or fails `max_retries + 1` times in
+    /// total. The last result of `send` is returned.
+    pub fn run<T, F: FnMut() -> io::Result<T>>(&self, mut send: F) -> io::Result<T> {
+        let mut backoff = self.backoff;
+
+        for retry in 1..=self.max_retries {
+            match send() {
+                Err(ref error) if !errno::is_fatal(error) => {
+                    log::trace!(
+                        "a send has failed ({error}), retry #{retry} in {backoff:?}.",
+                        error = error,
+                        retry = retry,
+                        backoff = backoff,
+                    );
+
+                    thread::sleep(backoff);
+                    backoff = backoff.checked_mul(2).unwrap_or(backoff);
+                }
+                result => return result,
+            }
+        }
+
+        send()
+    }
+
+    /// Calls `send` through `run` with all the `packets`, and then again with
+    /// the unsent rest of them while it sends only a part, but at most
+    /// `partial_retries` times. Like `sendmmsg`, `send` returns a number of
+    /// the first packets sent. Returns a total number of packets sent.
+    ///
+    /// Only the first call may return an error: if a retry fails, the packets
+    /// sent before are still returned, so they're accounted for.
+    pub fn run_partial<P, F: FnMut(&mut [P]) -> io::Result<usize>>(
+        &self,
+        packets: &mut [P],
+        mut send: F,
+    ) -> io::Result<usize> {
+        let mut packets_sent = self.run(|| send(packets))?;
+
+        for retry in 1..=self.partial_retries {
+            if packets_sent >= packets.len() {
+                break;
+            }
+            log::trace!(
+                "only {sent} of {total} packets have been sent, partial retry #{retry}.",
+                sent = packets_sent,
+                total = packets.len(),
+                retry = retry,
+            );
+
+            match self.run(|| send(&mut packets[packets_sent..])) {
+                Ok(0) => break,
+                Ok(sent) => packets_sent += sent,
+                Err(error) => {
+                    log::trace!(
+                        "a partial retry has failed ({error}), {unsent} packets are left unsent.",
+                        error = error,
+                        unsent = packets.len() - packets_sent,
+                    );
+                    break;
+                }
+            }
+        }
+
+        Ok(packets_sent)
+    }
+}
+
+impl Default for RetryPolicy {
+    /// Returns the policy that doesn't retry anything.
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 0,
+            backoff: Duration::from_secs(0),
+            partial_retries: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+
+    fn failing_send(
+        attempts: &mut usize,
+        failures: usize,
+        errno: i32,
+    ) -> impl FnMut() -> io::Result<usize> + '_ {
+        move || {
+            *attempts += 1;
+            if *attempts <= failures {
+                Err(io::Error::from_raw_os_error(errno))
+            } else {
+                Ok(*attempts)
+            }
+        }
+    }
+
+    #[test]
+    fn retries_transient_errors() {
+        let policy = RetryPolicy {
+            max_retries: 3,
+            backoff: Duration::from_millis(5),
+            partial_retries: 0,
+        };
+
+        let start = Instant::now();
+        let mut attempts = 0;
+        assert_eq!(
+            policy
+                .run(failing_send(&mut attempts, 2, libc::ENOBUFS))
+                .unwrap(),
+            3
+        );
+
+        // Waited 5ms before the first retry and 10ms before the second one
+        assert!(start.elapsed() >= Duration::from_millis(15));
+    }
+
+    #[test]
+    fn gives_up_after_max_retries() {
+        let policy = RetryPolicy {
+            max_retries: 2,
+            backoff: Duration::from_millis(1),
+            partial_retries: 0,
+        };
+
+        let mut attempts = 0;
+        let error = policy
+            .run(failing_send(&mut attempts, 10, libc::EAGAIN))
+            .unwrap_err();
+
+        assert_eq!(error.raw_os_error(), Some(libc::EAGAIN));
+        assert_eq!(attempts, 3);
+    }
+
+    #[test]
+    fn doesnt_retry_fatal_errors() {
+        let policy = RetryPolicy {
+            max_retries: 5,
+            backoff: Duration::from_secs(60),
+            partial_retries: 0,
+        };
+
+        let mut attempts = 0;
+        let error = policy
+            .run(failing_send(&mut attempts, 10, libc::EMSGSIZE))
+            .unwrap_err();
+
+        assert_eq!(error.raw_os_error(), Some(libc::EMSGSIZE));
+        assert_eq!(attempts, 1);
+    }
+
+    /// Simulates `sendmmsg` sending at most `limit` of the packets per call,
+    /// which marks them as sent.
+    fn partial_send(
+        calls: &mut usize,
+        limit: usize,
+    ) -> impl FnMut(&mut [bool]) -> io::Result<usize> + '_ {
+        move |packets| {
+            *calls += 1;
+            let sent = packets.len().min(limit);
+            packets[..sent].iter_mut().for_each(|packet| *packet = true);
+            Ok(sent)
+        }
+    }
+
+    #[test]
+    fn retries_partial_sends() {
+        let policy = RetryPolicy {
+            partial_retries: 5,
+            ..RetryPolicy::default()
+        };
+
+        let (mut packets, mut calls) = ([false; 10], 0);
+        assert_eq!(
+            policy
+                .run_partial(&mut packets, partial_send(&mut calls, 4))
+                .unwrap(),
+            10
+        );
+        assert!(packets.iter().all(|&sent| sent));
+        assert_eq!(calls, 3);
+
+        // Not more than `partial_retries` times
+        let policy = RetryPolicy {
+            partial_retries: 2,
+            ..RetryPolicy::default()
+        };
+        let (mut packets, mut calls) = ([false; 10], 0);
+        assert_eq!(
+            policy
+                .run_partial(&mut packets, partial_send(&mut calls, 3))
+                .unwrap(),
+            9
+        );
+        assert_eq!(calls, 3);
+        assert!(!packets[9]);
+    }
+
+    #[test]
+    fn keeps_packets_sent_before_failed_partial_retry() {
+        let policy = RetryPolicy {
+            partial_retries: 3,
+            ..RetryPolicy::default()
+        };
+
+        let mut calls = 0;
+        let sent = policy
+            .run_partial(&mut [(); 10], |_| {
+                calls += 1;
+                match calls {
+                    1 => Ok(6),
+                    _ => Err(io::Error::from_raw_os_error(libc::EAGAIN)),
+                }
+            })
+            .unwrap();
+
+        assert_eq!(sent, 6);
+        assert_eq!(calls, 2);
+    }
+
+    #[test]
+    fn default_policy_doesnt_retry() {
+        let mut attempts = 0;
+        assert!(RetryPolicy::default()
+            .run(failing_send(&mut attempts, 1, libc::EAGAIN))
+            .is_err());
+        assert_eq!(attempts, 1);
+    }
+}
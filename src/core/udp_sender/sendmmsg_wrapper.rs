@@ -18,14 +18,29 @@
 
 //! Minimal safe bindings to `libc::sendmmsg`.
 
-use std::io;
-use std::io::IoSlice;
+use std::io::{self, IoSlice};
 use std::mem;
 
-use super::DataPortion;
+/// A type alias that represents a portion to be sent. `transmitted` is a
+/// number of bytes sent, and `slices` are the IP/UDP headers and the payload of
+/// a packet to be sent, which are gathered by the kernel into one datagram.
+#[derive(Debug)]
+pub struct DataPortion<'a> {
+    pub transmitted: usize,
+    pub slices: [IoSlice<'a>; 2],
+}
+
+impl<'a> DataPortion<'a> {
+    /// Returns a number of bytes of a whole packet to be sent.
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.slices.iter().map(|slice| slice.len()).sum()
+    }
+}
 
 /// Sends all the specified `packets` using a single system call. `fd` is a
-/// file descriptor of a socket.
+/// file descriptor of a socket. The call is restarted if it's interrupted by
+/// a signal handler before sending anything (`EINTR`).
 ///
 /// # Returns
 /// It returns a total number of transmitted messages. It can be less or equal
@@ -36,14 +51,21 @@ use super::DataPortion;
 pub fn sendmmsg(fd: libc::c_int, packets: &mut [DataPortion]) -> io::Result<usize> {
     let mut messages: Vec<libc::mmsghdr> = prepare_mmsghdr_vector(packets);
 
-    unsafe {
-        match libc::sendmmsg(
-            fd,
-            &mut messages[0] as *mut libc::mmsghdr,
-            messages.len() as libc::c_uint,
-            0,
-        ) {
-            -1 => Err(io::Error::last_os_error()),
+    loop {
+        match unsafe {
+            libc::sendmmsg(
+                fd,
+                &mut messages[0] as *mut libc::mmsghdr,
+                messages.len() as libc::c_uint,
+                0,
+            )
+        } {
+            -1 => {
+                let error = io::Error::last_os_error();
+                if error.kind() != io::ErrorKind::Interrupted {
+                    return Err(error);
+                }
+            }
             portions_sent => {
                 // libc::sendmmsg assigns a number of bytes sent for each packet to
                 // mmsghdr.msg_len, so copy it into our DataPortion
@@ -51,7 +73,7 @@ pub fn sendmmsg(fd: libc::c_int, packets: &mut [DataPortion]) -> io::Result<usiz
                     packets[i].transmitted = messages[i].msg_len as usize;
                 }
 
-                Ok(portions_sent as usize)
+                return Ok(portions_sent as usize);
             }
         }
     }
@@ -59,14 +81,14 @@ pub fn sendmmsg(fd: libc::c_int, packets: &mut [DataPortion]) -> io::Result<usiz
 
 /// Converts an mutable slice of the `DataPortion` structure to a vector of
 /// `mmsghdr` that is able to be transmitted by `libc::sendmmsg`.
-fn prepare_mmsghdr_vector(packets: &mut [DataPortion]) -> Vec<libc::mmsghdr> {
+pub fn prepare_mmsghdr_vector(packets: &mut [DataPortion]) -> Vec<libc::mmsghdr> {
     packets
         .iter_mut()
         .map(|packet| libc::mmsghdr {
             msg_hdr: {
                 let mut message = unsafe { mem::zeroed::<libc::msghdr>() };
-                message.msg_iov = &mut packet.slice as *mut IoSlice as *mut libc::iovec;
-                message.msg_iovlen = 1;
+                message.msg_iov = packet.slices.as_mut_ptr() as *mut libc::iovec;
+                message.msg_iovlen = packet.slices.len() as _;
 
                 message
             },
@@ -78,8 +100,14 @@ fn prepare_mmsghdr_vector(packets: &mut [DataPortion]) -> Vec<libc::mmsghdr> {
 
 #[cfg(test)]
 mod test {
+    use std::io::IoSlice;
     use std::net::UdpSocket;
     use std::os::unix::io::AsRawFd;
+    use std::os::unix::net::UnixDatagram;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
 
     use super::*;
 
@@ -93,15 +121,18 @@ mod test {
         let packets = &mut [
             DataPortion {
                 transmitted: 0usize,
-                slice: IoSlice::new(b"Welcome to the jungle"),
+                slices: [IoSlice::new(b"Welcome "), IoSlice::new(b"to the jungle")],
             },
             DataPortion {
                 transmitted: 0usize,
-                slice: IoSlice::new(b"We got fun 'n' games"),
+                slices: [IoSlice::new(b"We "), IoSlice::new(b"got fun 'n' games")],
             },
             DataPortion {
                 transmitted: 0usize,
-                slice: IoSlice::new(b"We got everything you want"),
+                slices: [
+                    IoSlice::new(b"We "),
+                    IoSlice::new(b"got everything you want"),
+                ],
             },
         ];
 
@@ -111,24 +142,106 @@ mod test {
         );
 
         for packet in packets {
-            assert_eq!(packet.transmitted, packet.slice.len());
+            assert_eq!(packet.transmitted, packet.size());
+        }
+    }
+
+    extern "C" fn ignore_signal(_signal: libc::c_int) {}
+
+    /// Stops a thread interrupting the test with `SIGUSR1` and then restores
+    /// the previous action of this signal, even if the test panics.
+    struct InterruptGuard {
+        finished: Arc<AtomicBool>,
+        interrupter: Option<thread::JoinHandle<()>>,
+        old_action: libc::sigaction,
+    }
+
+    impl Drop for InterruptGuard {
+        fn drop(&mut self) {
+            self.finished.store(true, Ordering::SeqCst);
+            if let Some(interrupter) = self.interrupter.take() {
+                let _ = interrupter.join();
+            }
+            unsafe { libc::sigaction(libc::SIGUSR1, &self.old_action, std::ptr::null_mut()) };
         }
     }
 
+    #[test]
+    fn restarts_interrupted_sends() {
+        // Unlike `libc::signal`, `libc::sigaction` doesn't imply `SA_RESTART`,
+        // so the kernel doesn't restart interrupted system calls by itself
+        let mut old_action = unsafe { mem::zeroed::<libc::sigaction>() };
+        unsafe {
+            let mut action = mem::zeroed::<libc::sigaction>();
+            action.sa_sigaction = ignore_signal as extern "C" fn(libc::c_int) as usize;
+            libc::sigaction(libc::SIGUSR1, &action, &mut old_action);
+        }
+
+        // A receiver reads datagrams slower than they're sent, so sendmmsg
+        // blocks on a full socket buffer and gets interrupted by signals
+        const PACKETS_COUNT: usize = 10_000;
+        let (sender, receiver) = UnixDatagram::pair().expect("UnixDatagram::pair() has failed");
+        let reader = thread::spawn(move || {
+            let mut buffer = [0u8; 2048];
+            for received in 0..PACKETS_COUNT {
+                receiver
+                    .recv(&mut buffer)
+                    .expect("receiver.recv() has failed");
+                if received % 100 == 0 {
+                    thread::sleep(Duration::from_millis(1));
+                }
+            }
+        });
+
+        let sending_thread = unsafe { libc::pthread_self() };
+        let finished = Arc::new(AtomicBool::new(false));
+        let _guard = InterruptGuard {
+            finished: finished.clone(),
+            interrupter: Some(thread::spawn(move || {
+                while !finished.load(Ordering::SeqCst) {
+                    unsafe { libc::pthread_kill(sending_thread, libc::SIGUSR1) };
+                    thread::sleep(Duration::from_micros(100));
+                }
+            })),
+            old_action,
+        };
+
+        let payload = [0u8; 1024];
+        let packets = &mut (0..PACKETS_COUNT)
+            .map(|_| DataPortion {
+                transmitted: 0usize,
+                slices: [IoSlice::new(b"Header"), IoSlice::new(&payload)],
+            })
+            .collect::<Vec<DataPortion>>();
+
+        // A signal may also interrupt sendmmsg after sending a part of
+        // datagrams, which isn't an error, so send the rest of them again
+        let mut sent = 0;
+        while sent < packets.len() {
+            sent += sendmmsg(sender.as_raw_fd(), &mut packets[sent..])
+                .expect("An interrupted send has failed");
+        }
+
+        reader.join().unwrap();
+    }
+
     #[test]
     fn prepares_messages() {
         let packets = &mut [
             DataPortion {
                 transmitted: 0usize,
-                slice: IoSlice::new(b"Welcome to the jungle"),
+                slices: [IoSlice::new(b"Welcome "), IoSlice::new(b"to the jungle")],
             },
             DataPortion {
                 transmitted: 0usize,
-                slice: IoSlice::new(b"We got fun 'n' games"),
+                slices: [IoSlice::new(b"We "), IoSlice::new(b"got fun 'n' games")],
             },
             DataPortion {
                 transmitted: 0usize,
-                slice: IoSlice::new(b"We got everything you want"),
+                slices: [
+                    IoSlice::new(b"We "),
+                    IoSlice::new(b"got everything you want"),
+                ],
             },
         ];
 
@@ -139,9 +252,9 @@ mod test {
 
             assert_eq!(
                 headers.msg_hdr.msg_iov as *const libc::iovec,
-                &packet.slice as *const IoSlice as *const libc::iovec
+                packet.slices.as_ptr() as *const libc::iovec
             );
-            assert_eq!(headers.msg_hdr.msg_iovlen, 1);
+            assert_eq!(headers.msg_hdr.msg_iovlen, 2);
         }
     }
 }
@@ -0,0 +1,213 @@
+// anevicon: A high-performant UDP-based load generator, written in Rust.
+// Copyright (C) 2019  Temirkhan Myrzamadi <gymmasssorla@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// For more information see <https://github.com/Gymmasssorla/anevicon>.
+
+//! A pool of raw sockets reused by consecutive tests, so running many short
+//! tests in a single process doesn't create (and close) sockets each time.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::num::NonZeroUsize;
+use std::ops::{Deref, DerefMut};
+use std::os::unix::io::RawFd;
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+
+use super::{create_socket, CreateUdpSenderError, UdpSender};
+
+/// The settings that sockets must share to be interchangeable.
+#[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+struct SocketKey {
+    ipv6: bool,
+    broadcast: bool,
+    ttl: u8,
+}
+
+/// Caches the sockets of released senders and hands them out to new ones
+/// created with the same settings (see `SocketPool::acquire`).
+///
+/// The pool is `Sync`, so testers running on different threads can share it.
+/// A socket belongs to a single `PooledSender` while it's in use, and is
+/// returned into the pool when this sender is dropped. Idle sockets are closed
+/// together with the pool, but the pool returned by `SocketPool::global` is
+/// never dropped, so its sockets live until the process exits.
+#[derive(Debug, Default)]
+pub struct SocketPool {
+    idle: Mutex<HashMap<SocketKey, Vec<RawFd>>>,
+}
+
+/// A sender created by `SocketPool::acquire`. It's used just like `UdpSender`,
+/// and returns its socket into the pool when dropped.
+pub struct PooledSender<'p, 'a> {
+    sender: Option<UdpSender<'a>>,
+
+    /// The pool to return the socket into, or `None` for a sender created by
+    /// `PooledSender::unpooled`.
+    home: Option<(&'p SocketPool, SocketKey)>,
+}
+
+impl SocketPool {
+    /// Returns the pool shared by all the tests of this process.
+    pub fn global() -> &'static SocketPool {
+        lazy_static! {
+            static ref GLOBAL: SocketPool = SocketPool::default();
+        }
+
+        &GLOBAL
+    }
+
+    /// Returns a sender connected to `dest`, just like `UdpSender::new`, but
+    /// reuses an idle socket created with the same `broadcast` and `ttl` if
+    /// there is one. `ttl` is the TTL (hop limit) of the datagrams sent through
+    /// the socket.
+    ///
+    /// Only the socket is reused: the sender has an empty buffer of
+    /// `test_intensity` packets and the default settings, as a new one does.
+    pub fn acquire<'a>(
+        &self,
+        test_intensity: NonZeroUsize,
+        dest: &SocketAddr,
+        broadcast: bool,
+        ttl: u8,
+    ) -> Result<PooledSender<'_, 'a>, CreateUdpSenderError> {
+        let key = SocketKey {
+            ipv6: dest.is_ipv6(),
+            broadcast,
+            ttl,
+        };
+
+        let idle = self
+            .idle
+            .lock()
+            .expect("The socket pool is poisoned")
+            .get_mut(&key)
+            .and_then(Vec::pop);
+        let fd = match idle {
+            Some(fd) => fd,
+            None => create_socket(&dest.ip(), broadcast)?,
+        };
+
+        // If connecting fails, the socket is closed together with the sender
+        let mut sender = UdpSender::from_socket(fd, test_intensity);
+        sender.connect(dest)?;
+
+        log::trace!(
+            "SocketPool::acquire has succeed (fd = {fd}, reused = {reused}).",
+            fd = fd,
+            reused = idle.is_some()
+        );
+        Ok(PooledSender {
+            sender: Some(sender),
+            home: Some((self, key)),
+        })
+    }
+
+    fn release(&self, key: SocketKey, fd: RawFd) {
+        self.idle
+            .lock()
+            .expect("The socket pool is poisoned")
+            .entry(key)
+            .or_default()
+            .push(fd);
+    }
+}
+
+impl<'a> PooledSender<'static, 'a> {
+    /// Wraps `sender` which doesn't belong to any pool (such as a sender of
+    /// an inherited socket), so it closes its socket when dropped, as usual.
+    pub fn unpooled(sender: UdpSender<'a>) -> PooledSender<'static, 'a> {
+        PooledSender {
+            sender: Some(sender),
+            home: None,
+        }
+    }
+}
+
+impl Drop for SocketPool {
+    fn drop(&mut self) {
+        let idle = self.idle.get_mut().expect("The socket pool is poisoned");
+
+        for &fd in idle.values().flatten() {
+            unsafe {
+                if libc::close(fd) == -1 {
+                    panic!("Failed to drop SocketPool");
+                }
+            }
+        }
+    }
+}
+
+impl<'p, 'a> Deref for PooledSender<'p, 'a> {
+    type Target = UdpSender<'a>;
+
+    fn deref(&self) -> &UdpSender<'a> {
+        self.sender.as_ref().unwrap()
+    }
+}
+
+impl<'p, 'a> DerefMut for PooledSender<'p, 'a> {
+    fn deref_mut(&mut self) -> &mut UdpSender<'a> {
+        self.sender.as_mut().unwrap()
+    }
+}
+
+impl<'p, 'a> Drop for PooledSender<'p, 'a> {
+    fn drop(&mut self) {
+        if let (Some(sender), Some((pool, key))) = (self.sender.take(), self.home) {
+            pool.release(key, sender.into_socket());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::UdpSocket;
+    use std::os::unix::io::AsRawFd;
+
+    use super::*;
+
+    #[test]
+    fn reuses_released_sockets() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").expect("UdpSocket::bind() has failed");
+        let local_addr = receiver.local_addr().unwrap();
+        let pool = SocketPool::default();
+
+        let first = pool
+            .acquire(NonZeroUsize::new(4).unwrap(), &local_addr, false, 64)
+            .expect("SocketPool::acquire(...) failed");
+        let fd = first.as_raw_fd();
+        drop(first);
+
+        let second = pool
+            .acquire(NonZeroUsize::new(8).unwrap(), &local_addr, false, 64)
+            .expect("SocketPool::acquire(...) failed");
+        assert_eq!(second.as_raw_fd(), fd);
+        assert!(second.is_connected());
+        assert_eq!(second.buffer.capacity(), 8);
+
+        // Sockets of other settings aren't interchangeable
+        let other_ttl = pool
+            .acquire(NonZeroUsize::new(8).unwrap(), &local_addr, false, 32)
+            .expect("SocketPool::acquire(...) failed");
+        assert_ne!(other_ttl.as_raw_fd(), fd);
+
+        drop(second);
+        drop(other_ttl);
+        assert_eq!(pool.idle.lock().unwrap().values().flatten().count(), 2);
+    }
+}
@@ -0,0 +1,110 @@
+// anevicon: A high-performant UDP-based load generator, written in Rust.
+// Copyright (C) 2019  Temirkhan Myrzamadi <gymmasssorla@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// For more information see <https://github.com/Gymmasssorla/anevicon>.
+
+//! Minimal safe bindings to software transmission timestamps
+//! (`SO_TIMESTAMPING`).
+
+use std::io;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::core::error_queue;
+
+/// Don't loop packets back to the error queue together with timestamps.
+const SOF_TIMESTAMPING_OPT_TSONLY: libc::c_uint = 1 << 11;
+
+/// Asks the kernel to generate a software timestamp for each packet sent
+/// through `fd` right before passing it to a network device driver.
+pub fn enable(fd: libc::c_int) -> io::Result<()> {
+    let flags: libc::c_uint = libc::SOF_TIMESTAMPING_TX_SOFTWARE
+        | libc::SOF_TIMESTAMPING_SOFTWARE
+        | SOF_TIMESTAMPING_OPT_TSONLY;
+
+    error_queue::set_int_option(
+        fd,
+        libc::SOL_SOCKET,
+        libc::SO_TIMESTAMPING,
+        flags as libc::c_int,
+    )
+}
+
+/// Stops generating transmission timestamps for packets sent through `fd`.
+pub fn disable(fd: libc::c_int) -> io::Result<()> {
+    error_queue::set_int_option(fd, libc::SOL_SOCKET, libc::SO_TIMESTAMPING, 0)
+}
+
+/// Reads all the transmission timestamps currently queued on `fd` without
+/// blocking. The timestamps use the system-wide real-time clock.
+///
+/// # References
+/// For more information please read https://www.kernel.org/doc/Documentation/networking/timestamping.txt.
+pub fn read_all(fd: libc::c_int) -> io::Result<Vec<SystemTime>> {
+    let mut timestamps = Vec::new();
+
+    error_queue::read_all(fd, |message| {
+        if message.level == libc::SOL_SOCKET && message.kind == libc::SCM_TIMESTAMPING {
+            // `struct scm_timestamping` contains three timestamps, and the
+            // first one is a software timestamp
+            if let Some(software) = unsafe { message.read::<libc::timespec>(0) } {
+                if software.tv_sec != 0 || software.tv_nsec != 0 {
+                    timestamps.push(
+                        UNIX_EPOCH + Duration::new(software.tv_sec as u64, software.tv_nsec as u32),
+                    );
+                }
+            }
+        }
+    })?;
+
+    Ok(timestamps)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::UdpSocket;
+    use std::os::unix::io::AsRawFd;
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn reads_loopback_timestamps() {
+        let socket = UdpSocket::bind("127.0.0.1:0").expect("UdpSocket::bind() has failed");
+        socket
+            .connect(socket.local_addr().unwrap())
+            .expect("socket.connect() has failed");
+        enable(socket.as_raw_fd()).expect("enable() has failed");
+
+        let sent_at = SystemTime::now();
+        for _ in 0..3 {
+            socket
+                .send(b"Timestamp me")
+                .expect("socket.send() has failed");
+        }
+
+        let mut timestamps = Vec::new();
+        for _ in 0..100 {
+            timestamps.extend(read_all(socket.as_raw_fd()).expect("read_all() has failed"));
+            if timestamps.len() == 3 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        assert_eq!(timestamps.len(), 3);
+        assert!(timestamps.iter().all(|&timestamp| timestamp >= sent_at));
+    }
+}
@@ -37,3 +37,17 @@ pub fn format_failure(error: &failure::Error) -> String {
 
     result
 }
+
+/// Formats a standard error and all its sources into `String` the same way as
+/// `format_failure` does.
+pub fn format_error(error: &dyn std::error::Error) -> String {
+    let mut result = format!("    Caused by: {}", error);
+
+    let mut source = error.source();
+    while let Some(cause) = source {
+        write!(result, "\n    Caused by: {}", cause).unwrap();
+        source = cause.source();
+    }
+
+    result
+}
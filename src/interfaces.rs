@@ -0,0 +1,111 @@
+// anevicon: A high-performant UDP-based load generator, written in Rust.
+// Copyright (C) 2019  Temirkhan Myrzamadi <gymmasssorla@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// For more information see <https://github.com/Gymmasssorla/anevicon>.
+
+//! A module containing the enumeration of network interfaces used by the
+//! `interfaces` subcommand.
+
+use std::ffi::CStr;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// A network interface of this machine together with all its IPv4/IPv6
+/// addresses.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Interface {
+    pub name: String,
+    pub addresses: Vec<IpAddr>,
+}
+
+/// Enumerates all the network interfaces using `libc::getifaddrs`. The
+/// interfaces are returned in the same order as the OS reports them.
+///
+/// # References
+/// For more information please read http://man7.org/linux/man-pages/man3/getifaddrs.3.html.
+pub fn interfaces() -> io::Result<Vec<Interface>> {
+    let mut ifaddrs = std::ptr::null_mut::<libc::ifaddrs>();
+    if unsafe { libc::getifaddrs(&mut ifaddrs) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut result = Vec::<Interface>::new();
+    let mut current = ifaddrs;
+
+    while !current.is_null() {
+        let entry = unsafe { &*current };
+        let name = unsafe { CStr::from_ptr(entry.ifa_name) }
+            .to_string_lossy()
+            .into_owned();
+
+        let index = match result.iter().position(|interface| interface.name == name) {
+            Some(index) => index,
+            None => {
+                result.push(Interface {
+                    name,
+                    addresses: Vec::new(),
+                });
+                result.len() - 1
+            }
+        };
+
+        if let Some(address) = unsafe { extract_address(entry.ifa_addr) } {
+            result[index].addresses.push(address);
+        }
+
+        current = entry.ifa_next;
+    }
+
+    unsafe { libc::freeifaddrs(ifaddrs) };
+    Ok(result)
+}
+
+/// Converts `sockaddr` into `IpAddr` if it is either IPv4 or IPv6. A null
+/// pointer or any other address family produces `None`.
+unsafe fn extract_address(address: *const libc::sockaddr) -> Option<IpAddr> {
+    if address.is_null() {
+        return None;
+    }
+
+    match i32::from((*address).sa_family) {
+        libc::AF_INET => {
+            let address = &*(address as *const libc::sockaddr_in);
+            Some(IpAddr::V4(Ipv4Addr::from(u32::from_be(
+                address.sin_addr.s_addr,
+            ))))
+        }
+        libc::AF_INET6 => {
+            let address = &*(address as *const libc::sockaddr_in6);
+            Some(IpAddr::V6(Ipv6Addr::from(address.sin6_addr.s6_addr)))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_loopback() {
+        let interfaces = interfaces().expect("interfaces() failed");
+
+        assert!(interfaces
+            .iter()
+            .flat_map(|interface| interface.addresses.iter())
+            .any(|address| address.is_loopback()));
+    }
+}
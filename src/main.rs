@@ -19,16 +19,19 @@
 #[macro_use]
 extern crate failure_derive;
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryInto;
+use std::fmt;
+use std::io::{self, Write};
 
 use termion::{color, style, terminal_size};
 
-use config::ArgsConfig;
+use config::{subnet, ArgsConfig, Command, Endpoints, PacketsCountMode, SendConfig};
 
 mod config;
 mod core;
 mod helpers;
+mod interfaces;
 mod logging;
 
 fn main() {
@@ -38,37 +41,346 @@ fn main() {
     logging::setup_logging(&config.logging_config);
     log::trace!("{:?}", config);
 
-    if check_config(&config).is_err() {
-        std::process::exit(libc::EXIT_FAILURE);
-    }
+    let result = match config
+        .command
+        .expect("ArgsConfig::setup() must ensure a subcommand")
+    {
+        Command::Send(send_config) => check_config(&send_config).and_then(|_| {
+            if send_config.mlockall {
+                lock_memory();
+            }
+            core::run(send_config)
+        }),
+        Command::Interfaces => display_interfaces(),
+        Command::SelfTest => core::self_test(),
+    };
 
-    if core::run(config).is_err() {
+    if result.is_err() {
         std::process::exit(libc::EXIT_FAILURE);
     }
 }
 
-fn check_config(config: &ArgsConfig) -> Result<(), ()> {
-    let mut keys = HashSet::new();
-    for next_endpoints in &config.packets_config.endpoints {
-        if next_endpoints.sender().port() == 0 {
-            log::warn!(
-                "datagrams sent from {source_address} might be dropped by a router because of the \
-                 unspecified source port!",
+fn check_config(config: &SendConfig) -> Result<(), ()> {
+    let mut misconfigured = false;
+    let mut positions = HashMap::<&Endpoints, Vec<usize>>::new();
+
+    for (index, next_endpoints) in config.packets_config.endpoints.iter().enumerate() {
+        if next_endpoints.sender().port() == 0 && config.sockets_config.count_responses {
+            log::error!(
+                "--count-responses requires a fixed source port, but {source_address} \
+                 (endpoints #{index}) has the unspecified one!",
                 source_address = next_endpoints.sender(),
+                index = index,
+            );
+            return Err(());
+        }
+        if next_endpoints.sender().port() == 0 {
+            misconfigured |= report_misconfiguration(
+                config.strict,
+                format_args!(
+                    "datagrams sent from {source_address} (endpoints #{index}) might be dropped \
+                     by a router because of the unspecified source port!",
+                    source_address = next_endpoints.sender(),
+                    index = index,
+                ),
+            );
+        }
+
+        // `--receiver-port-range` overrides receiver ports with positive ones
+        if next_endpoints.receiver().port() == 0
+            && config.packets_config.receiver_port_range.is_none()
+        {
+            misconfigured |= report_misconfiguration(
+                config.strict,
+                format_args!(
+                    "datagrams sent to {receiver_address} (endpoints #{index}) might be dropped \
+                     by a receiver because of the unspecified destination port!",
+                    receiver_address = next_endpoints.receiver(),
+                    index = index,
+                ),
             );
         }
 
-        if keys.contains(next_endpoints) {
+        positions.entry(next_endpoints).or_default().push(index);
+    }
+
+    // Report each duplicate only once, in order of their first appearance
+    let mut duplicates = positions
+        .iter()
+        .filter(|(_, indices)| indices.len() > 1)
+        .collect::<Vec<_>>();
+    duplicates.sort_by_key(|(_, indices)| indices[0]);
+
+    for (endpoints, indices) in &duplicates {
+        log::error!(
+            "all endpoints must be uniquely specified, but {endpoints} has been specified {count} \
+             times (endpoints #{indices})!",
+            endpoints = endpoints,
+            count = indices.len(),
+            indices = indices
+                .iter()
+                .map(usize::to_string)
+                .collect::<Vec<String>>()
+                .join(", #"),
+        );
+    }
+
+    if config.netflow_export.is_some() && !cfg!(feature = "netflow") {
+        log::error!("--netflow-export requires anevicon to be built with the `netflow` feature!");
+        return Err(());
+    }
+
+    if config.output_file.is_some() && !config.output_format.is_supported() {
+        log::error!(
+            "--output-format={format} requires anevicon to be built with the `{format}` feature!",
+            format = config.output_format,
+        );
+        return Err(());
+    }
+
+    if config.packets_config.payload_config.repeat_file.get() > 1
+        && config.packets_config.payload_config.mmap_files
+    {
+        log::error!("--repeat-file cannot be combined with --mmap-files!");
+        return Err(());
+    }
+
+    let multicast_option = match (
+        config.packets_config.multicast_ttl,
+        &config.sockets_config.multicast_if,
+    ) {
+        (Some(_), _) => Some("--multicast-ttl"),
+        (None, Some(_)) => Some("--multicast-if"),
+        (None, None) => None,
+    };
+    if let Some(option) = multicast_option {
+        if !config
+            .packets_config
+            .endpoints
+            .iter()
+            .any(|endpoints| endpoints.receiver().ip().is_multicast())
+        {
             log::error!(
-                "all endpoints must be uniquely specified, but {sender}&{receiver} has been \
-                 specified several times!",
-                sender = next_endpoints.sender(),
-                receiver = next_endpoints.receiver(),
+                "{option} requires a multicast receiver (224.0.0.0/4 or ff00::/8), but none of \
+                 the receivers is!",
+                option = option,
             );
+            return Err(());
+        }
+    }
+
+    // A socket can be connected to a single receiver at a time
+    if config.sockets_config.fd.is_some() && config.packets_config.endpoints.len() > 1 {
+        log::error!(
+            "--fd requires a single endpoint, but {count} are specified!",
+            count = config.packets_config.endpoints.len(),
+        );
+        return Err(());
+    }
 
+    let mut rate_indices = HashSet::<usize>::new();
+    for rate in &config.endpoint_rates {
+        if rate.index >= config.packets_config.endpoints.len() {
+            log::error!(
+                "--endpoint-rate={rate} refers to the endpoints #{index}, but only {count} \
+                 endpoints are specified!",
+                rate = rate,
+                index = rate.index,
+                count = config.packets_config.endpoints.len(),
+            );
             return Err(());
-        } else {
-            keys.insert(next_endpoints);
+        }
+        if !rate_indices.insert(rate.index) {
+            log::error!(
+                "--endpoint-rate has been specified several times for the endpoints #{index}!",
+                index = rate.index,
+            );
+            return Err(());
+        }
+    }
+
+    let mut weight_indices = HashSet::<usize>::new();
+    for weight in &config.packets_config.payload_weights {
+        if !weight_indices.insert(weight.index) {
+            log::error!(
+                "--payload-weight has been specified several times for the payload #{index}!",
+                index = weight.index,
+            );
+            return Err(());
+        }
+    }
+
+    let packet_rate = (0..config.packets_config.endpoints.len())
+        .map(|index| config.test_intensity_of(index).get())
+        .fold(0usize, usize::saturating_add);
+    if packet_rate > config.max_packet_rate.get() && !config.i_know_what_im_doing {
+        log::error!(
+            "the test would send up to {packet_rate} packets per second, which exceeds \
+             --max-packet-rate={max_packet_rate}! Decrease --test-intensity (or --endpoint-rate) \
+             or specify --i-know-what-im-doing to run it anyway.",
+            packet_rate = packet_rate,
+            max_packet_rate = config.max_packet_rate,
+        );
+        return Err(());
+    }
+
+    let mut violations = false;
+    for (index, next_endpoints) in config.packets_config.endpoints.iter().enumerate() {
+        if let Err(violation) = subnet::check_receiver(
+            next_endpoints.receiver().ip(),
+            &config.allow_subnets,
+            &config.deny_subnets,
+            config.safe,
+        ) {
+            log::error!(
+                "the receiver {receiver} (endpoints #{index}) {violation}!",
+                receiver = next_endpoints.receiver().ip(),
+                index = index,
+                violation = violation,
+            );
+            violations = true;
+        }
+    }
+    if violations {
+        return Err(());
+    }
+
+    if config.exit_config.packets_count_mode == PacketsCountMode::Total
+        && config.exit_config.packets_count.get() < config.packets_config.endpoints.len()
+    {
+        log::error!(
+            "--packets-count={packets_count} cannot be divided between {endpoints} endpoints in \
+             the total mode, each endpoint must send at least one packet!",
+            packets_count = config.exit_config.packets_count,
+            endpoints = config.packets_config.endpoints.len(),
+        );
+        return Err(());
+    }
+
+    if misconfigured || !duplicates.is_empty() {
+        return Err(());
+    }
+
+    confirm_global_receivers(config)
+}
+
+/// Asks a user to confirm testing of globally routable receivers, unless
+/// `--yes` is specified, nothing will be sent, or stdin isn't a terminal.
+fn confirm_global_receivers(config: &SendConfig) -> Result<(), ()> {
+    if config.yes
+        || config.count_only
+        || config.list_payloads
+        || config.show_wire_size
+        || !termion::is_tty(&io::stdin())
+    {
+        return Ok(());
+    }
+
+    let receivers = config
+        .packets_config
+        .endpoints
+        .iter()
+        .map(|endpoints| endpoints.receiver().ip())
+        .filter(|&receiver| subnet::is_globally_routable(receiver))
+        .map(|receiver| receiver.to_string())
+        .collect::<Vec<String>>();
+    if receivers.is_empty() {
+        return Ok(());
+    }
+
+    print!(
+        "{receivers} {verb} globally routable, are you sure you want to test {pronoun}? [y/N] ",
+        receivers = receivers.join(", "),
+        verb = if receivers.len() == 1 { "is" } else { "are" },
+        pronoun = if receivers.len() == 1 { "it" } else { "them" },
+    );
+    io::stdout().flush().expect("Failed to flush stdout");
+
+    let mut answer = String::new();
+    if io::stdin().read_line(&mut answer).is_ok()
+        && ["y", "yes"].contains(&answer.trim().to_lowercase().as_str())
+    {
+        Ok(())
+    } else {
+        log::error!("the test has been cancelled (specify --yes to skip the confirmation).");
+        Err(())
+    }
+}
+
+/// Locks all the current and future pages of the process into RAM
+/// (`--mlockall`). A failure isn't fatal, the test just runs without it.
+fn lock_memory() {
+    if unsafe { libc::mlockall(libc::MCL_CURRENT | libc::MCL_FUTURE) } == 0 {
+        log::debug!("all the memory of the process has been locked (--mlockall).");
+        return;
+    }
+
+    let error = io::Error::last_os_error();
+    match error.raw_os_error() {
+        Some(libc::ENOMEM) | Some(libc::EPERM) => log::warn!(
+            "failed to lock the memory (--mlockall) because RLIMIT_MEMLOCK is {limit} and \
+             CAP_IPC_LOCK is missing, continuing without it!\n    Caused by: {error}",
+            limit = memlock_limit(),
+            error = error,
+        ),
+        _ => log::warn!(
+            "failed to lock the memory (--mlockall), continuing without it!\n    Caused by: \
+             {error}",
+            error = error,
+        ),
+    }
+}
+
+/// Returns the soft `RLIMIT_MEMLOCK` of the process as a human-readable string.
+fn memlock_limit() -> String {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+
+    if unsafe { libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut limit) } == -1 {
+        String::from("unknown")
+    } else if limit.rlim_cur == libc::RLIM_INFINITY {
+        String::from("unlimited")
+    } else {
+        format!("{} bytes", limit.rlim_cur)
+    }
+}
+
+/// Prints a misconfiguration `message` as a warning, or as an error if
+/// `--strict` is specified. Returns `true` if the message was an error.
+fn report_misconfiguration(strict: bool, message: fmt::Arguments) -> bool {
+    if strict {
+        log::error!("{message}", message = message);
+    } else {
+        log::warn!("{message}", message = message);
+    }
+
+    strict
+}
+
+fn display_interfaces() -> Result<(), ()> {
+    let interfaces = match interfaces::interfaces() {
+        Err(error) => {
+            log::error!(
+                "failed to enumerate the network interfaces!\n    Caused by: {error}",
+                error = error,
+            );
+            return Err(());
+        }
+        Ok(interfaces) => interfaces,
+    };
+
+    for interface in interfaces {
+        println!(
+            "{cyan}{name}{reset}",
+            name = interface.name,
+            cyan = color::Fg(color::Cyan),
+            reset = color::Fg(color::Reset),
+        );
+
+        for address in interface.addresses {
+            println!("\t{address}", address = address);
         }
     }
 